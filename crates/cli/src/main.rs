@@ -4,11 +4,15 @@ use crate::repl::Repl;
 use clap::{Parser, Subcommand};
 use csv::ReaderBuilder;
 use eql_core::{
-    common::query_result::{ExpressionResult, QueryResult},
-    interpreter::Interpreter,
+    common::{
+        config::Config,
+        query_result::{AddressFormat, ExpressionResult, QueryResult, TimestampFormat},
+    },
+    interpreter::{backend::disk_cache, eql_explain, eql_follow, eql_validate, Interpreter},
 };
+use futures::StreamExt;
 use serde::Serialize;
-use std::error::Error;
+use std::{error::Error, path::PathBuf};
 use tabled::{builder::Builder, settings::Style, Table};
 
 #[derive(Parser)]
@@ -29,11 +33,66 @@ enum SubCommand {
 
     #[clap(name = "repl", about = "Start an interactive REPL")]
     Repl,
+
+    #[clap(
+        name = "clear-cache",
+        about = "Empty the cache of finalized data on disk"
+    )]
+    ClearCache(ClearCacheArguments),
+}
+
+#[derive(Debug, Parser)]
+struct ClearCacheArguments {
+    /// Directory of the cache, the `cache_dir` of the config by default
+    #[clap(long)]
+    cache_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Parser)]
 struct RunArguments {
     file: String,
+
+    /// Keep printing new blocks as they're produced. Only block queries on WebSocket RPCs can be followed
+    #[clap(long)]
+    follow: bool,
+
+    /// Print the RPC requests the queries would make, without running them
+    #[clap(long, conflicts_with = "follow")]
+    explain: bool,
+
+    /// Validate the queries without running them or contacting the network. Exits with an error
+    /// status if any query is invalid
+    #[clap(long, conflicts_with_all = ["follow", "explain"])]
+    no_network: bool,
+
+    /// Print and dump addresses in lowercase instead of EIP-55 checksummed
+    #[clap(long)]
+    lowercase_addresses: bool,
+
+    /// Also print and dump timestamps as RFC 3339 datetimes in UTC, next to their Unix epochs
+    #[clap(long)]
+    datetime_timestamps: bool,
+
+    /// Cache the finalized blocks, transactions and receipts in this directory, in place of the
+    /// `cache_dir` of the config
+    #[clap(long)]
+    cache_dir: Option<PathBuf>,
+}
+
+impl RunArguments {
+    fn address_format(&self) -> AddressFormat {
+        match self.lowercase_addresses {
+            true => AddressFormat::Lowercase,
+            false => AddressFormat::Checksummed,
+        }
+    }
+
+    fn timestamp_format(&self) -> TimestampFormat {
+        match self.datetime_timestamps {
+            true => TimestampFormat::Rfc3339,
+            false => TimestampFormat::Unix,
+        }
+    }
 }
 
 struct ResultHandler;
@@ -58,6 +117,21 @@ impl ResultHandler {
                 ExpressionResult::Log(query_res) => {
                     println!("{}", to_table(query_res)?);
                 }
+                ExpressionResult::Token(query_res) => {
+                    println!("{}", to_table(query_res)?);
+                }
+                ExpressionResult::Receipt(query_res) => {
+                    println!("{}", to_table(query_res)?);
+                }
+                ExpressionResult::Chain(query_res) => {
+                    println!("{}", to_table(query_res)?);
+                }
+                ExpressionResult::Aggregate(query_res) => {
+                    println!("{}", to_table(query_res)?);
+                }
+                ExpressionResult::Combined(results) => {
+                    self.handle_result(results.into_iter().map(QueryResult::new).collect())?;
+                }
             }
         }
 
@@ -98,22 +172,77 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
     let args = Arguments::parse();
 
     match args.subcmd {
-        SubCommand::Run(run_args) => {
+        SubCommand::Run(run_args) if run_args.no_network => {
             let source = std::fs::read_to_string(run_args.file)?;
-            let result_handler = ResultHandler::new();
-            let result = Interpreter::run_program(&source).await;
-            match result {
-                Ok(query_results) => {
-                    result_handler.handle_result(query_results)?;
+            let errors = eql_validate(&source);
+
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+
+            if !errors.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        SubCommand::Run(run_args) if run_args.explain => {
+            let source = std::fs::read_to_string(run_args.file)?;
+            match eql_explain(&source) {
+                Ok(plans) => {
+                    for plan in plans {
+                        println!("{}\n", plan);
+                    }
                 }
-                Err(e) => {
-                    eprintln!("{}", e);
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        SubCommand::Run(run_args) if run_args.follow => {
+            let source = std::fs::read_to_string(run_args.file)?;
+            let mut blocks = eql_follow(&source).await?;
+
+            // The stream ends when the connection to the RPC is lost
+            while let Some(block) = blocks.next().await {
+                match block {
+                    Ok(block) => println!("{}", to_table(vec![block])?),
+                    Err(e) => eprintln!("{}", e),
                 }
             }
         }
+        SubCommand::Run(run_args) => {
+            let source = std::fs::read_to_string(&run_args.file)?;
+            let result_handler = ResultHandler::new();
+            // The dumps are written while the program runs, so both are done in the address and
+            // timestamp formats
+            let run = async {
+                let results = match run_args.cache_dir.clone() {
+                    Some(cache_dir) => {
+                        Interpreter::run_program_with_disk_cache(&source, cache_dir).await
+                    }
+                    None => Interpreter::run_program(&source).await,
+                };
+                match results {
+                    Ok(query_results) => result_handler.handle_result(query_results),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        Ok(())
+                    }
+                }
+            };
+            let run = run_args.timestamp_format().scope(run);
+            run_args.address_format().scope(run).await?;
+        }
         SubCommand::Repl => {
             Repl::new().run().await?;
         }
+        SubCommand::ClearCache(args) => {
+            let cache_dir = match args.cache_dir {
+                Some(cache_dir) => cache_dir,
+                None => Config::new()
+                    .get_cache_dir()?
+                    .ok_or("No cache directory, pass --cache-dir or set cache_dir in the config")?,
+            };
+            disk_cache::clear(&cache_dir)?;
+            println!("Cleared {}", cache_dir.display());
+        }
     }
 
     Ok(())
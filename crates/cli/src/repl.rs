@@ -241,6 +241,33 @@ impl Repl {
                         queue!(stdout(), MoveToNextLine(1), Print(line.cyan())).unwrap();
                     });
                 }
+                ExpressionResult::Token(query_res) => {
+                    let table = to_table(query_res)?;
+                    table.to_string().split("\n").for_each(|line| {
+                        queue!(stdout(), MoveToNextLine(1), Print(line.magenta())).unwrap();
+                    });
+                }
+                ExpressionResult::Receipt(query_res) => {
+                    let table = to_table(query_res)?;
+                    table.to_string().split("\n").for_each(|line| {
+                        queue!(stdout(), MoveToNextLine(1), Print(line.yellow())).unwrap();
+                    });
+                }
+                ExpressionResult::Chain(query_res) => {
+                    let table = to_table(query_res)?;
+                    table.to_string().split("\n").for_each(|line| {
+                        queue!(stdout(), MoveToNextLine(1), Print(line.green())).unwrap();
+                    });
+                }
+                ExpressionResult::Aggregate(query_res) => {
+                    let table = to_table(query_res)?;
+                    table.to_string().split("\n").for_each(|line| {
+                        queue!(stdout(), MoveToNextLine(1), Print(line.blue())).unwrap();
+                    });
+                }
+                ExpressionResult::Combined(results) => {
+                    self.display_result(results.into_iter().map(QueryResult::new).collect())?;
+                }
             }
         }
 
@@ -1,6 +1,16 @@
-use super::ens::NameOrAddress;
+use super::{
+    aggregate::selected_field,
+    ens::NameOrAddress,
+    entity_id::{parse_block_number_or_tag, EntityIdError},
+};
 use crate::interpreter::frontend::parser::Rule;
-use alloy::hex::FromHexError;
+use alloy::{
+    dyn_abi::{DynSolType, DynSolValue, JsonAbiExt},
+    eips::BlockNumberOrTag,
+    hex::FromHexError,
+    json_abi::Function,
+    primitives::{Address, Bytes, U256},
+};
 use eql_macros::EnumVariants;
 use pest::iterators::{Pair, Pairs};
 use serde::{Deserialize, Serialize};
@@ -48,6 +58,16 @@ impl Account {
     pub fn fields(&self) -> Vec<AccountField> {
         self.fields.clone()
     }
+
+    /// Block at which the account state is queried, if a block filter was provided
+    pub fn block(&self) -> Option<BlockNumberOrTag> {
+        self.filter.as_ref().and_then(|filters| {
+            filters.iter().find_map(|filter| match filter {
+                AccountFilter::Block(block) => Some(*block),
+                _ => None,
+            })
+        })
+    }
 }
 
 impl TryFrom<Pairs<'_, Rule>> for Account {
@@ -71,6 +91,7 @@ impl TryFrom<Pairs<'_, Rule>> for Account {
                     }
 
                     fields = inner_pairs
+                        .filter_map(selected_field)
                         .map(|pair| AccountField::try_from(pair))
                         .collect::<Result<Vec<AccountField>, AccountFieldError>>()?;
                 }
@@ -81,12 +102,13 @@ impl TryFrom<Pairs<'_, Rule>> for Account {
                         id = Some(vec![NameOrAddress::from_str(pair.as_str())?]);
                     }
                 }
-                Rule::account_filter_list => {
-                    filter = Some(
-                        pair.into_inner()
-                            .map(|pair| AccountFilter::try_from(pair))
-                            .collect::<Result<Vec<AccountFilter>, AccountFilterError>>()?,
-                    );
+                Rule::account_filter => {
+                    let pair = pair.into_inner().next().ok_or_else(|| {
+                        AccountFilterError::UnexpectedToken("empty filter".to_string())
+                    })?;
+                    filter
+                        .get_or_insert_with(Vec::new)
+                        .push(AccountFilter::try_from(pair)?);
                 }
                 _ => {
                     return Err(AccountError::UnexpectedToken(pair.as_str().to_string()));
@@ -105,11 +127,15 @@ pub enum AccountFilterError {
 
     #[error(transparent)]
     FromHexError(#[from] FromHexError),
+
+    #[error(transparent)]
+    EntityIdError(#[from] EntityIdError),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum AccountFilter {
     Address(NameOrAddress),
+    Block(BlockNumberOrTag),
 }
 
 impl TryFrom<Pair<'_, Rule>> for AccountFilter {
@@ -117,10 +143,19 @@ impl TryFrom<Pair<'_, Rule>> for AccountFilter {
 
     fn try_from(pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
         match pair.as_rule() {
-            Rule::address_filter => {
-                let address = NameOrAddress::from_str(pair.as_str())?;
+            Rule::address_filter_type => {
+                // Skip the operator
+                let mut inner_pairs = pair.into_inner();
+                inner_pairs.next();
+                let address = NameOrAddress::from_str(inner_pairs.as_str())?;
                 Ok(AccountFilter::Address(address))
             }
+            Rule::account_block_filter_type => {
+                let mut inner_pairs = pair.into_inner();
+                inner_pairs.next();
+                let block = parse_block_number_or_tag(inner_pairs.as_str())?;
+                Ok(AccountFilter::Block(block))
+            }
             _ => {
                 return Err(AccountFilterError::UnexpectedToken(
                     pair.as_str().to_string(),
@@ -130,13 +165,35 @@ impl TryFrom<Pair<'_, Rule>> for AccountFilter {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, EnumVariants)]
+/// Fields of an account. The ones marked `#[enum_variants(skip)]` cost extra requests or are
+/// guessed, and are only read when the query lists them.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, EnumVariants)]
 pub enum AccountField {
     Address,
     Nonce,
     Balance,
     Code,
+    IsContract,
     Chain,
+    EnsName,
+    /// Balance of an ERC-20 token, identified by its contract address
+    TokenBalance(Address),
+    /// Value of a storage slot, identified by its index
+    Storage(U256),
+    /// First block at which the account had a nonzero nonce or balance, found with a binary search
+    /// over past blocks
+    #[enum_variants(skip)]
+    FirstActiveBlock,
+    /// 4-byte selectors of the functions the contract dispatches to, found in its code with a
+    /// heuristic
+    #[enum_variants(skip)]
+    Selectors,
+    /// Implementation a proxy delegates to, read from the storage slots of the proxy standards, one
+    /// request per slot
+    #[enum_variants(skip)]
+    Implementation,
+    /// Result of calling a function of the contract with `eth_call`
+    Call(ContractCall),
 }
 
 impl Display for AccountField {
@@ -146,7 +203,15 @@ impl Display for AccountField {
             AccountField::Nonce => write!(f, "nonce"),
             AccountField::Balance => write!(f, "balance"),
             AccountField::Code => write!(f, "code"),
+            AccountField::IsContract => write!(f, "is_contract"),
             AccountField::Chain => write!(f, "chain"),
+            AccountField::EnsName => write!(f, "ens_name"),
+            AccountField::TokenBalance(token) => write!(f, "balance_of {}", token),
+            AccountField::Storage(slot) => write!(f, "storage[{:#x}]", slot),
+            AccountField::FirstActiveBlock => write!(f, "first_active_block"),
+            AccountField::Selectors => write!(f, "selectors"),
+            AccountField::Implementation => write!(f, "implementation"),
+            AccountField::Call(call) => write!(f, "{call}"),
         }
     }
 }
@@ -156,6 +221,12 @@ pub enum AccountFieldError {
     #[error("Invalid field for entity Account: {0}")]
     InvalidField(String),
 
+    #[error("Invalid storage slot {0}")]
+    InvalidStorageSlot(String),
+
+    #[error("Invalid call of {0}: {1}")]
+    InvalidCall(String, String),
+
     #[error(transparent)]
     FromHexError(#[from] FromHexError),
 }
@@ -164,7 +235,18 @@ impl<'a> TryFrom<Pair<'a, Rule>> for AccountField {
     type Error = AccountFieldError;
 
     fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
-        AccountField::try_from(pair.as_str())
+        let mut inner_pairs = pair.clone().into_inner();
+
+        match inner_pairs.next() {
+            Some(signature) if signature.as_rule() == Rule::call_signature => {
+                let args = inner_pairs.map(|arg| arg.as_str().to_string()).collect();
+                Ok(AccountField::Call(ContractCall::new(
+                    unquote(signature.as_str()),
+                    args,
+                )?))
+            }
+            _ => AccountField::try_from(pair.as_str()),
+        }
     }
 }
 
@@ -172,13 +254,116 @@ impl TryFrom<&str> for AccountField {
     type Error = AccountFieldError;
 
     fn try_from(value: &str) -> Result<Self, AccountFieldError> {
+        if let Some(token) = value.strip_prefix("balance_of") {
+            return Ok(AccountField::TokenBalance(Address::from_str(token.trim())?));
+        }
+
+        if let Some(slot) = value.strip_prefix("storage") {
+            let slot = slot
+                .trim()
+                .strip_prefix('[')
+                .and_then(|slot| slot.strip_suffix(']'))
+                .map(str::trim)
+                .ok_or_else(|| AccountFieldError::InvalidStorageSlot(slot.to_string()))?;
+            return U256::from_str(slot)
+                .map(AccountField::Storage)
+                .map_err(|_| AccountFieldError::InvalidStorageSlot(slot.to_string()));
+        }
+
         match value {
             "address" => Ok(AccountField::Address),
             "nonce" => Ok(AccountField::Nonce),
             "balance" => Ok(AccountField::Balance),
             "code" => Ok(AccountField::Code),
+            "is_contract" => Ok(AccountField::IsContract),
             "chain" => Ok(AccountField::Chain),
+            "ens_name" => Ok(AccountField::EnsName),
+            "first_active_block" => Ok(AccountField::FirstActiveBlock),
+            "selectors" => Ok(AccountField::Selectors),
+            "implementation" => Ok(AccountField::Implementation),
             invalid_field => Err(AccountFieldError::InvalidField(invalid_field.to_string())),
         }
     }
 }
+
+/// Function of a contract called with its arguments, e.g.
+/// `call("balanceOf(address) returns (uint256)", 0x...)`. The result is decoded with the return
+/// types of the signature, and left as raw bytes when the signature has none.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ContractCall {
+    signature: String,
+    args: Vec<String>,
+    function: Function,
+    calldata: Bytes,
+}
+
+impl ContractCall {
+    /// Parses the signature and encodes the arguments, checking that they match its parameters
+    pub fn new(signature: &str, args: Vec<String>) -> Result<Self, AccountFieldError> {
+        let invalid =
+            |reason: String| AccountFieldError::InvalidCall(signature.to_string(), reason);
+        let function = Function::parse(signature).map_err(|e| invalid(e.to_string()))?;
+
+        if function.inputs.len() != args.len() {
+            return Err(invalid(format!(
+                "expected {} arguments, got {}",
+                function.inputs.len(),
+                args.len()
+            )));
+        }
+
+        let values = function
+            .inputs
+            .iter()
+            .zip(&args)
+            .map(|(input, arg)| {
+                let ty = DynSolType::parse(&input.selector_type())
+                    .map_err(|e| invalid(e.to_string()))?;
+                ty.coerce_str(unquote(arg))
+                    .map_err(|e| invalid(format!("argument {arg}: {e}")))
+            })
+            .collect::<Result<Vec<DynSolValue>, _>>()?;
+        let calldata = function
+            .abi_encode_input(&values)
+            .map(Bytes::from)
+            .map_err(|e| invalid(e.to_string()))?;
+
+        Ok(Self {
+            signature: signature.to_string(),
+            args,
+            function,
+            calldata,
+        })
+    }
+
+    pub fn function(&self) -> &Function {
+        &self.function
+    }
+
+    /// Calldata of the call, the selector of the function followed by the encoded arguments
+    pub fn calldata(&self) -> &Bytes {
+        &self.calldata
+    }
+
+    /// Name of the function with the arguments it's called with, e.g. `balanceOf(0x...)`
+    pub fn name(&self) -> String {
+        format!("{}({})", self.function.name, self.args.join(", "))
+    }
+}
+
+impl Display for ContractCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "call(\"{}\"", self.signature)?;
+        for arg in &self.args {
+            write!(f, ", {arg}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
@@ -0,0 +1,299 @@
+use super::{
+    query_result::{AggregateQueryRes, ExpressionResult},
+    serializer::result_cell,
+};
+use crate::interpreter::frontend::parser::Rule;
+use alloy::primitives::U256;
+use pest::iterators::{Pair, Pairs};
+use serde::Serialize;
+use std::fmt::Display;
+
+#[derive(thiserror::Error, Debug)]
+pub enum AggregateError {
+    #[error("Unexpected token {0}")]
+    UnexpectedToken(String),
+
+    #[error("Missing aggregate function")]
+    MissingFunction,
+
+    #[error("{0} must be computed over a field, e.g. {0}(value)")]
+    MissingField(AggregateFunction),
+
+    #[error("Unable to compute {0}: {1} isn't a field of the results")]
+    InvalidField(Aggregate, String),
+
+    #[error("Unable to compute {0}: {1} isn't a number")]
+    NonNumericValue(Aggregate, String),
+
+    #[error("Unable to compute {0}: the sum overflows")]
+    Overflow(Aggregate),
+
+    #[error("Unable to compute {0}: {1}")]
+    Serialization(Aggregate, String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Display for AggregateFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AggregateFunction::Count => write!(f, "count"),
+            AggregateFunction::Sum => write!(f, "sum"),
+            AggregateFunction::Avg => write!(f, "avg"),
+            AggregateFunction::Min => write!(f, "min"),
+            AggregateFunction::Max => write!(f, "max"),
+        }
+    }
+}
+
+impl TryFrom<&str> for AggregateFunction {
+    type Error = AggregateError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "count" => Ok(AggregateFunction::Count),
+            "sum" => Ok(AggregateFunction::Sum),
+            "avg" => Ok(AggregateFunction::Avg),
+            "min" => Ok(AggregateFunction::Min),
+            "max" => Ok(AggregateFunction::Max),
+            invalid => Err(AggregateError::UnexpectedToken(invalid.to_string())),
+        }
+    }
+}
+
+/// Aggregate computed over the results of a query, e.g. `sum(value)`. The field is `None` for
+/// `count(*)`, which counts the results.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    pub field: Option<String>,
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}({})", self.function, field),
+            None => write!(f, "{}(*)", self.function),
+        }
+    }
+}
+
+impl Aggregate {
+    pub fn new(function: AggregateFunction, field: Option<&str>) -> Self {
+        Self {
+            function,
+            field: field.map(str::to_string),
+        }
+    }
+
+    /// Computes the aggregate over the results. Sums are computed as 256-bit integers, and
+    /// averages are rounded down to the nearest integer, which is exact enough for amounts in
+    /// wei. `sum` and `count` of no values are zero, while `avg`, `min` and `max` are `None`.
+    pub(crate) fn compute(
+        &self,
+        result: &ExpressionResult,
+    ) -> Result<AggregateQueryRes, AggregateError> {
+        let values = self.result_values(result)?;
+
+        let value = match self.function {
+            AggregateFunction::Count => Some(U256::from(values.len())),
+            AggregateFunction::Sum => Some(self.sum(&values)?),
+            AggregateFunction::Avg if values.is_empty() => None,
+            AggregateFunction::Avg => Some(self.sum(&values)? / U256::from(values.len())),
+            AggregateFunction::Min => values.iter().min().copied(),
+            AggregateFunction::Max => values.iter().max().copied(),
+        };
+
+        Ok(AggregateQueryRes {
+            aggregate: self.to_string(),
+            value,
+        })
+    }
+
+    fn sum(&self, values: &[U256]) -> Result<U256, AggregateError> {
+        values.iter().try_fold(U256::ZERO, |sum, value| {
+            sum.checked_add(*value)
+                .ok_or_else(|| AggregateError::Overflow(self.clone()))
+        })
+    }
+
+    /// Values of the field in the results. The results of every entity of a combined query are
+    /// aggregated together.
+    fn result_values(&self, result: &ExpressionResult) -> Result<Vec<U256>, AggregateError> {
+        match result {
+            ExpressionResult::Account(accounts) => self.values(accounts),
+            ExpressionResult::Block(blocks) => self.values(blocks),
+            ExpressionResult::Transaction(txs) => self.values(txs),
+            ExpressionResult::Log(logs) => self.values(logs),
+            ExpressionResult::Token(tokens) => self.values(tokens),
+            ExpressionResult::Receipt(receipts) => self.values(receipts),
+            ExpressionResult::Chain(chains) => self.values(chains),
+            ExpressionResult::Aggregate(aggregates) => self.values(aggregates),
+            ExpressionResult::Combined(results) => Ok(results
+                .iter()
+                .map(|result| self.result_values(result))
+                .collect::<Result<Vec<_>, _>>()?
+                .concat()),
+        }
+    }
+
+    /// Values of the field in the results, leaving out the results without one. Every result
+    /// counts as a value of `count(*)`.
+    fn values<T: Serialize>(&self, results: &[T]) -> Result<Vec<U256>, AggregateError> {
+        let Some(field) = &self.field else {
+            return Ok(vec![U256::ZERO; results.len()]);
+        };
+        let mut values = vec![];
+
+        for result in results {
+            let cell = result_cell(result, field)
+                .map_err(|e| AggregateError::Serialization(self.clone(), e.to_string()))?
+                .ok_or_else(|| AggregateError::InvalidField(self.clone(), field.clone()))?;
+
+            let Some(cell) = cell else {
+                continue;
+            };
+
+            // Only counting the values doesn't require them to be numbers
+            if self.function == AggregateFunction::Count {
+                values.push(U256::ZERO);
+                continue;
+            }
+
+            let value = U256::from_str_radix(&cell, 10)
+                .map_err(|_| AggregateError::NonNumericValue(self.clone(), cell))?;
+            values.push(value);
+        }
+
+        Ok(values)
+    }
+}
+
+impl TryFrom<Pairs<'_, Rule>> for Aggregate {
+    type Error = AggregateError;
+
+    fn try_from(pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
+        let mut function = None;
+        let mut field = None;
+
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::aggregate_function => {
+                    function = Some(AggregateFunction::try_from(pair.as_str())?)
+                }
+                Rule::aggregate_field => field = Some(pair.as_str()),
+                Rule::wildcard => {}
+                _ => return Err(AggregateError::UnexpectedToken(pair.as_str().to_string())),
+            }
+        }
+
+        match function.ok_or(AggregateError::MissingFunction)? {
+            AggregateFunction::Count => Ok(Aggregate::new(AggregateFunction::Count, field)),
+            function if field.is_none() => Err(AggregateError::MissingField(function)),
+            function => Ok(Aggregate::new(function, field)),
+        }
+    }
+}
+
+/// Maps an item of a field list to the field it selects. Aggregates select the field they're
+/// computed over, if any, so it's fetched along with the results.
+pub(crate) fn selected_field(pair: Pair<'_, Rule>) -> Option<Pair<'_, Rule>> {
+    match pair.as_rule() {
+        Rule::aggregate => pair
+            .into_inner()
+            .find(|pair| pair.as_rule() == Rule::aggregate_field),
+        _ => Some(pair),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::query_result::TransactionQueryRes;
+
+    fn transactions(values: &[Option<U256>]) -> ExpressionResult {
+        ExpressionResult::Transaction(
+            values
+                .iter()
+                .map(|value| TransactionQueryRes {
+                    value: *value,
+                    ..Default::default()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_sum_does_not_overflow_u128() {
+        let result = transactions(&[Some(U256::from(u128::MAX)), Some(U256::from(u128::MAX))]);
+
+        let sum = Aggregate::new(AggregateFunction::Sum, Some("value"))
+            .compute(&result)
+            .unwrap();
+
+        assert_eq!(sum.value, Some(U256::from(u128::MAX) * U256::from(2)));
+    }
+
+    #[test]
+    fn test_aggregates_leave_out_missing_values() {
+        let result = transactions(&[Some(U256::from(3)), None, Some(U256::from(8))]);
+        let compute = |function, field| {
+            Aggregate::new(function, field)
+                .compute(&result)
+                .unwrap()
+                .value
+        };
+
+        assert_eq!(compute(AggregateFunction::Count, None), Some(U256::from(3)));
+        assert_eq!(
+            compute(AggregateFunction::Count, Some("value")),
+            Some(U256::from(2))
+        );
+        assert_eq!(
+            compute(AggregateFunction::Avg, Some("value")),
+            Some(U256::from(5))
+        );
+        assert_eq!(
+            compute(AggregateFunction::Min, Some("value")),
+            Some(U256::from(3))
+        );
+        assert_eq!(
+            compute(AggregateFunction::Max, Some("value")),
+            Some(U256::from(8))
+        );
+    }
+
+    #[test]
+    fn test_aggregates_of_no_results() {
+        let result = transactions(&[]);
+        let compute = |function| {
+            Aggregate::new(function, Some("value"))
+                .compute(&result)
+                .unwrap()
+                .value
+        };
+
+        assert_eq!(compute(AggregateFunction::Sum), Some(U256::ZERO));
+        assert_eq!(compute(AggregateFunction::Avg), None);
+        assert_eq!(compute(AggregateFunction::Max), None);
+    }
+
+    #[test]
+    fn test_aggregate_of_non_numeric_field() {
+        let result = ExpressionResult::Transaction(vec![TransactionQueryRes {
+            status: Some(true),
+            ..Default::default()
+        }]);
+
+        let sum = Aggregate::new(AggregateFunction::Sum, Some("status")).compute(&result);
+
+        assert!(matches!(sum, Err(AggregateError::NonNumericValue(_, _))));
+    }
+}
@@ -1,9 +1,19 @@
-use super::entity_id::{parse_block_number_or_tag, EntityIdError};
+use super::{
+    aggregate::selected_field,
+    entity_id::{parse_block_number_or_tag, parse_block_range, EntityIdError},
+    filters::{
+        expand_exponent, ComparisonFilter, EqualityFilter, EqualityFilterError, Filter,
+        FilterError, FilterType,
+    },
+};
 use crate::interpreter::frontend::parser::Rule;
 use alloy::{
     eips::BlockNumberOrTag,
+    hex::FromHexError,
+    primitives::{Address, B256},
     providers::{Provider, RootProvider},
-    transports::http::{Client, Http},
+    rpc::types::Header,
+    transports::BoxTransport,
 };
 use anyhow::Result;
 use eql_macros::EnumVariants;
@@ -11,6 +21,7 @@ use pest::iterators::{Pair, Pairs};
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::{self, Display, Formatter},
+    str::FromStr,
     sync::Arc,
 };
 
@@ -26,12 +37,17 @@ pub enum BlockError {
 
     #[error(transparent)]
     BlockFieldError(#[from] BlockFieldError),
+
+    #[error(transparent)]
+    FromHexError(#[from] FromHexError),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum BlockId {
     Number(BlockNumberOrTag),
     Range(BlockRange),
+    /// Pins the exact block, even if the chain reorganizes and its number is reused
+    Hash(B256),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -67,6 +83,62 @@ impl Block {
     pub fn filters(&self) -> Option<&Vec<BlockFilter>> {
         self.filter.as_ref()
     }
+
+    /// Blocks to fetch, either listed after `FROM block` or given by a `block` filter.
+    pub fn block_ids(&self) -> Vec<BlockId> {
+        let ids = self.ids.iter().flatten().cloned();
+        let ranges = self
+            .filter
+            .iter()
+            .flatten()
+            .filter_map(|filter| match filter {
+                BlockFilter::Range(range) => Some(BlockId::Range(range.clone())),
+                _ => None,
+            });
+
+        ids.chain(ranges).collect()
+    }
+
+    /// Whether the query only needs the number of transactions of its blocks, which is read without
+    /// fetching the blocks. Blocks looked up by hash, and blocks filtered on their header, are
+    /// still fetched.
+    pub fn counts_transactions_only(&self) -> bool {
+        let by_number = self
+            .ids
+            .iter()
+            .flatten()
+            .all(|id| !matches!(id, BlockId::Hash(_)));
+        let unfiltered = self
+            .filter
+            .iter()
+            .flatten()
+            .all(|filter| matches!(filter, BlockFilter::Range(_)));
+        let counts_only = self.fields.iter().all(|field| {
+            matches!(
+                field,
+                BlockField::TransactionCount | BlockField::Number | BlockField::Chain
+            )
+        });
+
+        by_number
+            && unfiltered
+            && counts_only
+            && self.fields.contains(&BlockField::TransactionCount)
+    }
+
+    /// Whether the block header passes all the filters
+    pub fn filter(&self, header: &Header) -> bool {
+        self.filter.iter().flatten().all(|filter| match filter {
+            BlockFilter::Range(_) => true,
+            BlockFilter::GasUsed(g) => g.compare(&header.gas_used),
+            BlockFilter::Timestamp(t) => t.compare(&header.timestamp),
+            BlockFilter::Miner(m) => m.compare(&header.miner),
+            // Blocks produced before London have no base fee, so they never match
+            BlockFilter::BaseFeePerGas(b) => {
+                header.base_fee_per_gas.is_some_and(|fee| b.compare(&fee))
+            }
+        })
+    }
 }
 
 impl TryFrom<Pairs<'_, Rule>> for Block {
@@ -90,6 +162,7 @@ impl TryFrom<Pairs<'_, Rule>> for Block {
                     }
 
                     fields = inner_pairs
+                        .filter_map(selected_field)
                         .map(|pair| BlockField::try_from(pair.as_str()))
                         .collect::<Result<Vec<BlockField>, BlockFieldError>>()?;
                 }
@@ -98,23 +171,19 @@ impl TryFrom<Pairs<'_, Rule>> for Block {
                     for inner_pair in pair.into_inner() {
                         match inner_pair.as_rule() {
                             Rule::block_range => {
-                                let block_id = inner_pair.as_str();
-                                let (start, end) = match block_id.split_once(":") {
-                                    Some((start, end)) => {
-                                        let start = parse_block_number_or_tag(start)?;
-                                        let end = parse_block_number_or_tag(end)?;
-                                        (start, Some(end))
-                                    }
-                                    None => parse_block_number_or_tag(block_id)
-                                        .map(|start| (start, None))?,
-                                };
-                                ids.push(BlockId::Range(BlockRange::new(start, end)));
+                                ids.push(BlockId::Range(parse_block_range(inner_pair.as_str())?));
+                            }
+                            Rule::relative_block => {
+                                ids.push(BlockId::Range(parse_block_range(inner_pair.as_str())?));
                             }
                             Rule::block_tag_or_number => {
                                 ids.push(BlockId::Number(parse_block_number_or_tag(
                                     inner_pair.as_str(),
                                 )?));
                             }
+                            Rule::hash => {
+                                ids.push(BlockId::Hash(B256::from_str(inner_pair.as_str())?));
+                            }
                             _ => {
                                 return Err(BlockError::UnexpectedToken(
                                     inner_pair.as_str().to_string(),
@@ -123,12 +192,13 @@ impl TryFrom<Pairs<'_, Rule>> for Block {
                         }
                     }
                 }
+                // Each filter of the WHERE clause is a separate pair
                 Rule::block_filter => {
-                    filter = Some(
-                        pair.into_inner()
-                            .map(|pair| BlockFilter::try_from(pair))
-                            .collect::<Result<Vec<BlockFilter>, BlockFilterError>>()?,
-                    );
+                    for inner_pair in pair.into_inner() {
+                        filter
+                            .get_or_insert_with(Vec::new)
+                            .push(BlockFilter::try_from(inner_pair)?);
+                    }
                 }
                 _ => {
                     return Err(BlockError::UnexpectedToken(pair.as_str().to_string()));
@@ -149,13 +219,69 @@ pub enum BlockFilterError {
     #[error("Invalid block filter property: {0}")]
     InvalidBlockFilterProperty(String),
 
+    #[error("Invalid value {0} for block filter")]
+    InvalidValue(String),
+    #[error("Missing operator in block filter")]
+    MissingOperator,
+    #[error("Missing bound in BETWEEN filter")]
+    MissingBound,
+
     #[error(transparent)]
     EntityIdError(#[from] EntityIdError),
+    #[error(transparent)]
+    FilterError(#[from] FilterError),
+    #[error(transparent)]
+    EqualityFilterError(#[from] EqualityFilterError),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum BlockFilter {
     Range(BlockRange),
+    GasUsed(FilterType<u128>),
+    Timestamp(FilterType<u64>),
+    Miner(EqualityFilter<Address>),
+    BaseFeePerGas(FilterType<u128>),
+}
+
+impl BlockFilter {
+    /// Splits a filter into its operator and its value.
+    fn parse_operand<T: FromStr>(
+        pair: Pair<'_, Rule>,
+    ) -> Result<(Pair<'_, Rule>, T), BlockFilterError> {
+        let mut inner_pair = pair.into_inner();
+        let operator = inner_pair.next().ok_or(BlockFilterError::MissingOperator)?;
+        let value = Self::parse_value(inner_pair.as_str())?;
+
+        Ok((operator, value))
+    }
+
+    /// Parses a numeric filter, either an operator and a value or a `BETWEEN` range.
+    fn parse_filter<T: FromStr>(pair: Pair<'_, Rule>) -> Result<FilterType<T>, BlockFilterError>
+    where
+        FilterType<T>: for<'a> TryFrom<(Pair<'a, Rule>, T), Error = FilterError>,
+    {
+        let mut inner_pair = pair.clone().into_inner();
+        match inner_pair.next() {
+            Some(op) if op.as_rule() == Rule::between_operator => {
+                let mut bound = || {
+                    inner_pair
+                        .next()
+                        .ok_or(BlockFilterError::MissingBound)
+                        .and_then(|value| Self::parse_value(value.as_str()))
+                };
+                let (min, max) = (bound()?, bound()?);
+                Ok(FilterType::Comparison(ComparisonFilter::Between(min, max)))
+            }
+            _ => Ok(FilterType::try_from(Self::parse_operand(pair)?)?),
+        }
+    }
+
+    fn parse_value<T: FromStr>(value: &str) -> Result<T, BlockFilterError> {
+        let value = value.trim();
+        expand_exponent(value)
+            .parse::<T>()
+            .map_err(|_| BlockFilterError::InvalidValue(value.to_string()))
+    }
 }
 
 impl TryFrom<Pair<'_, Rule>> for BlockFilter {
@@ -164,17 +290,20 @@ impl TryFrom<Pair<'_, Rule>> for BlockFilter {
     fn try_from(value: Pair<'_, Rule>) -> Result<Self, Self::Error> {
         match value.as_rule() {
             Rule::blockrange_filter => {
-                let range = value.as_str().trim_start_matches("block ").trim();
-                let (start, end) = match range.split_once(":") {
-                    //if ":" is present, we have an start and an end.
-                    Some((start, end)) => (
-                        parse_block_number_or_tag(start)?,
-                        Some(parse_block_number_or_tag(end)?),
-                    ),
-                    //else we only have start.
-                    None => (parse_block_number_or_tag(range)?, None),
-                };
-                Ok(BlockFilter::Range(BlockRange { start, end }))
+                let range = value
+                    .as_str()
+                    .trim_start_matches("block")
+                    .trim_start_matches(|c: char| c.is_whitespace() || c == '=')
+                    .trim();
+                Ok(BlockFilter::Range(parse_block_range(range)?))
+            }
+            Rule::gas_used_filter_type => Ok(BlockFilter::GasUsed(Self::parse_filter(value)?)),
+            Rule::timestamp_filter_type => Ok(BlockFilter::Timestamp(Self::parse_filter(value)?)),
+            Rule::miner_filter_type => Ok(BlockFilter::Miner(EqualityFilter::try_from(
+                Self::parse_operand(value)?,
+            )?)),
+            Rule::base_fee_per_gas_filter_type => {
+                Ok(BlockFilter::BaseFeePerGas(Self::parse_filter(value)?))
             }
             _ => Err(BlockFilterError::InvalidBlockFilterProperty(
                 value.as_str().to_string(),
@@ -189,7 +318,7 @@ pub enum BlockFieldError {
     InvalidBlockField(String),
 }
 
-// TODO: should include nonce, transactions and withdrawals
+// TODO: should include nonce and transactions
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, EnumVariants)]
 pub enum BlockField {
     Number,
@@ -205,10 +334,16 @@ pub enum BlockField {
     MixHash,
     TotalDifficulty,
     BaseFeePerGas,
+    /// Base fee of the next block, projected from this block's gas usage
+    NextBaseFee,
     WithdrawalsRoot,
+    Withdrawals,
     BlobGasUsed,
     ExcessBlobGas,
     ParentBeaconBlockRoot,
+    Uncles,
+    /// Number of transactions in the block
+    TransactionCount,
     Chain,
 }
 
@@ -228,10 +363,14 @@ impl Display for BlockField {
             BlockField::MixHash => write!(f, "mix_hash"),
             BlockField::TotalDifficulty => write!(f, "total_difficulty"),
             BlockField::BaseFeePerGas => write!(f, "base_fee_per_gas"),
+            BlockField::NextBaseFee => write!(f, "next_base_fee"),
             BlockField::WithdrawalsRoot => write!(f, "withdrawals_root"),
+            BlockField::Withdrawals => write!(f, "withdrawals"),
             BlockField::BlobGasUsed => write!(f, "blob_gas_used"),
             BlockField::ExcessBlobGas => write!(f, "excess_blob_gas"),
             BlockField::ParentBeaconBlockRoot => write!(f, "parent_beacon_block_root"),
+            BlockField::Uncles => write!(f, "uncles"),
+            BlockField::TransactionCount => write!(f, "transaction_count"),
             BlockField::Chain => write!(f, "chain"),
         }
     }
@@ -254,11 +393,15 @@ impl TryFrom<&str> for BlockField {
             "extra_data" => Ok(BlockField::ExtraData),
             "mix_hash" => Ok(BlockField::MixHash),
             "total_difficulty" => Ok(BlockField::TotalDifficulty),
-            "base_fee_per_gas" => Ok(BlockField::BaseFeePerGas),
+            "base_fee_per_gas" | "base_fee" => Ok(BlockField::BaseFeePerGas),
+            "next_base_fee" => Ok(BlockField::NextBaseFee),
             "withdrawals_root" => Ok(BlockField::WithdrawalsRoot),
+            "withdrawals" => Ok(BlockField::Withdrawals),
             "blob_gas_used" => Ok(BlockField::BlobGasUsed),
             "excess_blob_gas" => Ok(BlockField::ExcessBlobGas),
             "parent_beacon_block_root" => Ok(BlockField::ParentBeaconBlockRoot),
+            "uncles" => Ok(BlockField::Uncles),
+            "transaction_count" => Ok(BlockField::TransactionCount),
             "chain" => Ok(BlockField::Chain),
             invalid_field => Err(BlockFieldError::InvalidBlockField(
                 invalid_field.to_string(),
@@ -273,17 +416,84 @@ pub enum BlockRangeError {
     UnableToFetchBlockNumber(BlockNumberOrTag),
     #[error("Start block must be less than end block")]
     StartBlockMustBeLessThanEndBlock,
+    #[error("The {0} block isn't available, the chain may not support finality")]
+    FinalityNotSupported(BlockNumberOrTag),
+    #[error("Block range {0}:{1} spans more than {2} blocks, narrow it down or raise the limit")]
+    RangeTooLarge(u64, u64, u64),
+    #[error("{0}-{1} is before the genesis block, {0} is block {2}")]
+    OffsetBeforeGenesis(BlockNumberOrTag, u64, u64),
+}
+
+/// Maximum number of blocks a range can span, since every block of the range is fetched
+pub const DEFAULT_MAX_BLOCK_RANGE: u64 = 10_000;
+
+/// Number of blocks of a range fetched at once by default
+pub const DEFAULT_BLOCK_BATCH_SIZE: usize = 50;
+
+/// How the blocks of a range are fetched. Fetching a large range all at once overwhelms most
+/// providers, so the blocks are fetched in bounded groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockBatchConfig {
+    /// Maximum number of blocks requested at the same time for a block id of the query
+    pub size: usize,
+    /// Sends each group of `size` blocks as a single JSON-RPC batch request, one group at a time,
+    /// instead of one request per block. Not every RPC supports batch requests, and some cap
+    /// their size.
+    pub json_rpc: bool,
+}
+
+impl Default for BlockBatchConfig {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_BLOCK_BATCH_SIZE,
+            json_rpc: false,
+        }
+    }
+}
+
+/// Error for a block tag the RPC has no block for. The `safe` and `finalized` tags only resolve
+/// on chains with a notion of finality, like post-merge Ethereum or most rollups.
+pub(crate) fn missing_block_error(tag: BlockNumberOrTag) -> BlockRangeError {
+    match tag {
+        BlockNumberOrTag::Safe | BlockNumberOrTag::Finalized => {
+            BlockRangeError::FinalityNotSupported(tag)
+        }
+        tag => BlockRangeError::UnableToFetchBlockNumber(tag),
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct BlockRange {
     start: BlockNumberOrTag,
     end: Option<BlockNumberOrTag>,
+    /// Number of blocks before the start and the end, for relative blocks like `latest-100`
+    offsets: (u64, u64),
 }
 
 impl BlockRange {
     pub fn new(start: BlockNumberOrTag, end: Option<BlockNumberOrTag>) -> Self {
-        Self { start, end }
+        Self {
+            start,
+            end,
+            offsets: (0, 0),
+        }
+    }
+
+    /// Moves the start and the end of the range back by the given number of blocks. The offsets
+    /// are applied when the blocks are resolved, so `latest-100:latest` always covers the last
+    /// 100 blocks.
+    pub fn with_offsets(mut self, start_offset: u64, end_offset: u64) -> Self {
+        self.offsets = (start_offset, end_offset);
+        self
+    }
+
+    pub fn offsets(&self) -> (u64, u64) {
+        self.offsets
+    }
+
+    /// Whether the start or the end of the range is relative to a tag
+    pub fn is_relative(&self) -> bool {
+        self.offsets != (0, 0)
     }
 
     pub fn range(&self) -> (BlockNumberOrTag, Option<BlockNumberOrTag>) {
@@ -298,41 +508,61 @@ impl BlockRange {
         self.end
     }
 
+    /// Resolves the tags of the range and lists its block numbers. Ranges spanning more than
+    /// `max_block_range` blocks are rejected.
     pub async fn resolve_block_numbers(
         &self,
-        provider: &Arc<RootProvider<Http<Client>>>,
+        provider: &Arc<RootProvider<BoxTransport>>,
+        max_block_range: u64,
     ) -> Result<Vec<u64>> {
-        let (start_block, end_block) = self.range();
-        let start_block_number = self
-            .get_block_number_from_tag(provider.clone(), start_block)
-            .await?;
+        let (start, end) = self.resolve_bounds(provider).await?;
 
-        let end_block_number = match end_block {
-            Some(end) => Some(
-                self.get_block_number_from_tag(provider.clone(), end)
-                    .await?,
-            ),
-            None => None,
+        if end - start >= max_block_range {
+            return Err(BlockRangeError::RangeTooLarge(start, end, max_block_range).into());
+        }
+
+        Ok((start..=end).collect())
+    }
+
+    /// Resolves the first and last block numbers of the range, which are the same for a range
+    /// without an end.
+    pub async fn resolve_bounds(
+        &self,
+        provider: &Arc<RootProvider<BoxTransport>>,
+    ) -> Result<(u64, u64)> {
+        let start = self
+            .resolve_block(provider, self.start, self.offsets.0)
+            .await?;
+        let end = match self.end {
+            Some(end) => self.resolve_block(provider, end, self.offsets.1).await?,
+            None => start,
         };
 
-        if let Some(end) = end_block_number {
-            if start_block_number > end {
-                return Err(BlockRangeError::StartBlockMustBeLessThanEndBlock.into());
-            }
+        if start > end {
+            return Err(BlockRangeError::StartBlockMustBeLessThanEndBlock.into());
         }
 
-        match end_block_number {
-            Some(end) => {
-                let range = start_block_number..=end;
-                return Ok(range.collect());
-            }
-            None => Ok(vec![start_block_number]),
-        }
+        Ok((start, end))
+    }
+
+    async fn resolve_block(
+        &self,
+        provider: &Arc<RootProvider<BoxTransport>>,
+        number_or_tag: BlockNumberOrTag,
+        offset: u64,
+    ) -> Result<u64> {
+        let number = self
+            .get_block_number_from_tag(provider.clone(), number_or_tag)
+            .await?;
+
+        number.checked_sub(offset).ok_or_else(|| {
+            BlockRangeError::OffsetBeforeGenesis(number_or_tag, offset, number).into()
+        })
     }
 
     async fn get_block_number_from_tag(
         &self,
-        provider: Arc<RootProvider<Http<Client>>>,
+        provider: Arc<RootProvider<BoxTransport>>,
         number_or_tag: BlockNumberOrTag,
     ) -> Result<u64> {
         match number_or_tag {
@@ -342,7 +572,7 @@ impl BlockRange {
                     Some(number) => Ok(number),
                     None => Err(BlockRangeError::UnableToFetchBlockNumber(number_or_tag).into()),
                 },
-                None => Err(BlockRangeError::UnableToFetchBlockNumber(number_or_tag).into()),
+                None => Err(missing_block_error(number_or_tag).into()),
             },
         }
     }
@@ -350,17 +580,21 @@ impl BlockRange {
 
 impl Display for BlockRange {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let start = match &self.start {
-            BlockNumberOrTag::Number(number) => number.to_string(),
-            _ => self.start.to_string(),
+        let block = |number_or_tag: &BlockNumberOrTag, offset: u64| {
+            let block = match number_or_tag {
+                BlockNumberOrTag::Number(number) => number.to_string(),
+                _ => number_or_tag.to_string(),
+            };
+
+            match offset {
+                0 => block,
+                offset => format!("{}-{}", block, offset),
+            }
         };
+        let start = block(&self.start, self.offsets.0);
 
         if let Some(end) = &self.end {
-            let end = match end {
-                BlockNumberOrTag::Number(number) => number.to_string(),
-                _ => end.to_string(),
-            };
-            write!(f, "{}:{}", start, end)
+            write!(f, "{}:{}", start, block(end, self.offsets.1))
         } else {
             write!(f, "{}", start)
         }
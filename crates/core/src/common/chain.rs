@@ -1,15 +1,26 @@
 use crate::interpreter::frontend::parser::Rule;
 
 use super::config::Config;
+use crate::interpreter::backend::{
+    block_cache::{self, BlockCacheLayer},
+    chain_id::{self, ChainIdLayer},
+    disk_cache::{self, DiskCacheLayer},
+    failover::FailoverTransport,
+    metrics::{self, MetricsLayer},
+    rate_limit::{self, RateLimitLayer},
+    timeout,
+};
 use alloy::{
-    providers::{Provider, ProviderBuilder},
-    transports::http::reqwest::Url,
+    providers::{Provider, ProviderBuilder, RootProvider},
+    rpc::client::{BuiltInConnectionString, RpcClient},
+    transports::{http::reqwest::Url, BoxTransport, BoxTransportConnect, Transport},
 };
 use anyhow::Result;
 use core::fmt;
 use eql_macros::EnumVariants;
 use pest::iterators::Pairs;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tower::Layer;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ChainOrRpc {
@@ -25,23 +36,108 @@ impl ChainOrRpc {
         }
     }
 
+    /// RPCs the requests can be sent to, starting with the one given by `rpc_url`. The other RPCs
+    /// configured for a chain are used as fallbacks.
+    pub fn rpc_urls(&self) -> Result<Vec<Url>> {
+        match self {
+            ChainOrRpc::Chain(chain) => chain.rpc_urls(),
+            ChainOrRpc::Rpc(url) => Ok(vec![url.clone()]),
+        }
+    }
+
+    /// Connects to the RPC, picking the transport from the URL scheme: HTTP for `http://` and
+    /// `https://` URLs, WebSocket for `ws://` and `wss://` URLs, and IPC for `ipc://` URLs
+    /// pointing to the socket of a local node, when built with the `ipc` feature.
+    /// The requests are reported to the metrics of the running query, if it has any, and the
+    /// blocks are read from its block cache when it has one. The finalized blocks, transactions
+    /// and receipts are read from and written to its disk cache when it has one.
+    /// When the chain has fallback RPCs, requests that fail to reach an RPC are sent to the next.
+    /// HTTP requests fail once they take longer than the request timeout of the running query.
+    /// When the running query validates chain ids, the requests to an RPC of a chain fail if the
+    /// RPC serves another chain.
+    pub async fn provider(&self) -> Result<RootProvider<BoxTransport>> {
+        let urls = self.rpc_urls()?;
+        let url = &urls[0];
+        let metrics = metrics::current();
+        let block_cache = block_cache::current();
+        let disk_cache = disk_cache::current();
+        let request_timeout = timeout::current();
+        let rate_limiter = rate_limit::current();
+        let chain_ids = chain_id::current();
+
+        // Each RPC of a chain is validated before its first request, the fallback ones included
+        let validate_chain_id = |index: usize, transport: BoxTransport| match (self, &chain_ids) {
+            (ChainOrRpc::Chain(chain), Some(chain_ids)) => {
+                ChainIdLayer::new(chain.clone(), urls[index].to_string(), chain_ids.clone())
+                    .layer(transport)
+                    .boxed()
+            }
+            _ => transport,
+        };
+
+        let connect: BuiltInConnectionString = url.as_str().parse()?;
+        let mut transport = match urls.len() {
+            1 => validate_chain_id(0, timeout::connect(&connect, request_timeout).await?),
+            _ => {
+                let connects = urls
+                    .iter()
+                    .map(|url| url.as_str().parse())
+                    .collect::<Result<Vec<BuiltInConnectionString>, _>>()?;
+                FailoverTransport::connect(&connects, request_timeout, validate_chain_id)
+                    .await?
+                    .boxed()
+            }
+        };
+        if let Some(metrics) = metrics {
+            transport = MetricsLayer::new(metrics).layer(transport).boxed();
+        }
+        if let Some(rate_limiter) = rate_limiter {
+            transport = RateLimitLayer::new(rate_limiter).layer(transport).boxed();
+        }
+        // The caches wrap the metrics and the rate limit, so the data read from them isn't
+        // reported as requests nor held back
+        if let Some(disk_cache) = disk_cache {
+            transport = DiskCacheLayer::new(url.to_string(), disk_cache)
+                .layer(transport)
+                .boxed();
+        }
+        if let Some(block_cache) = block_cache {
+            transport = BlockCacheLayer::new(url.to_string(), block_cache)
+                .layer(transport)
+                .boxed();
+        }
+
+        let client = RpcClient::new(transport, connect.is_local());
+        Ok(ProviderBuilder::new().on_client(client))
+    }
+
     pub async fn to_chain(&self) -> Result<Chain> {
         match self {
             ChainOrRpc::Chain(chain) => Ok(chain.clone()),
-            ChainOrRpc::Rpc(rpc) => {
-                let provider = ProviderBuilder::new().on_http(rpc.clone());
-                let chain_id = provider.get_chain_id().await?;
-                let chain = chain_id.try_into()?;
+            ChainOrRpc::Rpc(url) => {
+                let provider = self.provider().await?;
+                // Recorded by queries validating chain ids, so it's only requested once
+                let chain_id = match chain_id::current() {
+                    Some(chain_ids) => chain_ids.get(url.as_str(), &provider).await?,
+                    None => provider.get_chain_id().await?,
+                };
+                // RPCs of networks that aren't known are tagged with their chain id
+                let chain = Chain::try_from(chain_id).unwrap_or_else(|_| Chain::Custom {
+                    id: chain_id,
+                    name: chain_id.to_string(),
+                });
                 Ok(chain)
             }
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, EnumVariants, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, EnumVariants)]
 pub enum Chain {
     Ethereum,
     Sepolia,
+    Holesky,
+    Hoodi,
     Arbitrum,
     Base,
     Blast,
@@ -62,12 +158,20 @@ pub enum Chain {
     Fantom,
     Kava,
     Gnosis,
+    /// Network that isn't known, like a new L2 or a devnet. It has no default RPC, so its RPC
+    /// is read from the config, under its name.
+    Custom {
+        id: u64,
+        name: String,
+    },
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum ChainError {
     #[error("Invalid chain {0}")]
     InvalidChain(String),
+    #[error("Chain {0} has no default RPC, its RPC must be set in the config")]
+    MissingRpc(String),
 }
 
 impl TryFrom<Pairs<'_, Rule>> for Chain {
@@ -108,15 +212,33 @@ impl Chain {
     pub fn rpc_url(&self) -> Result<Url> {
         match Config::new().get_chain_default_rpc(self) {
             Ok(Some(url)) => Ok(url),
-            Ok(None) => Ok(self.rpc_fallback().parse()?),
+            Ok(None) => match self.rpc_fallback() {
+                Some(url) => Ok(url.parse()?),
+                None => Err(ChainError::MissingRpc(self.to_string()).into()),
+            },
             Err(e) => Err(e),
         }
     }
 
-    fn rpc_fallback(&self) -> &str {
-        match self {
+    /// Default RPC of the chain, followed by the other RPCs configured for it
+    pub fn rpc_urls(&self) -> Result<Vec<Url>> {
+        let mut urls = vec![self.rpc_url()?];
+
+        for url in Config::new().get_chain_rpcs(self)?.unwrap_or_default() {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+
+        Ok(urls)
+    }
+
+    fn rpc_fallback(&self) -> Option<&str> {
+        let url = match self {
             Chain::Ethereum => "https://ethereum.drpc.org",
             Chain::Sepolia => "https://rpc.ankr.com/eth_sepolia",
+            Chain::Holesky => "https://holesky.drpc.org",
+            Chain::Hoodi => "https://hoodi.drpc.org",
             Chain::Arbitrum => "https://rpc.ankr.com/arbitrum",
             Chain::Base => "https://rpc.ankr.com/base",
             Chain::Blast => "https://rpc.ankr.com/blast",
@@ -137,7 +259,9 @@ impl Chain {
             Chain::Fantom => "https://fantom.drpc.org",
             Chain::Kava => "https://evm.kava.io",
             Chain::Gnosis => "https://gnosis.drpc.org",
-        }
+            Chain::Custom { .. } => return None,
+        };
+        Some(url)
     }
 }
 
@@ -154,6 +278,8 @@ impl TryFrom<&str> for Chain {
         match chain {
             "eth" => Ok(Chain::Ethereum),
             "sepolia" => Ok(Chain::Sepolia),
+            "holesky" => Ok(Chain::Holesky),
+            "hoodi" => Ok(Chain::Hoodi),
             "arb" => Ok(Chain::Arbitrum),
             "base" => Ok(Chain::Base),
             "blast" => Ok(Chain::Blast),
@@ -184,6 +310,8 @@ impl From<&Chain> for u64 {
         match value {
             Chain::Ethereum => 1,
             Chain::Sepolia => 11155111,
+            Chain::Holesky => 17000,
+            Chain::Hoodi => 560048,
             Chain::Arbitrum => 42161,
             Chain::Base => 8453,
             Chain::Blast => 238,
@@ -204,6 +332,7 @@ impl From<&Chain> for u64 {
             Chain::Fantom => 250,
             Chain::Kava => 2222,
             Chain::Gnosis => 100,
+            Chain::Custom { id, .. } => *id,
         }
     }
 }
@@ -215,6 +344,8 @@ impl TryFrom<u64> for Chain {
         match chain_id {
             1 => Ok(Chain::Ethereum),
             11155111 => Ok(Chain::Sepolia),
+            17000 => Ok(Chain::Holesky),
+            560048 => Ok(Chain::Hoodi),
             42161 => Ok(Chain::Arbitrum),
             8453 => Ok(Chain::Base),
             238 => Ok(Chain::Blast),
@@ -245,6 +376,8 @@ impl fmt::Display for Chain {
         let chain_str = match self {
             Chain::Ethereum => "eth",
             Chain::Sepolia => "sepolia",
+            Chain::Holesky => "holesky",
+            Chain::Hoodi => "hoodi",
             Chain::Arbitrum => "arb",
             Chain::Base => "base",
             Chain::Blast => "blast",
@@ -265,7 +398,166 @@ impl fmt::Display for Chain {
             Chain::Fantom => "fantom",
             Chain::Kava => "kava",
             Chain::Gnosis => "gnosis",
+            Chain::Custom { name, .. } => name,
         };
         write!(f, "{}", chain_str)
     }
 }
+
+/// Chains are written by their name, like they're written in queries.
+impl Serialize for Chain {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Reads the name of a chain, or the chain id custom chains are named after.
+impl<'de> Deserialize<'de> for Chain {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if let Ok(chain) = Chain::try_from(name.as_str()) {
+            return Ok(chain);
+        }
+        let id = name
+            .parse::<u64>()
+            .map_err(|_| serde::de::Error::custom(ChainError::InvalidChain(name.clone())))?;
+
+        Ok(Chain::try_from(id).unwrap_or(Chain::Custom { id, name }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{
+            block::{Block, BlockField, BlockId},
+            config::tests::{write_config, CONFIG_PATH_LOCK},
+            entity::Entity,
+            query_result::{BlockQueryRes, ExpressionResult},
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::{eips::BlockNumberOrTag, rpc::types::Block as RpcBlock};
+    use std::env;
+
+    fn devnet() -> Chain {
+        Chain::Custom {
+            id: 1337,
+            name: "devnet".to_string(),
+        }
+    }
+
+    fn block_expression(chain: ChainOrRpc) -> Expression {
+        Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![BlockId::Number(BlockNumberOrTag::Number(1))]),
+                None,
+                vec![BlockField::Number, BlockField::Chain],
+            )),
+            chains: vec![chain],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })
+    }
+
+    fn devnet_rpc() -> impl std::future::Future<Output = MockRpc> {
+        MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x539"),
+            "eth_getBlockByNumber" => result::<RpcBlock>(&RpcBlock {
+                header: alloy::rpc::types::Header {
+                    number: Some(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            method => Err(format!("Unexpected method {method}")),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_query_custom_chain_through_configured_rpc() {
+        let rpc = devnet_rpc().await;
+        let _lock = CONFIG_PATH_LOCK.lock().await;
+        let path = write_config(
+            "custom-chain",
+            &format!(
+                r#"{{ "chains": {{ "devnet": {{ "default": "{}", "rpcs": [] }} }} }}"#,
+                rpc.url()
+            ),
+        );
+        env::set_var("EQL_CONFIG_PATH", &path);
+
+        let results = ExecutionEngine::new()
+            .with_chain_id_validation()
+            .run(vec![block_expression(ChainOrRpc::Chain(devnet()))])
+            .await;
+        env::remove_var("EQL_CONFIG_PATH");
+
+        assert_eq!(
+            results.unwrap()[0].result,
+            ExpressionResult::Block(vec![BlockQueryRes {
+                number: Some(1),
+                chain: Some(devnet()),
+                ..Default::default()
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_when_custom_chain_has_no_rpc() {
+        let _lock = CONFIG_PATH_LOCK.lock().await;
+        env::remove_var("EQL_CONFIG_PATH");
+
+        let error = ChainOrRpc::Chain(devnet()).rpc_url().unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ChainError>(),
+            Some(ChainError::MissingRpc(name)) if name == "devnet"
+        ));
+    }
+
+    #[test]
+    fn test_ethereum_testnets() {
+        let testnets = [
+            (
+                "sepolia",
+                Chain::Sepolia,
+                11155111,
+                "https://rpc.ankr.com/eth_sepolia",
+            ),
+            ("holesky", Chain::Holesky, 17000, "https://holesky.drpc.org"),
+            ("hoodi", Chain::Hoodi, 560048, "https://hoodi.drpc.org"),
+        ];
+
+        for (name, chain, chain_id, rpc) in testnets {
+            assert_eq!(Chain::try_from(name).unwrap(), chain);
+            assert_eq!(chain.to_string(), name);
+            assert_eq!(u64::from(&chain), chain_id);
+            assert_eq!(Chain::try_from(chain_id).unwrap(), chain);
+            assert_eq!(chain.rpc_fallback(), Some(rpc));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rpc_of_unknown_chain_is_tagged_with_its_chain_id() {
+        let rpc = devnet_rpc().await;
+
+        let chain = ChainOrRpc::Rpc(rpc.url()).to_chain().await.unwrap();
+
+        assert_eq!(
+            chain,
+            Chain::Custom {
+                id: 1337,
+                name: "1337".to_string()
+            }
+        );
+        assert_eq!(u64::from(&chain), 1337);
+    }
+}
@@ -0,0 +1,117 @@
+use super::aggregate::selected_field;
+use crate::interpreter::frontend::parser::Rule;
+use eql_macros::EnumVariants;
+use pest::iterators::{Pair, Pairs};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChainStatusError {
+    #[error("Unexpected token {0}")]
+    UnexpectedToken(String),
+
+    #[error(transparent)]
+    ChainFieldError(#[from] ChainFieldError),
+}
+
+/// Status of the chains the query runs on, e.g. their latest block and gas price. The chains are
+/// the ids of the entity, so there's one result per chain.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ChainStatus {
+    fields: Vec<ChainField>,
+}
+
+impl ChainStatus {
+    pub fn new(fields: Vec<ChainField>) -> Self {
+        Self { fields }
+    }
+
+    pub fn fields(&self) -> &Vec<ChainField> {
+        &self.fields
+    }
+}
+
+impl TryFrom<Pairs<'_, Rule>> for ChainStatus {
+    type Error = ChainStatusError;
+
+    fn try_from(pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
+        let mut fields: Vec<ChainField> = vec![];
+
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::chain_fields => {
+                    let inner_pairs = pair.into_inner();
+
+                    if let Some(pair) = inner_pairs.peek() {
+                        if pair.as_rule() == Rule::wildcard {
+                            fields = ChainField::all_variants().to_vec();
+                            continue;
+                        }
+                    }
+
+                    fields = inner_pairs
+                        .filter_map(selected_field)
+                        .map(ChainField::try_from)
+                        .collect::<Result<Vec<ChainField>, ChainFieldError>>()?;
+                }
+                _ => {
+                    return Err(ChainStatusError::UnexpectedToken(pair.as_str().to_string()));
+                }
+            }
+        }
+
+        Ok(ChainStatus { fields })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, EnumVariants)]
+pub enum ChainField {
+    ChainId,
+    /// Number of the latest block
+    BlockNumber,
+    GasPrice,
+    /// Base fee of the latest block, empty on chains without EIP-1559
+    BaseFeePerGas,
+    Chain,
+}
+
+impl Display for ChainField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainField::ChainId => write!(f, "chain_id"),
+            ChainField::BlockNumber => write!(f, "block_number"),
+            ChainField::GasPrice => write!(f, "gas_price"),
+            ChainField::BaseFeePerGas => write!(f, "base_fee_per_gas"),
+            ChainField::Chain => write!(f, "chain"),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChainFieldError {
+    #[error("Invalid field for entity chain: {0}")]
+    InvalidField(String),
+}
+
+impl<'a> TryFrom<Pair<'a, Rule>> for ChainField {
+    type Error = ChainFieldError;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        ChainField::try_from(pair.as_str())
+    }
+}
+
+impl TryFrom<&str> for ChainField {
+    type Error = ChainFieldError;
+
+    fn try_from(value: &str) -> Result<Self, ChainFieldError> {
+        match value {
+            "chain_id" => Ok(ChainField::ChainId),
+            "block_number" => Ok(ChainField::BlockNumber),
+            "gas_price" => Ok(ChainField::GasPrice),
+            "base_fee_per_gas" => Ok(ChainField::BaseFeePerGas),
+            "chain" => Ok(ChainField::Chain),
+            invalid_field => Err(ChainFieldError::InvalidField(invalid_field.to_string())),
+        }
+    }
+}
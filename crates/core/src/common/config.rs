@@ -9,9 +9,20 @@ use std::path::PathBuf;
 
 const CONFIG_FILE: &str = "eql-config.json";
 
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Environment variable {0} used in RPC URL {1} is not set")]
+    MissingEnvVar(String, String),
+    #[error("Unclosed environment variable in RPC URL {0}")]
+    UnclosedEnvVar(String),
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct ConfigFile {
     chains: HashMap<String, ChainConfig>,
+    /// Directory the finalized blocks, transactions and receipts are cached in
+    #[serde(default)]
+    cache_dir: Option<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -61,38 +72,187 @@ impl Config {
         Config { file_path: None }
     }
 
+    /// Create a config instance reading the given file, regardless of the environment
+    pub fn from_path(file_path: PathBuf) -> Self {
+        Config {
+            file_path: Some(file_path),
+        }
+    }
+
+    /// RPC URL set as the default of the chain. Environment variables referenced as `${NAME}` in
+    /// the URL are expanded, so API keys don't have to be written in the config file.
     pub fn get_chain_default_rpc(&self, chain: &Chain) -> Result<Option<Url>> {
-        match &self.file_path {
-            Some(file_path) => {
-                let file = fs::read_to_string(file_path)?;
-                let config_file: ConfigFile = serde_json::from_str(&file)?;
+        match self.get_chain_config(chain)? {
+            Some(chain_config) => Ok(Some(parse_url(&chain_config.default)?)),
+            None => Ok(None),
+        }
+    }
 
-                if let Some(chain_config) = config_file.chains.get(&chain.to_string()) {
-                    let url = chain_config.default.parse::<Url>()?;
-                    Ok(Some(url))
-                } else {
-                    Ok(None)
-                }
+    pub fn get_chain_rpcs(&self, chain: &Chain) -> Result<Option<Vec<Url>>> {
+        match self.get_chain_config(chain)? {
+            Some(chain_config) => {
+                let urls: Result<Vec<Url>> =
+                    chain_config.rpcs.iter().map(|rpc| parse_url(rpc)).collect();
+                Ok(Some(urls?))
             }
             None => Ok(None),
         }
     }
 
-    pub fn get_chain_rpcs(&self, chain: &Chain) -> Result<Option<Vec<Url>>> {
+    /// Directory queries cache their finalized data in, if the config sets one
+    pub fn get_cache_dir(&self) -> Result<Option<PathBuf>> {
+        Ok(self.read()?.and_then(|config_file| config_file.cache_dir))
+    }
+
+    fn get_chain_config(&self, chain: &Chain) -> Result<Option<ChainConfig>> {
+        match self.read()? {
+            Some(mut config_file) => Ok(config_file.chains.remove(&chain.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn read(&self) -> Result<Option<ConfigFile>> {
         match &self.file_path {
             Some(file_path) => {
                 let file = fs::read_to_string(file_path)?;
-                let config_file: ConfigFile = serde_json::from_str(&file)?;
-
-                if let Some(chain_config) = config_file.chains.get(&chain.to_string()) {
-                    let urls: Result<Vec<Url>, _> =
-                        chain_config.rpcs.iter().map(|rpc| rpc.parse()).collect();
-                    Ok(Some(urls?))
-                } else {
-                    Ok(None)
-                }
+                Ok(Some(serde_json::from_str(&file)?))
             }
             None => Ok(None),
         }
     }
 }
+
+fn parse_url(url: &str) -> Result<Url> {
+    Ok(expand_env_vars(url)?.parse::<Url>()?)
+}
+
+/// Replaces every `${NAME}` in the URL with the value of the environment variable `NAME`
+fn expand_env_vars(url: &str) -> Result<String, ConfigError> {
+    let mut expanded = String::new();
+    let mut rest = url;
+
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| ConfigError::UnclosedEnvVar(url.to_string()))?;
+        let name = &rest[start + 2..start + end];
+        let value = env::var(name)
+            .map_err(|_| ConfigError::MissingEnvVar(name.to_string(), url.to_string()))?;
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        common::chain::ChainOrRpc,
+        interpreter::backend::mock_rpc::{result, MockRpc},
+    };
+    use alloy::providers::Provider;
+
+    /// Held by the tests setting `EQL_CONFIG_PATH`, since the environment is shared by all tests
+    pub(crate) static CONFIG_PATH_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Writes the config to a file unique to the test, so tests running concurrently don't clash
+    pub(crate) fn write_config(name: &str, config: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("eql-config-{}-{}.json", name, std::process::id()));
+        fs::write(&path, config).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_expand_env_vars_in_rpc_urls() {
+        env::set_var("EQL_TEST_API_KEY", "secret");
+        let path = write_config(
+            "env",
+            r#"{
+                "chains": {
+                    "eth": {
+                        "default": "https://eth.example.com/v2/${EQL_TEST_API_KEY}",
+                        "rpcs": ["https://eth.example.com/v2/${EQL_TEST_API_KEY}", "https://eth.llamarpc.com"]
+                    },
+                    "base": {
+                        "default": "https://base.example.com/${EQL_TEST_UNSET_API_KEY}",
+                        "rpcs": []
+                    }
+                }
+            }"#,
+        );
+        let config = Config::from_path(path);
+
+        assert_eq!(
+            config.get_chain_default_rpc(&Chain::Ethereum).unwrap(),
+            Some("https://eth.example.com/v2/secret".parse().unwrap())
+        );
+        assert_eq!(
+            config.get_chain_rpcs(&Chain::Ethereum).unwrap().unwrap()[0],
+            "https://eth.example.com/v2/secret".parse().unwrap()
+        );
+        assert_eq!(config.get_chain_default_rpc(&Chain::Polygon).unwrap(), None);
+        assert!(config
+            .get_chain_default_rpc(&Chain::Base)
+            .unwrap_err()
+            .to_string()
+            .contains("EQL_TEST_UNSET_API_KEY"));
+    }
+
+    #[test]
+    fn test_cache_dir_is_optional() {
+        let with_cache = write_config(
+            "cache-dir",
+            r#"{ "chains": {}, "cache_dir": "/tmp/eql-cache" }"#,
+        );
+        let without_cache = write_config("no-cache-dir", r#"{ "chains": {} }"#);
+
+        assert_eq!(
+            Config::from_path(with_cache).get_cache_dir().unwrap(),
+            Some(PathBuf::from("/tmp/eql-cache"))
+        );
+        assert_eq!(
+            Config::from_path(without_cache).get_cache_dir().unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_error_on_unclosed_env_var() {
+        assert!(matches!(
+            expand_env_vars("https://eth.example.com/${API_KEY"),
+            Err(ConfigError::UnclosedEnvVar(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_provider_is_built_from_configured_rpc() {
+        let _lock = CONFIG_PATH_LOCK.lock().await;
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_blockNumber" => result(&"0x2a"),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        env::set_var("EQL_TEST_RPC_PORT", rpc.url().port().unwrap().to_string());
+        // Only Moonriver is configured, so the tests reading other chains keep their defaults
+        let path = write_config(
+            "provider",
+            r#"{ "chains": { "moonriver": { "default": "http://127.0.0.1:${EQL_TEST_RPC_PORT}", "rpcs": [] } } }"#,
+        );
+        env::set_var("EQL_CONFIG_PATH", &path);
+
+        let provider = ChainOrRpc::Chain(Chain::Moonriver)
+            .provider()
+            .await
+            .unwrap();
+        let block_number = provider.get_block_number().await.unwrap();
+        env::remove_var("EQL_CONFIG_PATH");
+
+        assert_eq!(block_number, 42);
+        assert_eq!(rpc.calls("eth_blockNumber"), 1);
+    }
+}
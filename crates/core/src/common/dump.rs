@@ -7,15 +7,29 @@ use std::fmt::Display;
 pub struct Dump {
     pub name: String,
     pub format: DumpFormat,
+    /// Whether the file is gzip-compressed, e.g. `>> txs.csv.gz`
+    pub compressed: bool,
 }
 
 impl Dump {
     pub fn new(name: String, format: DumpFormat) -> Self {
-        Self { name, format }
+        Self {
+            name,
+            format,
+            compressed: false,
+        }
+    }
+
+    pub fn with_compression(mut self, compressed: bool) -> Self {
+        self.compressed = compressed;
+        self
     }
 
     pub fn path(&self) -> String {
-        format!("{}.{}", self.name, self.format)
+        match self.compressed {
+            true => format!("{}.{}.gz", self.name, self.format),
+            false => format!("{}.{}", self.name, self.format),
+        }
     }
 }
 
@@ -47,13 +61,18 @@ impl<'a> TryFrom<Pairs<'a, Rule>> for Dump {
             .as_str()
             .try_into()?;
 
-        Ok(Dump::new(name, format))
+        let compressed = pairs
+            .next()
+            .is_some_and(|pair| pair.as_rule() == Rule::compression);
+
+        Ok(Dump::new(name, format).with_compression(compressed))
     }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum DumpFormat {
     Json,
+    Jsonl,
     Csv,
     Parquet,
 }
@@ -64,6 +83,7 @@ impl TryFrom<&str> for DumpFormat {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
             "json" => Ok(DumpFormat::Json),
+            "jsonl" => Ok(DumpFormat::Jsonl),
             "csv" => Ok(DumpFormat::Csv),
             "parquet" => Ok(DumpFormat::Parquet),
             invalid_format => Err(DumpError::InvalidDumpFormat(invalid_format.to_string())),
@@ -75,6 +95,7 @@ impl Display for DumpFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DumpFormat::Json => write!(f, "json"),
+            DumpFormat::Jsonl => write!(f, "jsonl"),
             DumpFormat::Csv => write!(f, "csv"),
             DumpFormat::Parquet => write!(f, "parquet"),
         }
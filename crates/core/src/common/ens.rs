@@ -1,12 +1,19 @@
 /// Based on foundry-common implementation
 /// https://github.com/foundry-rs/foundry/blob/master/crates/common/src/ens.rs
-use self::EnsResolver::EnsResolverInstance;
-use alloy::primitives::{address, Address, Keccak256, B256};
-use alloy::providers::RootProvider;
+use self::{EnsRegistry::EnsRegistryInstance, EnsResolver::EnsResolverInstance};
+use super::chain::{Chain, ChainOrRpc};
+use crate::interpreter::backend::timeout;
+use alloy::primitives::{address, keccak256, Address, Bytes, Keccak256, TxKind, B256};
+use alloy::providers::{Provider, RootProvider};
+use alloy::rpc::types::TransactionRequest;
 use alloy::sol;
-use alloy::transports::http::{Client, Http};
+use alloy::sol_types::{SolCall, SolError, SolValue};
+use alloy::transports::{http::reqwest, BoxTransport, RpcError, TransportError};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::Deserialize;
+use serde_json::json;
 use std::fmt::Display;
-use std::{borrow::Cow, str::FromStr};
+use std::{borrow::Cow, collections::HashMap, str::FromStr, sync::OnceLock};
 
 // ENS Registry and Resolver contracts.
 sol! {
@@ -25,14 +32,78 @@ sol! {
 
         /// Returns the name associated with an ENS node, for reverse records.
         function name(bytes32 node) view returns (string);
+
+        /// Runs a record call for a DNS-encoded name on an ENSIP-10 extended resolver, which also
+        /// serves the subnames of the name it's set for.
+        function resolve(bytes name, bytes data) view returns (bytes);
+
+        /// Whether the resolver implements the interface, ERC-165.
+        function supportsInterface(bytes4 interfaceID) view returns (bool);
     }
+
+    /// Revert of a contract asking for data to be fetched from offchain gateways (EIP-3668).
+    error OffchainLookup(
+        address sender,
+        string[] urls,
+        bytes callData,
+        bytes4 callbackFunction,
+        bytes extraData
+    );
 }
 
+/// ERC-165 interface id of the extended resolvers serving the subnames of their name (ENSIP-10)
+const EXTENDED_RESOLVER_INTERFACE: [u8; 4] = [0x90, 0x61, 0xb9, 0x23];
+
+/// Offchain lookups a call can chain before it's abandoned
+const MAX_OFFCHAIN_LOOKUPS: usize = 4;
+
 /// ENS registry address (`0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e`)
 pub const ENS_ADDRESS: Address = address!("00000000000C2E074eC69A0dFb2997BA6C7d2e1e");
 
 pub const ENS_REVERSE_REGISTRAR_DOMAIN: &str = "addr.reverse";
 
+/// ENS registry contract, connected to the chain it's deployed on.
+pub type EnsRegistryContract = EnsRegistryInstance<BoxTransport, RootProvider<BoxTransport>>;
+
+/// ENS deployment names are resolved against, Ethereum mainnet's by default.
+/// Testnets like Sepolia and L2s have their own deployments, with a registry at another address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnsConfig {
+    pub chain: ChainOrRpc,
+    pub registry: Address,
+}
+
+impl EnsConfig {
+    pub fn new(chain: ChainOrRpc, registry: Address) -> Self {
+        Self { chain, registry }
+    }
+
+    /// Connects to the registry of the deployment.
+    pub async fn connect(&self) -> anyhow::Result<EnsRegistryContract> {
+        Ok(EnsRegistry::new(
+            self.registry,
+            self.chain.provider().await?,
+        ))
+    }
+
+    /// Deployment of the chain, for Ethereum and the testnets ENS is deployed on. Their registries
+    /// are at the same address.
+    pub fn of_chain(chain: &Chain) -> Option<Self> {
+        match chain {
+            Chain::Ethereum | Chain::Sepolia | Chain::Holesky => {
+                Some(Self::new(ChainOrRpc::Chain(chain.clone()), ENS_ADDRESS))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for EnsConfig {
+    fn default() -> Self {
+        Self::new(ChainOrRpc::Chain(Chain::Ethereum), ENS_ADDRESS)
+    }
+}
+
 /// Error type for ENS resolution.
 #[derive(Debug, thiserror::Error)]
 pub enum EnsError {
@@ -48,6 +119,17 @@ pub enum EnsError {
     /// Failed to resolve ENS name to an address.
     #[error("Failed to resolve ENS name to an address: {0}")]
     Resolve(alloy::contract::Error),
+    /// Failed to fetch the offchain data a resolver asked for (EIP-3668).
+    #[error("Failed offchain lookup of ENS name: {0}")]
+    OffchainLookup(String),
+}
+
+/// ENS name that couldn't be resolved by `resolve_names`, with the reason.
+#[derive(Debug, thiserror::Error)]
+#[error("Unable to resolve ENS name {name}: {source}")]
+pub struct NameResolutionError {
+    pub name: String,
+    pub source: EnsError,
 }
 
 /// ENS name or Ethereum Address.
@@ -61,46 +143,241 @@ pub enum NameOrAddress {
 
 impl NameOrAddress {
     /// Resolves the name to an Ethereum Address.
-    pub async fn resolve(
-        &self,
-        provider: &RootProvider<Http<Client>>,
-    ) -> Result<Address, EnsError> {
+    pub async fn resolve(&self, registry: &EnsRegistryContract) -> Result<Address, EnsError> {
         match self {
-            Self::Name(name) => self.resolve_name(name, provider).await,
+            Self::Name(name) => self.resolve_name(name, registry).await,
             Self::Address(addr) => Ok(*addr),
         }
     }
 
+    /// Names whose resolver is set on a parent name are resolved through the `resolve` function
+    /// of the parent's resolver (ENSIP-10). Resolvers serving their records from offchain gateways
+    /// revert with an `OffchainLookup`, which is followed (EIP-3668).
     async fn resolve_name(
         &self,
         name: &str,
-        provider: &RootProvider<Http<Client>>,
+        registry: &EnsRegistryContract,
     ) -> Result<Address, EnsError> {
         let node = namehash(name);
-        let registry = EnsRegistry::new(ENS_ADDRESS, provider.clone());
+        let (resolver, wildcard) = find_resolver(name, registry).await?;
+        let addr_call = EnsResolver::addrCall { node };
+
+        let record = match wildcard {
+            true => {
+                let resolve_call = EnsResolver::resolveCall {
+                    name: dns_encode(name).into(),
+                    data: addr_call.abi_encode().into(),
+                };
+                let output =
+                    ccip_call(resolver, resolve_call.abi_encode(), registry.provider()).await?;
+                EnsResolver::resolveCall::abi_decode_returns(&output, true)
+                    .map_err(|e| EnsError::Resolve(e.into()))?
+                    ._0
+            }
+            false => ccip_call(resolver, addr_call.abi_encode(), registry.provider()).await?,
+        };
+        let addr = EnsResolver::addrCall::abi_decode_returns(&record, true)
+            .map_err(|e| EnsError::Resolve(e.into()))?
+            ._0;
+
+        Ok(addr)
+    }
+}
 
-        let address = registry
-            .resolver(node)
+/// Resolves the names through the same registry connection, with at most `max_concurrency` of
+/// them resolved at the same time. Names repeated in `names` are resolved once.
+pub async fn resolve_names<'a>(
+    names: impl IntoIterator<Item = &'a String>,
+    registry: &EnsRegistryContract,
+    max_concurrency: usize,
+) -> Result<HashMap<String, Address>, NameResolutionError> {
+    let mut names: Vec<String> = names.into_iter().cloned().collect();
+    names.sort();
+    names.dedup();
+
+    stream::iter(names)
+        .map(|name| async move {
+            match NameOrAddress::Name(name.clone()).resolve(registry).await {
+                Ok(address) => Ok((name, address)),
+                Err(source) => Err(NameResolutionError { name, source }),
+            }
+        })
+        .buffer_unordered(max_concurrency.max(1))
+        .try_collect()
+        .await
+}
+
+/// Resolver of the name, or of its closest parent with one. Resolvers set on a parent are wildcard
+/// resolvers, which is returned alongside the resolver address, and only serve the name if they're
+/// extended resolvers. Otherwise the name has no resolver.
+async fn find_resolver(
+    name: &str,
+    registry: &EnsRegistryContract,
+) -> Result<(Address, bool), EnsError> {
+    let mut parent = name;
+
+    loop {
+        let resolver = registry
+            .resolver(namehash(parent))
             .call()
             .await
             .map_err(EnsError::Resolver)?
             ._0;
-        if address == Address::ZERO {
-            return Err(EnsError::ResolverNotFound(String::from(
-                "Resolved to zero address",
+        if resolver != Address::ZERO && parent == name {
+            return Ok((resolver, false));
+        }
+        if resolver != Address::ZERO {
+            return match is_extended_resolver(resolver, registry.provider()).await? {
+                true => Ok((resolver, true)),
+                false => Err(EnsError::ResolverNotFound(name.to_string())),
+            };
+        }
+
+        match parent.split_once('.') {
+            Some((_, grandparent)) => parent = grandparent,
+            None => {
+                return Err(EnsError::ResolverNotFound(String::from(
+                    "Resolved to zero address",
+                )))
+            }
+        }
+    }
+}
+
+/// Whether the resolver supports the ENSIP-10 interface. Resolvers predating ERC-165 revert or
+/// return nothing, and don't support it.
+async fn is_extended_resolver(
+    resolver: Address,
+    provider: &RootProvider<BoxTransport>,
+) -> Result<bool, EnsError> {
+    let supports = EnsResolverInstance::new(resolver, provider)
+        .supportsInterface(EXTENDED_RESOLVER_INTERFACE.into())
+        .call()
+        .await;
+
+    match supports {
+        Ok(supports) => Ok(supports._0),
+        Err(alloy::contract::Error::TransportError(RpcError::ErrorResp(_))) => Ok(false),
+        Err(error @ alloy::contract::Error::TransportError(_)) => Err(EnsError::Resolver(error)),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Calls the resolver, following the offchain lookups it reverts with: the gateways listed by the
+/// lookup are queried, and their response is passed to the callback function of the resolver,
+/// which returns the result of the call or asks for another lookup.
+async fn ccip_call(
+    resolver: Address,
+    calldata: Vec<u8>,
+    provider: &RootProvider<BoxTransport>,
+) -> Result<Bytes, EnsError> {
+    let mut calldata = Bytes::from(calldata);
+
+    for _ in 0..MAX_OFFCHAIN_LOOKUPS {
+        let request = TransactionRequest {
+            to: Some(TxKind::Call(resolver)),
+            input: calldata.clone().into(),
+            ..Default::default()
+        };
+        let error = match provider.call(&request).await {
+            Ok(output) => return Ok(output),
+            Err(error) => error,
+        };
+        let Some(lookup) = offchain_lookup(&error) else {
+            return Err(EnsError::Resolve(error.into()));
+        };
+        // The callback is made to the resolver, so a lookup on behalf of another contract is a
+        // malformed one
+        if lookup.sender != resolver {
+            return Err(EnsError::OffchainLookup(format!(
+                "lookup sent by {} instead of the resolver {resolver}",
+                lookup.sender
             )));
         }
 
-        let resolver = EnsResolverInstance::new(address, provider);
-        let addr = resolver
-            .addr(node)
-            .call()
-            .await
-            .map_err(EnsError::Resolve)
-            .inspect_err(|e| eprintln!("{e:?}"))?
-            ._0;
+        let response = query_gateways(&lookup).await?;
+        calldata = [
+            lookup.callbackFunction.as_slice(),
+            &(response, lookup.extraData).abi_encode_params(),
+        ]
+        .concat()
+        .into();
+    }
 
-        Ok(addr)
+    Err(EnsError::OffchainLookup(format!(
+        "more than {MAX_OFFCHAIN_LOOKUPS} chained lookups"
+    )))
+}
+
+/// `OffchainLookup` the call reverted with, if any
+fn offchain_lookup(error: &TransportError) -> Option<OffchainLookup> {
+    let RpcError::ErrorResp(payload) = error else {
+        return None;
+    };
+    let data = payload.try_data_as::<Bytes>()?.ok()?;
+
+    OffchainLookup::abi_decode(&data, true).ok()
+}
+
+/// Response of a gateway to an offchain lookup
+#[derive(Deserialize)]
+struct GatewayResponse {
+    data: Bytes,
+}
+
+/// HTTP client of the gateways, shared by all the lookups so their connections are reused
+fn gateway_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Queries the gateways of the lookup in order until one answers. URLs holding a `{data}`
+/// placeholder are queried with GET, and the others with a POST of the sender and the data.
+/// A gateway answering with a 4xx status fails the lookup, while other errors, like a gateway not
+/// answering within the request timeout of the query, move on to the next.
+async fn query_gateways(lookup: &OffchainLookup) -> Result<Bytes, EnsError> {
+    let client = gateway_client();
+    let sender = format!("{:#x}", lookup.sender);
+    let data = lookup.callData.to_string();
+    let mut errors = vec![];
+
+    for url in &lookup.urls {
+        let gateway = url.replace("{sender}", &sender).replace("{data}", &data);
+        let request = match url.contains("{data}") {
+            true => client.get(&gateway),
+            false => client
+                .post(&gateway)
+                .header("Content-Type", "application/json")
+                .body(json!({ "data": data, "sender": sender }).to_string()),
+        };
+
+        let response = match request.timeout(timeout::current()).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                errors.push(format!("{gateway}: {e}"));
+                continue;
+            }
+        };
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if status.is_client_error() {
+            return Err(EnsError::OffchainLookup(format!(
+                "{gateway} answered {status}: {body}"
+            )));
+        }
+
+        match serde_json::from_str::<GatewayResponse>(&body) {
+            Ok(response) if status.is_success() => return Ok(response.data),
+            Ok(_) => errors.push(format!("{gateway} answered {status}")),
+            Err(e) => errors.push(format!("{gateway}: {e}")),
+        }
+    }
+
+    match errors.is_empty() {
+        true => Err(EnsError::OffchainLookup(String::from(
+            "no gateway to query",
+        ))),
+        false => Err(EnsError::OffchainLookup(errors.join(", "))),
     }
 }
 
@@ -143,6 +420,43 @@ impl Display for NameOrAddress {
     }
 }
 
+/// Returns the primary ENS name of an address, following the
+/// [reverse resolution](https://docs.ens.domains/ensip/3) spec. `None` is returned when the
+/// address has no reverse record, or when the name doesn't resolve back to the same address.
+pub async fn lookup_address(
+    address: &Address,
+    registry: &EnsRegistryContract,
+) -> Result<Option<String>, EnsError> {
+    let node = namehash(&reverse_address(address));
+
+    let resolver_address = registry
+        .resolver(node)
+        .call()
+        .await
+        .map_err(EnsError::Resolver)?
+        ._0;
+    if resolver_address == Address::ZERO {
+        return Ok(None);
+    }
+
+    let resolver = EnsResolverInstance::new(resolver_address, registry.provider());
+    let name = resolver
+        .name(node)
+        .call()
+        .await
+        .map_err(EnsError::Lookup)?
+        ._0;
+    if name.is_empty() {
+        return Ok(None);
+    }
+
+    // The reverse record is set by the address owner, so it's only trusted if the forward record matches
+    match NameOrAddress::Name(name.clone()).resolve(registry).await {
+        Ok(forward_address) if forward_address == *address => Ok(Some(name)),
+        _ => Ok(None),
+    }
+}
+
 /// Returns the ENS namehash as specified in [EIP-137](https://eips.ethereum.org/EIPS/eip-137)
 pub fn namehash(name: &str) -> B256 {
     if name.is_empty() {
@@ -176,6 +490,25 @@ pub fn namehash(name: &str) -> B256 {
     buffer[..32].try_into().unwrap()
 }
 
+/// Encodes the name in the DNS wire format passed to extended resolvers (ENSIP-10): each label is
+/// prefixed with its length, and the name ends with an empty label. Labels longer than 255 bytes
+/// are replaced with their hash, as `[<hex labelhash>]`.
+pub fn dns_encode(name: &str) -> Vec<u8> {
+    let mut encoded = vec![];
+
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        let label = match label.len() {
+            0..=255 => label.to_string(),
+            _ => format!("[{:x}]", keccak256(label)),
+        };
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+
+    encoded
+}
+
 /// Returns the reverse-registrar name of an address.
 pub fn reverse_address(addr: &Address) -> String {
     format!("{addr:x}.{ENS_REVERSE_REGISTRAR_DOMAIN}")
@@ -184,12 +517,34 @@ pub fn reverse_address(addr: &Address) -> String {
 #[cfg(test)]
 mod test {
     use super::*;
-    use alloy::primitives::hex;
+    use crate::interpreter::backend::mock_rpc::{result, revert, MockRpc};
+    use alloy::primitives::{hex, U256};
+    use serde_json::Value;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
 
     fn assert_hex(hash: B256, val: &str) {
         assert_eq!(hash.0[..], hex::decode(val).unwrap()[..]);
     }
 
+    #[test]
+    fn test_testnets_have_their_own_deployment() {
+        for chain in [Chain::Ethereum, Chain::Sepolia, Chain::Holesky] {
+            assert_eq!(
+                EnsConfig::of_chain(&chain),
+                Some(EnsConfig::new(ChainOrRpc::Chain(chain), ENS_ADDRESS))
+            );
+        }
+        assert_eq!(EnsConfig::of_chain(&Chain::Hoodi), None);
+        assert_eq!(EnsConfig::of_chain(&Chain::Arbitrum), None);
+    }
+
     #[test]
     fn test_namehash() {
         for (name, expected) in &[
@@ -218,6 +573,23 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_dns_encode() {
+        assert_eq!(
+            dns_encode("1.offchainexample.eth"),
+            b"\x011\x0foffchainexample\x03eth\x00".to_vec()
+        );
+        assert_eq!(dns_encode(""), vec![0]);
+
+        let long_label = "a".repeat(256);
+        let encoded = dns_encode(&format!("{long_label}.eth"));
+        assert_eq!(encoded[0], 66);
+        assert_eq!(
+            &encoded[1..67],
+            format!("[{:x}]", keccak256(&long_label)).as_bytes()
+        );
+    }
+
     #[test]
     fn test_reverse_address() {
         for (addr, expected) in [
@@ -233,4 +605,336 @@ mod test {
             assert_eq!(reverse_address(&addr.parse().unwrap()), expected, "{addr}");
         }
     }
+
+    const VITALIK: Address = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+    const RESOLVER: Address = address!("231b0Ee14048e9dCcD1d247744d114a4EB5E8E63");
+
+    /// Mocks the registry and a resolver holding the given reverse and forward records
+    async fn mock_ens(reverse_name: &'static str, forward_address: Address) -> MockRpc {
+        MockRpc::start(move |_, params: &Value| {
+            let input = params[0]["input"]
+                .as_str()
+                .or(params[0]["data"].as_str())
+                .unwrap()
+                .parse::<Bytes>()
+                .unwrap();
+            let output = match input[..4].try_into().unwrap() {
+                EnsRegistry::resolverCall::SELECTOR if reverse_name.is_empty() => {
+                    Address::ZERO.abi_encode()
+                }
+                EnsRegistry::resolverCall::SELECTOR => RESOLVER.abi_encode(),
+                EnsResolver::nameCall::SELECTOR => reverse_name.abi_encode(),
+                EnsResolver::addrCall::SELECTOR => forward_address.abi_encode(),
+                _ => U256::ZERO.abi_encode(),
+            };
+            result(&Bytes::from(output))
+        })
+        .await
+    }
+
+    fn registry(rpc: &MockRpc) -> EnsRegistryContract {
+        EnsRegistry::new(ENS_ADDRESS, rpc.provider().as_ref().clone())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_names_concurrently() {
+        let names = ["vitalik.eth", "nick.eth", "brantly.eth"].map(String::from);
+        let addresses: HashMap<B256, Address> = names
+            .iter()
+            .zip(1u8..)
+            .map(|(name, n)| (namehash(name), Address::repeat_byte(n)))
+            .collect();
+        let rpc = MockRpc::start_with_latency(Duration::from_millis(50), move |_, params| {
+            let input = params[0]["input"]
+                .as_str()
+                .or(params[0]["data"].as_str())
+                .unwrap()
+                .parse::<Bytes>()
+                .unwrap();
+            let output = match input[..4].try_into().unwrap() {
+                EnsRegistry::resolverCall::SELECTOR => RESOLVER.abi_encode(),
+                EnsResolver::addrCall::SELECTOR => {
+                    let node = EnsResolver::addrCall::abi_decode(&input, true)
+                        .unwrap()
+                        .node;
+                    addresses[&node].abi_encode()
+                }
+                selector => return Err(format!("Unexpected call {selector:?}")),
+            };
+            result(&Bytes::from(output))
+        })
+        .await;
+
+        let resolved = resolve_names(&names, &registry(&rpc), 3).await.unwrap();
+
+        assert_eq!(
+            resolved,
+            HashMap::from([
+                (names[0].clone(), Address::repeat_byte(1)),
+                (names[1].clone(), Address::repeat_byte(2)),
+                (names[2].clone(), Address::repeat_byte(3)),
+            ])
+        );
+        // The registry and then the resolver of the three names are called at the same time
+        assert_eq!(rpc.max_in_flight(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_address_with_primary_name() {
+        let rpc = mock_ens("vitalik.eth", VITALIK).await;
+
+        let name = lookup_address(&VITALIK, &registry(&rpc)).await.unwrap();
+
+        assert_eq!(name, Some(String::from("vitalik.eth")));
+    }
+
+    #[tokio::test]
+    async fn test_lookup_address_without_reverse_record() {
+        let rpc = mock_ens("", VITALIK).await;
+
+        let name = lookup_address(&VITALIK, &registry(&rpc)).await.unwrap();
+
+        assert_eq!(name, None);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_address_ignores_mismatched_forward_record() {
+        let rpc = mock_ens("vitalik.eth", Address::ZERO).await;
+
+        let name = lookup_address(&VITALIK, &registry(&rpc)).await.unwrap();
+
+        assert_eq!(name, None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_name_with_custom_registry() {
+        let registry = address!("1111111111111111111111111111111111111111");
+        let rpc = MockRpc::start(move |_, params: &Value| {
+            let to = params[0]["to"]
+                .as_str()
+                .unwrap()
+                .parse::<Address>()
+                .unwrap();
+            let output = match to {
+                to if to == registry => RESOLVER.abi_encode(),
+                to if to == RESOLVER => VITALIK.abi_encode(),
+                to => return Err(format!("Unexpected call to {to}")),
+            };
+            result(&Bytes::from(output))
+        })
+        .await;
+        let ens = EnsConfig::new(ChainOrRpc::Rpc(rpc.url()), registry);
+
+        let address = NameOrAddress::Name(String::from("vitalik.eth"))
+            .resolve(&ens.connect().await.unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(address, VITALIK);
+    }
+
+    /// Callback the offchain resolver of the tests asks to be called with the gateway response
+    const CALLBACK: [u8; 4] = [0xab, 0xcd, 0xef, 0x01];
+
+    /// Gateway answering every request with `status` and `body`, returning its URL template and
+    /// the paths it's requested
+    async fn gateway(status: &'static str, body: String) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/{{sender}}/{{data}}.json",
+            listener.local_addr().unwrap()
+        );
+        let paths = Arc::new(Mutex::new(vec![]));
+        let requested = paths.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                requested.lock().unwrap().push(path);
+                let response = format!(
+                    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, paths)
+    }
+
+    /// Mocks the registry with a wildcard resolver set on `offchain.eth`, which serves its
+    /// subnames from the gateway and expects `gateway_data` back in its callback
+    async fn mock_offchain_ens(gateway_url: String, gateway_data: Bytes) -> MockRpc {
+        MockRpc::start(move |_, params: &Value| {
+            let to: Address = params[0]["to"].as_str().unwrap().parse().unwrap();
+            let input = params[0]["input"]
+                .as_str()
+                .or(params[0]["data"].as_str())
+                .unwrap()
+                .parse::<Bytes>()
+                .unwrap();
+
+            match (to, <[u8; 4]>::try_from(&input[..4]).unwrap()) {
+                (ENS_ADDRESS, _) => {
+                    let node = EnsRegistry::resolverCall::abi_decode(&input, true)
+                        .unwrap()
+                        .node;
+                    let resolver = match node == namehash("offchain.eth") {
+                        true => RESOLVER,
+                        false => Address::ZERO,
+                    };
+                    result(&Bytes::from(resolver.abi_encode()))
+                }
+                (RESOLVER, EnsResolver::supportsInterfaceCall::SELECTOR) => {
+                    result(&Bytes::from(true.abi_encode()))
+                }
+                (RESOLVER, EnsResolver::resolveCall::SELECTOR) => revert(
+                    &OffchainLookup {
+                        sender: RESOLVER,
+                        urls: vec![gateway_url.clone()],
+                        callData: Bytes::from_static(&[0x12, 0x34]),
+                        callbackFunction: CALLBACK.into(),
+                        extraData: Bytes::from_static(&[0x99]),
+                    }
+                    .abi_encode(),
+                ),
+                (RESOLVER, CALLBACK) => {
+                    let (response, extra_data) =
+                        <(Bytes, Bytes)>::abi_decode_params(&input[4..], true).unwrap();
+                    if response != gateway_data || extra_data[..] != [0x99] {
+                        return Err(String::from("Unexpected callback"));
+                    }
+                    let record = Bytes::from(VITALIK.abi_encode());
+                    result(&Bytes::from(record.abi_encode()))
+                }
+                (to, _) => Err(format!("Unexpected call to {to}")),
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_resolve_offchain_name_through_gateway() {
+        let gateway_data = Bytes::from_static(&[0x56, 0x78]);
+        let (url, paths) = gateway(
+            "200 OK",
+            json!({ "data": gateway_data.to_string() }).to_string(),
+        )
+        .await;
+        let rpc = mock_offchain_ens(url, gateway_data).await;
+
+        let address = NameOrAddress::Name(String::from("alice.offchain.eth"))
+            .resolve(&registry(&rpc))
+            .await
+            .unwrap();
+
+        assert_eq!(address, VITALIK);
+        // The placeholders of the gateway URL are replaced with the sender and the call data
+        assert_eq!(
+            *paths.lock().unwrap(),
+            vec![format!("/{:#x}/0x1234.json", RESOLVER)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parent_resolver_without_extended_interface_does_not_serve_subnames() {
+        let rpc = MockRpc::start(move |_, params: &Value| {
+            let to: Address = params[0]["to"].as_str().unwrap().parse().unwrap();
+            let input = params[0]["input"]
+                .as_str()
+                .or(params[0]["data"].as_str())
+                .unwrap()
+                .parse::<Bytes>()
+                .unwrap();
+
+            match (to, <[u8; 4]>::try_from(&input[..4]).unwrap()) {
+                (ENS_ADDRESS, _) => {
+                    let node = EnsRegistry::resolverCall::abi_decode(&input, true)
+                        .unwrap()
+                        .node;
+                    let resolver = match node == namehash("parent.eth") {
+                        true => RESOLVER,
+                        false => Address::ZERO,
+                    };
+                    result(&Bytes::from(resolver.abi_encode()))
+                }
+                // A resolver predating ERC-165, without a fallback
+                (RESOLVER, EnsResolver::supportsInterfaceCall::SELECTOR) => revert(&Bytes::new()),
+                (to, _) => Err(format!("Unexpected call to {to}")),
+            }
+        })
+        .await;
+
+        let error = NameOrAddress::Name(String::from("alice.parent.eth"))
+            .resolve(&registry(&rpc))
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(&error, EnsError::ResolverNotFound(name) if name == "alice.parent.eth"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_when_gateway_rejects_offchain_lookup() {
+        let (url, _) = gateway("404 Not Found", String::from("{}")).await;
+        let rpc = mock_offchain_ens(url, Bytes::new()).await;
+
+        let error = NameOrAddress::Name(String::from("alice.offchain.eth"))
+            .resolve(&registry(&rpc))
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(&error, EnsError::OffchainLookup(message) if message.contains("404")),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_when_gateway_does_not_answer() {
+        // Accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/{{data}}.json", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut streams = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        let rpc = mock_offchain_ens(url, Bytes::new()).await;
+
+        let (name, registry) = (
+            NameOrAddress::Name(String::from("alice.offchain.eth")),
+            registry(&rpc),
+        );
+
+        let lookup = timeout::scope(Duration::from_millis(200), name.resolve(&registry));
+        let error = tokio::time::timeout(Duration::from_secs(5), lookup)
+            .await
+            .expect("the gateway request should time out")
+            .unwrap_err();
+
+        assert!(matches!(error, EnsError::OffchainLookup(_)), "{error}");
+    }
+
+    // Resolving the name needs mainnet and its offchain gateway, so this test only runs when the
+    // `ccip-read-tests` feature is enabled
+    #[cfg(feature = "ccip-read-tests")]
+    #[tokio::test]
+    async fn test_resolve_offchain_name_on_mainnet() {
+        // Served by the offchain resolver example of ENS, through its gateway
+        let name = NameOrAddress::Name(String::from("1.offchainexample.eth"));
+
+        let address = name
+            .resolve(&EnsConfig::default().connect().await.unwrap())
+            .await
+            .unwrap();
+
+        assert_ne!(address, Address::ZERO);
+    }
 }
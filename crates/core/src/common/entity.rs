@@ -1,8 +1,18 @@
-use super::account::AccountError;
+use super::account::{AccountError, AccountField};
+use super::chain_status::{ChainStatus, ChainStatusError};
 use super::logs::LogsError;
+use super::receipt::ReceiptError;
+use super::token::TokenError;
 use super::transaction::TransactionError;
 use crate::common::{
-    account::Account, block::Block, block::BlockError, logs::Logs, transaction::Transaction,
+    account::Account,
+    block::Block,
+    block::BlockError,
+    logs::Logs,
+    query_result::DedupKeys,
+    receipt::Receipt,
+    token::Token,
+    transaction::{Transaction, TransactionField},
 };
 use crate::interpreter::frontend::parser::Rule;
 use pest::iterators::Pairs;
@@ -26,6 +36,15 @@ pub enum EntityError {
 
     #[error(transparent)]
     AccountError(#[from] AccountError),
+
+    #[error(transparent)]
+    TokenError(#[from] TokenError),
+
+    #[error(transparent)]
+    ReceiptError(#[from] ReceiptError),
+
+    #[error(transparent)]
+    ChainStatusError(#[from] ChainStatusError),
 }
 
 #[derive(Debug, PartialEq)]
@@ -34,6 +53,90 @@ pub enum Entity {
     Block(Block),
     Transaction(Transaction),
     Logs(Logs),
+    Token(Token),
+    Receipt(Receipt),
+    Chain(ChainStatus),
+}
+
+impl Entity {
+    /// Names of the fields selected by the query, as they're written in it
+    pub fn field_names(&self) -> Vec<String> {
+        match self {
+            Entity::Account(account) => account.fields().iter().map(ToString::to_string).collect(),
+            Entity::Block(block) => block.fields().iter().map(ToString::to_string).collect(),
+            Entity::Transaction(tx) => tx.fields().iter().map(ToString::to_string).collect(),
+            Entity::Logs(logs) => logs.fields().iter().map(ToString::to_string).collect(),
+            Entity::Token(token) => token.fields().iter().map(ToString::to_string).collect(),
+            Entity::Receipt(receipt) => receipt.fields().iter().map(ToString::to_string).collect(),
+            Entity::Chain(chain) => chain.fields().iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    /// Keys of the selected fields in the results, in the order they're written in the query. The
+    /// token balances, storage slots and calls of an account are all written under a single key.
+    pub fn field_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = vec![];
+        let names = match self {
+            Entity::Account(account) => account
+                .fields()
+                .iter()
+                .map(|field| match field {
+                    AccountField::TokenBalance(_) => "token_balances".to_string(),
+                    AccountField::Storage(_) => "storage".to_string(),
+                    AccountField::Call(_) => "calls".to_string(),
+                    field => field.to_string(),
+                })
+                .collect(),
+            _ => self.field_names(),
+        };
+
+        for name in names {
+            if !keys.contains(&name) {
+                keys.push(name);
+            }
+        }
+
+        keys
+    }
+}
+
+impl Entity {
+    /// Entity with the fields its results are deduplicated on selected: the chain and the hash of
+    /// transactions, and the chain and the address of accounts. They're resolved rather than taken
+    /// from the selected fields, so results are told apart even without them. `None` for the
+    /// entities that aren't deduplicated.
+    pub(crate) fn with_dedup_keys(&self) -> Option<(Entity, DedupKeys)> {
+        match self {
+            Entity::Transaction(tx) => {
+                let mut fields = tx.fields().clone();
+                let keys = DedupKeys {
+                    hide_chain: select(&mut fields, TransactionField::Chain),
+                    hide_id: select(&mut fields, TransactionField::Hash),
+                };
+                let tx = Transaction::new(tx.ids().cloned(), tx.filters().cloned(), fields);
+                Some((Entity::Transaction(tx), keys))
+            }
+            Entity::Account(account) => {
+                let mut fields = account.fields();
+                let keys = DedupKeys {
+                    hide_chain: select(&mut fields, AccountField::Chain),
+                    hide_id: select(&mut fields, AccountField::Address),
+                };
+                let account = Account::new(account.ids().cloned(), account.filter(), fields);
+                Some((Entity::Account(account), keys))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Adds the field to the selected ones, returning whether it wasn't selected
+fn select<T: PartialEq>(fields: &mut Vec<T>, field: T) -> bool {
+    let missing = !fields.contains(&field);
+    if missing {
+        fields.push(field);
+    }
+    missing
 }
 
 impl TryFrom<Pairs<'_, Rule>> for Entity {
@@ -58,6 +161,18 @@ impl TryFrom<Pairs<'_, Rule>> for Entity {
                     let logs = Logs::try_from(pair.into_inner())?;
                     return Ok(Entity::Logs(logs));
                 }
+                Rule::token_get => {
+                    let token = Token::try_from(pair.into_inner())?;
+                    return Ok(Entity::Token(token));
+                }
+                Rule::receipt_get => {
+                    let receipt = Receipt::try_from(pair.into_inner())?;
+                    return Ok(Entity::Receipt(receipt));
+                }
+                Rule::chain_get => {
+                    let chain = ChainStatus::try_from(pair.into_inner())?;
+                    return Ok(Entity::Chain(chain));
+                }
                 _ => return Err(EntityError::UnexpectedToken(pair.as_str().to_string())),
             }
         }
@@ -1,3 +1,4 @@
+use super::block::BlockRange;
 use alloy::eips::BlockNumberOrTag;
 
 #[derive(Debug, PartialEq, Eq, thiserror::Error)]
@@ -20,3 +21,40 @@ pub fn parse_block_number_or_tag(id: &str) -> Result<BlockNumberOrTag, EntityIdE
             .map_err(|_| EntityIdError::InvalidBlockNumberOrTag(id.to_string())),
     }
 }
+
+/// Parses a block range (`start:end`) or a single block. Open ranges start at the genesis block
+/// (`:end`) or end at the latest block (`start:`), which is resolved when the query runs.
+pub fn parse_block_range(range: &str) -> Result<BlockRange, EntityIdError> {
+    match range.split_once(':') {
+        Some((start, end)) => {
+            let (start, start_offset) = match start.trim() {
+                "" => (BlockNumberOrTag::Number(0), 0),
+                start => parse_relative_block(start)?,
+            };
+            let (end, end_offset) = match end.trim() {
+                "" => (BlockNumberOrTag::Latest, 0),
+                end => parse_relative_block(end)?,
+            };
+            Ok(BlockRange::new(start, Some(end)).with_offsets(start_offset, end_offset))
+        }
+        None => {
+            let (block, offset) = parse_relative_block(range)?;
+            Ok(BlockRange::new(block, None).with_offsets(offset, 0))
+        }
+    }
+}
+
+/// Parses a block number or tag, which can be followed by a number of blocks before it, like
+/// `latest-100`.
+fn parse_relative_block(id: &str) -> Result<(BlockNumberOrTag, u64), EntityIdError> {
+    match id.trim().split_once('-') {
+        Some((block, offset)) => {
+            let offset = offset
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| EntityIdError::InvalidBlockNumberOrTag(id.to_string()))?;
+            Ok((parse_block_number_or_tag(block)?, offset))
+        }
+        None => Ok((parse_block_number_or_tag(id)?, 0)),
+    }
+}
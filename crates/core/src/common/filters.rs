@@ -1,11 +1,12 @@
 use crate::interpreter::frontend::parser::Rule;
 use pest::iterators::Pair;
+use std::borrow::Cow;
 
 pub trait Filter<T> {
     fn compare(&self, a: &T) -> bool;
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum FilterType<T> {
     Equality(EqualityFilter<T>),
     Comparison(ComparisonFilter<T>),
@@ -60,6 +61,9 @@ pub enum FilterError {
 pub enum EqualityFilter<T> {
     Eq(T),
     Neq(T),
+    /// Matches any of the values, e.g. `to IN (0x..., 0x...)`
+    In(Vec<T>),
+    NotIn(Vec<T>),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -86,6 +90,40 @@ impl<T> TryFrom<(Pair<'_, Rule>, T)> for EqualityFilter<T> {
     }
 }
 
+impl<T> EqualityFilter<T> {
+    /// Builds an `IN` or `NOT IN` filter from its operator and list of values
+    pub fn try_from_list(
+        operator: Pair<'_, Rule>,
+        values: Vec<T>,
+    ) -> Result<Self, EqualityFilterError> {
+        let inner_operator = operator.into_inner().next().ok_or_else(|| {
+            EqualityFilterError::InvalidOperator("Missing operator in filter".to_string())
+        })?;
+
+        match inner_operator.as_rule() {
+            Rule::in_operator => Ok(Self::In(values)),
+            Rule::not_in_operator => Ok(Self::NotIn(values)),
+            _ => Err(EqualityFilterError::InvalidOperator(
+                inner_operator.as_str().to_string(),
+            )),
+        }
+    }
+
+    pub fn values(&self) -> &[T] {
+        match self {
+            EqualityFilter::Eq(value) | EqualityFilter::Neq(value) => std::slice::from_ref(value),
+            EqualityFilter::In(values) | EqualityFilter::NotIn(values) => values,
+        }
+    }
+
+    pub fn values_mut(&mut self) -> &mut [T] {
+        match self {
+            EqualityFilter::Eq(value) | EqualityFilter::Neq(value) => std::slice::from_mut(value),
+            EqualityFilter::In(values) | EqualityFilter::NotIn(values) => values,
+        }
+    }
+}
+
 impl<T> Filter<T> for EqualityFilter<T>
 where
     T: PartialEq,
@@ -94,6 +132,8 @@ where
         match self {
             EqualityFilter::Eq(value) => a == value,
             EqualityFilter::Neq(value) => a != value,
+            EqualityFilter::In(values) => values.contains(a),
+            EqualityFilter::NotIn(values) => !values.contains(a),
         }
     }
 }
@@ -104,6 +144,8 @@ pub enum ComparisonFilter<T> {
     Gte(T),
     Lt(T),
     Lte(T),
+    /// Matches the values between the bounds, inclusive, e.g. `value BETWEEN 1 AND 5`
+    Between(T, T),
 }
 
 impl<T> Filter<T> for ComparisonFilter<T>
@@ -116,6 +158,7 @@ where
             ComparisonFilter::Gte(value) => a >= value,
             ComparisonFilter::Lt(value) => a < value,
             ComparisonFilter::Lte(value) => a <= value,
+            ComparisonFilter::Between(min, max) => a >= min && a <= max,
         }
     }
 }
@@ -148,6 +191,24 @@ impl<T> TryFrom<(Pair<'_, Rule>, T)> for ComparisonFilter<T> {
     }
 }
 
+/// Writes a number given in the `1e17` form out in full, so it parses like any other integer.
+/// Exponents too large for a `U256` are left as they are, to fail parsing.
+pub(crate) fn expand_exponent(value: &str) -> Cow<'_, str> {
+    let is_integer = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    match value.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) if is_integer(mantissa) && is_integer(exponent) => {
+            match exponent.parse::<usize>() {
+                Ok(exponent) if mantissa.len() + exponent <= 78 => {
+                    Cow::Owned(format!("{mantissa}{}", "0".repeat(exponent)))
+                }
+                _ => Cow::Borrowed(value),
+            }
+        }
+        _ => Cow::Borrowed(value),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,6 +220,17 @@ mod tests {
         assert!(!filter.compare(&2));
     }
 
+    #[test]
+    fn test_list_filter() {
+        let filter = EqualityFilter::In(vec![1, 2, 3]);
+        assert!(filter.compare(&2));
+        assert!(!filter.compare(&4));
+
+        let filter = EqualityFilter::NotIn(vec![1, 2, 3]);
+        assert!(!filter.compare(&2));
+        assert!(filter.compare(&4));
+    }
+
     #[test]
     fn test_comparison_filter() {
         let filter = ComparisonFilter::Gt(1);
@@ -167,6 +239,26 @@ mod tests {
         assert!(!filter.compare(&0));
     }
 
+    #[test]
+    fn test_between_filter_includes_bounds() {
+        let filter = ComparisonFilter::Between(10, 20);
+        assert!(!filter.compare(&9));
+        assert!(filter.compare(&10));
+        assert!(filter.compare(&15));
+        assert!(filter.compare(&20));
+        assert!(!filter.compare(&21));
+    }
+
+    #[test]
+    fn test_expand_exponent() {
+        assert_eq!(expand_exponent("1e17"), "100000000000000000");
+        assert_eq!(expand_exponent("25E2"), "2500");
+        assert_eq!(expand_exponent("3e0"), "3");
+        assert_eq!(expand_exponent("100"), "100");
+        assert_eq!(expand_exponent("0xe1"), "0xe1");
+        assert_eq!(expand_exponent("1e100"), "1e100");
+    }
+
     #[test]
     fn test_filter_type_equality() {
         let filter = FilterType::Equality(EqualityFilter::Eq(1));
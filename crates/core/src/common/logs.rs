@@ -1,11 +1,13 @@
 use super::{
+    aggregate::selected_field,
     block::BlockRange,
-    entity_id::{parse_block_number_or_tag, EntityIdError},
+    entity_id::{self, EntityIdError},
 };
 use crate::interpreter::frontend::parser::{ParserError, Rule};
 use alloy::{
     eips::BlockNumberOrTag,
     hex::FromHexError,
+    json_abi::Event,
     primitives::{Address, AddressError, B256},
     rpc::types::Filter,
 };
@@ -46,12 +48,22 @@ impl Logs {
     pub fn build_bloom_filter(&self) -> Filter {
         LogFilter::build_filter(&self.filter)
     }
+
+    /// Event of the `event_signature` filter, which the data of the logs is decoded with
+    pub fn event(&self) -> Option<Event> {
+        self.filter.iter().find_map(|filter| match filter {
+            LogFilter::EventSignature(signature) => Event::parse(signature).ok(),
+            _ => None,
+        })
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum LogsError {
     #[error("Invalid log filter {0}")]
     InvalidLogFilter(String),
+    #[error("Invalid event signature {0}: {1}")]
+    InvalidEventSignature(String, String),
     #[error(transparent)]
     FromHexError(#[from] FromHexError),
     #[error(transparent)]
@@ -96,6 +108,7 @@ impl TryFrom<Pairs<'_, Rule>> for Logs {
                     }
 
                     fields = inner_pairs
+                        .filter_map(selected_field)
                         .map(|pair| LogField::try_from(pair.as_str()))
                         .collect::<Result<Vec<LogField>, LogFieldError>>()?;
                 }
@@ -147,9 +160,11 @@ impl TryFrom<Pair<'_, Rule>> for LogFilter {
             Rule::blockhash_filter_type => {
                 extract_value(pair, |s| Ok(LogFilter::BlockHash(s.parse::<B256>()?)))
             }
-            Rule::event_signature_filter_type => {
-                extract_value(pair, |s| Ok(LogFilter::EventSignature(s.to_string())))
-            }
+            Rule::event_signature_filter_type => extract_value(pair, |s| {
+                Event::parse(s)
+                    .map_err(|e| LogsError::InvalidEventSignature(s.to_string(), e.to_string()))?;
+                Ok(LogFilter::EventSignature(s.to_string()))
+            }),
             Rule::topic0_filter_type => {
                 extract_value(pair, |s| Ok(LogFilter::Topic0(s.parse::<B256>()?)))
             }
@@ -169,20 +184,17 @@ impl TryFrom<Pair<'_, Rule>> for LogFilter {
 
 // Helper function to handle block range parsing
 fn parse_block_range(pair: Pair<'_, Rule>) -> Result<LogFilter, LogsError> {
+    // The equality operator is optional, so both `block = 1:2` and `block 1:2` are valid
     let range = pair
         .as_str()
         .strip_prefix("block")
-        .and_then(|s| s.find('=').map(|i| s[i + 1..].trim()))
+        .map(|s| {
+            s.trim_start_matches(|c: char| c.is_whitespace() || c == '=')
+                .trim()
+        })
         .ok_or_else(|| LogsError::InvalidLogFilter("Invalid block range format".to_string()))?;
 
-    let (start, end) = match range.split_once(':') {
-        Some((start, end)) => (
-            parse_block_number_or_tag(start)?,
-            Some(parse_block_number_or_tag(end)?),
-        ),
-        None => (parse_block_number_or_tag(range)?, None),
-    };
-    Ok(LogFilter::BlockRange(BlockRange::new(start, end)))
+    Ok(LogFilter::BlockRange(entity_id::parse_block_range(range)?))
 }
 
 impl LogFilter {
@@ -206,7 +218,12 @@ impl LogFilter {
             }
             LogFilter::BlockHash(hash) => filter.at_block_hash(*hash),
             LogFilter::EmitterAddress(address) => filter.address(*address),
-            LogFilter::EventSignature(signature) => filter.event(signature),
+            // The topic is the hash of the canonical signature, without the names and `indexed`
+            // markers of the parameters
+            LogFilter::EventSignature(signature) => match Event::parse(signature) {
+                Ok(event) => filter.event_signature(event.selector()),
+                Err(_) => filter.event(signature),
+            },
             LogFilter::Topic0(topic_hash) => filter.event_signature(*topic_hash),
             LogFilter::Topic1(topic_hash) => filter.topic1(*topic_hash),
             LogFilter::Topic2(topic_hash) => filter.topic2(*topic_hash),
@@ -238,6 +255,8 @@ pub enum LogField {
     TransactionIndex,
     LogIndex,
     Removed,
+    /// Parameters of the event, decoded with the `event_signature` filter
+    Decoded,
     Chain,
 }
 
@@ -257,6 +276,7 @@ impl std::fmt::Display for LogField {
             LogField::TransactionIndex => write!(f, "transaction_index"),
             LogField::LogIndex => write!(f, "log_index"),
             LogField::Removed => write!(f, "removed"),
+            LogField::Decoded => write!(f, "decoded"),
             LogField::Chain => write!(f, "chain"),
         }
     }
@@ -286,6 +306,7 @@ impl TryFrom<&str> for LogField {
             "transaction_index" => Ok(LogField::TransactionIndex),
             "log_index" => Ok(LogField::LogIndex),
             "removed" => Ok(LogField::Removed),
+            "decoded" => Ok(LogField::Decoded),
             "chain" => Ok(LogField::Chain),
             invalid_field => Err(LogFieldError::InvalidLogField(invalid_field.to_string())),
         }
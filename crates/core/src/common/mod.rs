@@ -1,6 +1,8 @@
 pub mod account;
+pub mod aggregate;
 pub mod block;
 pub mod chain;
+pub mod chain_status;
 pub mod config;
 pub mod dump;
 pub mod ens;
@@ -8,7 +10,10 @@ pub mod entity;
 pub mod entity_id;
 pub mod filters;
 pub mod logs;
+pub mod order;
 pub mod query_result;
+pub mod receipt;
 pub mod serializer;
+pub mod token;
 pub mod transaction;
 pub mod types;
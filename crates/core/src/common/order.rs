@@ -0,0 +1,161 @@
+use super::serializer::result_cell;
+use crate::interpreter::frontend::parser::Rule;
+use alloy::primitives::U256;
+use pest::iterators::Pairs;
+use serde::Serialize;
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
+
+#[derive(thiserror::Error, Debug)]
+pub enum OrderByError {
+    #[error("Unexpected token {0}")]
+    UnexpectedToken(String),
+
+    #[error("Missing field to order by")]
+    MissingField,
+
+    #[error("Results can't be ordered by {0}")]
+    InvalidField(String),
+
+    #[error("Unable to order by {0}: {1}")]
+    Serialization(String, String),
+}
+
+/// Ordering of the results of a query, e.g. `ORDER BY value DESC`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OrderBy {
+    pub field: String,
+    pub direction: OrderDirection,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum OrderDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl Display for OrderDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderDirection::Asc => write!(f, "ASC"),
+            OrderDirection::Desc => write!(f, "DESC"),
+        }
+    }
+}
+
+impl OrderBy {
+    pub fn new(field: &str, direction: OrderDirection) -> Self {
+        Self {
+            field: field.to_string(),
+            direction,
+        }
+    }
+
+    /// Sorts the results by the value of the field. Numbers, including the ones serialized as
+    /// strings like `value`, are compared numerically and other values as text. Results without
+    /// a value for the field go last, whatever the direction.
+    pub(crate) fn sort<T: Serialize>(&self, results: &mut Vec<T>) -> Result<(), OrderByError> {
+        let mut keyed = results
+            .drain(..)
+            .map(|result| {
+                let cell = result_cell(&result, &self.field)
+                    .map_err(|e| OrderByError::Serialization(self.field.clone(), e.to_string()))?
+                    .ok_or_else(|| OrderByError::InvalidField(self.field.clone()))?;
+                Ok((cell, result))
+            })
+            .collect::<Result<Vec<_>, OrderByError>>()?;
+
+        keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+            (Some(a), Some(b)) => match self.direction {
+                OrderDirection::Asc => compare_cells(a, b),
+                OrderDirection::Desc => compare_cells(b, a),
+            },
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+        results.extend(keyed.into_iter().map(|(_, result)| result));
+
+        Ok(())
+    }
+}
+
+fn compare_cells(a: &str, b: &str) -> Ordering {
+    match (U256::from_str(a), U256::from_str(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+impl TryFrom<Pairs<'_, Rule>> for OrderBy {
+    type Error = OrderByError;
+
+    fn try_from(pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
+        let mut field = None;
+        let mut direction = OrderDirection::default();
+
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::order_field => field = Some(pair.as_str()),
+                Rule::order_direction => {
+                    direction = match pair.as_str() {
+                        "DESC" => OrderDirection::Desc,
+                        _ => OrderDirection::Asc,
+                    }
+                }
+                _ => return Err(OrderByError::UnexpectedToken(pair.as_str().to_string())),
+            }
+        }
+
+        Ok(OrderBy::new(
+            field.ok_or(OrderByError::MissingField)?,
+            direction,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::query_result::TransactionQueryRes;
+
+    fn transaction(value: Option<u64>) -> TransactionQueryRes {
+        TransactionQueryRes {
+            value: value.map(U256::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sort_numbers_numerically() {
+        let mut results = vec![
+            transaction(Some(9)),
+            transaction(None),
+            transaction(Some(100)),
+            transaction(Some(10)),
+        ];
+
+        OrderBy::new("value", OrderDirection::Desc)
+            .sort(&mut results)
+            .unwrap();
+
+        assert_eq!(
+            results,
+            vec![
+                transaction(Some(100)),
+                transaction(Some(10)),
+                transaction(Some(9)),
+                transaction(None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_unknown_field() {
+        let mut results = vec![transaction(Some(1))];
+
+        let result = OrderBy::new("balance", OrderDirection::Asc).sort(&mut results);
+
+        assert!(matches!(result, Err(OrderByError::InvalidField(_))));
+    }
+}
@@ -1,15 +1,174 @@
-use crate::common::chain::Chain;
-use alloy::primitives::{Address, Bloom, Bytes, FixedBytes, B256, U256};
+use crate::common::{
+    chain::Chain,
+    order::{OrderBy, OrderByError},
+};
+use alloy::{
+    eips::{eip2930::AccessList, eip7702::SignedAuthorization},
+    primitives::{Address, Bloom, Bytes, FixedBytes, B256, I256, U256},
+    rpc::types::{Block as RpcBlock, Log, Transaction as RpcTransaction, Withdrawal},
+};
+use chrono::{DateTime, SecondsFormat};
 use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    future::Future,
+    hash::Hash,
+};
+
+/// Case of the addresses in the serialized results. Addresses are EIP-55 checksummed by default,
+/// so a mistyped character of a copied address is caught by wallets. Lowercase addresses are
+/// easier to compare and join on for tools that treat them as plain strings.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum AddressFormat {
+    #[default]
+    Checksummed,
+    Lowercase,
+}
+
+tokio::task_local! {
+    static ADDRESS_FORMAT: AddressFormat;
+}
+
+impl AddressFormat {
+    /// Runs the future with the results it serializes, including the dumped ones, using this
+    /// format for addresses.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        ADDRESS_FORMAT.scope(self, future).await
+    }
+
+    /// Same as `scope`, for code serializing results outside of a future.
+    pub fn sync_scope<R>(self, f: impl FnOnce() -> R) -> R {
+        ADDRESS_FORMAT.sync_scope(self, f)
+    }
+
+    fn current() -> Self {
+        ADDRESS_FORMAT
+            .try_with(|format| *format)
+            .unwrap_or_default()
+    }
+
+    fn format(self, address: &Address) -> String {
+        match self {
+            AddressFormat::Checksummed => address.to_checksum(None),
+            AddressFormat::Lowercase => format!("{:#x}", address),
+        }
+    }
+}
+
+/// Representation of the timestamps in the serialized results. Timestamps are Unix epochs in
+/// seconds, and can also be written as RFC 3339 datetimes in UTC for reports, in a `_utc` column
+/// next to the epoch.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum TimestampFormat {
+    #[default]
+    Unix,
+    Rfc3339,
+}
+
+tokio::task_local! {
+    static TIMESTAMP_FORMAT: TimestampFormat;
+}
+
+impl TimestampFormat {
+    /// Runs the future with the results it serializes, including the dumped ones, using this
+    /// format for timestamps.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        TIMESTAMP_FORMAT.scope(self, future).await
+    }
+
+    /// Same as `scope`, for code serializing results outside of a future.
+    pub fn sync_scope<R>(self, f: impl FnOnce() -> R) -> R {
+        TIMESTAMP_FORMAT.sync_scope(self, f)
+    }
+
+    fn current() -> Self {
+        TIMESTAMP_FORMAT
+            .try_with(|format| *format)
+            .unwrap_or_default()
+    }
+
+    /// The datetime of the timestamp, e.g. `2015-07-30T15:26:28Z`, or `None` when the timestamp
+    /// is written as an epoch or is too far in the future to be a date.
+    fn datetime(self, timestamp: u64) -> Option<String> {
+        match self {
+            TimestampFormat::Unix => None,
+            TimestampFormat::Rfc3339 => {
+                let datetime = DateTime::from_timestamp(i64::try_from(timestamp).ok()?, 0)?;
+                Some(datetime.to_rfc3339_opts(SecondsFormat::Secs, true))
+            }
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct QueryResult {
     pub result: ExpressionResult,
+    /// Errors of the items left out of the result, when the query returns partial results
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    /// Keys of the fields in the order they were selected, which the serialized results follow
+    #[serde(skip)]
+    fields: Vec<String>,
+    /// Blocks and transactions the results were projected from, as returned by the RPCs, when
+    /// the engine returns them
+    #[serde(skip)]
+    pub raw: Option<RawItems>,
+}
+
+/// Blocks and transactions of a query, as returned by the RPCs, with all their fields. They're the
+/// items that passed the filters of the query, in the order they were fetched, before `ORDER BY`
+/// and `LIMIT` are applied to the results.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct RawItems {
+    pub blocks: Vec<RpcBlock>,
+    pub transactions: Vec<RpcTransaction>,
 }
 
 impl QueryResult {
     pub fn new(result: ExpressionResult) -> QueryResult {
-        QueryResult { result }
+        QueryResult {
+            result,
+            errors: vec![],
+            fields: vec![],
+            raw: None,
+        }
+    }
+
+    pub fn with_errors(mut self, errors: Vec<String>) -> QueryResult {
+        self.errors = errors;
+        self
+    }
+
+    pub fn with_raw(mut self, raw: RawItems) -> QueryResult {
+        self.raw = Some(raw);
+        self
+    }
+
+    /// Sets the order of the fields in the serialized results. Fields not listed are written
+    /// after the listed ones, in the order they're declared in.
+    pub fn with_fields(mut self, fields: Vec<String>) -> QueryResult {
+        self.fields = fields;
+        self
+    }
+
+    /// Serializes the results as a JSON array, which is empty when there are no results. Keys are
+    /// the snake_case field names, in the order the fields were selected, and addresses, hashes
+    /// and bytes are written as hex strings.
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        super::serializer::to_json(&self.result, &self.fields)
+    }
+
+    /// Serializes the results as newline-delimited JSON, with one result per line.
+    pub fn to_jsonl(&self) -> Result<String, Box<dyn std::error::Error>> {
+        super::serializer::to_jsonl(&self.result, &self.fields)
+    }
+
+    /// Serializes the results as CSV. The header row lists the fields set in at least one result,
+    /// in the order they were selected, byte fields are hex-encoded and missing values are left
+    /// empty.
+    pub fn to_csv(&self) -> Result<String, Box<dyn std::error::Error>> {
+        super::serializer::to_csv(&self.result, &self.fields)
     }
 }
 
@@ -23,6 +182,101 @@ pub enum ExpressionResult {
     Transaction(Vec<TransactionQueryRes>),
     #[serde(rename = "log")]
     Log(Vec<LogQueryRes>),
+    #[serde(rename = "token")]
+    Token(Vec<TokenQueryRes>),
+    #[serde(rename = "receipt")]
+    Receipt(Vec<ReceiptQueryRes>),
+    #[serde(rename = "chain")]
+    Chain(Vec<ChainQueryRes>),
+    #[serde(rename = "aggregate")]
+    Aggregate(Vec<AggregateQueryRes>),
+    /// Results of each entity of a combined query, in the order the entities are selected
+    #[serde(rename = "combined")]
+    Combined(Vec<ExpressionResult>),
+}
+
+impl ExpressionResult {
+    pub(crate) fn sort(&mut self, order_by: &OrderBy) -> Result<(), OrderByError> {
+        match self {
+            ExpressionResult::Account(accounts) => order_by.sort(accounts),
+            ExpressionResult::Block(blocks) => order_by.sort(blocks),
+            ExpressionResult::Transaction(txs) => order_by.sort(txs),
+            ExpressionResult::Log(logs) => order_by.sort(logs),
+            ExpressionResult::Token(tokens) => order_by.sort(tokens),
+            ExpressionResult::Receipt(receipts) => order_by.sort(receipts),
+            ExpressionResult::Chain(chains) => order_by.sort(chains),
+            ExpressionResult::Aggregate(aggregates) => order_by.sort(aggregates),
+            ExpressionResult::Combined(results) => results
+                .iter_mut()
+                .try_for_each(|result| result.sort(order_by)),
+        }
+    }
+
+    /// Removes the transactions with the same chain and hash as an earlier one, and the accounts
+    /// with the same chain and address. The results are resolved with their chain and hash or
+    /// address, see `Entity::with_dedup_keys`, which are hidden afterwards when `keys` says they
+    /// weren't selected. The results of other entities are kept.
+    pub(crate) fn dedup(&mut self, keys: DedupKeys) {
+        match self {
+            ExpressionResult::Transaction(txs) => {
+                dedup_by_key(txs, |tx| tx.hash.map(|hash| (tx.chain.clone(), hash)));
+                for tx in txs {
+                    if keys.hide_chain {
+                        tx.chain = None;
+                    }
+                    if keys.hide_id {
+                        tx.hash = None;
+                    }
+                }
+            }
+            ExpressionResult::Account(accounts) => {
+                dedup_by_key(accounts, |account| {
+                    account
+                        .address
+                        .map(|address| (account.chain.clone(), address))
+                });
+                for account in accounts {
+                    if keys.hide_chain {
+                        account.chain = None;
+                    }
+                    if keys.hide_id {
+                        account.address = None;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        match self {
+            ExpressionResult::Account(accounts) => accounts.truncate(len),
+            ExpressionResult::Block(blocks) => blocks.truncate(len),
+            ExpressionResult::Transaction(txs) => txs.truncate(len),
+            ExpressionResult::Log(logs) => logs.truncate(len),
+            ExpressionResult::Token(tokens) => tokens.truncate(len),
+            ExpressionResult::Receipt(receipts) => receipts.truncate(len),
+            ExpressionResult::Chain(chains) => chains.truncate(len),
+            ExpressionResult::Aggregate(aggregates) => aggregates.truncate(len),
+            ExpressionResult::Combined(results) => {
+                results.iter_mut().for_each(|result| result.truncate(len))
+            }
+        }
+    }
+}
+
+/// Fields the results are deduplicated on that the query didn't select, so they're hidden once the
+/// results are deduplicated. The id is the hash of transactions and the address of accounts.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct DedupKeys {
+    pub(crate) hide_chain: bool,
+    pub(crate) hide_id: bool,
+}
+
+/// Keeps the first of the results with the same key. Results without a key are all kept.
+fn dedup_by_key<T, K: Eq + Hash>(results: &mut Vec<T>, key: impl Fn(&T) -> Option<K>) {
+    let mut seen = HashSet::new();
+    results.retain(|result| key(result).map_or(true, |key| seen.insert(key)));
 }
 
 // TODO: should this be replaced with Alloy's Block?
@@ -32,6 +286,13 @@ pub struct BlockQueryRes {
     pub chain: Option<Chain>,
     pub number: Option<u64>,
     pub timestamp: Option<u64>,
+    /// Epoch of `timestamp`, written as a datetime next to it within `TimestampFormat::Rfc3339`
+    #[serde(
+        skip_deserializing,
+        skip_serializing_if = "skip_datetime",
+        serialize_with = "serialize_option_datetime"
+    )]
+    pub timestamp_utc: Option<u64>,
     pub hash: Option<B256>,
     #[serde(serialize_with = "serialize_option_u256")]
     pub size: Option<U256>,
@@ -44,10 +305,18 @@ pub struct BlockQueryRes {
     pub mix_hash: Option<B256>,
     pub total_difficulty: Option<U256>,
     pub base_fee_per_gas: Option<u128>,
+    /// Base fee of the next block following EIP-1559, only set after London
+    pub next_base_fee: Option<u128>,
     pub withdrawals_root: Option<B256>,
+    /// Validator withdrawals, only included in blocks produced after Shanghai
+    #[serde(serialize_with = "serialize_option_json")]
+    pub withdrawals: Option<Vec<Withdrawal>>,
     pub blob_gas_used: Option<u128>,
     pub excess_blob_gas: Option<u128>,
     pub parent_beacon_block_root: Option<B256>,
+    /// Hashes of the uncles (ommers) included in the block, always empty after the merge
+    pub uncles: Option<Vec<B256>>,
+    pub transaction_count: Option<u64>,
 }
 
 impl Default for BlockQueryRes {
@@ -56,6 +325,7 @@ impl Default for BlockQueryRes {
             chain: None,
             number: None,
             timestamp: None,
+            timestamp_utc: None,
             hash: None,
             size: None,
             parent_hash: None,
@@ -67,14 +337,25 @@ impl Default for BlockQueryRes {
             mix_hash: None,
             total_difficulty: None,
             base_fee_per_gas: None,
+            next_base_fee: None,
             withdrawals_root: None,
+            withdrawals: None,
             blob_gas_used: None,
             excess_blob_gas: None,
             parent_beacon_block_root: None,
+            uncles: None,
+            transaction_count: None,
         }
     }
 }
 
+impl BlockQueryRes {
+    /// Base fee per gas in gwei
+    pub fn base_fee_per_gas_gwei(&self) -> Option<f64> {
+        self.base_fee_per_gas.map(wei_to_gwei)
+    }
+}
+
 #[serde_with::skip_serializing_none]
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct AccountQueryRes {
@@ -82,8 +363,29 @@ pub struct AccountQueryRes {
     pub nonce: Option<u64>,
     #[serde(serialize_with = "serialize_option_u256")]
     pub balance: Option<U256>,
+    #[serde(serialize_with = "serialize_option_address")]
     pub address: Option<Address>,
     pub code: Option<Bytes>,
+    pub is_contract: Option<bool>,
+    pub ens_name: Option<String>,
+    #[serde(serialize_with = "serialize_option_token_balances")]
+    pub token_balances: Option<BTreeMap<Address, U256>>,
+    /// Token balances divided by the decimals of their token, only set for the tokens whose
+    /// decimals were read
+    #[serde(serialize_with = "serialize_option_token_balances")]
+    pub token_balances_formatted: Option<BTreeMap<Address, String>>,
+    #[serde(serialize_with = "serialize_option_storage")]
+    pub storage: Option<BTreeMap<U256, B256>>,
+    pub first_active_block: Option<u64>,
+    /// Function selectors found in the code of the account, empty for accounts without code
+    #[serde(serialize_with = "serialize_option_json")]
+    pub selectors: Option<Vec<FixedBytes<4>>>,
+    /// Implementation of the proxy, empty for accounts that aren't proxies
+    #[serde(serialize_with = "serialize_option_address")]
+    pub implementation: Option<Address>,
+    /// Decoded results of the `call` fields, keyed by the function and its arguments
+    #[serde(serialize_with = "serialize_option_json")]
+    pub calls: Option<BTreeMap<String, Value>>,
 }
 
 impl Default for AccountQueryRes {
@@ -94,8 +396,198 @@ impl Default for AccountQueryRes {
             balance: None,
             address: None,
             code: None,
+            is_contract: None,
+            ens_name: None,
+            token_balances: None,
+            token_balances_formatted: None,
+            storage: None,
+            first_active_block: None,
+            selectors: None,
+            implementation: None,
+            calls: None,
+        }
+    }
+}
+
+/// Changes of an account between two snapshots of the same query. Only the fields that changed
+/// are set.
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct AccountDiff {
+    pub chain: Option<Chain>,
+    #[serde(serialize_with = "serialize_option_address")]
+    pub address: Option<Address>,
+    pub nonce: Option<Delta>,
+    pub balance: Option<Delta>,
+    pub code: Option<Change<Bytes>>,
+    pub is_contract: Option<Change<bool>>,
+    pub ens_name: Option<Change<String>>,
+    #[serde(serialize_with = "serialize_option_json")]
+    pub token_balances: Option<BTreeMap<Address, Delta>>,
+    #[serde(serialize_with = "serialize_option_json")]
+    pub storage: Option<BTreeMap<U256, Change<B256>>>,
+}
+
+/// Values of a field before and after it changed. A value is missing when the account, or the
+/// field, isn't in one of the snapshots.
+#[serde_with::skip_serializing_none]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Change<T> {
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+
+/// Change of a numeric field, with values in decimal like in the results
+#[serde_with::skip_serializing_none]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Delta {
+    #[serde(serialize_with = "serialize_option_u256")]
+    pub before: Option<U256>,
+    #[serde(serialize_with = "serialize_option_u256")]
+    pub after: Option<U256>,
+    /// `after - before`, when both values are known
+    pub delta: Option<I256>,
+}
+
+/// Compares two snapshots of accounts, e.g. the results of the same query run at different
+/// times. Accounts are matched by chain and address, and those without an address are left out.
+/// Accounts that didn't change are left out too, and the others are listed in the order they
+/// appear in, with the accounts only in `after` last.
+pub fn diff(before: &[AccountQueryRes], after: &[AccountQueryRes]) -> Vec<AccountDiff> {
+    let mut keys: Vec<(Option<Chain>, Address)> = vec![];
+
+    for account in before.iter().chain(after) {
+        if let Some(address) = account.address {
+            let key = (account.chain.clone(), address);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
         }
     }
+
+    keys.into_iter()
+        .filter_map(|(chain, address)| {
+            let find = |accounts: &'_ [AccountQueryRes]| {
+                accounts
+                    .iter()
+                    .find(|account| account.chain == chain && account.address == Some(address))
+                    .cloned()
+                    .unwrap_or_default()
+            };
+            let (before, after) = (find(before), find(after));
+
+            let diff = AccountDiff {
+                chain: chain.clone(),
+                address: Some(address),
+                nonce: delta(before.nonce.map(U256::from), after.nonce.map(U256::from)),
+                balance: delta(before.balance, after.balance),
+                code: change(before.code, after.code),
+                is_contract: change(before.is_contract, after.is_contract),
+                ens_name: change(before.ens_name, after.ens_name),
+                token_balances: map_changes(before.token_balances, after.token_balances, delta),
+                storage: map_changes(before.storage, after.storage, change),
+            };
+
+            let unchanged = AccountDiff {
+                chain,
+                address: Some(address),
+                ..Default::default()
+            };
+            (diff != unchanged).then_some(diff)
+        })
+        .collect()
+}
+
+fn change<T: PartialEq>(before: Option<T>, after: Option<T>) -> Option<Change<T>> {
+    (before != after).then_some(Change { before, after })
+}
+
+fn delta(before: Option<U256>, after: Option<U256>) -> Option<Delta> {
+    (before != after).then(|| Delta {
+        before,
+        after,
+        // Exact as long as the difference fits in 255 bits
+        delta: before
+            .zip(after)
+            .map(|(before, after)| I256::from_raw(after.wrapping_sub(before))),
+    })
+}
+
+/// Changes of the entries of a map field, keeping only the entries that changed
+fn map_changes<K: Ord + Clone, V, C>(
+    before: Option<BTreeMap<K, V>>,
+    after: Option<BTreeMap<K, V>>,
+    change: impl Fn(Option<V>, Option<V>) -> Option<C>,
+) -> Option<BTreeMap<K, C>> {
+    if before.is_none() && after.is_none() {
+        return None;
+    }
+
+    let mut before = before.unwrap_or_default();
+    let mut after = after.unwrap_or_default();
+    let keys: BTreeSet<K> = before.keys().chain(after.keys()).cloned().collect();
+
+    let changes: BTreeMap<K, C> = keys
+        .into_iter()
+        .filter_map(|key| {
+            let change = change(before.remove(&key), after.remove(&key))?;
+            Some((key, change))
+        })
+        .collect();
+
+    (!changes.is_empty()).then_some(changes)
+}
+
+/// Value of an aggregate computed over the results of a query, e.g. `sum(value)`
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct AggregateQueryRes {
+    pub aggregate: String,
+    #[serde(serialize_with = "serialize_option_u256")]
+    pub value: Option<U256>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct TokenQueryRes {
+    pub chain: Option<Chain>,
+    #[serde(serialize_with = "serialize_option_address")]
+    pub address: Option<Address>,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+    #[serde(serialize_with = "serialize_option_u256")]
+    pub total_supply: Option<U256>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ReceiptQueryRes {
+    pub chain: Option<Chain>,
+    pub transaction_hash: Option<B256>,
+    #[serde(rename = "type")]
+    pub receipt_type: Option<u8>,
+    pub status: Option<bool>,
+    pub gas_used: Option<u128>,
+    pub effective_gas_price: Option<u128>,
+    pub cumulative_gas_used: Option<u128>,
+    pub logs_bloom: Option<Bloom>,
+    #[serde(serialize_with = "serialize_option_address")]
+    pub contract_address: Option<Address>,
+    #[serde(serialize_with = "serialize_option_json")]
+    pub logs: Option<Vec<Log>>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ChainQueryRes {
+    pub chain: Option<Chain>,
+    pub chain_id: Option<u64>,
+    /// Number of the latest block
+    pub block_number: Option<u64>,
+    pub gas_price: Option<u128>,
+    /// Base fee of the latest block, only set on chains with EIP-1559
+    pub base_fee_per_gas: Option<u128>,
 }
 
 #[serde_with::skip_serializing_none]
@@ -103,10 +595,18 @@ impl Default for AccountQueryRes {
 pub struct TransactionQueryRes {
     pub chain: Option<Chain>,
     pub transaction_type: Option<u8>,
+    pub tx_type_label: Option<String>,
     pub hash: Option<FixedBytes<32>>,
+    pub transaction_index: Option<u64>,
+    pub block_number: Option<u64>,
+    pub block_hash: Option<FixedBytes<32>>,
+    #[serde(serialize_with = "serialize_option_address")]
     pub from: Option<Address>,
+    #[serde(serialize_with = "serialize_option_address")]
     pub to: Option<Address>,
+    pub nonce: Option<u64>,
     pub data: Option<Bytes>,
+    pub method_id: Option<FixedBytes<4>>,
     #[serde(serialize_with = "serialize_option_u256")]
     pub value: Option<U256>,
     pub gas_price: Option<u128>,
@@ -117,9 +617,36 @@ pub struct TransactionQueryRes {
     pub r: Option<U256>,
     pub s: Option<U256>,
     pub max_fee_per_blob_gas: Option<u128>,
+    pub blob_versioned_hashes: Option<Vec<FixedBytes<32>>>,
+    pub blob_count: Option<usize>,
     pub max_fee_per_gas: Option<u128>,
     pub max_priority_fee_per_gas: Option<u128>,
+    pub effective_tip: Option<u128>,
+    pub gas_price_percentile: Option<u8>,
+    pub confirmations: Option<u64>,
+    #[serde(serialize_with = "serialize_option_json")]
+    pub access_list: Option<AccessList>,
     pub y_parity: Option<bool>,
+    #[serde(serialize_with = "serialize_option_json")]
+    pub authorization_list: Option<Vec<SignedAuthorization>>,
+    pub raw: Option<Bytes>,
+    pub signer_valid: Option<bool>,
+    pub gas_used: Option<u128>,
+    pub effective_gas_price: Option<u128>,
+    pub cumulative_gas_used: Option<u128>,
+    pub logs_bloom: Option<Bloom>,
+    #[serde(serialize_with = "serialize_option_address")]
+    pub contract_address: Option<Address>,
+    #[serde(serialize_with = "serialize_option_json")]
+    pub logs: Option<Vec<Log>>,
+    #[serde(serialize_with = "serialize_option_u256")]
+    pub l1_fee: Option<U256>,
+    pub l1_gas_used: Option<u128>,
+    pub l1_gas_price: Option<u128>,
+    pub l1_fee_scalar: Option<String>,
+    #[serde(serialize_with = "serialize_option_json")]
+    pub trace: Option<CallFrame>,
+    pub revert_reason: Option<String>,
 }
 
 impl Default for TransactionQueryRes {
@@ -127,10 +654,16 @@ impl Default for TransactionQueryRes {
         Self {
             chain: None,
             transaction_type: None,
+            tx_type_label: None,
             hash: None,
+            transaction_index: None,
+            block_number: None,
+            block_hash: None,
             from: None,
             to: None,
+            nonce: None,
             data: None,
+            method_id: None,
             value: None,
             gas_price: None,
             gas: None,
@@ -140,17 +673,88 @@ impl Default for TransactionQueryRes {
             r: None,
             s: None,
             max_fee_per_blob_gas: None,
+            blob_versioned_hashes: None,
+            blob_count: None,
             max_fee_per_gas: None,
             max_priority_fee_per_gas: None,
+            effective_tip: None,
+            gas_price_percentile: None,
+            confirmations: None,
+            access_list: None,
             y_parity: None,
+            authorization_list: None,
+            raw: None,
+            signer_valid: None,
+            gas_used: None,
+            effective_gas_price: None,
+            cumulative_gas_used: None,
+            logs_bloom: None,
+            contract_address: None,
+            logs: None,
+            l1_fee: None,
+            l1_gas_used: None,
+            l1_gas_price: None,
+            l1_fee_scalar: None,
+            trace: None,
+            revert_reason: None,
         }
     }
 }
 
+/// Helpers formatting the wei amounts of a transaction in more readable units. The raw values are
+/// left untouched.
+impl TransactionQueryRes {
+    pub fn gas_price_gwei(&self) -> Option<f64> {
+        self.gas_price.map(wei_to_gwei)
+    }
+
+    pub fn max_fee_per_gas_gwei(&self) -> Option<f64> {
+        self.max_fee_per_gas.map(wei_to_gwei)
+    }
+
+    pub fn max_priority_fee_per_gas_gwei(&self) -> Option<f64> {
+        self.max_priority_fee_per_gas.map(wei_to_gwei)
+    }
+
+    pub fn effective_gas_price_gwei(&self) -> Option<f64> {
+        self.effective_gas_price.map(wei_to_gwei)
+    }
+
+    pub fn effective_tip_gwei(&self) -> Option<f64> {
+        self.effective_tip.map(wei_to_gwei)
+    }
+
+    pub fn value_ether(&self) -> Option<f64> {
+        self.value.map(wei_to_ether)
+    }
+}
+
+/// Call made while executing a transaction, along with the calls it made in turn, as returned by
+/// the `callTracer` of `debug_traceTransaction`.
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    /// CALL, STATICCALL, DELEGATECALL, CREATE, SELFDESTRUCT, ...
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value: Option<U256>,
+    pub gas: U256,
+    pub gas_used: U256,
+    pub input: Bytes,
+    pub output: Option<Bytes>,
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+}
+
 #[serde_with::skip_serializing_none]
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct LogQueryRes {
     pub chain: Option<Chain>,
+    #[serde(serialize_with = "serialize_option_address")]
     pub address: Option<Address>,
     pub topic0: Option<FixedBytes<32>>,
     pub topic1: Option<FixedBytes<32>>,
@@ -160,10 +764,21 @@ pub struct LogQueryRes {
     pub block_hash: Option<B256>,
     pub block_number: Option<u64>,
     pub block_timestamp: Option<u64>,
+    /// Epoch of `block_timestamp`, written as a datetime next to it within
+    /// `TimestampFormat::Rfc3339`
+    #[serde(
+        skip_deserializing,
+        skip_serializing_if = "skip_datetime",
+        serialize_with = "serialize_option_datetime"
+    )]
+    pub block_timestamp_utc: Option<u64>,
     pub transaction_hash: Option<B256>,
     pub transaction_index: Option<u64>,
     pub log_index: Option<u64>,
     pub removed: Option<bool>,
+    /// Parameters of the event by name, with numbers in decimal and bytes in hex
+    #[serde(serialize_with = "serialize_option_json")]
+    pub decoded: Option<Map<String, Value>>,
 }
 
 impl Default for LogQueryRes {
@@ -179,23 +794,95 @@ impl Default for LogQueryRes {
             block_hash: None,
             block_number: None,
             block_timestamp: None,
+            block_timestamp_utc: None,
             transaction_hash: None,
             transaction_index: None,
             log_index: None,
             removed: None,
+            decoded: None,
         }
     }
 }
 
+const WEI_PER_GWEI: f64 = 1e9;
+const WEI_PER_ETHER: f64 = 1e18;
+
+fn wei_to_gwei(wei: u128) -> f64 {
+    wei as f64 / WEI_PER_GWEI
+}
+
+fn wei_to_ether(wei: U256) -> f64 {
+    f64::from(wei) / WEI_PER_ETHER
+}
+
+/// Writes a raw token amount in units of the token, e.g. `1.5` for 1500000 with 6 decimals.
+/// The amount is split on its digits rather than divided, so it stays exact for any number of
+/// decimals. Trailing zeros are dropped, keeping a single one after the point, and tokens
+/// without decimals are written as integers.
+pub fn format_units(amount: U256, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+
+    if decimals == 0 {
+        return digits;
+    }
+
+    let digits = format!("{digits:0>width$}", width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+
+    match fraction.is_empty() {
+        true => format!("{integer}.0"),
+        false => format!("{integer}.{fraction}"),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
 
-    use super::serialize_option_u256;
-    use alloy::primitives::U256;
+    use super::{
+        diff, format_units, serialize_option_u256, AccountDiff, AccountQueryRes, AddressFormat,
+        BlockQueryRes, Change, DedupKeys, Delta, ExpressionResult, LogQueryRes, TimestampFormat,
+        TransactionQueryRes,
+    };
+    use crate::common::chain::Chain;
+    use alloy::{
+        primitives::{address, I256, U256},
+        rpc::types::Log,
+    };
     use serde::Serialize;
     use serde_json::json;
 
+    #[test]
+    fn test_dedup_accounts_by_chain_and_address() {
+        let account = |chain, address| AccountQueryRes {
+            chain,
+            address,
+            ..Default::default()
+        };
+        let usdt = Some(address!("dAC17F958D2ee523a2206206994597C13D831ec7"));
+        let mut result = ExpressionResult::Account(vec![
+            account(Some(Chain::Ethereum), usdt),
+            account(Some(Chain::Polygon), usdt),
+            account(Some(Chain::Ethereum), usdt),
+            account(Some(Chain::Ethereum), None),
+            account(Some(Chain::Ethereum), None),
+        ]);
+
+        result.dedup(DedupKeys::default());
+
+        assert_eq!(
+            result,
+            ExpressionResult::Account(vec![
+                account(Some(Chain::Ethereum), usdt),
+                account(Some(Chain::Polygon), usdt),
+                account(Some(Chain::Ethereum), None),
+                account(Some(Chain::Ethereum), None),
+            ])
+        );
+    }
+
     #[derive(Serialize)]
     struct U256Serializable {
         #[serde(serialize_with = "serialize_option_u256")]
@@ -209,6 +896,236 @@ mod test {
         let u256_str = json!(u256).to_string();
         assert_eq!("{\"value\":\"100\"}", u256_str);
     }
+
+    #[test]
+    fn test_addresses_are_checksummed() {
+        let tx = TransactionQueryRes {
+            from: Some(address!("dac17f958d2ee523a2206206994597c13d831ec7")),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            json!(tx)["from"],
+            "0xdAC17F958D2ee523a2206206994597C13D831ec7"
+        );
+    }
+
+    #[test]
+    fn test_lowercase_addresses() {
+        let usdt = address!("dac17f958d2ee523a2206206994597c13d831ec7");
+        let account = AccountQueryRes {
+            address: Some(usdt),
+            token_balances: Some([(usdt, U256::from(1))].into()),
+            ..Default::default()
+        };
+
+        let account = AddressFormat::Lowercase.sync_scope(|| json!(account));
+
+        assert_eq!(
+            account["address"],
+            "0xdac17f958d2ee523a2206206994597c13d831ec7"
+        );
+        assert_eq!(
+            account["token_balances"],
+            r#"{"0xdac17f958d2ee523a2206206994597c13d831ec7":"1"}"#
+        );
+    }
+
+    #[test]
+    fn test_timestamps_as_utc_datetimes() {
+        // Block 1 of Ethereum mainnet
+        let block = BlockQueryRes {
+            number: Some(1),
+            timestamp: Some(1438269988),
+            timestamp_utc: Some(1438269988),
+            ..Default::default()
+        };
+
+        let datetime = TimestampFormat::Rfc3339.sync_scope(|| json!(block));
+        let epoch = json!(block);
+
+        assert_eq!(datetime["timestamp"], 1438269988);
+        assert_eq!(datetime["timestamp_utc"], "2015-07-30T15:26:28Z");
+        assert_eq!(epoch["timestamp"], 1438269988);
+        assert_eq!(epoch.get("timestamp_utc"), None);
+        // Past the last representable date, so its epoch is written as a string
+        let far_future = LogQueryRes {
+            block_timestamp: Some(u64::MAX),
+            block_timestamp_utc: Some(u64::MAX),
+            ..Default::default()
+        };
+        let datetime = TimestampFormat::Rfc3339.sync_scope(|| json!(far_future));
+        assert_eq!(datetime["block_timestamp"], u64::MAX);
+        assert_eq!(datetime["block_timestamp_utc"], u64::MAX.to_string());
+    }
+
+    #[test]
+    fn test_token_amounts_in_units_of_the_token() {
+        // 1 USDC, which has 6 decimals
+        assert_eq!(format_units(U256::from(1_000_000), 6), "1.0");
+        assert_eq!(format_units(U256::from(1_500_000), 6), "1.5");
+        assert_eq!(format_units(U256::from(1), 18), "0.000000000000000001");
+        assert_eq!(format_units(U256::ZERO, 18), "0.0");
+        // Tokens without decimals are indivisible
+        assert_eq!(format_units(U256::from(1_000_000), 0), "1000000");
+        // More decimals than U256 has digits
+        assert_eq!(
+            format_units(U256::MAX, 255),
+            format!("0.{:0>255}", U256::MAX.to_string())
+        );
+    }
+
+    #[test]
+    fn test_gas_prices_in_gwei() {
+        let tx = TransactionQueryRes {
+            gas_price: Some(5_000_000_000),
+            max_priority_fee_per_gas: Some(1_500_000_000),
+            ..Default::default()
+        };
+        let block = BlockQueryRes {
+            base_fee_per_gas: Some(12_345_000_000),
+            ..Default::default()
+        };
+
+        assert_eq!(tx.gas_price_gwei(), Some(5.0));
+        assert_eq!(tx.max_priority_fee_per_gas_gwei(), Some(1.5));
+        assert_eq!(tx.max_fee_per_gas_gwei(), None);
+        assert_eq!(block.base_fee_per_gas_gwei(), Some(12.345));
+        // The raw value is kept
+        assert_eq!(tx.gas_price, Some(5_000_000_000));
+    }
+
+    #[test]
+    fn test_value_in_ether() {
+        let tx = TransactionQueryRes {
+            value: Some(U256::from(10).pow(U256::from(18))),
+            ..Default::default()
+        };
+
+        assert_eq!(tx.value_ether(), Some(1.0));
+        assert_eq!(TransactionQueryRes::default().value_ether(), None);
+    }
+
+    #[test]
+    fn test_logs_serialization() {
+        let tx = TransactionQueryRes {
+            logs: Some(vec![Log::default()]),
+            ..Default::default()
+        };
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer
+            .serialize(&tx)
+            .expect("Unable to serialize logs to csv");
+
+        let tx_json = json!(tx);
+        let logs: Vec<Log> = serde_json::from_str(tx_json["logs"].as_str().unwrap()).unwrap();
+        assert_eq!(logs, vec![Log::default()]);
+    }
+
+    #[test]
+    fn test_diff_of_account_snapshots() {
+        let vitalik = address!("d8da6bf26964af9d7eed9e10c34bb2f8c5e8e4ac");
+        let usdt = address!("dac17f958d2ee523a2206206994597c13d831ec7");
+        let snapshot = |balance: u64, nonce: u64, usdt_balance: u64| AccountQueryRes {
+            chain: Some(Chain::Ethereum),
+            address: Some(vitalik),
+            balance: Some(U256::from(balance)),
+            nonce: Some(nonce),
+            ens_name: Some("vitalik.eth".to_string()),
+            token_balances: Some([(usdt, U256::from(usdt_balance))].into()),
+            ..Default::default()
+        };
+
+        let diffs = diff(&[snapshot(1_000, 5, 10)], &[snapshot(400, 7, 10)]);
+
+        assert_eq!(
+            diffs,
+            vec![AccountDiff {
+                chain: Some(Chain::Ethereum),
+                address: Some(vitalik),
+                nonce: Some(Delta {
+                    before: Some(U256::from(5)),
+                    after: Some(U256::from(7)),
+                    delta: Some(I256::try_from(2).unwrap()),
+                }),
+                balance: Some(Delta {
+                    before: Some(U256::from(1_000)),
+                    after: Some(U256::from(400)),
+                    delta: Some(I256::try_from(-600).unwrap()),
+                }),
+                ..Default::default()
+            }]
+        );
+        assert_eq!(json!(diffs[0])["balance"]["delta"], "-600");
+        assert_eq!(diff(&[snapshot(1, 1, 1)], &[snapshot(1, 1, 1)]), vec![]);
+    }
+
+    #[test]
+    fn test_diff_of_added_and_removed_accounts() {
+        let vitalik = address!("d8da6bf26964af9d7eed9e10c34bb2f8c5e8e4ac");
+        let account = |chain: Chain| AccountQueryRes {
+            chain: Some(chain),
+            address: Some(vitalik),
+            is_contract: Some(false),
+            ..Default::default()
+        };
+
+        let diffs = diff(&[account(Chain::Ethereum)], &[account(Chain::Base)]);
+
+        assert_eq!(
+            diffs,
+            vec![
+                AccountDiff {
+                    chain: Some(Chain::Ethereum),
+                    address: Some(vitalik),
+                    is_contract: Some(Change {
+                        before: Some(false),
+                        after: None,
+                    }),
+                    ..Default::default()
+                },
+                AccountDiff {
+                    chain: Some(Chain::Base),
+                    address: Some(vitalik),
+                    is_contract: Some(Change {
+                        before: None,
+                        after: Some(false),
+                    }),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+}
+
+fn serialize_option_address<S>(option: &Option<Address>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match option {
+        Some(address) => serializer.serialize_some(&AddressFormat::current().format(address)),
+        None => serializer.serialize_none(),
+    }
+}
+
+// Datetimes are only written within `TimestampFormat::Rfc3339`, the epoch column is always written
+fn skip_datetime(option: &Option<u64>) -> bool {
+    option.is_none() || TimestampFormat::current() == TimestampFormat::Unix
+}
+
+// Timestamps too far in the future to be a date are written as their epoch, still as a string so
+// the column has a single type
+fn serialize_option_datetime<S>(option: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match option {
+        Some(timestamp) => match TimestampFormat::current().datetime(*timestamp) {
+            Some(datetime) => serializer.serialize_some(&datetime),
+            None => serializer.serialize_some(&timestamp.to_string()),
+        },
+        None => serializer.serialize_none(),
+    }
 }
 
 fn serialize_option_u256<S>(option: &Option<U256>, serializer: S) -> Result<S::Ok, S::Error>
@@ -220,3 +1137,63 @@ where
         None => serializer.serialize_none(),
     }
 }
+
+// Nested values like logs and access lists are serialized as a JSON string, since tables and
+// CSV files can't hold them
+fn serialize_option_json<T, S>(option: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    match option {
+        Some(value) => {
+            let value = serde_json::to_string(value).map_err(serde::ser::Error::custom)?;
+            serializer.serialize_some(&value)
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+// Token balances are serialized as a JSON string, with balances in decimal, for the same reason as logs
+fn serialize_option_token_balances<T, S>(
+    option: &Option<BTreeMap<Address, T>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    T: ToString,
+    S: Serializer,
+{
+    match option {
+        Some(balances) => {
+            let format = AddressFormat::current();
+            let balances: BTreeMap<String, String> = balances
+                .iter()
+                .map(|(token, balance)| (format.format(token), balance.to_string()))
+                .collect();
+            let balances = serde_json::to_string(&balances).map_err(serde::ser::Error::custom)?;
+            serializer.serialize_some(&balances)
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+// Storage is serialized as a JSON string mapping each slot to its value, like token balances
+fn serialize_option_storage<S>(
+    option: &Option<BTreeMap<U256, B256>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match option {
+        Some(storage) => {
+            let storage: BTreeMap<String, &B256> = storage
+                .iter()
+                .map(|(slot, value)| (format!("{:#x}", slot), value))
+                .collect();
+            let storage = serde_json::to_string(&storage).map_err(serde::ser::Error::custom)?;
+            serializer.serialize_some(&storage)
+        }
+        None => serializer.serialize_none(),
+    }
+}
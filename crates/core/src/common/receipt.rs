@@ -0,0 +1,142 @@
+use super::aggregate::selected_field;
+use crate::interpreter::frontend::parser::Rule;
+use alloy::{hex::FromHexError, primitives::B256};
+use eql_macros::EnumVariants;
+use pest::iterators::{Pair, Pairs};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReceiptError {
+    #[error("Unexpected token {0}")]
+    UnexpectedToken(String),
+
+    #[error(transparent)]
+    ReceiptFieldError(#[from] ReceiptFieldError),
+
+    #[error(transparent)]
+    FromHexError(#[from] FromHexError),
+}
+
+/// Receipts of transactions, identified by the hash of the transaction
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Receipt {
+    ids: Vec<B256>,
+    fields: Vec<ReceiptField>,
+}
+
+impl Receipt {
+    pub fn new(ids: Vec<B256>, fields: Vec<ReceiptField>) -> Self {
+        Self { ids, fields }
+    }
+
+    pub fn ids(&self) -> &Vec<B256> {
+        &self.ids
+    }
+
+    pub fn fields(&self) -> &Vec<ReceiptField> {
+        &self.fields
+    }
+}
+
+impl TryFrom<Pairs<'_, Rule>> for Receipt {
+    type Error = ReceiptError;
+
+    fn try_from(pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
+        let mut fields: Vec<ReceiptField> = vec![];
+        let mut ids: Vec<B256> = vec![];
+
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::receipt_fields => {
+                    let inner_pairs = pair.into_inner();
+
+                    if let Some(pair) = inner_pairs.peek() {
+                        if pair.as_rule() == Rule::wildcard {
+                            fields = ReceiptField::all_variants().to_vec();
+                            continue;
+                        }
+                    }
+
+                    fields = inner_pairs
+                        .filter_map(selected_field)
+                        .map(ReceiptField::try_from)
+                        .collect::<Result<Vec<ReceiptField>, ReceiptFieldError>>()?;
+                }
+                Rule::receipt_id => {
+                    ids.push(B256::from_str(pair.as_str())?);
+                }
+                _ => {
+                    return Err(ReceiptError::UnexpectedToken(pair.as_str().to_string()));
+                }
+            }
+        }
+
+        Ok(Receipt { ids, fields })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, EnumVariants)]
+pub enum ReceiptField {
+    TransactionHash,
+    Type,
+    Status,
+    GasUsed,
+    EffectiveGasPrice,
+    CumulativeGasUsed,
+    LogsBloom,
+    ContractAddress,
+    Logs,
+    Chain,
+}
+
+impl Display for ReceiptField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiptField::TransactionHash => write!(f, "transaction_hash"),
+            ReceiptField::Type => write!(f, "type"),
+            ReceiptField::Status => write!(f, "status"),
+            ReceiptField::GasUsed => write!(f, "gas_used"),
+            ReceiptField::EffectiveGasPrice => write!(f, "effective_gas_price"),
+            ReceiptField::CumulativeGasUsed => write!(f, "cumulative_gas_used"),
+            ReceiptField::LogsBloom => write!(f, "logs_bloom"),
+            ReceiptField::ContractAddress => write!(f, "contract_address"),
+            ReceiptField::Logs => write!(f, "logs"),
+            ReceiptField::Chain => write!(f, "chain"),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReceiptFieldError {
+    #[error("Invalid field for entity receipt: {0}")]
+    InvalidField(String),
+}
+
+impl<'a> TryFrom<Pair<'a, Rule>> for ReceiptField {
+    type Error = ReceiptFieldError;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        ReceiptField::try_from(pair.as_str())
+    }
+}
+
+impl TryFrom<&str> for ReceiptField {
+    type Error = ReceiptFieldError;
+
+    fn try_from(value: &str) -> Result<Self, ReceiptFieldError> {
+        match value {
+            "transaction_hash" => Ok(ReceiptField::TransactionHash),
+            "type" => Ok(ReceiptField::Type),
+            "status" => Ok(ReceiptField::Status),
+            "gas_used" => Ok(ReceiptField::GasUsed),
+            "effective_gas_price" => Ok(ReceiptField::EffectiveGasPrice),
+            "cumulative_gas_used" => Ok(ReceiptField::CumulativeGasUsed),
+            "logs_bloom" => Ok(ReceiptField::LogsBloom),
+            "contract_address" => Ok(ReceiptField::ContractAddress),
+            "logs" => Ok(ReceiptField::Logs),
+            "chain" => Ok(ReceiptField::Chain),
+            invalid_field => Err(ReceiptFieldError::InvalidField(invalid_field.to_string())),
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 use std::sync::Arc;
 
 use super::{
@@ -9,48 +11,576 @@ use arrow::array::{ArrayRef, StringArray};
 use arrow::datatypes::{DataType, Field, Schema};
 use arrow::record_batch::RecordBatch;
 use parquet::arrow::ArrowWriter;
-use serde::Serialize;
+use serde::ser::{self, Impossible, Serialize, SerializeMap, SerializeStruct, Serializer};
+use serde_json::Value;
 
 use csv::WriterBuilder;
+use flate2::{write::GzEncoder, Compression};
 
-pub(crate) fn dump_results(result: &ExpressionResult, dump: &Dump) -> Result<(), Box<dyn Error>> {
-    match dump.format {
-        DumpFormat::Json => {
-            let content = serialize_json(result)?;
-            std::fs::write(dump.path(), content)?;
+#[derive(Debug, thiserror::Error)]
+pub enum SerializerError {
+    #[error("The results of several entities can't be written as Parquet")]
+    CombinedParquet,
+
+    #[error("Results can't be written as {0} a chunk at a time, only as CSV or JSONL")]
+    UnsupportedChunkedFormat(DumpFormat),
+}
+
+/// Writes the results to the dump file. The fields are written in the order of `keys`, see
+/// `ordered_rows`.
+pub(crate) fn dump_results(
+    result: &ExpressionResult,
+    dump: &Dump,
+    keys: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let content = match dump.format {
+        DumpFormat::Json => serialize_json(result, keys)?.into_bytes(),
+        DumpFormat::Jsonl => to_jsonl(result, keys)?.into_bytes(),
+        DumpFormat::Csv => to_csv(result, keys)?.into_bytes(),
+        DumpFormat::Parquet => serialize_parquet(result)?,
+    };
+    let mut output = Output::new(BufWriter::new(File::create(dump.path())?), dump.compressed);
+
+    output.write_all(&content)?;
+    output.finish()?.flush()?;
+    Ok(())
+}
+
+/// Writes results a chunk at a time, like the chunks of transactions yielded by
+/// `ExecutionEngine::stream_transactions`, so exports of large block ranges don't hold every
+/// result in memory. Only CSV and JSONL can be written in chunks.
+///
+/// With `compressed`, the output is gzip-compressed as it's written, e.g. to a `.csv.gz` file.
+/// `finish` must be called once every chunk is written, to write the end of the gzip stream.
+pub struct ResultWriter<W: Write> {
+    output: Output<W>,
+    format: DumpFormat,
+    keys: Vec<String>,
+    /// Columns of the CSV header, the selected fields, set by the first chunk with results
+    columns: Option<Vec<&'static str>>,
+}
+
+impl<W: Write> ResultWriter<W> {
+    pub fn new(writer: W, format: DumpFormat, compressed: bool) -> Result<Self, SerializerError> {
+        match format {
+            DumpFormat::Csv | DumpFormat::Jsonl => Ok(Self {
+                output: Output::new(writer, compressed),
+                format,
+                keys: vec![],
+                columns: None,
+            }),
+            format => Err(SerializerError::UnsupportedChunkedFormat(format)),
         }
-        DumpFormat::Csv => {
-            let content = match result {
-                ExpressionResult::Account(accounts) => serialize_csv(accounts)?,
-                ExpressionResult::Block(blocks) => serialize_csv(blocks)?,
-                ExpressionResult::Transaction(txs) => serialize_csv(txs)?,
-                ExpressionResult::Log(logs) => serialize_csv(logs)?,
-            };
-
-            std::fs::write(dump.path(), content)?;
+    }
+
+    /// Sets the order of the fields in the written results, see `QueryResult::with_fields`.
+    pub fn with_fields(mut self, fields: Vec<String>) -> Self {
+        self.keys = fields;
+        self
+    }
+
+    /// Writes the results of a chunk after the ones already written. The CSV columns are the
+    /// selected fields, or every field when none were set with `with_fields`, whether or not the
+    /// results of the first chunk have them.
+    pub fn write(&mut self, result: &ExpressionResult) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            DumpFormat::Csv => self.write_csv(result),
+            _ => Ok(self
+                .output
+                .write_all(to_jsonl(result, &self.keys)?.as_bytes())?),
+        }
+    }
+
+    /// Writes what's left of the output, and returns the underlying writer.
+    pub fn finish(self) -> Result<W, Box<dyn Error>> {
+        Ok(self.output.finish()?)
+    }
+
+    fn write_csv(&mut self, result: &ExpressionResult) -> Result<(), Box<dyn Error>> {
+        let rows: Vec<Row> = entity_rows(result, &self.keys)?
+            .into_iter()
+            .flat_map(|(_, rows)| rows)
+            .collect();
+        let mut writer = WriterBuilder::new().from_writer(&mut self.output);
+
+        if self.columns.is_none() && !rows.is_empty() {
+            let columns: Vec<&'static str> = rows[0]
+                .iter()
+                .enumerate()
+                .filter(|(i, (name, _))| {
+                    let key = field_key(name);
+                    let selected = self.keys.is_empty() || self.keys.iter().any(|k| k == key);
+                    // Companion columns are set for every result or none, depending on how the
+                    // results are serialized, e.g. with `TimestampFormat::Rfc3339`
+                    selected && (key == *name || rows.iter().any(|row| row[*i].1.is_some()))
+                })
+                .map(|(_, (name, _))| *name)
+                .collect();
+            writer.write_record(&columns)?;
+            self.columns = Some(columns);
         }
-        DumpFormat::Parquet => {
-            let content = serialize_parquet(result)?;
-            std::fs::write(dump.path(), content)?;
+
+        let columns = self.columns.as_deref().unwrap_or_default();
+        for row in &rows {
+            writer.write_record(columns.iter().map(|column| {
+                row.iter()
+                    .find(|(name, _)| name == column)
+                    .and_then(|(_, value)| cell(value))
+                    .unwrap_or_default()
+            }))?;
         }
+
+        writer.flush()?;
+        Ok(())
     }
-    Ok(())
 }
 
-fn serialize_json<T: Serialize>(result: &T) -> Result<String, Box<dyn Error>> {
-    Ok(serde_json::to_string_pretty(result)?)
+/// Destination of serialized results, which are gzip-compressed as they're written when the
+/// output is compressed.
+enum Output<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+}
+
+impl<W: Write> Output<W> {
+    fn new(writer: W, compressed: bool) -> Self {
+        match compressed {
+            true => Output::Gzip(GzEncoder::new(writer, Compression::default())),
+            false => Output::Plain(writer),
+        }
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Output::Plain(writer) => Ok(writer),
+            Output::Gzip(encoder) => encoder.finish(),
+        }
+    }
 }
 
-fn serialize_csv<T: Serialize>(results: &Vec<T>) -> Result<String, Box<dyn Error>> {
-    let mut writer = WriterBuilder::new().has_headers(true).from_writer(vec![]);
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(writer) => writer.write(buf),
+            Output::Gzip(encoder) => encoder.write(buf),
+        }
+    }
 
-    for result in results {
-        writer.serialize(result)?
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(writer) => writer.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Writes the results as a JSON object with a single key naming the entity, e.g. `account`.
+/// Combined results are written as an array of such objects, one per entity.
+fn serialize_json(result: &ExpressionResult, keys: &[String]) -> Result<String, Box<dyn Error>> {
+    let mut entities = json_entities(result, keys)?;
+
+    match result {
+        ExpressionResult::Combined(_) => Ok(serde_json::to_string_pretty(&entities)?),
+        _ => Ok(serde_json::to_string_pretty(&entities.remove(0))?),
+    }
+}
+
+pub(crate) fn to_json(
+    result: &ExpressionResult,
+    keys: &[String],
+) -> Result<String, Box<dyn Error>> {
+    let content = match result {
+        ExpressionResult::Account(accounts) => serialize_json_array(accounts, keys)?,
+        ExpressionResult::Block(blocks) => serialize_json_array(blocks, keys)?,
+        ExpressionResult::Transaction(txs) => serialize_json_array(txs, keys)?,
+        ExpressionResult::Log(logs) => serialize_json_array(logs, keys)?,
+        ExpressionResult::Token(tokens) => serialize_json_array(tokens, keys)?,
+        ExpressionResult::Receipt(receipts) => serialize_json_array(receipts, keys)?,
+        ExpressionResult::Chain(chains) => serialize_json_array(chains, keys)?,
+        ExpressionResult::Aggregate(aggregates) => serialize_json_array(aggregates, keys)?,
+        ExpressionResult::Combined(_) => serde_json::to_string(&json_entities(result, keys)?)?,
+    };
+
+    Ok(content)
+}
+
+fn serialize_json_array<T: Serialize>(
+    results: &[T],
+    keys: &[String],
+) -> Result<String, Box<dyn Error>> {
+    let rows = ordered_rows(results, keys)?;
+    let objects: Vec<JsonObject> = rows.into_iter().map(JsonObject).collect();
+
+    Ok(serde_json::to_string(&objects)?)
+}
+
+pub(crate) fn to_jsonl(
+    result: &ExpressionResult,
+    keys: &[String],
+) -> Result<String, Box<dyn Error>> {
+    match result {
+        ExpressionResult::Account(accounts) => serialize_jsonl(accounts, keys),
+        ExpressionResult::Block(blocks) => serialize_jsonl(blocks, keys),
+        ExpressionResult::Transaction(txs) => serialize_jsonl(txs, keys),
+        ExpressionResult::Log(logs) => serialize_jsonl(logs, keys),
+        ExpressionResult::Token(tokens) => serialize_jsonl(tokens, keys),
+        ExpressionResult::Receipt(receipts) => serialize_jsonl(receipts, keys),
+        ExpressionResult::Chain(chains) => serialize_jsonl(chains, keys),
+        ExpressionResult::Aggregate(aggregates) => serialize_jsonl(aggregates, keys),
+        // Every line names the entity of its result, e.g. `{"block":{"number":1}}`
+        ExpressionResult::Combined(_) => {
+            let mut content = String::new();
+
+            for (entity, rows) in entity_rows(result, keys)? {
+                for row in rows {
+                    content.push_str(&serde_json::to_string(&Tagged(entity, JsonObject(row)))?);
+                    content.push('\n');
+                }
+            }
+
+            Ok(content)
+        }
+    }
+}
+
+fn serialize_jsonl<T: Serialize>(results: &[T], keys: &[String]) -> Result<String, Box<dyn Error>> {
+    let mut content = String::new();
+
+    for row in ordered_rows(results, keys)? {
+        content.push_str(&serde_json::to_string(&JsonObject(row))?);
+        content.push('\n');
+    }
+
+    Ok(content)
+}
+
+pub(crate) fn to_csv(result: &ExpressionResult, keys: &[String]) -> Result<String, Box<dyn Error>> {
+    match result {
+        ExpressionResult::Account(accounts) => serialize_csv(accounts, keys),
+        ExpressionResult::Block(blocks) => serialize_csv(blocks, keys),
+        ExpressionResult::Transaction(txs) => serialize_csv(txs, keys),
+        ExpressionResult::Log(logs) => serialize_csv(logs, keys),
+        ExpressionResult::Token(tokens) => serialize_csv(tokens, keys),
+        ExpressionResult::Receipt(receipts) => serialize_csv(receipts, keys),
+        ExpressionResult::Chain(chains) => serialize_csv(chains, keys),
+        ExpressionResult::Aggregate(aggregates) => serialize_csv(aggregates, keys),
+        // The results of each entity are written as a table of their own, after an empty line
+        ExpressionResult::Combined(results) => Ok(results
+            .iter()
+            .map(|result| to_csv(result, keys))
+            .collect::<Result<Vec<_>, _>>()?
+            .join("\n")),
+    }
+}
+
+/// Writes the results as CSV, with one column per field that is set in at least one of the
+/// results. Columns follow the order of `keys`, see `ordered_rows`, and fields that are `None`
+/// are written as empty cells.
+fn serialize_csv<T: Serialize>(results: &[T], keys: &[String]) -> Result<String, Box<dyn Error>> {
+    let rows = ordered_rows(results, keys)?;
+    let columns: Vec<usize> = match rows.first() {
+        Some(first) => (0..first.len())
+            .filter(|&i| rows.iter().any(|row| row[i].1.is_some()))
+            .collect(),
+        None => vec![],
+    };
+    let mut writer = WriterBuilder::new().from_writer(vec![]);
+
+    if let Some(first) = rows.first() {
+        writer.write_record(columns.iter().map(|&i| first[i].0))?;
+    }
+
+    for row in &rows {
+        writer.write_record(columns.iter().map(|&i| cell(&row[i].1).unwrap_or_default()))?;
     }
 
     Ok(String::from_utf8(writer.into_inner()?)?)
 }
 
+/// Serializes every result into a `Row`, with the fields listed in `keys` first, in that order,
+/// so the output follows the order in which the query selected them. The datetime of a timestamp,
+/// e.g. `timestamp_utc`, follows its epoch. The other fields follow in declaration order.
+fn ordered_rows<T: Serialize>(results: &[T], keys: &[String]) -> Result<Vec<Row>, RowError> {
+    results
+        .iter()
+        .map(|result| {
+            let mut row = result.serialize(RowSerializer)?;
+            row.sort_by_key(|(name, _)| {
+                keys.iter()
+                    .position(|key| key == field_key(name))
+                    .unwrap_or(keys.len())
+            });
+            Ok(row)
+        })
+        .collect()
+}
+
+/// Key of the field a column belongs to. Columns written next to a field, like the datetime of a
+/// timestamp in `timestamp_utc` or the balances in units of their token in
+/// `token_balances_formatted`, belong to that field.
+fn field_key(name: &str) -> &str {
+    ["_utc", "_formatted"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(name)
+}
+
+/// Text of a value in a CSV cell. Strings are written without quotes, which keeps hex values like
+/// hashes readable.
+fn cell(value: &Option<Value>) -> Option<String> {
+    match value {
+        None => None,
+        Some(Value::String(value)) => Some(value.clone()),
+        Some(value) => Some(value.to_string()),
+    }
+}
+
+/// Rows of the results of each entity, along with the name of the entity. Combined results have
+/// the rows of every entity they combine.
+fn entity_rows(
+    result: &ExpressionResult,
+    keys: &[String],
+) -> Result<Vec<(&'static str, Vec<Row>)>, RowError> {
+    Ok(match result {
+        ExpressionResult::Account(accounts) => vec![("account", ordered_rows(accounts, keys)?)],
+        ExpressionResult::Block(blocks) => vec![("block", ordered_rows(blocks, keys)?)],
+        ExpressionResult::Transaction(txs) => vec![("transaction", ordered_rows(txs, keys)?)],
+        ExpressionResult::Log(logs) => vec![("log", ordered_rows(logs, keys)?)],
+        ExpressionResult::Token(tokens) => vec![("token", ordered_rows(tokens, keys)?)],
+        ExpressionResult::Receipt(receipts) => vec![("receipt", ordered_rows(receipts, keys)?)],
+        ExpressionResult::Chain(chains) => vec![("chain", ordered_rows(chains, keys)?)],
+        ExpressionResult::Aggregate(aggregates) => {
+            vec![("aggregate", ordered_rows(aggregates, keys)?)]
+        }
+        ExpressionResult::Combined(results) => results
+            .iter()
+            .map(|result| entity_rows(result, keys))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect(),
+    })
+}
+
+/// Results of each entity as a JSON object with a single key naming the entity
+fn json_entities(
+    result: &ExpressionResult,
+    keys: &[String],
+) -> Result<Vec<Tagged<Vec<JsonObject>>>, RowError> {
+    Ok(entity_rows(result, keys)?
+        .into_iter()
+        .map(|(entity, rows)| Tagged(entity, rows.into_iter().map(JsonObject).collect()))
+        .collect())
+}
+
+/// Row written as a JSON object, leaving out the fields that are `None`
+struct JsonObject(Row);
+
+impl Serialize for JsonObject {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (name, value) in &self.0 {
+            if let Some(value) = value {
+                map.serialize_entry(name, value)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// Value written as a JSON object with a single key naming its entity, e.g. `{"block": ...}`
+struct Tagged<T>(&'static str, T);
+
+impl<T: Serialize> Serialize for Tagged<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.0, &self.1)?;
+        map.end()
+    }
+}
+
+/// Cell of the field named `field` in a result, as it's written in CSV. Returns `None` if the
+/// result has no such field.
+pub(crate) fn result_cell<T: Serialize>(
+    result: &T,
+    field: &str,
+) -> Result<Option<Option<String>>, RowError> {
+    let row = result.serialize(RowSerializer)?;
+
+    Ok(row
+        .into_iter()
+        .find(|(name, _)| *name == field)
+        .map(|(_, value)| cell(&value)))
+}
+
+/// Name and value of each field of a result, in declaration order. Fields that are `None` have no
+/// value.
+type Row = Vec<(&'static str, Option<Value>)>;
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub(crate) struct RowError(String);
+
+impl ser::Error for RowError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        RowError(msg.to_string())
+    }
+}
+
+/// Serializes a result struct into a `Row`. Unlike the csv crate, fields skipped because they
+/// are `None` are kept, so every row has the same columns.
+struct RowSerializer;
+
+struct RowFields(Row);
+
+impl SerializeStruct for RowFields {
+    type Ok = Row;
+    type Error = RowError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), RowError> {
+        let value = match serde_json::to_value(value).map_err(ser::Error::custom)? {
+            Value::Null => None,
+            value => Some(value),
+        };
+        self.0.push((key, value));
+        Ok(())
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), RowError> {
+        self.0.push((key, None));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Row, RowError> {
+        Ok(self.0)
+    }
+}
+
+macro_rules! unsupported {
+    ($($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(fn $method(self, $(_: $arg),*) -> Result<Row, RowError> {
+            Err(ser::Error::custom("only structs can be serialized as CSV rows"))
+        })*
+    };
+}
+
+impl Serializer for RowSerializer {
+    type Ok = Row;
+    type Error = RowError;
+    type SerializeSeq = Impossible<Row, RowError>;
+    type SerializeTuple = Impossible<Row, RowError>;
+    type SerializeTupleStruct = Impossible<Row, RowError>;
+    type SerializeTupleVariant = Impossible<Row, RowError>;
+    type SerializeMap = Impossible<Row, RowError>;
+    type SerializeStruct = RowFields;
+    type SerializeStructVariant = Impossible<Row, RowError>;
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<RowFields, RowError> {
+        Ok(RowFields(Vec::with_capacity(len)))
+    }
+
+    unsupported!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    );
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Row, RowError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Row, RowError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Row, RowError> {
+        Err(ser::Error::custom(
+            "only structs can be serialized as CSV rows",
+        ))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, RowError> {
+        Err(ser::Error::custom(
+            "only structs can be serialized as CSV rows",
+        ))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, RowError> {
+        Err(ser::Error::custom(
+            "only structs can be serialized as CSV rows",
+        ))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, RowError> {
+        Err(ser::Error::custom(
+            "only structs can be serialized as CSV rows",
+        ))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, RowError> {
+        Err(ser::Error::custom(
+            "only structs can be serialized as CSV rows",
+        ))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, RowError> {
+        Err(ser::Error::custom(
+            "only structs can be serialized as CSV rows",
+        ))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, RowError> {
+        Err(ser::Error::custom(
+            "only structs can be serialized as CSV rows",
+        ))
+    }
+}
+
 fn serialize_parquet(result: &ExpressionResult) -> Result<Vec<u8>, Box<dyn Error>> {
     let (schema, data) = match result {
         ExpressionResult::Account(accounts) => create_parquet_schema_and_data(accounts)?,
@@ -59,6 +589,11 @@ fn serialize_parquet(result: &ExpressionResult) -> Result<Vec<u8>, Box<dyn Error
             create_parquet_schema_and_data(transactions)?
         }
         ExpressionResult::Log(logs) => create_parquet_schema_and_data(logs)?,
+        ExpressionResult::Token(tokens) => create_parquet_schema_and_data(tokens)?,
+        ExpressionResult::Receipt(receipts) => create_parquet_schema_and_data(receipts)?,
+        ExpressionResult::Chain(chains) => create_parquet_schema_and_data(chains)?,
+        ExpressionResult::Aggregate(aggregates) => create_parquet_schema_and_data(aggregates)?,
+        ExpressionResult::Combined(_) => return Err(SerializerError::CombinedParquet.into()),
     };
 
     let batch = RecordBatch::try_new(Arc::new(schema), data)?;
@@ -108,10 +643,16 @@ fn create_parquet_schema_and_data<T: Serialize>(
 
 #[cfg(test)]
 mod test {
-    use super::{serialize_csv, serialize_json, serialize_parquet};
-    use crate::common::query_result::{AccountQueryRes, ExpressionResult};
-    use alloy::primitives::U256;
-    use std::str::FromStr;
+    use super::{serialize_csv, serialize_json, serialize_parquet, ResultWriter};
+    use crate::common::chain::Chain;
+    use crate::common::dump::DumpFormat;
+    use crate::common::query_result::{
+        AccountQueryRes, BlockQueryRes, ExpressionResult, QueryResult, TimestampFormat,
+        TokenQueryRes, TransactionQueryRes,
+    };
+    use alloy::primitives::{address, b256, bytes, U256};
+    use flate2::read::GzDecoder;
+    use std::{io::Read, str::FromStr};
 
     #[test]
     fn test_serialize_json() {
@@ -120,14 +661,104 @@ mod test {
             balance: Some(U256::from_str("100").unwrap()),
             nonce: Some(0),
             code: None,
+            is_contract: None,
             chain: None,
+            ens_name: None,
+            token_balances: None,
+            token_balances_formatted: None,
+            storage: None,
+            first_active_block: None,
+            selectors: None,
+            implementation: None,
+            calls: None,
         };
         let result = ExpressionResult::Account(vec![res]);
-        let content = serialize_json(&result).unwrap();
+        let content = serialize_json(&result, &[]).unwrap();
 
         assert_eq!(content, "{\n  \"account\": [\n    {\n      \"nonce\": 0,\n      \"balance\": \"100\"\n    }\n  ]\n}");
     }
 
+    #[test]
+    fn test_serialize_json_array() {
+        let accounts = vec![AccountQueryRes {
+            chain: Some(Chain::Ethereum),
+            address: Some(address!("dAC17F958D2ee523a2206206994597C13D831ec7")),
+            balance: Some(U256::from(100)),
+            nonce: Some(1),
+            ..Default::default()
+        }];
+        let result = QueryResult::new(ExpressionResult::Account(accounts.clone()));
+        let content = result.to_json().unwrap();
+
+        assert_eq!(
+            content,
+            r#"[{"chain":"eth","nonce":1,"balance":"100","address":"0xdAC17F958D2ee523a2206206994597C13D831ec7"}]"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Vec<AccountQueryRes>>(&content).unwrap(),
+            accounts
+        );
+    }
+
+    #[test]
+    fn test_serialize_unknown_chain_by_its_id() {
+        let accounts = vec![AccountQueryRes {
+            chain: Some(Chain::Custom {
+                id: 1337,
+                name: "1337".to_string(),
+            }),
+            nonce: Some(1),
+            ..Default::default()
+        }];
+        let result = QueryResult::new(ExpressionResult::Account(accounts.clone()));
+        let json = result.to_json().unwrap();
+        let csv = serialize_csv(&accounts, &["chain".to_string(), "nonce".to_string()]).unwrap();
+
+        assert_eq!(json, r#"[{"chain":"1337","nonce":1}]"#);
+        assert_eq!(csv, "chain,nonce\n1337,1\n");
+        assert_eq!(
+            serde_json::from_str::<Vec<AccountQueryRes>>(&json).unwrap(),
+            accounts
+        );
+    }
+
+    #[test]
+    fn test_serialize_jsonl() {
+        let accounts = vec![
+            AccountQueryRes {
+                nonce: Some(0),
+                ..Default::default()
+            },
+            AccountQueryRes {
+                nonce: Some(1),
+                code: Some(bytes!("6080")),
+                ..Default::default()
+            },
+        ];
+        let result = QueryResult::new(ExpressionResult::Account(accounts.clone()));
+        let content = result.to_jsonl().unwrap();
+
+        assert_eq!(
+            content,
+            "{\"nonce\":0}\n{\"nonce\":1,\"code\":\"0x6080\"}\n"
+        );
+        assert_eq!(
+            content
+                .lines()
+                .map(|line| serde_json::from_str::<AccountQueryRes>(line).unwrap())
+                .collect::<Vec<_>>(),
+            accounts
+        );
+    }
+
+    #[test]
+    fn test_serialize_json_without_results() {
+        let result = QueryResult::new(ExpressionResult::Log(vec![]));
+
+        assert_eq!(result.to_json().unwrap(), "[]");
+        assert_eq!(result.to_jsonl().unwrap(), "");
+    }
+
     #[test]
     fn test_serialize_csv() {
         let res = vec![
@@ -136,21 +767,219 @@ mod test {
                 balance: Some(U256::from_str("100").unwrap()),
                 nonce: Some(0),
                 code: None,
+                is_contract: None,
                 chain: None,
+                ens_name: None,
+                token_balances: None,
+                token_balances_formatted: None,
+                storage: None,
+                first_active_block: None,
+                selectors: None,
+                implementation: None,
+                calls: None,
             },
             AccountQueryRes {
                 address: None,
                 balance: Some(U256::from_str("200").unwrap()),
                 nonce: Some(1),
                 code: None,
+                is_contract: None,
                 chain: None,
+                ens_name: None,
+                token_balances: None,
+                token_balances_formatted: None,
+                storage: None,
+                first_active_block: None,
+                selectors: None,
+                implementation: None,
+                calls: None,
             },
         ];
-        let content = serialize_csv(&res).unwrap();
+        let content = serialize_csv(&res, &[]).unwrap();
 
         assert_eq!(content, "nonce,balance\n0,100\n1,200\n");
     }
 
+    #[test]
+    fn test_serialize_csv_with_missing_fields() {
+        let hash = b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890");
+        let result = QueryResult::new(ExpressionResult::Transaction(vec![
+            TransactionQueryRes {
+                hash: Some(hash),
+                to: None,
+                data: Some(bytes!("a9059cbb")),
+                value: Some(U256::from(0)),
+                ..Default::default()
+            },
+            TransactionQueryRes {
+                hash: Some(hash),
+                to: Some(address!("dAC17F958D2ee523a2206206994597C13D831ec7")),
+                data: Some(bytes!("")),
+                value: Some(U256::from(1000)),
+                ..Default::default()
+            },
+        ]));
+        let content = result.to_csv().unwrap();
+        let mut lines = content.lines();
+
+        assert_eq!(lines.next(), Some("hash,to,data,value"));
+        assert_eq!(
+            lines.next(),
+            Some(
+                "0x72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890,,0xa9059cbb,0"
+            )
+        );
+        assert_eq!(
+            lines.next(),
+            Some("0x72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890,0xdAC17F958D2ee523a2206206994597C13D831ec7,0x,1000")
+        );
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn test_serialize_in_order_of_fields() {
+        let result = QueryResult::new(ExpressionResult::Transaction(vec![TransactionQueryRes {
+            from: Some(address!("dAC17F958D2ee523a2206206994597C13D831ec7")),
+            to: Some(address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")),
+            value: Some(U256::from(1000)),
+            ..Default::default()
+        }]))
+        .with_fields(vec![
+            "to".to_string(),
+            "value".to_string(),
+            "from".to_string(),
+        ]);
+
+        assert_eq!(
+            result.to_csv().unwrap(),
+            "to,value,from\n0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48,1000,0xdAC17F958D2ee523a2206206994597C13D831ec7\n"
+        );
+        assert_eq!(
+            result.to_jsonl().unwrap(),
+            "{\"to\":\"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48\",\"value\":\"1000\",\"from\":\"0xdAC17F958D2ee523a2206206994597C13D831ec7\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_serialize_datetime_next_to_its_timestamp() {
+        let result = QueryResult::new(ExpressionResult::Block(vec![BlockQueryRes {
+            number: Some(1),
+            timestamp: Some(1438269988),
+            timestamp_utc: Some(1438269988),
+            ..Default::default()
+        }]))
+        .with_fields(vec!["timestamp".to_string(), "number".to_string()]);
+
+        assert_eq!(
+            TimestampFormat::Rfc3339
+                .sync_scope(|| result.to_csv())
+                .unwrap(),
+            "timestamp,timestamp_utc,number\n1438269988,2015-07-30T15:26:28Z,1\n"
+        );
+        assert_eq!(result.to_csv().unwrap(), "timestamp,number\n1438269988,1\n");
+    }
+
+    #[test]
+    fn test_serialize_combined_results() {
+        let result = QueryResult::new(ExpressionResult::Combined(vec![
+            ExpressionResult::Block(vec![BlockQueryRes {
+                number: Some(1),
+                ..Default::default()
+            }]),
+            ExpressionResult::Transaction(vec![
+                TransactionQueryRes {
+                    value: Some(U256::from(1)),
+                    ..Default::default()
+                },
+                TransactionQueryRes {
+                    value: Some(U256::from(2)),
+                    ..Default::default()
+                },
+            ]),
+        ]));
+
+        assert_eq!(
+            result.to_json().unwrap(),
+            r#"[{"block":[{"number":1}]},{"transaction":[{"value":"1"},{"value":"2"}]}]"#
+        );
+        assert_eq!(
+            result.to_jsonl().unwrap(),
+            "{\"block\":{\"number\":1}}\n{\"transaction\":{\"value\":\"1\"}}\n{\"transaction\":{\"value\":\"2\"}}\n"
+        );
+        assert_eq!(result.to_csv().unwrap(), "number\n1\n\nvalue\n1\n2\n");
+    }
+
+    #[test]
+    fn test_write_compressed_chunks() {
+        let chunk = |values: &[u64]| {
+            ExpressionResult::Transaction(
+                values
+                    .iter()
+                    .map(|&value| TransactionQueryRes {
+                        value: Some(U256::from(value)),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+        };
+        let mut writer = ResultWriter::new(vec![], DumpFormat::Csv, true)
+            .unwrap()
+            .with_fields(vec!["value".to_string(), "to".to_string()]);
+
+        for values in [&[1, 2][..], &[], &[3]] {
+            writer.write(&chunk(values)).unwrap();
+        }
+        let compressed = writer.finish().unwrap();
+        let mut content = String::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut content)
+            .unwrap();
+
+        // The `to` column is selected, so it's written even though no result has one
+        assert_eq!(content, "value,to\n1,\n2,\n3,\n");
+    }
+
+    #[test]
+    fn test_write_chunks_with_column_first_set_in_later_chunk() {
+        let chunk = |decimals: u8, name: Option<&str>| {
+            ExpressionResult::Token(vec![TokenQueryRes {
+                name: name.map(str::to_string),
+                decimals: Some(decimals),
+                ..Default::default()
+            }])
+        };
+        let mut writer = ResultWriter::new(vec![], DumpFormat::Csv, false).unwrap();
+
+        writer.write(&chunk(18, None)).unwrap();
+        writer.write(&chunk(6, Some("USD Coin"))).unwrap();
+        let content = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        // Without selected fields every column is written, even the ones the first chunk lacks
+        assert_eq!(
+            content,
+            "chain,address,name,symbol,decimals,total_supply\n,,,,18,\n,,USD Coin,,6,\n"
+        );
+    }
+
+    #[test]
+    fn test_only_csv_and_jsonl_are_written_in_chunks() {
+        assert!(ResultWriter::new(vec![], DumpFormat::Jsonl, false).is_ok());
+        assert_eq!(
+            ResultWriter::new(vec![], DumpFormat::Parquet, true)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Results can't be written as parquet a chunk at a time, only as CSV or JSONL"
+        );
+    }
+
+    #[test]
+    fn test_serialize_csv_without_results() {
+        let result = QueryResult::new(ExpressionResult::Account(vec![]));
+
+        assert_eq!(result.to_csv().unwrap(), "");
+    }
+
     #[test]
     fn test_serialize_parquet() {
         let res = AccountQueryRes {
@@ -158,7 +987,16 @@ mod test {
             balance: Some(U256::from_str("100").unwrap()),
             nonce: Some(0),
             code: None,
+            is_contract: None,
             chain: None,
+            ens_name: None,
+            token_balances: None,
+            token_balances_formatted: None,
+            storage: None,
+            first_active_block: None,
+            selectors: None,
+            implementation: None,
+            calls: None,
         };
         let result = ExpressionResult::Account(vec![res]);
         let content = serialize_parquet(&result).unwrap();
@@ -0,0 +1,130 @@
+use super::aggregate::selected_field;
+use crate::interpreter::frontend::parser::Rule;
+use alloy::{hex::FromHexError, primitives::Address};
+use eql_macros::EnumVariants;
+use pest::iterators::{Pair, Pairs};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+#[derive(thiserror::Error, Debug)]
+pub enum TokenError {
+    #[error("Unexpected token {0}")]
+    UnexpectedToken(String),
+
+    #[error(transparent)]
+    TokenFieldError(#[from] TokenFieldError),
+
+    #[error(transparent)]
+    FromHexError(#[from] FromHexError),
+}
+
+/// ERC-20 tokens, identified by their contract address
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token {
+    ids: Vec<Address>,
+    fields: Vec<TokenField>,
+}
+
+impl Token {
+    pub fn new(ids: Vec<Address>, fields: Vec<TokenField>) -> Self {
+        Self { ids, fields }
+    }
+
+    pub fn ids(&self) -> &Vec<Address> {
+        &self.ids
+    }
+
+    pub fn fields(&self) -> &Vec<TokenField> {
+        &self.fields
+    }
+}
+
+impl TryFrom<Pairs<'_, Rule>> for Token {
+    type Error = TokenError;
+
+    fn try_from(pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
+        let mut fields: Vec<TokenField> = vec![];
+        let mut ids: Vec<Address> = vec![];
+
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::token_fields => {
+                    let inner_pairs = pair.into_inner();
+
+                    if let Some(pair) = inner_pairs.peek() {
+                        if pair.as_rule() == Rule::wildcard {
+                            fields = TokenField::all_variants().to_vec();
+                            continue;
+                        }
+                    }
+
+                    fields = inner_pairs
+                        .filter_map(selected_field)
+                        .map(TokenField::try_from)
+                        .collect::<Result<Vec<TokenField>, TokenFieldError>>()?;
+                }
+                Rule::token_id => {
+                    ids.push(Address::from_str(pair.as_str())?);
+                }
+                _ => {
+                    return Err(TokenError::UnexpectedToken(pair.as_str().to_string()));
+                }
+            }
+        }
+
+        Ok(Token { ids, fields })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, EnumVariants)]
+pub enum TokenField {
+    Address,
+    Name,
+    Symbol,
+    Decimals,
+    TotalSupply,
+    Chain,
+}
+
+impl Display for TokenField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenField::Address => write!(f, "address"),
+            TokenField::Name => write!(f, "name"),
+            TokenField::Symbol => write!(f, "symbol"),
+            TokenField::Decimals => write!(f, "decimals"),
+            TokenField::TotalSupply => write!(f, "total_supply"),
+            TokenField::Chain => write!(f, "chain"),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TokenFieldError {
+    #[error("Invalid field for entity token: {0}")]
+    InvalidField(String),
+}
+
+impl<'a> TryFrom<Pair<'a, Rule>> for TokenField {
+    type Error = TokenFieldError;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        TokenField::try_from(pair.as_str())
+    }
+}
+
+impl TryFrom<&str> for TokenField {
+    type Error = TokenFieldError;
+
+    fn try_from(value: &str) -> Result<Self, TokenFieldError> {
+        match value {
+            "address" => Ok(TokenField::Address),
+            "name" => Ok(TokenField::Name),
+            "symbol" => Ok(TokenField::Symbol),
+            "decimals" => Ok(TokenField::Decimals),
+            "total_supply" => Ok(TokenField::TotalSupply),
+            "chain" => Ok(TokenField::Chain),
+            invalid_field => Err(TokenFieldError::InvalidField(invalid_field.to_string())),
+        }
+    }
+}
@@ -1,22 +1,28 @@
 use super::{
-    block::{BlockId, BlockRange},
-    entity_id::{parse_block_number_or_tag, EntityIdError},
+    aggregate::selected_field,
+    block::BlockId,
+    ens::NameOrAddress,
+    entity_id::{parse_block_range, EntityIdError},
     filters::{
-        ComparisonFilterError, EqualityFilter, EqualityFilterError, Filter, FilterError, FilterType,
+        expand_exponent, ComparisonFilter, ComparisonFilterError, EqualityFilter,
+        EqualityFilterError, Filter, FilterError, FilterType,
     },
     query_result::TransactionQueryRes,
 };
 use crate::interpreter::frontend::parser::Rule;
 use alloy::{
     hex::FromHexError,
-    primitives::{Address, AddressError, B256, U256},
+    primitives::{Address, AddressError, FixedBytes, B256, U256},
 };
 use eql_macros::EnumVariants;
 use pest::iterators::{Pair, Pairs};
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Transaction {
     ids: Option<Vec<B256>>,
     filters: Option<Vec<TransactionFilter>>,
@@ -60,43 +66,142 @@ impl Transaction {
             .ok_or(TransactionFilterError::InvalidBlockIdFilter)
     }
 
+    /// Whether the transaction passes all the filters. The filters grouped with `OR` pass if any of
+    /// them does.
     pub fn filter(&self, tx: &TransactionQueryRes) -> bool {
-        if let Some(filters) = &self.filters {
-            filters.iter().all(|filter| match filter {
-                TransactionFilter::TransactionType(t) => t.compare(&tx.transaction_type.unwrap()),
-                TransactionFilter::Hash(h) => h.compare(&tx.hash.unwrap()),
-                TransactionFilter::From(f) => f.compare(&tx.from.unwrap()),
-                TransactionFilter::To(t) => t.compare(&tx.to.unwrap()),
-                TransactionFilter::Data(d) => d.compare(&tx.data.clone().unwrap()),
-                TransactionFilter::Value(v) => v.compare(&tx.value.unwrap()),
-                TransactionFilter::GasPrice(gp) => gp.compare(&tx.gas_price.unwrap()),
-                TransactionFilter::Gas(g) => g.compare(&tx.gas.unwrap()),
-                TransactionFilter::ChainId(cid) => cid.compare(&tx.chain_id.unwrap()),
-                TransactionFilter::Status(s) => s.compare(&tx.status.unwrap()),
-                TransactionFilter::V(v) => v.compare(&tx.v.unwrap()),
-                TransactionFilter::R(r) => r.compare(&tx.r.unwrap()),
-                TransactionFilter::S(s) => s.compare(&tx.s.unwrap()),
-                TransactionFilter::MaxFeePerBlobGas(mfbg) => {
-                    mfbg.compare(&tx.max_fee_per_blob_gas.unwrap())
-                }
-                TransactionFilter::MaxFeePerGas(mfg) => mfg.compare(&tx.max_fee_per_gas.unwrap()),
-                TransactionFilter::MaxPriorityFeePerGas(mpfpg) => {
-                    mpfpg.compare(&tx.max_priority_fee_per_gas.unwrap())
-                }
-                TransactionFilter::YParity(yp) => yp.compare(&tx.y_parity.unwrap()),
-                // TODO: once we have implemented the transaction receipt fields, should validate the block id
-                TransactionFilter::BlockId(_) => true,
+        self.filters().map_or(true, |filters| {
+            filters.iter().all(|filter| filter.matches(tx))
+        })
+    }
+
+    /// The filters, with the ones grouped with `OR` and `AND` taken out of their groups
+    fn conditions(&self) -> impl Iterator<Item = &TransactionFilter> {
+        self.filters()
+            .into_iter()
+            .flatten()
+            .flat_map(TransactionFilter::conditions)
+    }
+
+    /// ENS names used by the `from` and `to` filters
+    pub fn ens_names(&self) -> HashSet<&String> {
+        self.conditions()
+            .filter_map(|filter| match filter {
+                TransactionFilter::From(f) | TransactionFilter::To(f) => Some(f.values()),
+                _ => None,
             })
-        } else {
-            true
+            .flatten()
+            .filter_map(|id| match id {
+                NameOrAddress::Name(name) => Some(name),
+                NameOrAddress::Address(_) => None,
+            })
+            .collect()
+    }
+
+    /// Replaces the ENS names of the `from` and `to` filters with the addresses they resolve to.
+    /// Until then, the filters don't match any transaction.
+    pub fn resolve_names(&mut self, addresses: &HashMap<String, Address>) {
+        for filter in self.filters.iter_mut().flatten() {
+            filter.resolve_names(addresses);
         }
     }
 
+    /// Replaces the block filter, or adds one if there is none.
+    pub fn set_block_id(&mut self, block_id: BlockId) {
+        let filters = self.filters.get_or_insert_with(Vec::new);
+        filters.retain(|f| !matches!(f, TransactionFilter::BlockId(_)));
+        filters.push(TransactionFilter::BlockId(block_id));
+    }
+
+    pub fn has_method_id_filter(&self) -> bool {
+        self.conditions()
+            .any(|f| matches!(f, TransactionFilter::MethodId(_)))
+    }
+
+    pub fn has_nonce_filter(&self) -> bool {
+        self.conditions()
+            .any(|f| matches!(f, TransactionFilter::Nonce(_)))
+    }
+
+    pub fn has_from_filter(&self) -> bool {
+        self.conditions().any(|f| {
+            matches!(
+                f,
+                TransactionFilter::From(_) | TransactionFilter::FromRange(_)
+            )
+        })
+    }
+
+    pub fn has_to_filter(&self) -> bool {
+        self.conditions()
+            .any(|f| matches!(f, TransactionFilter::To(_) | TransactionFilter::ToRange(_)))
+    }
+
+    /// Sender and nonce of the transaction, for queries filtered by `from = <address>` and
+    /// `nonce = <n>`, which match at most one transaction.
+    pub fn sender_and_nonce(&self) -> Option<(&NameOrAddress, u64)> {
+        let filters = self.filters()?;
+        let sender = filters.iter().find_map(|f| match f {
+            TransactionFilter::From(EqualityFilter::Eq(sender)) => Some(sender),
+            _ => None,
+        })?;
+        let nonce = filters.iter().find_map(|f| match f {
+            TransactionFilter::Nonce(FilterType::Equality(EqualityFilter::Eq(nonce))) => {
+                Some(*nonce)
+            }
+            _ => None,
+        })?;
+
+        Some((sender, nonce))
+    }
+
+    /// Whether the query is filtered by `from` or `to` without the hashes, the block or the nonce
+    /// of its transactions, so they're searched for in the latest blocks.
+    pub fn scans_latest_blocks(&self) -> bool {
+        self.ids.is_none()
+            && !self.has_block_filter()
+            && self.sender_and_nonce().is_none()
+            && self.filters().is_some_and(|filters| {
+                filters.iter().any(|f| {
+                    matches!(
+                        f,
+                        TransactionFilter::From(_)
+                            | TransactionFilter::To(_)
+                            | TransactionFilter::FromRange(_)
+                            | TransactionFilter::ToRange(_)
+                    )
+                })
+            })
+    }
+
+    /// Whether the transactions are read from whole blocks, so every transaction of the blocks
+    /// they're in is fetched along with them.
+    pub fn reads_whole_blocks(&self) -> bool {
+        self.ids.is_none() && (self.has_block_filter() || self.scans_latest_blocks())
+    }
+
+    pub fn has_status_filter(&self) -> bool {
+        self.conditions()
+            .any(|f| matches!(f, TransactionFilter::Status(_)))
+    }
+
+    /// Whether the fields or the filters of the query need the transaction bodies. Without them,
+    /// the blocks are fetched with only the hashes of their transactions.
+    pub fn needs_bodies(&self) -> bool {
+        let filters_need_bodies = self.conditions().any(|f| {
+            !matches!(
+                f,
+                TransactionFilter::BlockId(_) | TransactionFilter::Hash(_)
+            )
+        });
+
+        filters_need_bodies || self.fields.iter().any(TransactionField::needs_body)
+    }
+
     pub fn has_block_filter(&self) -> bool {
         match self.filters() {
             Some(filters) => filters
                 .iter()
-                .any(|f| matches!(f, TransactionFilter::BlockId(BlockId::Range(_)))),
+                .any(|f| matches!(f, TransactionFilter::BlockId(_))),
             None => false,
         }
     }
@@ -125,6 +230,7 @@ impl TryFrom<Pairs<'_, Rule>> for Transaction {
         let mut ids: Option<Vec<B256>> = None;
         let mut filter: Option<Vec<TransactionFilter>> = None;
         let mut fields: Vec<TransactionField> = vec![];
+        let mut wildcard = false;
 
         for pair in pairs {
             match pair.as_rule() {
@@ -144,17 +250,30 @@ impl TryFrom<Pairs<'_, Rule>> for Transaction {
                     }
                 }
                 Rule::tx_fields => {
-                    let inner_pairs = pair.into_inner();
+                    let mut inner_pairs = pair.into_inner();
 
                     if let Some(pair) = inner_pairs.peek() {
                         if pair.as_rule() == Rule::wildcard {
-                            fields = TransactionField::all_variants().to_vec();
-                            continue;
+                            inner_pairs.next();
+                            wildcard = true;
                         }
                     }
-                    fields = inner_pairs
+                    let listed_fields = inner_pairs
+                        .filter_map(selected_field)
                         .map(|pair| TransactionField::try_from(pair.as_str()))
                         .collect::<Result<Vec<TransactionField>, TransactionFieldError>>()?;
+
+                    if wildcard {
+                        // The fields listed after `*` are added to it, e.g. receipt fields
+                        fields = TransactionField::wildcard_fields();
+                        for field in listed_fields {
+                            if !fields.contains(&field) {
+                                fields.push(field);
+                            }
+                        }
+                    } else {
+                        fields = listed_fields;
+                    }
                 }
                 _ => {
                     return Err(TransactionError::UnexpectedToken(pair.as_str().to_string()));
@@ -170,13 +289,25 @@ impl TryFrom<Pairs<'_, Rule>> for Transaction {
     }
 }
 
+/// Fields of a transaction. The ones marked `#[enum_variants(skip)]` cost extra requests or change
+/// between runs, and are only read when the query lists them.
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, EnumVariants)]
 pub enum TransactionField {
     TransactionType,
+    /// Name of the transaction type, like "legacy" or "eip1559"
+    TxTypeLabel,
     Hash,
+    /// Position of the transaction in its block
+    TransactionIndex,
+    BlockNumber,
+    BlockHash,
     From,
     To,
+    /// Number of transactions sent by the sender before this one
+    Nonce,
     Data,
+    /// The 4-byte function selector at the start of the input data
+    MethodId,
     Value,
     GasPrice,
     Gas,
@@ -186,20 +317,119 @@ pub enum TransactionField {
     R,
     S,
     MaxFeePerBlobGas,
+    BlobVersionedHashes,
+    /// Number of blobs carried by the transaction, derived from the blob versioned hashes
+    BlobCount,
     MaxFeePerGas,
     MaxPriorityFeePerGas,
+    /// Priority fee per gas paid to the block producer, after the base fee of the block. Transactions
+    /// queried by hash fetch the header of their block for it
+    #[enum_variants(skip)]
+    EffectiveTip,
+    /// Percentage of the transactions of the block paying a lower gas price, ranked against every
+    /// transaction of the block
+    #[enum_variants(skip)]
+    GasPricePercentile,
+    /// Number of blocks produced on top of the block of the transaction, which grows with every new
+    /// block
+    #[enum_variants(skip)]
+    Confirmations,
+    AccessList,
     YParity,
+    /// Authorizations of an EIP-7702 set-code transaction
+    AuthorizationList,
+    /// RLP-encoded signed transaction, as it would be broadcast
+    Raw,
+    /// Whether the signer recovered from the signature is `from`, and the hash of the signed
+    /// fields is the transaction hash
+    SignerValid,
     Chain,
+    GasUsed,
+    EffectiveGasPrice,
+    CumulativeGasUsed,
+    LogsBloom,
+    ContractAddress,
+    Logs,
+    /// Fee paid for posting the transaction data to L1, reported by OP-stack chains
+    L1Fee,
+    /// L1 gas used by the transaction data, reported by OP-stack chains
+    L1GasUsed,
+    /// L1 gas price the L1 fee was computed with, reported by OP-stack chains
+    L1GasPrice,
+    /// Scalar applied to the L1 fee, reported by OP-stack chains before the Ecotone upgrade
+    L1FeeScalar,
+    /// Tree of the calls made by the transaction, with `debug_traceTransaction`, which only some
+    /// RPCs serve
+    #[enum_variants(skip)]
+    Trace,
+    /// Reason a failed transaction reverted, read by replaying it with `eth_call` at its block
+    #[enum_variants(skip)]
+    RevertReason,
+}
+
+impl TransactionField {
+    /// Fields selected by `*`. The receipt fields cost a request per transaction, so they're left
+    /// out unless they're listed after the `*`, as in `GET *, status FROM tx ...`.
+    pub fn wildcard_fields() -> Vec<TransactionField> {
+        TransactionField::all_variants()
+            .iter()
+            .filter(|field| !field.is_receipt_field())
+            .copied()
+            .collect()
+    }
+
+    /// Whether the field is read from the transaction body. The other fields are known from the
+    /// block listing the transaction hashes, or read from the receipt or the trace.
+    pub fn needs_body(&self) -> bool {
+        match self {
+            TransactionField::Hash
+            | TransactionField::TransactionIndex
+            | TransactionField::BlockNumber
+            | TransactionField::BlockHash
+            | TransactionField::Chain
+            | TransactionField::Confirmations
+            | TransactionField::Trace => false,
+            // The transaction is replayed from its body
+            TransactionField::RevertReason => true,
+            field => !field.is_receipt_field(),
+        }
+    }
+
+    /// Whether the field is taken from the transaction receipt instead of the transaction itself
+    pub fn is_receipt_field(&self) -> bool {
+        matches!(
+            self,
+            TransactionField::Status
+                | TransactionField::GasUsed
+                | TransactionField::EffectiveGasPrice
+                | TransactionField::CumulativeGasUsed
+                | TransactionField::LogsBloom
+                | TransactionField::ContractAddress
+                | TransactionField::Logs
+                | TransactionField::L1Fee
+                | TransactionField::L1GasUsed
+                | TransactionField::L1GasPrice
+                | TransactionField::L1FeeScalar
+                // Only the transactions the receipt reports as failed are replayed
+                | TransactionField::RevertReason
+        )
+    }
 }
 
 impl std::fmt::Display for TransactionField {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             TransactionField::TransactionType => write!(f, "transaction_type"),
+            TransactionField::TxTypeLabel => write!(f, "tx_type_label"),
+            TransactionField::TransactionIndex => write!(f, "transaction_index"),
+            TransactionField::BlockNumber => write!(f, "block_number"),
+            TransactionField::BlockHash => write!(f, "block_hash"),
             TransactionField::Hash => write!(f, "hash"),
             TransactionField::From => write!(f, "from"),
             TransactionField::To => write!(f, "to"),
+            TransactionField::Nonce => write!(f, "nonce"),
             TransactionField::Data => write!(f, "data"),
+            TransactionField::MethodId => write!(f, "method_id"),
             TransactionField::Value => write!(f, "value"),
             TransactionField::GasPrice => write!(f, "gas_price"),
             TransactionField::Gas => write!(f, "gas"),
@@ -209,10 +439,31 @@ impl std::fmt::Display for TransactionField {
             TransactionField::R => write!(f, "r"),
             TransactionField::S => write!(f, "s"),
             TransactionField::MaxFeePerBlobGas => write!(f, "max_fee_per_blob_gas"),
+            TransactionField::BlobVersionedHashes => write!(f, "blob_versioned_hashes"),
+            TransactionField::BlobCount => write!(f, "blob_count"),
             TransactionField::MaxFeePerGas => write!(f, "max_fee_per_gas"),
             TransactionField::MaxPriorityFeePerGas => write!(f, "max_priority_fee_per_gas"),
+            TransactionField::EffectiveTip => write!(f, "effective_tip"),
+            TransactionField::GasPricePercentile => write!(f, "gas_price_percentile"),
+            TransactionField::Confirmations => write!(f, "confirmations"),
+            TransactionField::AccessList => write!(f, "access_list"),
             TransactionField::YParity => write!(f, "y_parity"),
+            TransactionField::AuthorizationList => write!(f, "authorization_list"),
+            TransactionField::Raw => write!(f, "raw"),
+            TransactionField::SignerValid => write!(f, "signer_valid"),
             TransactionField::Chain => write!(f, "chain"),
+            TransactionField::GasUsed => write!(f, "gas_used"),
+            TransactionField::EffectiveGasPrice => write!(f, "effective_gas_price"),
+            TransactionField::CumulativeGasUsed => write!(f, "cumulative_gas_used"),
+            TransactionField::LogsBloom => write!(f, "logs_bloom"),
+            TransactionField::ContractAddress => write!(f, "contract_address"),
+            TransactionField::Logs => write!(f, "logs"),
+            TransactionField::L1Fee => write!(f, "l1_fee"),
+            TransactionField::L1GasUsed => write!(f, "l1_gas_used"),
+            TransactionField::L1GasPrice => write!(f, "l1_gas_price"),
+            TransactionField::L1FeeScalar => write!(f, "l1_fee_scalar"),
+            TransactionField::Trace => write!(f, "trace"),
+            TransactionField::RevertReason => write!(f, "revert_reason"),
         }
     }
 }
@@ -230,10 +481,16 @@ impl TryFrom<&str> for TransactionField {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
             "transaction_type" => Ok(TransactionField::TransactionType),
+            "tx_type_label" => Ok(TransactionField::TxTypeLabel),
+            "transaction_index" => Ok(TransactionField::TransactionIndex),
+            "block_number" => Ok(TransactionField::BlockNumber),
+            "block_hash" => Ok(TransactionField::BlockHash),
             "hash" => Ok(TransactionField::Hash),
             "from" => Ok(TransactionField::From),
             "to" => Ok(TransactionField::To),
+            "nonce" => Ok(TransactionField::Nonce),
             "data" => Ok(TransactionField::Data),
+            "method_id" => Ok(TransactionField::MethodId),
             "value" => Ok(TransactionField::Value),
             "gas_price" => Ok(TransactionField::GasPrice),
             "gas" => Ok(TransactionField::Gas),
@@ -243,10 +500,31 @@ impl TryFrom<&str> for TransactionField {
             "r" => Ok(TransactionField::R),
             "s" => Ok(TransactionField::S),
             "max_fee_per_blob_gas" => Ok(TransactionField::MaxFeePerBlobGas),
+            "blob_versioned_hashes" => Ok(TransactionField::BlobVersionedHashes),
+            "blob_count" => Ok(TransactionField::BlobCount),
             "max_fee_per_gas" => Ok(TransactionField::MaxFeePerGas),
             "max_priority_fee_per_gas" => Ok(TransactionField::MaxPriorityFeePerGas),
+            "effective_tip" => Ok(TransactionField::EffectiveTip),
+            "gas_price_percentile" => Ok(TransactionField::GasPricePercentile),
+            "confirmations" => Ok(TransactionField::Confirmations),
+            "access_list" => Ok(TransactionField::AccessList),
             "y_parity" => Ok(TransactionField::YParity),
+            "authorization_list" => Ok(TransactionField::AuthorizationList),
+            "raw" => Ok(TransactionField::Raw),
+            "signer_valid" => Ok(TransactionField::SignerValid),
             "chain" => Ok(TransactionField::Chain),
+            "gas_used" => Ok(TransactionField::GasUsed),
+            "effective_gas_price" => Ok(TransactionField::EffectiveGasPrice),
+            "cumulative_gas_used" => Ok(TransactionField::CumulativeGasUsed),
+            "logs_bloom" => Ok(TransactionField::LogsBloom),
+            "contract_address" => Ok(TransactionField::ContractAddress),
+            "logs" => Ok(TransactionField::Logs),
+            "l1_fee" => Ok(TransactionField::L1Fee),
+            "l1_gas_used" => Ok(TransactionField::L1GasUsed),
+            "l1_gas_price" => Ok(TransactionField::L1GasPrice),
+            "l1_fee_scalar" => Ok(TransactionField::L1FeeScalar),
+            "trace" => Ok(TransactionField::Trace),
+            "revert_reason" => Ok(TransactionField::RevertReason),
             invalid_field => Err(TransactionFieldError::InvalidTransactionField(
                 invalid_field.to_string(),
             )),
@@ -260,6 +538,10 @@ pub enum TransactionFilterError {
     InvalidTransactionFilterProperty(String),
     #[error("Missing operator in filter")]
     MissingOperator,
+    #[error("Missing bound in BETWEEN filter")]
+    MissingBound,
+    #[error("Invalid number in filter: {0}")]
+    InvalidNumber(String),
     #[error(transparent)]
     EntityIdError(#[from] EntityIdError),
     #[error(transparent)]
@@ -272,13 +554,18 @@ pub enum TransactionFilterError {
     FilterError(#[from] FilterError),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum TransactionFilter {
     TransactionType(EqualityFilter<u8>),
     Hash(EqualityFilter<B256>),
-    From(EqualityFilter<Address>),
-    To(EqualityFilter<Address>),
+    From(EqualityFilter<NameOrAddress>),
+    To(EqualityFilter<NameOrAddress>),
+    /// Compares the sender as a 160-bit integer, e.g. `from < 0x0000...1000`
+    FromRange(ComparisonFilter<U256>),
+    /// Compares the recipient as a 160-bit integer, e.g. `to < 0x0000...1000`
+    ToRange(ComparisonFilter<U256>),
     Data(EqualityFilter<alloy::primitives::Bytes>),
+    MethodId(EqualityFilter<FixedBytes<4>>),
     Value(FilterType<U256>),
     GasPrice(FilterType<u128>),
     Gas(FilterType<u128>),
@@ -292,9 +579,83 @@ pub enum TransactionFilter {
     MaxFeePerGas(FilterType<u128>),
     MaxPriorityFeePerGas(FilterType<u128>),
     YParity(EqualityFilter<bool>),
+    Nonce(FilterType<u64>),
+    /// Passes if any of the filters passes, e.g. `(to 0x... OR to 0x...)`
+    Or(Vec<TransactionFilter>),
+    /// Passes if all the filters pass, e.g. the `to 0x... AND value > 0` branch of an `OR` group
+    And(Vec<TransactionFilter>),
 }
 
 impl TransactionFilter {
+    /// Whether the transaction passes the filter
+    pub fn matches(&self, tx: &TransactionQueryRes) -> bool {
+        match self {
+            Self::TransactionType(t) => t.compare(&tx.transaction_type.unwrap()),
+            Self::Hash(h) => h.compare(&tx.hash.unwrap()),
+            Self::From(f) => f.compare(&NameOrAddress::Address(tx.from.unwrap())),
+            // Contract creations have no recipient, so they only match `!=` and `NOT IN`
+            Self::To(t) => match tx.to {
+                Some(to) => t.compare(&NameOrAddress::Address(to)),
+                None => matches!(t, EqualityFilter::Neq(_) | EqualityFilter::NotIn(_)),
+            },
+            Self::FromRange(f) => f.compare(&address_to_u256(tx.from.unwrap())),
+            Self::ToRange(t) => tx.to.is_some_and(|to| t.compare(&address_to_u256(to))),
+            Self::Data(d) => d.compare(&tx.data.clone().unwrap()),
+            // Transactions without a selector, like plain transfers, never match
+            Self::MethodId(m) => tx.method_id.is_some_and(|id| m.compare(&id)),
+            Self::Value(v) => v.compare(&tx.value.unwrap()),
+            Self::GasPrice(gp) => gp.compare(&tx.gas_price.unwrap()),
+            Self::Gas(g) => g.compare(&tx.gas.unwrap()),
+            Self::ChainId(cid) => cid.compare(&tx.chain_id.unwrap()),
+            // The status is read from the receipt, which the resolver fetches for the filter
+            Self::Status(s) => tx.status.is_some_and(|status| s.compare(&status)),
+            Self::V(v) => v.compare(&tx.v.unwrap()),
+            Self::R(r) => r.compare(&tx.r.unwrap()),
+            Self::S(s) => s.compare(&tx.s.unwrap()),
+            Self::MaxFeePerBlobGas(mfbg) => mfbg.compare(&tx.max_fee_per_blob_gas.unwrap()),
+            Self::MaxFeePerGas(mfg) => mfg.compare(&tx.max_fee_per_gas.unwrap()),
+            Self::MaxPriorityFeePerGas(mpfpg) => {
+                mpfpg.compare(&tx.max_priority_fee_per_gas.unwrap())
+            }
+            Self::YParity(yp) => yp.compare(&tx.y_parity.unwrap()),
+            Self::Nonce(n) => n.compare(&tx.nonce.unwrap()),
+            // TODO: once we have implemented the transaction receipt fields, should validate the block id
+            Self::BlockId(_) => true,
+            Self::Or(filters) => filters.iter().any(|filter| filter.matches(tx)),
+            Self::And(filters) => filters.iter().all(|filter| filter.matches(tx)),
+        }
+    }
+
+    /// The filter, or the filters of its group, recursively
+    fn conditions(&self) -> Box<dyn Iterator<Item = &TransactionFilter> + '_> {
+        match self {
+            Self::Or(filters) | Self::And(filters) => {
+                Box::new(filters.iter().flat_map(TransactionFilter::conditions))
+            }
+            filter => Box::new(std::iter::once(filter)),
+        }
+    }
+
+    fn resolve_names(&mut self, addresses: &HashMap<String, Address>) {
+        match self {
+            Self::From(f) | Self::To(f) => {
+                for id in f.values_mut() {
+                    if let NameOrAddress::Name(name) = id {
+                        if let Some(address) = addresses.get(name) {
+                            *id = NameOrAddress::Address(*address);
+                        }
+                    }
+                }
+            }
+            Self::Or(filters) | Self::And(filters) => {
+                for filter in filters {
+                    filter.resolve_names(addresses);
+                }
+            }
+            _ => {}
+        }
+    }
+
     pub fn as_block_id(&self) -> Result<&BlockId, TransactionFilterError> {
         if let TransactionFilter::BlockId(block_id) = self {
             Ok(block_id)
@@ -310,15 +671,27 @@ impl TransactionFilter {
         constructor: impl FnOnce(FilterType<T>) -> TransactionFilter,
     ) -> Result<TransactionFilter, TransactionFilterError>
     where
-        F: FnOnce(&str) -> T,
+        F: Fn(&str) -> Result<T, TransactionFilterError>,
         FilterType<T>: TryFrom<(Pair<'a, Rule>, T), Error = FilterError>,
     {
         let mut inner_pair = pair.into_inner();
         let operator = inner_pair.next();
 
         match operator {
+            Some(op) if op.as_rule() == Rule::between_operator => {
+                let mut bound = || {
+                    inner_pair
+                        .next()
+                        .map(|value| value_parser(&expand_exponent(value.as_str().trim())))
+                        .ok_or(TransactionFilterError::MissingBound)?
+                };
+                let (min, max) = (bound()?, bound()?);
+                Ok(constructor(FilterType::Comparison(
+                    ComparisonFilter::Between(min, max),
+                )))
+            }
             Some(op) => {
-                let value = value_parser(inner_pair.as_str().trim());
+                let value = value_parser(&expand_exponent(inner_pair.as_str().trim()))?;
                 Ok(constructor(FilterType::try_from((op, value))?))
             }
             None => Err(TransactionFilterError::MissingOperator),
@@ -349,6 +722,58 @@ impl TransactionFilter {
         }
     }
 
+    // Helper function to parse the `from` and `to` filters compared with `<`, `>`, `<=`, `>=` or
+    // `BETWEEN`, which order the addresses as 160-bit integers
+    fn parse_address_range_filter(
+        pair: Pair<'_, Rule>,
+        constructor: impl FnOnce(ComparisonFilter<U256>) -> TransactionFilter,
+    ) -> Result<TransactionFilter, TransactionFilterError> {
+        let mut inner_pair = pair.into_inner();
+        let operator = inner_pair
+            .next()
+            .ok_or(TransactionFilterError::MissingOperator)?;
+        let mut address = || -> Result<U256, TransactionFilterError> {
+            let value = inner_pair
+                .next()
+                .ok_or(TransactionFilterError::MissingBound)?;
+            Ok(address_to_u256(Address::from_str(value.as_str().trim())?))
+        };
+
+        let filter = match operator.as_rule() {
+            Rule::between_operator => ComparisonFilter::Between(address()?, address()?),
+            _ => ComparisonFilter::try_from((operator, address()?))?,
+        };
+
+        Ok(constructor(filter))
+    }
+
+    // Helper function to parse the `from` and `to` filters, which compare against either a single
+    // name or address, or a list of them with `IN` and `NOT IN`
+    fn parse_name_or_address_filter(
+        pair: Pair<'_, Rule>,
+        constructor: impl FnOnce(EqualityFilter<NameOrAddress>) -> TransactionFilter,
+    ) -> Result<TransactionFilter, TransactionFilterError> {
+        let mut inner_pair = pair.into_inner();
+        let operator = inner_pair
+            .next()
+            .ok_or(TransactionFilterError::MissingOperator)?;
+
+        let filter = match operator.as_rule() {
+            Rule::list_operators => {
+                let values = inner_pair
+                    .map(|value| NameOrAddress::from_str(value.as_str()).unwrap())
+                    .collect();
+                EqualityFilter::try_from_list(operator, values)
+            }
+            _ => {
+                let value = NameOrAddress::from_str(inner_pair.as_str()).unwrap();
+                EqualityFilter::try_from((operator, value))
+            }
+        };
+
+        Ok(constructor(filter.map_err(FilterError::from)?))
+    }
+
     // Implementation using the helper
     fn try_from(pair: Pair<'_, Rule>) -> Result<Self, TransactionFilterError> {
         match pair.as_rule() {
@@ -359,45 +784,53 @@ impl TransactionFilter {
                     .trim_start_matches(|c: char| c.is_whitespace() || c == '=')
                     .trim();
 
-                let (start, end) = match range.split_once(':') {
-                    // if ":" is present, we have a start and an end
-                    Some((start, end)) => (
-                        parse_block_number_or_tag(start)?,
-                        Some(parse_block_number_or_tag(end)?),
-                    ),
-                    // else we only have start
-                    None => (parse_block_number_or_tag(range)?, None),
-                };
-                Ok(TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
-                    start, end,
-                ))))
+                if range.starts_with("0x") {
+                    return Ok(TransactionFilter::BlockId(BlockId::Hash(B256::from_str(
+                        range,
+                    )?)));
+                }
+
+                Ok(TransactionFilter::BlockId(BlockId::Range(
+                    parse_block_range(range)?,
+                )))
+            }
+            Rule::tx_or_filter => {
+                let branches = pair
+                    .into_inner()
+                    .map(|branch| {
+                        let mut filters = branch
+                            .into_inner()
+                            .map(Self::try_from)
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(match filters.len() {
+                            1 => filters.remove(0),
+                            _ => TransactionFilter::And(filters),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, TransactionFilterError>>()?;
+
+                Ok(TransactionFilter::Or(branches))
+            }
+            Rule::value_filter_type => {
+                Self::parse_filter(pair, parse_number::<U256>, TransactionFilter::Value)
+            }
+            Rule::gas_price_filter_type => {
+                Self::parse_filter(pair, parse_number::<u128>, TransactionFilter::GasPrice)
             }
-            Rule::value_filter_type => Self::parse_filter(
-                pair,
-                |s| U256::from_str(s).unwrap(),
-                TransactionFilter::Value,
-            ),
-            Rule::gas_price_filter_type => Self::parse_filter(
-                pair,
-                |s| s.parse::<u128>().unwrap(),
-                TransactionFilter::GasPrice,
-            ),
             Rule::gas_filter_type => {
-                Self::parse_filter(pair, |s| s.parse::<u128>().unwrap(), TransactionFilter::Gas)
+                Self::parse_filter(pair, parse_number::<u128>, TransactionFilter::Gas)
             }
             Rule::max_fee_per_blob_gas_filter_type => Self::parse_filter(
                 pair,
-                |s| s.parse::<u128>().unwrap(),
+                parse_number::<u128>,
                 TransactionFilter::MaxFeePerBlobGas,
             ),
-            Rule::max_fee_per_gas_filter_type => Self::parse_filter(
-                pair,
-                |s| s.parse::<u128>().unwrap(),
-                TransactionFilter::MaxFeePerGas,
-            ),
+            Rule::max_fee_per_gas_filter_type => {
+                Self::parse_filter(pair, parse_number::<u128>, TransactionFilter::MaxFeePerGas)
+            }
             Rule::max_priority_fee_per_gas_filter_type => Self::parse_filter(
                 pair,
-                |s| s.parse::<u128>().unwrap(),
+                parse_number::<u128>,
                 TransactionFilter::MaxPriorityFeePerGas,
             ),
             Rule::status_filter_type => {
@@ -409,16 +842,16 @@ impl TransactionFilter {
                     EqualityFilter::try_from((operator, value == "true")).unwrap(),
                 ))
             }
-            Rule::from_filter_type => Self::parse_equality_filter(
-                pair,
-                |s| Address::from_str(s).unwrap(),
-                TransactionFilter::From,
-            ),
-            Rule::to_filter_type => Self::parse_equality_filter(
-                pair,
-                |s| Address::from_str(s).unwrap(),
-                TransactionFilter::To,
-            ),
+            Rule::from_filter_type if is_address_range(&pair) => {
+                Self::parse_address_range_filter(pair, TransactionFilter::FromRange)
+            }
+            Rule::to_filter_type if is_address_range(&pair) => {
+                Self::parse_address_range_filter(pair, TransactionFilter::ToRange)
+            }
+            Rule::from_filter_type => {
+                Self::parse_name_or_address_filter(pair, TransactionFilter::From)
+            }
+            Rule::to_filter_type => Self::parse_name_or_address_filter(pair, TransactionFilter::To),
             Rule::data_filter_type => {
                 let mut inner_pair = pair.into_inner();
                 let operator = inner_pair.next().unwrap();
@@ -427,6 +860,14 @@ impl TransactionFilter {
 
                 Ok(TransactionFilter::Data(result))
             }
+            Rule::method_id_filter_type => Self::parse_equality_filter(
+                pair,
+                |s| FixedBytes::<4>::from_str(s.trim()).unwrap(),
+                TransactionFilter::MethodId,
+            ),
+            Rule::nonce_filter_type => {
+                Self::parse_filter(pair, parse_number::<u64>, TransactionFilter::Nonce)
+            }
             Rule::y_parity_filter_type => {
                 let mut inner_pair = pair.into_inner();
                 let operator = inner_pair.next().unwrap();
@@ -445,10 +886,34 @@ impl TransactionFilter {
     }
 }
 
+/// Parses the value of a numeric filter, which fails for decimals like `1.5` and for values that
+/// don't fit the field
+fn parse_number<T: FromStr>(value: &str) -> Result<T, TransactionFilterError> {
+    value
+        .parse()
+        .map_err(|_| TransactionFilterError::InvalidNumber(value.to_string()))
+}
+
+/// Whether a `from` or `to` filter compares the addresses as integers rather than for equality
+fn is_address_range(pair: &Pair<'_, Rule>) -> bool {
+    pair.clone().into_inner().next().is_some_and(|operator| {
+        matches!(
+            operator.as_rule(),
+            Rule::comparison_operators | Rule::between_operator
+        )
+    })
+}
+
+/// The address as a 160-bit big-endian integer, so addresses are ordered like their hex strings
+fn address_to_u256(address: Address) -> U256 {
+    U256::from_be_slice(address.as_slice())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::common::filters::ComparisonFilter;
+    use alloy::eips::BlockNumberOrTag;
 
     #[test]
     fn test_return_true_if_tx_passes_all_filters() {
@@ -487,4 +952,111 @@ mod tests {
 
         assert_eq!(false, transaction.filter(&tx_query_res));
     }
+
+    #[test]
+    fn test_or_filter_matches_tx_passing_any_branch() {
+        let (a, b, c) = (
+            Address::repeat_byte(0xa),
+            Address::repeat_byte(0xb),
+            Address::ZERO,
+        );
+        let to =
+            |address| TransactionFilter::To(EqualityFilter::Eq(NameOrAddress::Address(address)));
+        let tx = |to, value| TransactionQueryRes {
+            to: Some(to),
+            value: Some(U256::from(value)),
+            ..Default::default()
+        };
+        // (to a OR to b) AND value > 100
+        let transaction = Transaction::new(
+            None,
+            Some(vec![
+                TransactionFilter::Or(vec![to(a), to(b)]),
+                TransactionFilter::Value(FilterType::Comparison(ComparisonFilter::Gt(U256::from(
+                    100,
+                )))),
+            ]),
+            vec![TransactionField::Hash],
+        );
+
+        assert!(transaction.filter(&tx(a, 101)));
+        assert!(transaction.filter(&tx(b, 101)));
+        assert!(!transaction.filter(&tx(c, 101)));
+        assert!(!transaction.filter(&tx(a, 100)));
+    }
+
+    #[test]
+    fn test_contract_creations_only_match_negated_to_filters() {
+        let recipient = NameOrAddress::Address(Address::repeat_byte(0xa));
+        let creation = TransactionQueryRes {
+            to: None,
+            ..Default::default()
+        };
+        let matches = |filter| TransactionFilter::To(filter).matches(&creation);
+
+        assert!(!matches(EqualityFilter::Eq(recipient.clone())));
+        assert!(!matches(EqualityFilter::In(vec![recipient.clone()])));
+        assert!(matches(EqualityFilter::Neq(recipient.clone())));
+        assert!(matches(EqualityFilter::NotIn(vec![recipient])));
+    }
+
+    #[test]
+    fn test_to_range_filter_selects_addresses_below_threshold() {
+        let threshold = Address::with_last_byte(0x10);
+        let tx = |to| TransactionQueryRes {
+            to,
+            ..Default::default()
+        };
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::ToRange(ComparisonFilter::Lt(
+                address_to_u256(threshold),
+            ))]),
+            vec![TransactionField::Hash],
+        );
+
+        assert!(transaction.filter(&tx(Some(Address::ZERO))));
+        assert!(transaction.filter(&tx(Some(Address::with_last_byte(0x0f)))));
+        assert!(!transaction.filter(&tx(Some(threshold))));
+        // Compared as integers, so the leading bytes weigh the most
+        assert!(!transaction.filter(&tx(Some(Address::left_padding_from(&[1, 0])))));
+        // Contract creations have no recipient
+        assert!(!transaction.filter(&tx(None)));
+    }
+
+    #[test]
+    fn test_method_id_filter_never_matches_tx_without_selector() {
+        let tx_query_res = TransactionQueryRes::default();
+
+        for filter in [EqualityFilter::Eq, EqualityFilter::Neq] {
+            let transaction = Transaction::new(
+                None,
+                Some(vec![TransactionFilter::MethodId(filter(FixedBytes::new(
+                    [0xa9, 0x05, 0x9c, 0xbb],
+                )))]),
+                vec![TransactionField::Hash],
+            );
+
+            assert_eq!(false, transaction.filter(&tx_query_res));
+        }
+    }
+
+    #[test]
+    fn test_only_body_fields_and_filters_need_bodies() {
+        let block_filter = TransactionFilter::BlockId(BlockId::Number(BlockNumberOrTag::Latest));
+        let transaction = |fields, filter: Option<TransactionFilter>| {
+            let filters = std::iter::once(block_filter.clone())
+                .chain(filter)
+                .collect();
+            Transaction::new(None, Some(filters), fields)
+        };
+        let from =
+            TransactionFilter::From(EqualityFilter::Eq(NameOrAddress::Address(Address::ZERO)));
+
+        assert!(!transaction(vec![TransactionField::Hash], None).needs_bodies());
+        assert!(!transaction(vec![TransactionField::GasUsed], None).needs_bodies());
+        assert!(transaction(vec![TransactionField::Value], None).needs_bodies());
+        assert!(transaction(vec![TransactionField::RevertReason], None).needs_bodies());
+        assert!(transaction(vec![TransactionField::Hash], Some(from)).needs_bodies());
+    }
 }
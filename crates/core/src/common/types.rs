@@ -1,15 +1,18 @@
 use super::{
+    aggregate::{Aggregate, AggregateError},
     chain::{Chain, ChainError, ChainOrRpc},
     dump::{Dump, DumpError},
     entity::{Entity, EntityError},
+    order::{OrderBy, OrderByError},
 };
 use crate::interpreter::frontend::parser::Rule;
 use alloy::transports::http::reqwest::Url;
-use pest::iterators::Pairs;
+use pest::iterators::{Pair, Pairs};
 
 #[derive(Debug, PartialEq)]
 pub enum Expression {
     Get(GetExpression),
+    Combined(CombinedExpression),
 }
 
 #[derive(Debug, PartialEq)]
@@ -17,14 +20,28 @@ pub struct GetExpression {
     pub entity: Entity,
     pub chains: Vec<ChainOrRpc>,
     pub dump: Option<Dump>,
+    pub order_by: Option<OrderBy>,
+    pub limit: Option<usize>,
+    /// Aggregates computed over the results instead of returning them, e.g. `sum(value)`
+    pub aggregates: Vec<Aggregate>,
 }
 
 impl GetExpression {
-    fn new(entity: Entity, chains: Vec<ChainOrRpc>, dump: Option<Dump>) -> Self {
+    fn new(
+        entity: Entity,
+        chains: Vec<ChainOrRpc>,
+        dump: Option<Dump>,
+        order_by: Option<OrderBy>,
+        limit: Option<usize>,
+        aggregates: Vec<Aggregate>,
+    ) -> Self {
         Self {
             entity,
             chains,
             dump,
+            order_by,
+            limit,
+            aggregates,
         }
     }
 }
@@ -44,6 +61,16 @@ pub enum GetExpressionError {
     ChainError(#[from] ChainError),
     #[error(transparent)]
     DumpError(#[from] DumpError),
+    #[error(transparent)]
+    OrderByError(#[from] OrderByError),
+    #[error("Results can only be ordered by a selected field, {0} isn't selected")]
+    OrderByFieldNotSelected(String),
+    #[error("Invalid limit: {0}")]
+    InvalidLimit(String),
+    #[error(transparent)]
+    AggregateError(#[from] AggregateError),
+    #[error("Aggregates can't be selected along with fields")]
+    AggregatesWithFields,
 }
 
 impl TryFrom<Pairs<'_, Rule>> for GetExpression {
@@ -53,11 +80,16 @@ impl TryFrom<Pairs<'_, Rule>> for GetExpression {
         let mut entity: Option<Entity> = None;
         let mut chains: Option<Vec<ChainOrRpc>> = None;
         let mut dump: Option<Dump> = None;
+        let mut order_by: Option<OrderBy> = None;
+        let mut limit: Option<usize> = None;
+        let mut aggregates: Vec<Aggregate> = vec![];
 
         for pair in pairs {
             match pair.as_rule() {
                 Rule::entity => {
-                    entity = Some(Entity::try_from(pair.into_inner())?);
+                    let (selected, selected_aggregates) = entity_and_aggregates(pair)?;
+                    entity = Some(selected);
+                    aggregates = selected_aggregates;
                 }
                 Rule::chain_selector => {
                     let selector = pair.as_str();
@@ -71,6 +103,17 @@ impl TryFrom<Pairs<'_, Rule>> for GetExpression {
                 Rule::dump => {
                     dump = Some(Dump::try_from(pair.into_inner())?);
                 }
+                Rule::order_by => {
+                    order_by = Some(OrderBy::try_from(pair.into_inner())?);
+                }
+                Rule::limit => {
+                    let value = pair.into_inner().as_str();
+                    limit = Some(
+                        value
+                            .parse()
+                            .map_err(|_| GetExpressionError::InvalidLimit(value.to_string()))?,
+                    );
+                }
                 _ => {
                     return Err(GetExpressionError::UnexpectedToken(
                         pair.as_str().to_string(),
@@ -79,10 +122,117 @@ impl TryFrom<Pairs<'_, Rule>> for GetExpression {
             }
         }
 
+        let entity = entity.ok_or(GetExpressionError::MissingEntity)?;
+
+        if let Some(order_by) = &order_by {
+            if !entity.field_names().contains(&order_by.field) {
+                return Err(GetExpressionError::OrderByFieldNotSelected(
+                    order_by.field.clone(),
+                ));
+            }
+        }
+
         Ok(GetExpression::new(
-            entity.ok_or(GetExpressionError::MissingEntity)?,
+            entity,
             chains.ok_or(GetExpressionError::MissingChainOrRpc)?,
             dump,
+            order_by,
+            limit,
+            aggregates,
         ))
     }
 }
+
+/// Entity selected by the query, and the aggregates computed over its results.
+fn entity_and_aggregates(
+    pair: Pair<'_, Rule>,
+) -> Result<(Entity, Vec<Aggregate>), GetExpressionError> {
+    let mut aggregates: Vec<Aggregate> = vec![];
+    // Number of fields selected along with the aggregates
+    let mut fields = 0;
+
+    for pair in pair.clone().into_inner().flatten() {
+        match pair.as_rule() {
+            Rule::aggregate => aggregates.push(Aggregate::try_from(pair.into_inner())?),
+            Rule::account_field
+            | Rule::block_field
+            | Rule::tx_field
+            | Rule::log_field
+            | Rule::token_field
+            | Rule::receipt_field
+            | Rule::chain_field => fields += 1,
+            _ => {}
+        }
+    }
+
+    if !aggregates.is_empty() && fields > 0 {
+        return Err(GetExpressionError::AggregatesWithFields);
+    }
+
+    Ok((Entity::try_from(pair.into_inner())?, aggregates))
+}
+
+/// Several entities fetched from the same chains in a single statement, e.g. a block and its
+/// transactions. Each entity is resolved as a `GetExpression` of its own, and their results are
+/// returned together.
+#[derive(Debug, PartialEq)]
+pub struct CombinedExpression {
+    pub expressions: Vec<GetExpression>,
+}
+
+impl CombinedExpression {
+    /// Keys of the fields selected for all the entities, in the order they're written in the query
+    pub fn field_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = vec![];
+
+        for key in self
+            .expressions
+            .iter()
+            .flat_map(|expr| expr.entity.field_keys())
+        {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        keys
+    }
+}
+
+impl TryFrom<Pairs<'_, Rule>> for CombinedExpression {
+    type Error = GetExpressionError;
+
+    fn try_from(pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
+        let mut entities: Vec<(Entity, Vec<Aggregate>)> = vec![];
+        let mut chains: Option<Vec<ChainOrRpc>> = None;
+
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::entity => entities.push(entity_and_aggregates(pair)?),
+                Rule::chain_selector => {
+                    chains = Some(Chain::from_selector(pair.as_str())?);
+                }
+                Rule::rpc_url => {
+                    let url = Url::parse(pair.as_str())
+                        .map_err(|e| GetExpressionError::UrlParseError(e.to_string()))?;
+                    chains = Some(vec![ChainOrRpc::Rpc(url)]);
+                }
+                _ => {
+                    return Err(GetExpressionError::UnexpectedToken(
+                        pair.as_str().to_string(),
+                    ))
+                }
+            }
+        }
+
+        let chains = chains.ok_or(GetExpressionError::MissingChainOrRpc)?;
+        let expressions = entities
+            .into_iter()
+            .map(|(entity, aggregates)| {
+                GetExpression::new(entity, chains.clone(), None, None, None, aggregates)
+            })
+            .collect();
+
+        Ok(CombinedExpression { expressions })
+    }
+}
@@ -0,0 +1,183 @@
+//! Cache of the blocks fetched by number while a query runs. A query can fetch the same block
+//! several times, e.g. when its expressions cover overlapping ranges, so the blocks are kept in
+//! memory for the duration of the query and shared by the providers it creates.
+//! Blocks requested with a tag like `latest` are never cached, since the block they point to
+//! changes over time.
+use alloy::{
+    eips::BlockNumberOrTag,
+    rpc::json_rpc::{RequestPacket, Response, ResponsePacket, ResponsePayload, SerializedRequest},
+    transports::{Transport, TransportError, TransportFut},
+};
+use serde_json::value::RawValue;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// RPC URL, block number and whether the block includes the full transactions
+type Key = (String, u64, bool);
+
+#[derive(Clone, Default)]
+pub(crate) struct BlockCache {
+    blocks: Arc<Mutex<HashMap<Key, Box<RawValue>>>>,
+}
+
+tokio::task_local! {
+    static BLOCK_CACHE: BlockCache;
+}
+
+/// Runs the future with a new cache shared by the providers it creates.
+pub(crate) async fn scope<F: Future>(future: F) -> F::Output {
+    BLOCK_CACHE.scope(BlockCache::default(), future).await
+}
+
+/// Cache of the query being run, if it has one.
+pub(crate) fn current() -> Option<BlockCache> {
+    BLOCK_CACHE.try_with(Clone::clone).ok()
+}
+
+/// Transport layer answering `eth_getBlockByNumber` requests from the cache when possible.
+pub(crate) struct BlockCacheLayer {
+    url: String,
+    cache: BlockCache,
+}
+
+impl BlockCacheLayer {
+    pub(crate) fn new(url: String, cache: BlockCache) -> Self {
+        Self { url, cache }
+    }
+}
+
+impl<S> Layer<S> for BlockCacheLayer {
+    type Service = BlockCacheService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BlockCacheService {
+            inner,
+            url: self.url.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct BlockCacheService<S> {
+    inner: S,
+    url: String,
+    cache: BlockCache,
+}
+
+impl<S> BlockCacheService<S> {
+    fn key(&self, request: &SerializedRequest) -> Option<Key> {
+        if request.method() != "eth_getBlockByNumber" {
+            return None;
+        }
+
+        let params = request.params()?;
+        match serde_json::from_str(params.get()).ok()? {
+            (BlockNumberOrTag::Number(number), hydrate) => {
+                Some((self.url.clone(), number, hydrate))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<S: Transport> Service<RequestPacket> for BlockCacheService<S> {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let RequestPacket::Single(single) = &request else {
+            return self.inner.call(request);
+        };
+        let Some(key) = self.key(single) else {
+            return self.inner.call(request);
+        };
+
+        if let Some(block) = self.cache.blocks.lock().unwrap().get(&key) {
+            let response = ResponsePacket::Single(Response {
+                id: single.id().clone(),
+                payload: ResponsePayload::Success(block.clone()),
+            });
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let cache = self.cache.clone();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await?;
+
+            if let ResponsePacket::Single(Response {
+                payload: ResponsePayload::Success(block),
+                ..
+            }) = &response
+            {
+                // Blocks the RPC doesn't have yet are returned as null, and may exist later on
+                if block.get() != "null" {
+                    cache.blocks.lock().unwrap().insert(key, block.clone());
+                }
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        common::{
+            block::{Block, BlockField, BlockId},
+            chain::ChainOrRpc,
+            entity::Entity,
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::{eips::BlockNumberOrTag, rpc::types::Block as RpcBlock};
+
+    #[tokio::test]
+    async fn test_blocks_are_fetched_once_per_query() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result::<RpcBlock>(&Default::default()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let expression = || {
+            Expression::Get(GetExpression {
+                entity: Entity::Block(Block::new(
+                    Some(vec![BlockId::Number(BlockNumberOrTag::Number(1))]),
+                    None,
+                    vec![BlockField::Number],
+                )),
+                chains: vec![ChainOrRpc::Rpc(rpc.url())],
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            })
+        };
+        let engine = ExecutionEngine::new().with_block_cache();
+
+        engine.run(vec![expression(), expression()]).await.unwrap();
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+
+        // The cache only lives as long as the query, so the next one fetches the block again
+        engine.run(vec![expression()]).await.unwrap();
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 2);
+    }
+}
@@ -0,0 +1,329 @@
+//! Validation of the chain served by the RPCs of a query. The RPCs of a chain can be set in the
+//! config file, and nothing prevents an RPC of one network from being configured for another, in
+//! which case the query would silently return the data of the wrong network.
+//!
+//! When the query validates chain ids, each RPC of a chain, the fallback ones included, is asked
+//! for its chain id before the first request sent to it, and the request fails if it doesn't match
+//! the chain. The chain ids are recorded for the duration of the query, so each RPC is only asked
+//! once, even by requests sent to it concurrently.
+use crate::common::chain::Chain;
+use alloy::{
+    primitives::U64,
+    providers::{Provider, RootProvider},
+    rpc::{
+        client::RpcClient,
+        json_rpc::{RequestPacket, ResponsePacket},
+    },
+    transports::{BoxTransport, RpcError, Transport, TransportError, TransportFut},
+};
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    future::{poll_fn, Future},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::sync::OnceCell;
+use tower::{Layer, Service};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChainIdError {
+    #[error("RPC {0} is configured for chain {1}, but serves chain id {2}")]
+    Mismatch(String, Chain, u64),
+}
+
+/// Chain ids of the RPCs used by the query, by RPC URL
+#[derive(Clone, Default)]
+pub(crate) struct ChainIds {
+    ids: Arc<Mutex<HashMap<String, Arc<OnceCell<u64>>>>>,
+}
+
+tokio::task_local! {
+    static CHAIN_IDS: ChainIds;
+}
+
+/// Runs the future validating the chain id of the RPCs of the providers it creates.
+pub(crate) async fn scope<F: Future>(future: F) -> F::Output {
+    CHAIN_IDS.scope(ChainIds::default(), future).await
+}
+
+/// Chain ids recorded by the query being run, if it validates them.
+pub(crate) fn current() -> Option<ChainIds> {
+    CHAIN_IDS.try_with(Clone::clone).ok()
+}
+
+impl ChainIds {
+    /// Chain id of the RPC, only requested from it the first time. Concurrent callers wait for
+    /// the same request.
+    async fn get_or_request<F, Fut, E>(&self, url: &str, request: F) -> Result<u64, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<u64, E>>,
+    {
+        let cell = self
+            .ids
+            .lock()
+            .unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .clone();
+        cell.get_or_try_init(request).await.copied()
+    }
+
+    /// Chain id of the RPC, only requested from it the first time.
+    pub(crate) async fn get(
+        &self,
+        url: &str,
+        provider: &RootProvider<BoxTransport>,
+    ) -> Result<u64> {
+        Ok(self.get_or_request(url, || provider.get_chain_id()).await?)
+    }
+}
+
+/// Transport layer validating the chain id of an RPC before the first request sent to it.
+pub(crate) struct ChainIdLayer {
+    chain: Chain,
+    url: String,
+    chain_ids: ChainIds,
+}
+
+impl ChainIdLayer {
+    pub(crate) fn new(chain: Chain, url: String, chain_ids: ChainIds) -> Self {
+        Self {
+            chain,
+            url,
+            chain_ids,
+        }
+    }
+}
+
+impl<S> Layer<S> for ChainIdLayer {
+    type Service = ChainIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ChainIdService {
+            inner,
+            chain: self.chain.clone(),
+            url: self.url.clone(),
+            chain_ids: self.chain_ids.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ChainIdService<S> {
+    inner: S,
+    chain: Chain,
+    url: String,
+    chain_ids: ChainIds,
+}
+
+impl<S: Transport + Clone> Service<RequestPacket> for ChainIdService<S> {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // The inner transport is polled once the chain id is validated
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let chain = self.chain.clone();
+        let url = self.url.clone();
+        let chain_ids = self.chain_ids.clone();
+
+        Box::pin(async move {
+            let client = RpcClient::new(inner.clone(), false);
+            let chain_id = chain_ids
+                .get_or_request(&url, || async {
+                    let chain_id = client.request::<_, U64>("eth_chainId", ()).await?;
+                    Ok::<_, TransportError>(chain_id.to::<u64>())
+                })
+                .await?;
+
+            // Not a transport error, so it isn't failed over to the next RPC
+            if chain_id != u64::from(&chain) {
+                let error = ChainIdError::Mismatch(url, chain, chain_id);
+                return Err(RpcError::LocalUsageError(Box::new(error)));
+            }
+
+            poll_fn(|cx| inner.poll_ready(cx)).await?;
+            inner.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{
+            block::{Block, BlockField, BlockId},
+            chain::ChainOrRpc,
+            config::tests::{write_config, CONFIG_PATH_LOCK},
+            entity::Entity,
+            query_result::{BlockQueryRes, ExpressionResult},
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::{eips::BlockNumberOrTag, rpc::types::Block as RpcBlock};
+    use std::{env, net::TcpListener, time::Duration};
+
+    fn block_expression(chain: Chain) -> Expression {
+        blocks_expression(chain, vec![1])
+    }
+
+    fn blocks_expression(chain: Chain, numbers: Vec<u64>) -> Expression {
+        let ids = numbers
+            .into_iter()
+            .map(|number| BlockId::Number(BlockNumberOrTag::Number(number)))
+            .collect();
+        Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(Some(ids), None, vec![BlockField::Chain])),
+            chains: vec![ChainOrRpc::Chain(chain)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })
+    }
+
+    /// Runs the expressions validating chain ids, with `rpc` configured as the RPC of `chain`
+    async fn run_with_config(
+        rpc: &MockRpc,
+        chain: &str,
+        expressions: Vec<Expression>,
+    ) -> Result<Vec<ExpressionResult>> {
+        run_with_rpcs(chain, &rpc.url().to_string(), &[], expressions).await
+    }
+
+    /// Runs the expressions validating chain ids, with `default` and the fallback `rpcs`
+    /// configured as the RPCs of `chain`
+    async fn run_with_rpcs(
+        chain: &str,
+        default: &str,
+        rpcs: &[String],
+        expressions: Vec<Expression>,
+    ) -> Result<Vec<ExpressionResult>> {
+        let _lock = CONFIG_PATH_LOCK.lock().await;
+        let path = write_config(
+            &format!("chain-id-{chain}"),
+            &format!(
+                r#"{{ "chains": {{ "{chain}": {{ "default": "{default}", "rpcs": {} }} }} }}"#,
+                serde_json::to_string(rpcs).unwrap()
+            ),
+        );
+        env::set_var("EQL_CONFIG_PATH", &path);
+
+        let results = ExecutionEngine::new()
+            .with_chain_id_validation()
+            .run(expressions)
+            .await;
+        env::remove_var("EQL_CONFIG_PATH");
+
+        Ok(results?.into_iter().map(|result| result.result).collect())
+    }
+
+    fn mismatch(error: &anyhow::Error) -> Option<&ChainIdError> {
+        error.chain().find_map(|e| e.downcast_ref::<ChainIdError>())
+    }
+
+    #[tokio::test]
+    async fn test_error_when_rpc_serves_another_chain() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result::<RpcBlock>(&Default::default()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        let error = run_with_config(&rpc, "polygon", vec![block_expression(Chain::Polygon)])
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            mismatch(&error),
+            Some(ChainIdError::Mismatch(_, Chain::Polygon, 1))
+        ));
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_error_when_fallback_rpc_serves_another_chain() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result::<RpcBlock>(&Default::default()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        // Nothing listens on the port once the listener is dropped, so the default RPC is down
+        let unreachable = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let error = run_with_rpcs(
+            "polygon",
+            &unreachable,
+            &[unreachable.clone(), rpc.url().to_string()],
+            vec![block_expression(Chain::Polygon)],
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            mismatch(&error),
+            Some(ChainIdError::Mismatch(url, Chain::Polygon, 1)) if *url == rpc.url().to_string()
+        ));
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_chain_id_is_requested_once_by_concurrent_requests() {
+        let rpc =
+            MockRpc::start_with_latency(Duration::from_millis(50), |method, _| match method {
+                "eth_chainId" => result(&"0x89"),
+                "eth_getBlockByNumber" => result::<RpcBlock>(&Default::default()),
+                method => Err(format!("Unexpected method {method}")),
+            })
+            .await;
+
+        let expression = blocks_expression(Chain::Polygon, vec![1, 2, 3, 4]);
+        run_with_config(&rpc, "polygon", vec![expression])
+            .await
+            .unwrap();
+
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 4);
+        assert_eq!(rpc.calls("eth_chainId"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_chain_id_is_requested_once_per_rpc() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x89"),
+            "eth_getBlockByNumber" => result::<RpcBlock>(&Default::default()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        let expressions = vec![
+            block_expression(Chain::Polygon),
+            block_expression(Chain::Polygon),
+        ];
+
+        let results = run_with_config(&rpc, "polygon", expressions).await.unwrap();
+
+        let block = ExpressionResult::Block(vec![BlockQueryRes {
+            chain: Some(Chain::Polygon),
+            ..Default::default()
+        }]);
+        assert_eq!(results, vec![block.clone(), block]);
+        assert_eq!(rpc.calls("eth_chainId"), 1);
+    }
+}
@@ -0,0 +1,529 @@
+//! Cache on disk of the blocks, transactions and receipts fetched by the queries. Once a block is
+//! finalized it can't be reorged out, so the data of the finalized blocks never changes and is
+//! kept across queries, and across runs of the program, in a directory of the file system.
+//!
+//! The data is keyed by chain id and by block number or hash, so RPCs serving the same chain
+//! share it. It's only written once the RPC reports its block as finalized: blocks requested with
+//! a tag like `latest`, pending transactions and the blocks after the finalized one are always
+//! fetched from the RPC. RPCs that don't serve the `finalized` tag never have their data cached.
+//!
+//! The chain id of each RPC is kept in the cache as well, so a query whose data is all cached is
+//! answered without contacting the RPC. An RPC URL later serving another chain, like a local
+//! devnet restarted on another chain id, needs the cache to be cleared.
+use alloy::{
+    eips::BlockNumberOrTag,
+    primitives::{keccak256, B256, U64},
+    rpc::{
+        client::RpcClient,
+        json_rpc::{RequestPacket, Response, ResponsePacket, ResponsePayload, SerializedRequest},
+    },
+    transports::{Transport, TransportError, TransportFut},
+};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// Cache directory of the query being run, with the chain ids and finalized blocks of its RPCs,
+/// which are only requested once per query.
+#[derive(Clone)]
+pub(crate) struct DiskCache {
+    dir: PathBuf,
+    chain_ids: Arc<Mutex<HashMap<String, u64>>>,
+    /// `None` for RPCs that couldn't report their finalized block
+    finalized: Arc<Mutex<HashMap<String, Option<u64>>>>,
+}
+
+tokio::task_local! {
+    static DISK_CACHE: DiskCache;
+}
+
+/// Runs the future with the providers it creates reading and writing the cache in `dir`.
+pub(crate) async fn scope<F: Future>(dir: PathBuf, future: F) -> F::Output {
+    DISK_CACHE.scope(DiskCache::new(dir), future).await
+}
+
+/// Disk cache of the query being run, if it has one.
+pub(crate) fn current() -> Option<DiskCache> {
+    DISK_CACHE.try_with(Clone::clone).ok()
+}
+
+/// Removes everything cached in `dir`. Clearing a directory that doesn't exist does nothing.
+pub fn clear(dir: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(dir) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Cached item, stored in `<dir>/<chain id>/<kind>/<id>.json`
+#[derive(Debug, PartialEq, Eq)]
+struct Key {
+    kind: &'static str,
+    id: String,
+    /// Block of the item, when the request gives it. Otherwise it's read from the response.
+    block_number: Option<u64>,
+}
+
+/// Block number of a block, transaction or receipt, absent for pending ones
+#[derive(Debug, Deserialize)]
+struct BlockNumber {
+    #[serde(alias = "blockNumber")]
+    number: Option<U64>,
+}
+
+impl DiskCache {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            chain_ids: Default::default(),
+            finalized: Default::default(),
+        }
+    }
+
+    fn path(&self, chain_id: u64, key: &Key) -> PathBuf {
+        self.dir
+            .join(chain_id.to_string())
+            .join(key.kind)
+            .join(format!("{}.json", key.id))
+    }
+
+    /// Cached item, if any. Unreadable files are ignored, and overwritten once the item is
+    /// fetched again.
+    fn read(&self, chain_id: u64, key: &Key) -> Option<Box<RawValue>> {
+        let contents = fs::read_to_string(self.path(chain_id, key)).ok()?;
+        RawValue::from_string(contents).ok()
+    }
+
+    /// Writes the item to a temporary file first, so a query running alongside never reads it
+    /// half written.
+    fn write(&self, chain_id: u64, key: &Key, value: &RawValue) -> io::Result<()> {
+        let path = self.path(chain_id, key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary, value.get())?;
+        fs::rename(temporary, path)
+    }
+
+    /// File holding the chain id of the RPC, named after the hash of its URL so the API keys the
+    /// URL may hold aren't written in the file names.
+    fn chain_id_path(&self, url: &str) -> PathBuf {
+        self.dir
+            .join("chain_ids")
+            .join(format!("{:x}.json", keccak256(url)))
+    }
+
+    async fn chain_id<S: Transport + Clone>(
+        &self,
+        url: &str,
+        client: &RpcClient<S>,
+    ) -> Option<u64> {
+        if let Some(chain_id) = self.chain_ids.lock().unwrap().get(url) {
+            return Some(*chain_id);
+        }
+
+        let path = self.chain_id_path(url);
+        let cached = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| contents.trim().parse::<u64>().ok());
+        let chain_id = match cached {
+            Some(chain_id) => chain_id,
+            None => {
+                let chain_id = client
+                    .request::<_, U64>("eth_chainId", ())
+                    .await
+                    .ok()?
+                    .to::<u64>();
+                let _ = fs::create_dir_all(path.parent().unwrap())
+                    .and_then(|_| fs::write(&path, chain_id.to_string()));
+                chain_id
+            }
+        };
+        self.chain_ids
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), chain_id);
+        Some(chain_id)
+    }
+
+    async fn finalized<S: Transport + Clone>(
+        &self,
+        url: &str,
+        client: &RpcClient<S>,
+    ) -> Option<u64> {
+        if let Some(finalized) = self.finalized.lock().unwrap().get(url) {
+            return *finalized;
+        }
+
+        let finalized = client
+            .request::<_, Option<BlockNumber>>(
+                "eth_getBlockByNumber",
+                (BlockNumberOrTag::Finalized, false),
+            )
+            .await
+            .ok()
+            .flatten()
+            .and_then(|block| block.number)
+            .map(|number| number.to::<u64>());
+        self.finalized
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), finalized);
+        finalized
+    }
+}
+
+/// Transport layer answering the requests of blocks, transactions and receipts from the disk
+/// cache when possible, and caching the finalized ones it fetches.
+pub(crate) struct DiskCacheLayer {
+    url: String,
+    cache: DiskCache,
+}
+
+impl DiskCacheLayer {
+    pub(crate) fn new(url: String, cache: DiskCache) -> Self {
+        Self { url, cache }
+    }
+}
+
+impl<S> Layer<S> for DiskCacheLayer {
+    type Service = DiskCacheService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DiskCacheService {
+            inner,
+            url: self.url.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct DiskCacheService<S> {
+    inner: S,
+    url: String,
+    cache: DiskCache,
+}
+
+fn key(request: &SerializedRequest) -> Option<Key> {
+    let params = request.params()?.get();
+    let hydrated = |hydrate: bool| match hydrate {
+        true => "full",
+        false => "hashes",
+    };
+
+    match request.method() {
+        "eth_getBlockByNumber" => match serde_json::from_str(params).ok()? {
+            (BlockNumberOrTag::Number(number), hydrate) => Some(Key {
+                kind: "blocks",
+                id: format!("{number}-{}", hydrated(hydrate)),
+                block_number: Some(number),
+            }),
+            _ => None,
+        },
+        "eth_getBlockByHash" => {
+            let (hash, hydrate): (B256, bool) = serde_json::from_str(params).ok()?;
+            Some(Key {
+                kind: "blocks",
+                id: format!("{hash}-{}", hydrated(hydrate)),
+                block_number: None,
+            })
+        }
+        "eth_getTransactionByHash" => {
+            let (hash,): (B256,) = serde_json::from_str(params).ok()?;
+            Some(Key {
+                kind: "transactions",
+                id: hash.to_string(),
+                block_number: None,
+            })
+        }
+        "eth_getTransactionReceipt" => {
+            let (hash,): (B256,) = serde_json::from_str(params).ok()?;
+            Some(Key {
+                kind: "receipts",
+                id: hash.to_string(),
+                block_number: None,
+            })
+        }
+        _ => None,
+    }
+}
+
+impl<S: Transport + Clone> Service<RequestPacket> for DiskCacheService<S> {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let RequestPacket::Single(single) = &request else {
+            return self.inner.call(request);
+        };
+        if single.method() == "eth_chainId" {
+            return self.chain_id(request);
+        }
+        let Some(key) = key(single) else {
+            return self.inner.call(request);
+        };
+
+        let id = single.id().clone();
+        let mut inner = self.inner.clone();
+        let url = self.url.clone();
+        let cache = self.cache.clone();
+
+        Box::pin(async move {
+            // The chain id and the finalized block are requested from the RPC behind the cache
+            let client = RpcClient::new(inner.clone(), false);
+            let Some(chain_id) = cache.chain_id(&url, &client).await else {
+                return inner.call(request).await;
+            };
+
+            if let Some(value) = cache.read(chain_id, &key) {
+                return Ok(ResponsePacket::Single(Response {
+                    id,
+                    payload: ResponsePayload::Success(value),
+                }));
+            }
+
+            let response = inner.call(request).await?;
+
+            if let ResponsePacket::Single(Response {
+                payload: ResponsePayload::Success(value),
+                ..
+            }) = &response
+            {
+                // Items the RPC doesn't have are returned as null, and pending ones have no block
+                let block_number = key.block_number.or_else(|| {
+                    serde_json::from_str::<Option<BlockNumber>>(value.get())
+                        .ok()
+                        .flatten()
+                        .and_then(|item| item.number)
+                        .map(|number| number.to::<u64>())
+                });
+                if let Some(block_number) = block_number.filter(|_| value.get() != "null") {
+                    let finalized = cache.finalized(&url, &client).await;
+                    if finalized.is_some_and(|finalized| block_number <= finalized) {
+                        // The cache only saves requests, so failing to write it doesn't fail the
+                        // query
+                        let _ = cache.write(chain_id, &key, value);
+                    }
+                }
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+impl<S: Transport + Clone> DiskCacheService<S> {
+    /// Answers the chain id of the RPC from the cache, requesting it on the first query
+    fn chain_id(&mut self, request: RequestPacket) -> TransportFut<'static> {
+        let mut inner = self.inner.clone();
+        let url = self.url.clone();
+        let cache = self.cache.clone();
+
+        Box::pin(async move {
+            let RequestPacket::Single(single) = &request else {
+                return inner.call(request).await;
+            };
+            let client = RpcClient::new(inner.clone(), false);
+            let chain_id = cache
+                .chain_id(&url, &client)
+                .await
+                .and_then(|chain_id| serde_json::to_string(&U64::from(chain_id)).ok())
+                .and_then(|chain_id| RawValue::from_string(chain_id).ok());
+
+            match chain_id {
+                Some(chain_id) => Ok(ResponsePacket::Single(Response {
+                    id: single.id().clone(),
+                    payload: ResponsePayload::Success(chain_id),
+                })),
+                None => inner.call(request).await,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{
+            block::{Block, BlockField, BlockId},
+            chain::ChainOrRpc,
+            entity::Entity,
+            query_result::ExpressionResult,
+            transaction::{Transaction, TransactionField},
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::rpc::types::{Block as RpcBlock, Header, Transaction as RpcTransaction};
+
+    /// Empty directory of the test, under the temporary directory of the system
+    fn cache_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("eql-disk-cache-{test}-{}", std::process::id()));
+        clear(&dir).unwrap();
+        dir
+    }
+
+    /// RPC whose finalized block is 100
+    async fn finalized_rpc() -> MockRpc {
+        MockRpc::start(|method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => {
+                let number = match params[0].as_str().unwrap() {
+                    "finalized" => 100,
+                    number => u64::from_str_radix(number.trim_start_matches("0x"), 16).unwrap(),
+                };
+                result::<RpcBlock>(&RpcBlock {
+                    header: Header {
+                        number: Some(number),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+            }
+            "eth_getTransactionByHash" => result(&RpcTransaction {
+                hash: params[0].as_str().unwrap().parse().unwrap(),
+                block_number: Some(99),
+                ..Default::default()
+            }),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await
+    }
+
+    fn block_expression(rpc: &MockRpc, number: u64) -> Expression {
+        Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![BlockId::Number(BlockNumberOrTag::Number(number))]),
+                None,
+                vec![BlockField::Number],
+            )),
+            chains: vec![ChainOrRpc::Rpc(rpc.url())],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_finalized_blocks_are_read_from_the_cache_by_later_queries() {
+        let dir = cache_dir("finalized-blocks");
+        let rpc = finalized_rpc().await;
+
+        ExecutionEngine::new()
+            .with_disk_cache(&dir)
+            .run(vec![block_expression(&rpc, 1)])
+            .await
+            .unwrap();
+        // The block and the finalized one
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 2);
+
+        let calls = rpc.total_calls();
+
+        // Like a later run of the program, with nothing kept in memory
+        let results = ExecutionEngine::new()
+            .with_disk_cache(&dir)
+            .run(vec![block_expression(&rpc, 1)])
+            .await
+            .unwrap();
+        // Not even the chain id is requested
+        assert_eq!(rpc.total_calls(), calls);
+        match &results[0].result {
+            ExpressionResult::Block(blocks) => assert_eq!(blocks[0].number, Some(1)),
+            result => panic!("Expected blocks, got {result:?}"),
+        }
+
+        clear(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_blocks_after_the_finalized_one_are_not_cached() {
+        let dir = cache_dir("unfinalized-blocks");
+        let rpc = finalized_rpc().await;
+        let engine = ExecutionEngine::new().with_disk_cache(&dir);
+
+        engine.run(vec![block_expression(&rpc, 101)]).await.unwrap();
+        engine.run(vec![block_expression(&rpc, 101)]).await.unwrap();
+
+        // The block is fetched by both queries, along with the finalized block
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 4);
+
+        clear(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cleared_cache_is_fetched_again() {
+        let dir = cache_dir("cleared");
+        let rpc = finalized_rpc().await;
+        let expression = || {
+            Expression::Get(GetExpression {
+                entity: Entity::Transaction(Transaction::new(
+                    Some(vec![B256::with_last_byte(1)]),
+                    None,
+                    vec![TransactionField::BlockNumber],
+                )),
+                chains: vec![ChainOrRpc::Rpc(rpc.url())],
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            })
+        };
+        let engine = ExecutionEngine::new().with_disk_cache(&dir);
+
+        engine.run(vec![expression()]).await.unwrap();
+        engine.run(vec![expression()]).await.unwrap();
+        assert_eq!(rpc.calls("eth_getTransactionByHash"), 1);
+
+        clear(&dir).unwrap();
+        assert!(!dir.exists());
+        engine.run(vec![expression()]).await.unwrap();
+        assert_eq!(rpc.calls("eth_getTransactionByHash"), 2);
+
+        clear(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_blocks_requested_by_tag_are_not_cached() {
+        let request = |params: &str| {
+            alloy::rpc::json_rpc::Request::new(
+                "eth_getBlockByNumber",
+                alloy::rpc::json_rpc::Id::Number(0),
+                serde_json::from_str::<serde_json::Value>(params).unwrap(),
+            )
+            .serialize()
+            .unwrap()
+        };
+
+        assert_eq!(key(&request(r#"["latest", false]"#)), None);
+        assert_eq!(
+            key(&request(r#"["0x10", true]"#)),
+            Some(Key {
+                kind: "blocks",
+                id: "16-full".to_string(),
+                block_number: Some(16),
+            })
+        );
+    }
+}
@@ -1,15 +1,56 @@
 use super::{
+    block_cache, chain_id, disk_cache,
+    explain::QueryPlan,
+    metrics::{self, Metrics},
+    partial_results, rate_limit, raw_items,
     resolve_account::resolve_account_query,
-    resolve_block::resolve_block_query,
+    resolve_block::{resolve_block_query, stream_block_query},
+    resolve_chain::resolve_chain_query,
     resolve_logs::resolve_log_query,
-    resolve_transaction::resolve_transaction_query,
+    resolve_receipt::resolve_receipt_query,
+    resolve_token::resolve_token_query,
+    resolve_transaction::{
+        resolve_latest_transactions, resolve_transaction_query, stream_transaction_query,
+        DEFAULT_MAX_CONCURRENCY, DEFAULT_SCAN_DEPTH,
+    },
+    retry::{self, RetryConfig},
+    timeout::{self, DEFAULT_REQUEST_TIMEOUT},
 };
 use crate::common::{
-    entity::Entity, query_result::{ExpressionResult, QueryResult}, serializer::dump_results, types::{Expression, GetExpression}
+    block::{BlockBatchConfig, DEFAULT_MAX_BLOCK_RANGE},
+    chain::ChainOrRpc,
+    ens::EnsConfig,
+    entity::Entity,
+    order::OrderDirection,
+    query_result::{BlockQueryRes, ExpressionResult, QueryResult, TransactionQueryRes},
+    serializer::dump_results,
+    types::{CombinedExpression, Expression, GetExpression},
 };
 use anyhow::Result;
+use futures::{
+    future::try_join_all,
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use std::{future::Future, num::NonZeroU32, path::PathBuf, sync::Arc, time::Duration};
 
-pub struct ExecutionEngine;
+pub struct ExecutionEngine {
+    metrics: Option<Arc<dyn Metrics>>,
+    max_block_range: u64,
+    block_cache: bool,
+    disk_cache: Option<PathBuf>,
+    ens: Option<EnsConfig>,
+    partial_results: bool,
+    request_timeout: Duration,
+    retry: RetryConfig,
+    chain_id_validation: bool,
+    rate_limit: Option<NonZeroU32>,
+    block_batch: BlockBatchConfig,
+    deduplication: bool,
+    token_decimals: bool,
+    scan_depth: u64,
+    raw_items: bool,
+}
 
 #[derive(Debug, PartialEq, Eq, thiserror::Error)]
 pub enum ExecutionEngineError {
@@ -17,24 +58,204 @@ pub enum ExecutionEngineError {
     NoEntityIdOrFilter,
     #[error("Multiple filters are not supported for block queries.")]
     MultipleFiltersNotSupported,
+    #[error("Only block queries can be followed.")]
+    FollowNotSupported,
+    #[error("Only transaction queries can be streamed.")]
+    StreamNotSupported,
+}
+
+impl Default for ExecutionEngine {
+    fn default() -> Self {
+        ExecutionEngine {
+            metrics: None,
+            max_block_range: DEFAULT_MAX_BLOCK_RANGE,
+            block_cache: false,
+            disk_cache: None,
+            ens: None,
+            partial_results: false,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            retry: RetryConfig::default(),
+            chain_id_validation: false,
+            rate_limit: None,
+            block_batch: BlockBatchConfig::default(),
+            deduplication: false,
+            token_decimals: false,
+            scan_depth: DEFAULT_SCAN_DEPTH,
+            raw_items: false,
+        }
+    }
 }
 
 impl ExecutionEngine {
     pub fn new() -> ExecutionEngine {
-        ExecutionEngine
+        ExecutionEngine::default()
     }
 
-    pub async fn run(
-        &self,
-        expressions: Vec<Expression>,
-    ) -> Result<Vec<QueryResult>> {
+    /// Creates an engine reporting every RPC request made by the queries to `metrics`.
+    pub fn with_metrics(metrics: Arc<dyn Metrics>) -> ExecutionEngine {
+        ExecutionEngine {
+            metrics: Some(metrics),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the maximum number of blocks a block range can span, `DEFAULT_MAX_BLOCK_RANGE` by default.
+    pub fn with_max_block_range(mut self, max_block_range: u64) -> ExecutionEngine {
+        self.max_block_range = max_block_range;
+        self
+    }
+
+    /// Sets how the blocks of a block range are fetched, in groups of `DEFAULT_BLOCK_BATCH_SIZE`
+    /// blocks sent as separate requests by default.
+    pub fn with_block_batches(mut self, block_batch: BlockBatchConfig) -> ExecutionEngine {
+        self.block_batch = block_batch;
+        self
+    }
+
+    /// Sets the ENS deployment account names are resolved against on every chain. By default they
+    /// are resolved against Ethereum mainnet's, or the one of the testnet a query is on.
+    pub fn with_ens(mut self, ens: EnsConfig) -> ExecutionEngine {
+        self.ens = Some(ens);
+        self
+    }
+
+    /// Keeps the blocks fetched by number in memory while the expressions of a `run` call are
+    /// resolved, so a block used by several expressions is only fetched once. The cache is
+    /// dropped once the call returns.
+    pub fn with_block_cache(mut self) -> ExecutionEngine {
+        self.block_cache = true;
+        self
+    }
+
+    /// Keeps the finalized blocks, transactions and receipts fetched by the queries in `dir`, so
+    /// later queries over the same data, including the ones of other engines and programs, read
+    /// them from disk instead of the RPCs. The cache is cleared with `disk_cache::clear`.
+    pub fn with_disk_cache(mut self, dir: impl Into<PathBuf>) -> ExecutionEngine {
+        self.disk_cache = Some(dir.into());
+        self
+    }
+
+    /// Returns the transactions and accounts that could be fetched when some of the items of a
+    /// query fail, instead of failing the whole query. The errors of the failed items are set on
+    /// the `errors` of the query result.
+    pub fn with_partial_results(mut self) -> ExecutionEngine {
+        self.partial_results = true;
+        self
+    }
+
+    /// Sets the time after which an HTTP request to an RPC fails, `DEFAULT_REQUEST_TIMEOUT` by
+    /// default. When the chain has fallback RPCs, the request is sent to the next one instead.
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> ExecutionEngine {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Sets how many times, and after which delay, a request failing with a transient error
+    /// (rate limits, server errors and network failures) is retried, 3 times from 250ms by default.
+    pub fn with_retry(mut self, retry: RetryConfig) -> ExecutionEngine {
+        self.retry = retry;
+        self
+    }
+
+    /// Checks that the RPCs of the chains serve the chain they're configured for, failing the
+    /// query when an RPC serves another chain. Each RPC is asked for its chain id once per `run`.
+    pub fn with_chain_id_validation(mut self) -> ExecutionEngine {
+        self.chain_id_validation = true;
+        self
+    }
+
+    /// Limits the requests sent to the RPCs to `requests_per_second`, shared by all the requests of
+    /// a `run` call. Requests beyond the limit wait for their turn instead of failing.
+    pub fn with_rate_limit(mut self, requests_per_second: NonZeroU32) -> ExecutionEngine {
+        self.rate_limit = Some(requests_per_second);
+        self
+    }
+
+    /// Collapses the duplicated results of a query, like the same transaction fetched from
+    /// overlapping block ranges or the same account listed twice, before they're aggregated,
+    /// sorted and limited. Transactions are keyed by chain and hash, and accounts by chain and
+    /// address.
+    pub fn with_deduplication(mut self) -> ExecutionEngine {
+        self.deduplication = true;
+        self
+    }
+
+    /// Reads the decimals of the tokens whose balances are queried, once per token and chain, and
+    /// sets the balances in units of the token on `token_balances_formatted`, next to the raw
+    /// ones. Tokens whose decimals can't be read only have their raw balance.
+    pub fn with_token_decimals(mut self) -> ExecutionEngine {
+        self.token_decimals = true;
+        self
+    }
+
+    /// Sets the number of blocks searched, back from the latest one, for the transactions of
+    /// queries filtered by `from` or `to` without a hash or block filter, `DEFAULT_SCAN_DEPTH` by
+    /// default. The search stops earlier once the `LIMIT` of the query is reached.
+    pub fn with_scan_depth(mut self, scan_depth: u64) -> ExecutionEngine {
+        self.scan_depth = scan_depth;
+        self
+    }
+
+    /// Returns the blocks and transactions the results of each query were projected from on the
+    /// `raw` of its result, as the alloy types returned by the RPCs, with all their fields. See
+    /// `RawItems`.
+    pub fn with_raw_items(mut self) -> ExecutionEngine {
+        self.raw_items = true;
+        self
+    }
+
+    pub async fn run(&self, expressions: Vec<Expression>) -> Result<Vec<QueryResult>> {
+        // Boxed, so the scopes wrapping it don't each make room for the whole query on the stack
+        let run = timeout::scope(
+            self.request_timeout,
+            Box::pin(self.run_expressions(expressions)),
+        );
+        let run = retry::scope(self.retry.clone(), run);
+        let run = async {
+            match self.chain_id_validation {
+                true => chain_id::scope(run).await,
+                false => run.await,
+            }
+        };
+        let run = async {
+            match &self.disk_cache {
+                Some(dir) => disk_cache::scope(dir.clone(), run).await,
+                None => run.await,
+            }
+        };
+        let run = async {
+            match self.rate_limit {
+                Some(requests_per_second) => rate_limit::scope(requests_per_second, run).await,
+                None => run.await,
+            }
+        };
+
+        match &self.metrics {
+            Some(metrics) => metrics::scope(metrics.clone(), run).await,
+            None => run.await,
+        }
+    }
+
+    async fn run_expressions(&self, expressions: Vec<Expression>) -> Result<Vec<QueryResult>> {
+        if self.block_cache {
+            return block_cache::scope(self.resolve_expressions(expressions)).await;
+        }
+
+        self.resolve_expressions(expressions).await
+    }
+
+    async fn resolve_expressions(&self, expressions: Vec<Expression>) -> Result<Vec<QueryResult>> {
         let mut query_results = vec![];
 
         for expression in expressions {
             match expression {
                 Expression::Get(get_expr) => {
-                    let result = self.run_get_expr(&get_expr).await?;
-                    query_results.push(QueryResult::new(result));
+                    let result = self.query_result(self.run_get_expr(&get_expr)).await?;
+                    query_results.push(result.with_fields(get_expr.entity.field_keys()));
+                }
+                Expression::Combined(combined) => {
+                    let result = self.query_result(self.run_combined_expr(&combined)).await?;
+                    query_results.push(result.with_fields(combined.field_keys()));
                 }
             }
         }
@@ -42,23 +263,218 @@ impl ExecutionEngine {
         Ok(query_results)
     }
 
-    async fn run_get_expr(
+    /// Runs an expression, collecting the errors of its items when it returns partial results and
+    /// the alloy types of its results when they're returned.
+    async fn query_result<F: Future<Output = Result<ExpressionResult>>>(
         &self,
-        expr: &GetExpression,
-    ) -> Result<ExpressionResult> {
-        let result = match &expr.entity {
-            Entity::Block(block) => ExpressionResult::Block(resolve_block_query(block, &expr.chains).await?),
-            Entity::Account(account) => ExpressionResult::Account(resolve_account_query(account, &expr.chains).await?),
-            Entity::Transaction(transaction) => ExpressionResult::Transaction(resolve_transaction_query(transaction, &expr.chains).await?),
-            Entity::Logs(logs) => ExpressionResult::Log(resolve_log_query(logs, &expr.chains).await?),
+        run: F,
+    ) -> Result<QueryResult> {
+        let run = async {
+            match self.raw_items {
+                true => {
+                    let (result, raw) = raw_items::scope(run).await;
+                    Ok(QueryResult::new(result?).with_raw(raw))
+                }
+                false => Ok(QueryResult::new(run.await?)),
+            }
         };
 
+        match self.partial_results {
+            true => {
+                let (result, errors) = partial_results::scope(run).await;
+                Ok(result?.with_errors(errors))
+            }
+            false => run.await,
+        }
+    }
+
+    /// Plans the RPC requests every expression would make, without running them. See `QueryPlan`.
+    /// Combined expressions have a plan for each of their entities.
+    pub fn explain(&self, expressions: &[Expression]) -> Result<Vec<QueryPlan>> {
+        expressions
+            .iter()
+            .flat_map(|expression| match expression {
+                Expression::Get(get_expr) => vec![get_expr],
+                Expression::Combined(combined) => combined.expressions.iter().collect(),
+            })
+            .map(QueryPlan::new)
+            .collect()
+    }
+
+    /// Runs a block query in follow mode, yielding the new blocks of every chain of the expression
+    /// as they're produced. Following requires WebSocket RPCs.
+    pub async fn follow(
+        &self,
+        expression: &Expression,
+    ) -> Result<BoxStream<'static, Result<BlockQueryRes>>> {
+        let Expression::Get(GetExpression {
+            entity: Entity::Block(block),
+            chains,
+            ..
+        }) = expression
+        else {
+            return Err(ExecutionEngineError::FollowNotSupported.into());
+        };
+
+        let streams = try_join_all(chains.iter().map(|chain| stream_block_query(block, chain)));
+        let streams = match &self.metrics {
+            Some(metrics) => metrics::scope(metrics.clone(), streams).await?,
+            None => streams.await?,
+        };
+
+        Ok(stream::select_all(streams.into_iter().map(StreamExt::boxed)).boxed())
+    }
+
+    /// Runs a transaction query `chunk_size` blocks at a time, yielding the transactions of each
+    /// chunk as it's resolved, so queries over large block ranges don't hold all of them in memory.
+    /// Block ranges aren't capped by the maximum block range.
+    pub fn stream_transactions(
+        &self,
+        expression: &Expression,
+        chunk_size: u64,
+    ) -> Result<BoxStream<'static, Result<Vec<TransactionQueryRes>>>> {
+        let Expression::Get(GetExpression {
+            entity: Entity::Transaction(transaction),
+            chains,
+            ..
+        }) = expression
+        else {
+            return Err(ExecutionEngineError::StreamNotSupported.into());
+        };
+
+        Ok(stream_transaction_query(
+            transaction.clone(),
+            chains.clone(),
+            chunk_size,
+            DEFAULT_MAX_CONCURRENCY,
+            self.scan_depth,
+            self.ens_of(chains),
+        ))
+    }
+
+    /// Resolves the entities of the expression concurrently, so they take about as long as the
+    /// slowest of them.
+    async fn run_combined_expr(&self, combined: &CombinedExpression) -> Result<ExpressionResult> {
+        let results = try_join_all(
+            combined
+                .expressions
+                .iter()
+                .map(|expr| self.run_get_expr(expr)),
+        )
+        .await?;
+
+        Ok(ExpressionResult::Combined(results))
+    }
+
+    async fn run_get_expr(&self, expr: &GetExpression) -> Result<ExpressionResult> {
+        let deduplicated = match self.deduplication {
+            true => expr.entity.with_dedup_keys(),
+            false => None,
+        };
+        let entity = deduplicated
+            .as_ref()
+            .map_or(&expr.entity, |(entity, _)| entity);
+
+        let mut result = match entity {
+            Entity::Block(block) => ExpressionResult::Block(
+                resolve_block_query(block, &expr.chains, self.max_block_range, self.block_batch)
+                    .await?,
+            ),
+            Entity::Account(account) => ExpressionResult::Account(
+                resolve_account_query(
+                    account,
+                    &expr.chains,
+                    &self.ens_of(&expr.chains),
+                    self.token_decimals,
+                )
+                .await?,
+            ),
+            Entity::Transaction(transaction) if transaction.scans_latest_blocks() => {
+                ExpressionResult::Transaction(
+                    resolve_latest_transactions(
+                        transaction,
+                        &expr.chains,
+                        DEFAULT_MAX_CONCURRENCY,
+                        self.scan_depth,
+                        scan_limit(expr),
+                        &self.ens_of(&expr.chains),
+                    )
+                    .await?,
+                )
+            }
+            Entity::Transaction(transaction) => ExpressionResult::Transaction(
+                resolve_transaction_query(
+                    transaction,
+                    &expr.chains,
+                    DEFAULT_MAX_CONCURRENCY,
+                    self.max_block_range,
+                    &self.ens_of(&expr.chains),
+                )
+                .await?,
+            ),
+            Entity::Logs(logs) => {
+                ExpressionResult::Log(resolve_log_query(logs, &expr.chains).await?)
+            }
+            Entity::Token(token) => {
+                ExpressionResult::Token(resolve_token_query(token, &expr.chains).await?)
+            }
+            Entity::Receipt(receipt) => {
+                ExpressionResult::Receipt(resolve_receipt_query(receipt, &expr.chains).await?)
+            }
+            Entity::Chain(chain) => {
+                ExpressionResult::Chain(resolve_chain_query(chain, &expr.chains).await?)
+            }
+        };
+
+        if let Some((_, keys)) = deduplicated {
+            result.dedup(keys);
+        }
+
+        if !expr.aggregates.is_empty() {
+            let aggregates = expr
+                .aggregates
+                .iter()
+                .map(|aggregate| aggregate.compute(&result));
+            result = ExpressionResult::Aggregate(aggregates.collect::<Result<_, _>>()?);
+        }
+
+        // Applied to the results of all the chains, so the top results are the top ones overall
+        if let Some(order_by) = &expr.order_by {
+            result.sort(order_by)?;
+        }
+
+        if let Some(limit) = expr.limit {
+            result.truncate(limit);
+        }
+
         if let Some(dump) = &expr.dump {
-            dump_results(&result, dump);
+            dump_results(&result, dump, &expr.entity.field_keys());
         }
 
         Ok(result)
     }
+
+    /// ENS deployment the names of a query on `chains` are resolved against. Unless another one
+    /// was set with `with_ens`, queries on a single testnet with its own deployment, like
+    /// Sepolia, resolve names against the testnet's registry instead of mainnet's.
+    fn ens_of(&self, chains: &[ChainOrRpc]) -> EnsConfig {
+        match (&self.ens, chains) {
+            (Some(ens), _) => ens.clone(),
+            (None, [ChainOrRpc::Chain(chain)]) => EnsConfig::of_chain(chain).unwrap_or_default(),
+            (None, _) => EnsConfig::default(),
+        }
+    }
+}
+
+/// Number of transactions a scan of the latest blocks can stop at. The `LIMIT` keeps the latest
+/// transactions unless they're aggregated or sorted by another field than the block number.
+fn scan_limit(expr: &GetExpression) -> Option<usize> {
+    let keeps_latest = expr.aggregates.is_empty()
+        && expr.order_by.as_ref().map_or(true, |order_by| {
+            order_by.field == "block_number" && order_by.direction == OrderDirection::Desc
+        });
+
+    expr.limit.filter(|_| keeps_latest)
 }
 
 #[cfg(test)]
@@ -71,13 +487,21 @@ mod test {
         dump::{Dump, DumpFormat},
         ens::NameOrAddress,
         logs::{LogField, LogFilter, Logs},
-        query_result::{AccountQueryRes, BlockQueryRes, LogQueryRes, TransactionQueryRes},
-        transaction::{Transaction, TransactionField},
+        order::{OrderBy, OrderDirection},
+        query_result::{
+            AccountQueryRes, AggregateQueryRes, BlockQueryRes, LogQueryRes, TransactionQueryRes,
+        },
+        transaction::{Transaction, TransactionField, TransactionFilter},
         types::{Expression, GetExpression},
     };
+    use crate::interpreter::{
+        backend::mock_rpc::{result, MockRpc},
+        frontend::parser::Parser,
+    };
     use alloy::{
         eips::BlockNumberOrTag,
-        primitives::{address, b256, bloom, bytes, U256},
+        primitives::{address, b256, bloom, bytes, B256, U256},
+        rpc::types::{Block as RpcBlock, BlockTransactions, Header, Transaction as RpcTransaction},
     };
     use pretty_assertions::assert_eq;
     use std::str::FromStr;
@@ -92,9 +516,7 @@ mod test {
                         BlockNumberOrTag::Number(4638757),
                         Some(BlockNumberOrTag::Number(4638758)),
                     )),
-                    LogFilter::EmitterAddress(address!(
-                        "dac17f958d2ee523a2206206994597c13d831ec7"
-                    )),
+                    LogFilter::EmitterAddress(address!("dac17f958d2ee523a2206206994597c13d831ec7")),
                     LogFilter::Topic0(b256!(
                         "cb8241adb0c3fdb35b70c24ce35c5eb0c17af7431c99f827d44a445ca624176a"
                     )),
@@ -103,6 +525,9 @@ mod test {
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
         let execution_result = execution_engine.run(expressions).await;
         let expected = vec![LogQueryRes {
@@ -122,12 +547,15 @@ mod test {
             block_number: Some(4638757),
             // TODO: the provider is returning None for block_timestamp
             block_timestamp: None,
+            block_timestamp_utc: None,
             transaction_hash: Some(b256!(
                 "8cfc4f5f4729423f59dd1d263ead2f824b3f133b02b9e27383964c7d50cd47cb"
             )),
             transaction_index: Some(9),
             log_index: Some(5),
             removed: Some(false),
+            // No event signature to decode the data with
+            decoded: None,
             chain: Some(Chain::Ethereum),
         }];
 
@@ -143,24 +571,24 @@ mod test {
     async fn test_get_block_fields() {
         let execution_engine = ExecutionEngine::new();
         let expressions = vec![Expression::Get(GetExpression {
-            entity: Entity::Block(
-                Block::new(
-                    Some(vec![
-                        BlockId::Range(BlockRange::new(
-                            BlockNumberOrTag::Number(1),
-                            None,
-                        )),
-                    ]),
+            entity: Entity::Block(Block::new(
+                Some(vec![BlockId::Range(BlockRange::new(
+                    BlockNumberOrTag::Number(1),
                     None,
-                    BlockField::all_variants().to_vec(),
-                )
-            ),
+                ))]),
+                None,
+                BlockField::all_variants().to_vec(),
+            )),
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
         })];
         let expected = ExpressionResult::Block(vec![
             BlockQueryRes {
                 timestamp: Some(1438269988),
+                timestamp_utc: Some(1438269988),
                 number: Some(1),
                 hash: Some(b256!(
                     "88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6"
@@ -184,10 +612,14 @@ mod test {
                 total_difficulty: Some(U256::from(34351349760_u128)),
                 // The fields below were implemented by EIPs, 1st block doesn't have these
                 base_fee_per_gas: None,
+                next_base_fee: None,
                 withdrawals_root: None,
+                withdrawals: None,
                 blob_gas_used: None,
                 excess_blob_gas: None,
                 parent_beacon_block_root: None,
+                uncles: Some(vec![]),
+                transaction_count: Some(0),
                 chain: Some(Chain::Ethereum),
             },
         ]);
@@ -205,15 +637,18 @@ mod test {
     async fn test_get_account_fields_using_invalid_ens() {
         let execution_engine = ExecutionEngine::new();
         let expressions = vec![Expression::Get(GetExpression {
-            entity: Entity::Account(
-                Account::new(
-                    Some(vec![NameOrAddress::Name(String::from("thisisinvalid235790123801.eth"))]),
-                    None,
-                    vec![AccountField::Balance],
-                )
-            ),
+            entity: Entity::Account(Account::new(
+                Some(vec![NameOrAddress::Name(String::from(
+                    "thisisinvalid235790123801.eth",
+                ))]),
+                None,
+                vec![AccountField::Balance],
+            )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
         let execution_result = execution_engine.run(expressions).await;
         assert!(execution_result.is_err())
@@ -223,18 +658,38 @@ mod test {
     async fn test_get_transaction_fields() {
         let execution_engine = ExecutionEngine::new();
         let expressions = vec![Expression::Get(GetExpression {
-            entity: Entity::Transaction(
-                Transaction::new(
-                    Some(vec![
-                        b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890"),
-                        b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890")
-                    ]),
-                    None,
-                    TransactionField::all_variants().to_vec(),
-                )
-            ),
+            entity: Entity::Transaction(Transaction::new(
+                Some(vec![
+                    b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890"),
+                    b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890"),
+                ]),
+                None,
+                vec![
+                    TransactionField::TransactionType,
+                    TransactionField::Hash,
+                    TransactionField::From,
+                    TransactionField::To,
+                    TransactionField::Data,
+                    TransactionField::Value,
+                    TransactionField::GasPrice,
+                    TransactionField::Gas,
+                    TransactionField::Status,
+                    TransactionField::ChainId,
+                    TransactionField::V,
+                    TransactionField::R,
+                    TransactionField::S,
+                    TransactionField::MaxFeePerBlobGas,
+                    TransactionField::MaxFeePerGas,
+                    TransactionField::MaxPriorityFeePerGas,
+                    TransactionField::YParity,
+                    TransactionField::Chain,
+                ],
+            )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
         let expected = vec![ExpressionResult::Transaction(vec![
             TransactionQueryRes {
@@ -258,6 +713,7 @@ mod test {
                 max_priority_fee_per_gas: Some(0),
                 y_parity: Some(false),
                 chain: Some(Chain::Ethereum),
+                ..Default::default()
             },
             TransactionQueryRes {
                 transaction_type: Some(2),
@@ -280,8 +736,9 @@ mod test {
                 max_priority_fee_per_gas: Some(0),
                 y_parity: Some(false),
                 chain: Some(Chain::Ethereum),
-            }])    
-        ];            
+                ..Default::default()
+            }])
+        ];
 
         let result = execution_engine.run(expressions).await;
         match result {
@@ -296,17 +753,18 @@ mod test {
     async fn test_get_inexistent_transaction() {
         let execution_engine = ExecutionEngine::new();
         let expressions = vec![Expression::Get(GetExpression {
-            entity: Entity::Transaction(
-                Transaction::new(
-                    Some(vec![b256!(
-                        "0000000000000000000000000000000000000000000000000000000000000000"
-                    )]),
-                    None,
-                    TransactionField::all_variants().to_vec(),
-                )
-            ),
+            entity: Entity::Transaction(Transaction::new(
+                Some(vec![b256!(
+                    "0000000000000000000000000000000000000000000000000000000000000000"
+                )]),
+                None,
+                TransactionField::all_variants().to_vec(),
+            )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
         let result = execution_engine.run(expressions).await.unwrap();
 
@@ -317,20 +775,16 @@ mod test {
     async fn test_dump_results() {
         let execution_engine = ExecutionEngine::new();
         let expressions = vec![Expression::Get(GetExpression {
-            entity: Entity::Block(
-                Block::new(
-                    Some(vec![
-                        BlockId::Range(BlockRange::new(
-                            1.into(),
-                            None,
-                        ))
-                    ]),
-                    None,
-                    vec![BlockField::Timestamp],
-                )
-            ),
+            entity: Entity::Block(Block::new(
+                Some(vec![BlockId::Range(BlockRange::new(1.into(), None))]),
+                None,
+                vec![BlockField::Timestamp],
+            )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: Some(Dump::new(String::from("test"), DumpFormat::Json)),
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
         execution_engine.run(expressions).await.unwrap();
 
@@ -356,6 +810,31 @@ mod test {
         s.replace('\n', "").replace('\r', "").replace(" ", "")
     }
 
+    #[tokio::test]
+    async fn test_error_when_following_non_block_query() {
+        let expression = Expression::Get(GetExpression {
+            entity: Entity::Account(Account::new(
+                Some(vec![NameOrAddress::Address(address!(
+                    "dac17f958d2ee523a2206206994597c13d831ec7"
+                ))]),
+                None,
+                vec![AccountField::Balance],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        });
+
+        let result = ExecutionEngine::new().follow(&expression).await;
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Only block queries can be followed."
+        );
+    }
+
     #[tokio::test]
     async fn test_get_chain_field() {
         let execution_engine = ExecutionEngine::new();
@@ -369,6 +848,9 @@ mod test {
                     )),
                     chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
                     dump: None,
+                    order_by: None,
+                    limit: None,
+                    aggregates: vec![],
                 }),
                 ExpressionResult::Block(vec![BlockQueryRes {
                     chain: Some(Chain::Ethereum),
@@ -378,12 +860,17 @@ mod test {
             (
                 Expression::Get(GetExpression {
                     entity: Entity::Account(Account::new(
-                        Some(vec![NameOrAddress::Address(address!("dac17f958d2ee523a2206206994597c13d831ec7"))]),
+                        Some(vec![NameOrAddress::Address(address!(
+                            "dac17f958d2ee523a2206206994597c13d831ec7"
+                        ))]),
                         None,
                         vec![AccountField::Chain],
                     )),
                     chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
                     dump: None,
+                    order_by: None,
+                    limit: None,
+                    aggregates: vec![],
                 }),
                 ExpressionResult::Account(vec![AccountQueryRes {
                     chain: Some(Chain::Ethereum),
@@ -393,12 +880,17 @@ mod test {
             (
                 Expression::Get(GetExpression {
                     entity: Entity::Transaction(Transaction::new(
-                        Some(vec![b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890")]),
+                        Some(vec![b256!(
+                            "72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890"
+                        )]),
                         None,
                         vec![TransactionField::Chain],
                     )),
                     chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
                     dump: None,
+                    order_by: None,
+                    limit: None,
+                    aggregates: vec![],
                 }),
                 ExpressionResult::Transaction(vec![TransactionQueryRes {
                     chain: Some(Chain::Ethereum),
@@ -412,4 +904,332 @@ mod test {
             assert_eq!(result[0].result, expected);
         }
     }
+
+    #[tokio::test]
+    async fn test_get_top_transactions_by_value() {
+        let values = [5u64, 50, 1, 20, 10];
+        let block = RpcBlock {
+            transactions: BlockTransactions::Full(
+                values
+                    .iter()
+                    .map(|&value| RpcTransaction {
+                        hash: B256::with_last_byte(value as u8),
+                        value: U256::from(value),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let expressions = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                    BlockRange::new(18000000.into(), None),
+                ))]),
+                vec![TransactionField::Hash, TransactionField::Value],
+            )),
+            chains: vec![ChainOrRpc::Rpc(rpc.url())],
+            dump: None,
+            order_by: Some(OrderBy::new("value", OrderDirection::Desc)),
+            limit: Some(3),
+            aggregates: vec![],
+        })];
+
+        let result = ExecutionEngine::new().run(expressions).await.unwrap();
+
+        assert_eq!(
+            result[0].result,
+            ExpressionResult::Transaction(
+                [50u64, 20, 10]
+                    .map(|value| TransactionQueryRes {
+                        hash: Some(B256::with_last_byte(value as u8)),
+                        value: Some(U256::from(value)),
+                        ..Default::default()
+                    })
+                    .to_vec()
+            )
+        );
+    }
+
+    /// Serves blocks with a single transaction, whose hash and value are the block number
+    async fn numbered_blocks_rpc(chain_id: &'static str) -> MockRpc {
+        MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&chain_id),
+            "eth_getBlockByNumber" => {
+                let number =
+                    u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16)
+                        .unwrap();
+                result(&RpcBlock {
+                    header: Header {
+                        number: Some(number),
+                        ..Default::default()
+                    },
+                    transactions: BlockTransactions::Full(vec![RpcTransaction {
+                        hash: B256::with_last_byte(number as u8),
+                        block_number: Some(number),
+                        value: U256::from(number),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_duplicates_of_overlapping_queries_are_collapsed() {
+        // Two RPCs of mainnet serve the same blocks, and Optimism serves transactions with the
+        // same hashes
+        let (mainnet, mainnet_mirror, optimism) = (
+            numbered_blocks_rpc("0x1").await,
+            numbered_blocks_rpc("0x1").await,
+            numbered_blocks_rpc("0xa").await,
+        );
+        // Neither the chain nor the hash is selected
+        let expression = || {
+            Expression::Get(GetExpression {
+                entity: Entity::Transaction(Transaction::new(
+                    None,
+                    Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                        BlockRange::new(
+                            BlockNumberOrTag::Number(1),
+                            Some(BlockNumberOrTag::Number(2)),
+                        ),
+                    ))]),
+                    vec![TransactionField::Value],
+                )),
+                chains: [&mainnet, &mainnet_mirror, &optimism]
+                    .map(|rpc| ChainOrRpc::Rpc(rpc.url()))
+                    .to_vec(),
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            })
+        };
+
+        let duplicated = ExecutionEngine::new()
+            .run(vec![expression()])
+            .await
+            .unwrap();
+        let deduplicated = ExecutionEngine::new()
+            .with_deduplication()
+            .run(vec![expression()])
+            .await
+            .unwrap();
+
+        match &duplicated[0].result {
+            ExpressionResult::Transaction(txs) => assert_eq!(txs.len(), 6),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        // The transactions of the mirror are collapsed, the ones of Optimism are kept, and the
+        // chain and hash they're told apart by stay hidden
+        assert_eq!(
+            deduplicated[0].result,
+            ExpressionResult::Transaction(
+                [1u64, 2, 1, 2]
+                    .map(|value| TransactionQueryRes {
+                        value: Some(U256::from(value)),
+                        ..Default::default()
+                    })
+                    .to_vec()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_transaction_aggregates() {
+        let ether = U256::from(10).pow(U256::from(18));
+        let values = [1u64, 2, 3, 0, 100];
+        let block = RpcBlock {
+            transactions: BlockTransactions::Full(
+                values
+                    .iter()
+                    .map(|&value| RpcTransaction {
+                        value: U256::from(value) * ether,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let source = format!(
+            "GET count(*), sum(value), max(value) FROM tx WHERE block 18000000 ON {}",
+            rpc.url()
+        );
+        let expressions = Parser::new(&source).parse_expressions().unwrap();
+
+        let result = ExecutionEngine::new().run(expressions).await.unwrap();
+
+        assert_eq!(
+            result[0].result,
+            ExpressionResult::Aggregate(vec![
+                AggregateQueryRes {
+                    aggregate: "count(*)".to_string(),
+                    value: Some(U256::from(5)),
+                },
+                AggregateQueryRes {
+                    aggregate: "sum(value)".to_string(),
+                    value: Some(U256::from(106) * ether),
+                },
+                AggregateQueryRes {
+                    aggregate: "max(value)".to_string(),
+                    value: Some(U256::from(100) * ether),
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_retried_as_configured() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => Err("rate limit exceeded".to_string()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let source = format!("GET number FROM block 1 ON {}", rpc.url());
+        let expressions = Parser::new(&source).parse_expressions().unwrap();
+
+        let result = ExecutionEngine::new()
+            .with_retry(RetryConfig {
+                max_retries: 1,
+                base_delay: Duration::from_millis(1),
+            })
+            .run(expressions)
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_output_follows_order_of_selected_fields() {
+        let tx = RpcTransaction {
+            hash: b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890"),
+            from: address!("95222290DD7278Aa3Ddd389Cc1E1d165CC4BAfe5"),
+            to: Some(address!("dAC17F958D2ee523a2206206994597C13D831ec7")),
+            value: U256::from(1000),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionByHash" => result(&tx),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let source = format!(
+            "GET to, from, value FROM tx 0x72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890 ON {}",
+            rpc.url()
+        );
+        let expressions = Parser::new(&source).parse_expressions().unwrap();
+
+        let result = ExecutionEngine::new().run(expressions).await.unwrap();
+
+        assert_eq!(
+            result[0].to_csv().unwrap(),
+            "to,from,value\n0xdAC17F958D2ee523a2206206994597C13D831ec7,0x95222290DD7278Aa3Ddd389Cc1E1d165CC4BAfe5,1000\n"
+        );
+        assert_eq!(
+            result[0].to_json().unwrap(),
+            r#"[{"to":"0xdAC17F958D2ee523a2206206994597C13D831ec7","from":"0x95222290DD7278Aa3Ddd389Cc1E1d165CC4BAfe5","value":"1000"}]"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_block_and_its_transactions() {
+        let block_hash = b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890");
+        let block = RpcBlock {
+            header: alloy::rpc::types::Header {
+                number: Some(18000000),
+                hash: Some(block_hash),
+                ..Default::default()
+            },
+            transactions: BlockTransactions::Full(
+                [1u64, 2]
+                    .map(|value| RpcTransaction {
+                        hash: B256::with_last_byte(value as u8),
+                        value: U256::from(value),
+                        ..Default::default()
+                    })
+                    .to_vec(),
+            ),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let source = format!(
+            "GET number, hash FROM block 18000000 AND GET hash, value FROM tx WHERE block 18000000 ON {}",
+            rpc.url()
+        );
+        let expressions = Parser::new(&source).parse_expressions().unwrap();
+
+        let result = ExecutionEngine::new().run(expressions).await.unwrap();
+
+        assert_eq!(
+            result[0].result,
+            ExpressionResult::Combined(vec![
+                ExpressionResult::Block(vec![BlockQueryRes {
+                    number: Some(18000000),
+                    hash: Some(block_hash),
+                    ..Default::default()
+                }]),
+                ExpressionResult::Transaction(
+                    [1u64, 2]
+                        .map(|value| TransactionQueryRes {
+                            hash: Some(B256::with_last_byte(value as u8)),
+                            value: Some(U256::from(value)),
+                            ..Default::default()
+                        })
+                        .to_vec()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ens_deployment_of_chains() {
+        let sepolia = [ChainOrRpc::Chain(Chain::Sepolia)];
+        let engine = ExecutionEngine::new();
+
+        assert_eq!(
+            engine.ens_of(&sepolia),
+            EnsConfig::of_chain(&Chain::Sepolia).unwrap()
+        );
+        // Chains without a deployment of their own, and queries on several chains, use mainnet's
+        assert_eq!(
+            engine.ens_of(&[ChainOrRpc::Chain(Chain::Base)]),
+            EnsConfig::default()
+        );
+        assert_eq!(
+            engine.ens_of(&[sepolia[0].clone(), ChainOrRpc::Chain(Chain::Holesky)]),
+            EnsConfig::default()
+        );
+        // A deployment set with `with_ens` is used even if it's mainnet's
+        assert_eq!(
+            ExecutionEngine::new()
+                .with_ens(EnsConfig::default())
+                .ens_of(&sepolia),
+            EnsConfig::default()
+        );
+    }
 }
@@ -0,0 +1,704 @@
+//! Plan of the RPC requests a query will make, computed from its entity, fields and filters
+//! without contacting the network. Some counts depend on data only the RPC has, like the number
+//! of transactions in a block, so they're estimated. Ranges bounded by tags like `latest` can't
+//! be counted until the tags are resolved.
+//!
+//! The `eth_chainId` requests made to identify the chain of RPC URLs are left out of the plan.
+//!
+//! RPC providers with metered plans charge each method a different weight, e.g. Alchemy's compute
+//! units, so the plan can also be priced with a table of `MethodWeights` to budget a query
+//! before running it.
+use super::{resolve_account::AccountResolverErrors, resolve_block::BlockResolverErrors};
+use crate::common::{
+    account::{Account, AccountField},
+    block::{Block, BlockId, BlockRange},
+    chain_status::{ChainField, ChainStatus},
+    ens::NameOrAddress,
+    entity::Entity,
+    logs::{LogFilter, Logs},
+    receipt::Receipt,
+    token::{Token, TokenField},
+    transaction::{Transaction, TransactionField},
+    types::GetExpression,
+};
+use alloy::eips::BlockNumberOrTag;
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Display, Formatter},
+    ops::{Add, Mul},
+};
+
+/// Average number of transactions in an Ethereum mainnet block, used to estimate the requests
+/// made for every transaction of a block.
+pub const ESTIMATED_TRANSACTIONS_PER_BLOCK: u64 = 150;
+
+/// Requests made by resolving an ENS name: the resolver of the name, then its address.
+const ENS_RESOLUTION_CALLS: u64 = 2;
+
+/// Requests made by looking up the ENS name of an address: the reverse resolver, the name, then
+/// the address of the name, to check it points back to the address.
+const ENS_LOOKUP_CALLS: u64 = 2 + ENS_RESOLUTION_CALLS;
+
+/// Blocks probed to find the first active block of an account, log2 of the block number plus the
+/// queried block. The chain head isn't known, so this assumes a chain of about 30 million blocks.
+const ESTIMATED_FIRST_ACTIVE_BLOCK_PROBES: u64 = 26;
+
+/// Storage slots read to find the implementation of a proxy: the EIP-1967 implementation and beacon
+/// slots, the EIP-1822 slot and the ZeppelinOS slot.
+const PROXY_SLOTS: u64 = 4;
+
+/// Number of times a method is called
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub enum CallCount {
+    Exact(u64),
+    Estimated(u64),
+    Unknown,
+}
+
+impl Add for CallCount {
+    type Output = CallCount;
+
+    fn add(self, other: CallCount) -> CallCount {
+        match (self, other) {
+            (CallCount::Exact(a), CallCount::Exact(b)) => CallCount::Exact(a + b),
+            (CallCount::Unknown, _) | (_, CallCount::Unknown) => CallCount::Unknown,
+            (a, b) => CallCount::Estimated(a.value() + b.value()),
+        }
+    }
+}
+
+impl Mul for CallCount {
+    type Output = CallCount;
+
+    fn mul(self, other: CallCount) -> CallCount {
+        match (self, other) {
+            (CallCount::Exact(a), CallCount::Exact(b)) => CallCount::Exact(a * b),
+            (CallCount::Unknown, _) | (_, CallCount::Unknown) => CallCount::Unknown,
+            (a, b) => CallCount::Estimated(a.value() * b.value()),
+        }
+    }
+}
+
+impl CallCount {
+    fn value(&self) -> u64 {
+        match self {
+            CallCount::Exact(n) | CallCount::Estimated(n) => *n,
+            CallCount::Unknown => 0,
+        }
+    }
+}
+
+impl Display for CallCount {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CallCount::Exact(n) => write!(f, "{}", n),
+            CallCount::Estimated(n) => write!(f, "~{}", n),
+            CallCount::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct PlannedCall {
+    pub method: String,
+    pub count: CallCount,
+    /// What the requests fetch, e.g. `full blocks`
+    pub description: String,
+}
+
+/// Cost of a request of each method on a metered RPC plan. Methods missing from the table cost
+/// `default_weight`.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct MethodWeights {
+    weights: BTreeMap<String, u64>,
+    default_weight: u64,
+}
+
+impl MethodWeights {
+    pub fn new(default_weight: u64) -> MethodWeights {
+        MethodWeights {
+            weights: BTreeMap::new(),
+            default_weight,
+        }
+    }
+
+    /// Sets the cost of a request of `method`.
+    pub fn with_weight(mut self, method: &str, weight: u64) -> MethodWeights {
+        self.weights.insert(method.to_string(), weight);
+        self
+    }
+
+    /// Compute units of the methods used by the queries, as published by Alchemy. Providers
+    /// revise their pricing, so the weights are a rough guide and can be overridden with
+    /// `with_weight`.
+    pub fn alchemy() -> MethodWeights {
+        MethodWeights::new(26)
+            .with_weight("eth_getBlockByNumber", 16)
+            .with_weight("eth_getBlockByHash", 16)
+            .with_weight("eth_getBlockTransactionCountByNumber", 20)
+            .with_weight("eth_getTransactionByHash", 17)
+            .with_weight("eth_getTransactionReceipt", 15)
+            .with_weight("eth_getBalance", 19)
+            .with_weight("eth_getCode", 19)
+            .with_weight("eth_getStorageAt", 17)
+            .with_weight("eth_getTransactionCount", 26)
+            .with_weight("eth_call", 26)
+            .with_weight("eth_getLogs", 75)
+            .with_weight("debug_traceTransaction", 309)
+    }
+
+    pub fn weight(&self, method: &str) -> u64 {
+        self.weights
+            .get(method)
+            .copied()
+            .unwrap_or(self.default_weight)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct QueryPlan {
+    pub calls: Vec<PlannedCall>,
+}
+
+impl QueryPlan {
+    pub fn new(expr: &GetExpression) -> Result<QueryPlan> {
+        let mut plan = QueryPlan::default();
+        let chains = CallCount::Exact(expr.chains.len() as u64);
+
+        match &expr.entity {
+            Entity::Block(block) => plan_block(&mut plan, block, chains)?,
+            Entity::Account(account) => plan_account(&mut plan, account, chains)?,
+            Entity::Transaction(transaction) => plan_transaction(&mut plan, transaction, chains)?,
+            Entity::Logs(logs) => plan_logs(&mut plan, logs, chains),
+            Entity::Token(token) => plan_token(&mut plan, token, chains),
+            Entity::Receipt(receipt) => plan_receipt(&mut plan, receipt, chains),
+            Entity::Chain(chain) => plan_chain(&mut plan, chain, chains),
+        }
+
+        Ok(plan)
+    }
+
+    /// Number of requests made to `method`
+    pub fn count(&self, method: &str) -> CallCount {
+        self.calls
+            .iter()
+            .filter(|call| call.method == method)
+            .fold(CallCount::Exact(0), |count, call| count + call.count)
+    }
+
+    /// Number of requests made by the query
+    pub fn total(&self) -> CallCount {
+        self.calls
+            .iter()
+            .fold(CallCount::Exact(0), |count, call| count + call.count)
+    }
+
+    /// Cost of the requests made by the query, with each request costing the weight of its
+    /// method. The cost is estimated when some of the counts are, and unknown when any is.
+    pub fn cost(&self, weights: &MethodWeights) -> CallCount {
+        self.calls.iter().fold(CallCount::Exact(0), |cost, call| {
+            cost + call.count * CallCount::Exact(weights.weight(&call.method))
+        })
+    }
+
+    fn push(&mut self, method: &str, count: CallCount, description: &str) {
+        if count == CallCount::Exact(0) {
+            return;
+        }
+
+        self.calls.push(PlannedCall {
+            method: method.to_string(),
+            count,
+            description: description.to_string(),
+        });
+    }
+}
+
+impl Display for QueryPlan {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for call in &self.calls {
+            writeln!(f, "{} x{} ({})", call.method, call.count, call.description)?;
+        }
+        write!(f, "total: {} requests", self.total())
+    }
+}
+
+/// Number of blocks in the range. Ranges bounded by different tags, or by a tag and a number,
+/// can't be counted offline.
+fn block_count(range: &BlockRange) -> CallCount {
+    let (start_offset, end_offset) = range.offsets();
+
+    match range.range() {
+        (_, None) => CallCount::Exact(1),
+        (BlockNumberOrTag::Number(start), Some(BlockNumberOrTag::Number(end))) => {
+            CallCount::Exact((end + 1).saturating_sub(start))
+        }
+        (start, Some(end)) if start == end => {
+            CallCount::Exact((start_offset + 1).saturating_sub(end_offset))
+        }
+        _ => CallCount::Unknown,
+    }
+}
+
+/// Number of bounds of the range given as a tag, each one is resolved with a request
+fn tag_count(range: &BlockRange) -> CallCount {
+    let (start, end) = range.range();
+    let tags = [Some(start), end]
+        .into_iter()
+        .flatten()
+        .filter(|bound| !matches!(bound, BlockNumberOrTag::Number(_)))
+        .count();
+
+    CallCount::Exact(tags as u64)
+}
+
+fn plan_block(plan: &mut QueryPlan, block: &Block, chains: CallCount) -> Result<()> {
+    let ids = block.block_ids();
+    if ids.is_empty() {
+        return Err(BlockResolverErrors::IdsNotSet.into());
+    }
+
+    let (mut tags, mut blocks, mut hashes) = (
+        CallCount::Exact(0),
+        CallCount::Exact(0),
+        CallCount::Exact(0),
+    );
+    for id in &ids {
+        match id {
+            BlockId::Number(BlockNumberOrTag::Number(_)) => blocks = blocks + CallCount::Exact(1),
+            BlockId::Number(_) => {
+                tags = tags + CallCount::Exact(1);
+                blocks = blocks + CallCount::Exact(1);
+            }
+            BlockId::Range(range) => {
+                tags = tags + tag_count(range);
+                blocks = blocks + block_count(range);
+            }
+            BlockId::Hash(_) => hashes = hashes + CallCount::Exact(1),
+        }
+    }
+
+    plan.push("eth_getBlockByNumber", tags * chains, "block tags");
+    if block.counts_transactions_only() {
+        plan.push(
+            "eth_getBlockTransactionCountByNumber",
+            blocks * chains,
+            "transaction counts",
+        );
+    } else {
+        plan.push("eth_getBlockByNumber", blocks * chains, "block headers");
+    }
+    plan.push("eth_getBlockByHash", hashes * chains, "block headers");
+
+    Ok(())
+}
+
+fn plan_transaction(
+    plan: &mut QueryPlan,
+    transaction: &Transaction,
+    chains: CallCount,
+) -> Result<()> {
+    let names = CallCount::Exact(transaction.ens_names().len() as u64);
+    plan.push(
+        "eth_call",
+        names * CallCount::Exact(ENS_RESOLUTION_CALLS),
+        "ENS names",
+    );
+
+    // Transactions fetched by hash may all be in different blocks
+    let (transactions, blocks) = match transaction.ids() {
+        None if !transaction.has_block_filter() && transaction.sender_and_nonce().is_some() => {
+            let transactions = CallCount::Exact(1);
+            plan.push(
+                "eth_getTransactionBySenderAndNonce",
+                transactions * chains,
+                "transactions",
+            );
+            (transactions, transactions)
+        }
+        // The blocks are searched until enough transactions are found, so their number isn't known
+        None if transaction.scans_latest_blocks() => {
+            plan.push("eth_blockNumber", chains, "latest block");
+            plan.push(
+                "eth_getBlockByNumber",
+                CallCount::Unknown,
+                "full blocks, back from the latest",
+            );
+            (CallCount::Unknown, CallCount::Unknown)
+        }
+        Some(ids) => {
+            let transactions = CallCount::Exact(ids.len() as u64);
+            plan.push(
+                "eth_getTransactionByHash",
+                transactions * chains,
+                "transactions",
+            );
+            (transactions, transactions)
+        }
+        None => {
+            let per_block = CallCount::Estimated(ESTIMATED_TRANSACTIONS_PER_BLOCK);
+            let (method, tags, blocks) = match transaction.get_block_id_filter()? {
+                BlockId::Hash(_) => (
+                    "eth_getBlockByHash",
+                    CallCount::Exact(0),
+                    CallCount::Exact(1),
+                ),
+                // Blocks given by a single tag are fetched as is, without resolving the tag first
+                BlockId::Number(_) => (
+                    "eth_getBlockByNumber",
+                    CallCount::Exact(0),
+                    CallCount::Exact(1),
+                ),
+                BlockId::Range(range) if range.range() == (BlockNumberOrTag::Pending, None) => (
+                    "eth_getBlockByNumber",
+                    CallCount::Exact(0),
+                    CallCount::Exact(1),
+                ),
+                BlockId::Range(range) => {
+                    ("eth_getBlockByNumber", tag_count(range), block_count(range))
+                }
+            };
+            plan.push("eth_getBlockByNumber", tags * chains, "block tags");
+            let fetched = match transaction.needs_bodies() {
+                true => "full blocks",
+                false => "blocks, transaction hashes only",
+            };
+            plan.push(method, blocks * chains, fetched);
+            (blocks * per_block, blocks)
+        }
+    };
+
+    let fields = transaction.fields();
+    if transaction.has_status_filter() || fields.iter().any(|field| field.is_receipt_field()) {
+        plan.push(
+            "eth_getTransactionReceipt",
+            transactions * chains,
+            "receipts",
+        );
+    }
+    // Queries over blocks already have the base fee of the blocks they fetched
+    let reads_blocks = transaction.ids().is_none() && transaction.has_block_filter();
+    if fields.contains(&TransactionField::EffectiveTip) && !reads_blocks {
+        plan.push("eth_getBlockByNumber", blocks * chains, "base fees");
+    }
+    if fields.contains(&TransactionField::Confirmations) {
+        plan.push("eth_blockNumber", chains, "latest block, for confirmations");
+    }
+    // Queries over whole blocks already have the gas prices of every transaction in them
+    if fields.contains(&TransactionField::GasPricePercentile) && !transaction.reads_whole_blocks() {
+        plan.push("eth_getBlockByNumber", blocks * chains, "fee distributions");
+    }
+    if fields.contains(&TransactionField::Trace) {
+        plan.push("debug_traceTransaction", transactions * chains, "traces");
+    }
+    // Only the failed transactions are replayed, which is at most all of them
+    if fields.contains(&TransactionField::RevertReason) {
+        let replays = CallCount::Estimated((transactions * chains).value());
+        plan.push("eth_call", replays, "revert reasons");
+    }
+
+    Ok(())
+}
+
+/// Balances are assumed to be read through Multicall3 whenever there's more than one of them,
+/// which is the case on every chain it's deployed on.
+fn plan_account(plan: &mut QueryPlan, account: &Account, chains: CallCount) -> Result<()> {
+    let ids = account
+        .ids()
+        .ok_or(AccountResolverErrors::MissingAccountIds)?;
+    let names: HashSet<&String> = ids
+        .iter()
+        .filter_map(|id| match id {
+            NameOrAddress::Name(name) => Some(name),
+            NameOrAddress::Address(_) => None,
+        })
+        .collect();
+    plan.push(
+        "eth_call",
+        CallCount::Exact(names.len() as u64 * ENS_RESOLUTION_CALLS),
+        "ENS names",
+    );
+
+    let fields = account.fields();
+    let accounts = CallCount::Exact(ids.len() as u64);
+    let count = |matches: fn(&AccountField) -> bool| {
+        CallCount::Exact(fields.iter().filter(|field| matches(field)).count() as u64)
+    };
+    let balances = accounts
+        * count(|field| matches!(field, AccountField::Balance | AccountField::TokenBalance(_)));
+
+    if balances.value() > 1 {
+        plan.push("eth_getCode", chains, "Multicall3 deployment");
+        plan.push("eth_call", chains, "balances through Multicall3");
+    } else {
+        plan.push(
+            "eth_getBalance",
+            accounts * count(|field| *field == AccountField::Balance) * chains,
+            "balances",
+        );
+        plan.push(
+            "eth_call",
+            accounts * count(|field| matches!(field, AccountField::TokenBalance(_))) * chains,
+            "token balances",
+        );
+    }
+
+    let code = fields.iter().any(|field| {
+        matches!(
+            field,
+            AccountField::Code | AccountField::IsContract | AccountField::Selectors
+        )
+    });
+    plan.push(
+        "eth_getTransactionCount",
+        accounts * count(|field| *field == AccountField::Nonce) * chains,
+        "nonces",
+    );
+    plan.push(
+        "eth_getCode",
+        accounts * CallCount::Exact(code as u64) * chains,
+        "code",
+    );
+    plan.push(
+        "eth_getStorageAt",
+        accounts * count(|field| matches!(field, AccountField::Storage(_))) * chains,
+        "storage slots",
+    );
+    // Beacon proxies also take a call to their beacon, which isn't known before the slots are read
+    plan.push(
+        "eth_getStorageAt",
+        accounts
+            * count(|field| *field == AccountField::Implementation)
+            * CallCount::Exact(PROXY_SLOTS)
+            * chains,
+        "proxy slots",
+    );
+    plan.push(
+        "eth_call",
+        accounts * count(|field| matches!(field, AccountField::Call(_))) * chains,
+        "contract calls",
+    );
+
+    let searches = accounts * count(|field| *field == AccountField::FirstActiveBlock) * chains;
+    if searches.value() > 0 {
+        if !matches!(account.block(), Some(BlockNumberOrTag::Number(_))) {
+            plan.push(
+                "eth_getBlockByNumber",
+                searches,
+                "first active block bounds",
+            );
+        }
+        let probes = searches * CallCount::Estimated(ESTIMATED_FIRST_ACTIVE_BLOCK_PROBES);
+        plan.push("eth_getBalance", probes, "first active block probes");
+        plan.push(
+            "eth_getTransactionCount",
+            probes,
+            "first active block probes",
+        );
+    }
+    plan.push(
+        "eth_call",
+        accounts
+            * count(|field| *field == AccountField::EnsName)
+            * CallCount::Exact(ENS_LOOKUP_CALLS)
+            * chains,
+        "ENS name lookups",
+    );
+
+    Ok(())
+}
+
+fn plan_logs(plan: &mut QueryPlan, logs: &Logs, chains: CallCount) {
+    // Only relative ranges are resolved, other tags are sent as is
+    let tags = logs
+        .filter()
+        .iter()
+        .fold(CallCount::Exact(0), |tags, filter| match filter {
+            LogFilter::BlockRange(range) if range.is_relative() => tags + tag_count(range),
+            _ => tags,
+        });
+
+    plan.push("eth_getBlockByNumber", tags * chains, "block tags");
+    plan.push("eth_getLogs", chains, "logs");
+}
+
+fn plan_token(plan: &mut QueryPlan, token: &Token, chains: CallCount) {
+    let calls = token
+        .fields()
+        .iter()
+        .filter(|field| !matches!(field, TokenField::Address | TokenField::Chain))
+        .count();
+
+    plan.push(
+        "eth_call",
+        CallCount::Exact((token.ids().len() * calls) as u64) * chains,
+        "token metadata",
+    );
+}
+
+fn plan_receipt(plan: &mut QueryPlan, receipt: &Receipt, chains: CallCount) {
+    plan.push(
+        "eth_getTransactionReceipt",
+        CallCount::Exact(receipt.ids().len() as u64) * chains,
+        "receipts",
+    );
+}
+
+fn plan_chain(plan: &mut QueryPlan, chain: &ChainStatus, chains: CallCount) {
+    let fields = chain.fields();
+
+    if fields.contains(&ChainField::ChainId) {
+        plan.push("eth_chainId", chains, "chain id");
+    }
+    if fields.contains(&ChainField::GasPrice) {
+        plan.push("eth_gasPrice", chains, "gas price");
+    }
+    // The block number is read from the latest block when its base fee is fetched too
+    if fields.contains(&ChainField::BaseFeePerGas) {
+        plan.push("eth_getBlockByNumber", chains, "latest block");
+    } else if fields.contains(&ChainField::BlockNumber) {
+        plan.push("eth_blockNumber", chains, "latest block number");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{
+        block::BlockField,
+        chain::{Chain, ChainOrRpc},
+        transaction::TransactionFilter,
+    };
+
+    fn get_expression(entity: Entity, chains: Vec<ChainOrRpc>) -> GetExpression {
+        GetExpression {
+            entity,
+            chains,
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        }
+    }
+
+    #[test]
+    fn test_explain_receipt_calls_of_block_range() {
+        let range = BlockRange::new(
+            BlockNumberOrTag::Number(20_000_000),
+            Some(BlockNumberOrTag::Number(20_000_015)),
+        );
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(range))]),
+            vec![TransactionField::Hash, TransactionField::GasUsed],
+        );
+        let expr = get_expression(
+            Entity::Transaction(transaction),
+            vec![ChainOrRpc::Chain(Chain::Ethereum)],
+        );
+
+        let plan = QueryPlan::new(&expr).unwrap();
+
+        assert_eq!(plan.count("eth_getBlockByNumber"), CallCount::Exact(16));
+        assert_eq!(
+            plan.count("eth_getTransactionReceipt"),
+            CallCount::Estimated(2400)
+        );
+        assert_eq!(plan.total(), CallCount::Estimated(2416));
+    }
+
+    #[test]
+    fn test_cost_of_receipt_calls_of_block_range() {
+        let range = BlockRange::new(
+            BlockNumberOrTag::Number(20_000_000),
+            Some(BlockNumberOrTag::Number(20_000_015)),
+        );
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(range))]),
+            vec![TransactionField::Hash, TransactionField::GasUsed],
+        );
+        let expr = get_expression(
+            Entity::Transaction(transaction),
+            vec![ChainOrRpc::Chain(Chain::Ethereum)],
+        );
+        let weights = MethodWeights::new(10)
+            .with_weight("eth_getBlockByNumber", 16)
+            .with_weight("eth_getTransactionReceipt", 15);
+
+        let plan = QueryPlan::new(&expr).unwrap();
+
+        // 16 blocks and ~2400 receipts
+        assert_eq!(
+            plan.cost(&weights),
+            CallCount::Estimated(16 * 16 + 2400 * 15)
+        );
+        // Methods missing from the table cost the default weight
+        assert_eq!(
+            plan.cost(&MethodWeights::new(10)),
+            CallCount::Estimated(2416 * 10)
+        );
+    }
+
+    #[test]
+    fn test_cost_of_block_range_bounded_by_tags_is_unknown() {
+        let range = BlockRange::new(BlockNumberOrTag::Number(1), Some(BlockNumberOrTag::Latest));
+        let block = Block::new(Some(vec![BlockId::Range(range)]), None, vec![]);
+        let expr = get_expression(
+            Entity::Block(block),
+            vec![ChainOrRpc::Chain(Chain::Ethereum)],
+        );
+
+        let plan = QueryPlan::new(&expr).unwrap();
+
+        assert_eq!(plan.cost(&MethodWeights::alchemy()), CallCount::Unknown);
+    }
+
+    #[test]
+    fn test_explain_block_range_bounded_by_tags() {
+        let relative = BlockRange::new(BlockNumberOrTag::Latest, Some(BlockNumberOrTag::Latest))
+            .with_offsets(9, 0);
+        let open = BlockRange::new(BlockNumberOrTag::Number(1), Some(BlockNumberOrTag::Latest));
+        let plan = |range| {
+            let block = Block::new(Some(vec![BlockId::Range(range)]), None, vec![]);
+            let chains = vec![Chain::Ethereum, Chain::Base]
+                .into_iter()
+                .map(ChainOrRpc::Chain)
+                .collect();
+            QueryPlan::new(&get_expression(Entity::Block(block), chains)).unwrap()
+        };
+
+        // Two tags, resolved then fetched on both chains
+        assert_eq!(
+            plan(relative).count("eth_getBlockByNumber"),
+            CallCount::Exact(24)
+        );
+        assert_eq!(plan(open).count("eth_getBlockByNumber"), CallCount::Unknown);
+    }
+
+    #[test]
+    fn test_explain_transaction_count_of_block_range() {
+        let range = BlockRange::new(
+            BlockNumberOrTag::Number(1),
+            Some(BlockNumberOrTag::Number(10)),
+        );
+        let block = Block::new(
+            Some(vec![BlockId::Range(range)]),
+            None,
+            vec![BlockField::Number, BlockField::TransactionCount],
+        );
+        let plan = QueryPlan::new(&get_expression(
+            Entity::Block(block),
+            vec![ChainOrRpc::Chain(Chain::Ethereum)],
+        ))
+        .unwrap();
+
+        assert_eq!(
+            plan.count("eth_getBlockTransactionCountByNumber"),
+            CallCount::Exact(10)
+        );
+        assert_eq!(plan.count("eth_getBlockByNumber"), CallCount::Exact(0));
+    }
+}
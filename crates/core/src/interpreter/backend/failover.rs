@@ -0,0 +1,174 @@
+//! Failover between the RPCs of a chain. Public RPCs go down or start answering with server
+//! errors in the middle of long queries, so a request that can't get a response from an RPC is
+//! sent to the next one instead. The RPC that answered last keeps receiving the next requests.
+//!
+//! Only transport errors, like a refused connection or a non-200 HTTP status, fail over. Errors
+//! returned by the RPC itself, like a reverted call, are returned as is.
+use super::timeout;
+use alloy::{
+    rpc::{
+        client::BuiltInConnectionString,
+        json_rpc::{RequestPacket, ResponsePacket},
+    },
+    transports::{BoxTransport, RpcError, TransportError, TransportErrorKind, TransportFut},
+};
+use anyhow::Result;
+use std::{
+    future::poll_fn,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+#[derive(Clone)]
+pub(crate) struct FailoverTransport {
+    transports: Arc<Vec<BoxTransport>>,
+    /// Index of the transport the next request is sent to
+    current: Arc<AtomicUsize>,
+}
+
+impl FailoverTransport {
+    /// Connects to every RPC, in order of preference. The RPCs that can't be connected to are
+    /// left out, and an error is returned if none of them can. A request that times out on an RPC
+    /// is sent to the next, so a single unresponsive RPC doesn't stall the query.
+    /// `layer` wraps the transport of each RPC, given its index in `urls`.
+    pub(crate) async fn connect(
+        urls: &[BuiltInConnectionString],
+        timeout: Duration,
+        layer: impl Fn(usize, BoxTransport) -> BoxTransport,
+    ) -> Result<Self> {
+        let mut transports = Vec::new();
+        let mut error = None;
+
+        for (index, url) in urls.iter().enumerate() {
+            match timeout::connect(url, timeout).await {
+                Ok(transport) => transports.push(layer(index, transport)),
+                Err(e) => error = Some(e),
+            }
+        }
+
+        match error {
+            Some(e) if transports.is_empty() => Err(e.into()),
+            _ => Ok(Self {
+                transports: Arc::new(transports),
+                current: Arc::new(AtomicUsize::new(0)),
+            }),
+        }
+    }
+}
+
+impl Service<RequestPacket> for FailoverTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Each transport is polled when the request is sent to it
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let transports = self.transports.clone();
+        let current = self.current.clone();
+
+        Box::pin(async move {
+            let start = current.load(Ordering::Relaxed);
+            let mut last_error = RpcError::Transport(TransportErrorKind::BackendGone);
+
+            for i in 0..transports.len() {
+                let index = (start + i) % transports.len();
+                let mut transport = transports[index].clone();
+
+                let response = match poll_fn(|cx| transport.poll_ready(cx)).await {
+                    Ok(()) => transport.call(request.clone()).await,
+                    Err(e) => Err(e),
+                };
+
+                match response {
+                    Err(RpcError::Transport(e)) => last_error = RpcError::Transport(e),
+                    response => {
+                        current.store(index, Ordering::Relaxed);
+                        return response;
+                    }
+                }
+            }
+
+            Err(last_error)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        common::{
+            block::{Block, BlockField, BlockId},
+            chain::{Chain, ChainOrRpc},
+            config::tests::{write_config, CONFIG_PATH_LOCK},
+            entity::Entity,
+            query_result::{BlockQueryRes, ExpressionResult},
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::{eips::BlockNumberOrTag, rpc::types::Block as RpcBlock};
+    use std::{env, net::TcpListener};
+
+    /// URL of a port nothing listens on, so every connection to it is refused
+    fn unreachable_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_query_fails_over_to_next_rpc() {
+        let _lock = CONFIG_PATH_LOCK.lock().await;
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_getBlockByNumber" => result::<RpcBlock>(&Default::default()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let unreachable = unreachable_url();
+        let path = write_config(
+            "failover",
+            &format!(
+                r#"{{ "chains": {{ "ronin": {{ "default": "{unreachable}", "rpcs": ["{unreachable}", "{}"] }} }} }}"#,
+                rpc.url()
+            ),
+        );
+        env::set_var("EQL_CONFIG_PATH", &path);
+
+        let expression = || {
+            Expression::Get(GetExpression {
+                entity: Entity::Block(Block::new(
+                    Some(vec![BlockId::Number(BlockNumberOrTag::Number(1))]),
+                    None,
+                    vec![BlockField::Chain],
+                )),
+                chains: vec![ChainOrRpc::Chain(Chain::Ronin)],
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            })
+        };
+        let results = ExecutionEngine::new().run(vec![expression()]).await;
+        env::remove_var("EQL_CONFIG_PATH");
+
+        assert_eq!(
+            results.unwrap()[0].result,
+            ExpressionResult::Block(vec![BlockQueryRes {
+                chain: Some(Chain::Ronin),
+                ..Default::default()
+            }])
+        );
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+    }
+}
@@ -0,0 +1,191 @@
+//! Instrumentation of the RPC requests made while running a query. The hooks are invoked by the
+//! transport of every provider created while the query runs, so they see each request issued by
+//! the resolvers, including the ones sent in a batch or retried.
+use alloy::{
+    rpc::json_rpc::{RequestPacket, ResponsePacket},
+    transports::{Transport, TransportError, TransportFut},
+};
+use std::{
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower::{Layer, Service};
+
+/// Hooks called around each RPC request. Both do nothing by default, so implementations only
+/// need to override the ones they're interested in.
+pub trait Metrics: Send + Sync {
+    /// Called before a request for `method` is sent.
+    fn on_request(&self, _method: &str) {}
+
+    /// Called once the response to a request for `method` is received, or the request failed.
+    fn on_response(&self, _method: &str, _duration: Duration) {}
+}
+
+tokio::task_local! {
+    static METRICS: Arc<dyn Metrics>;
+}
+
+/// Runs the future with `metrics` instrumenting the providers it creates.
+pub(crate) async fn scope<F: Future>(metrics: Arc<dyn Metrics>, future: F) -> F::Output {
+    METRICS.scope(metrics, future).await
+}
+
+/// Metrics of the query being run, if any.
+pub(crate) fn current() -> Option<Arc<dyn Metrics>> {
+    METRICS.try_with(Arc::clone).ok()
+}
+
+/// Transport layer reporting the requests that go through it to the metrics.
+pub(crate) struct MetricsLayer {
+    metrics: Arc<dyn Metrics>,
+}
+
+impl MetricsLayer {
+    pub(crate) fn new(metrics: Arc<dyn Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MetricsService<S> {
+    inner: S,
+    metrics: Arc<dyn Metrics>,
+}
+
+impl<S: Transport> Service<RequestPacket> for MetricsService<S> {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let methods: Vec<String> = match &request {
+            RequestPacket::Single(request) => vec![request.method().to_string()],
+            RequestPacket::Batch(requests) => requests
+                .iter()
+                .map(|request| request.method().to_string())
+                .collect(),
+        };
+
+        for method in &methods {
+            self.metrics.on_request(method);
+        }
+
+        let metrics = self.metrics.clone();
+        let start = Instant::now();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await;
+            let duration = start.elapsed();
+
+            for method in &methods {
+                metrics.on_response(method, duration);
+            }
+
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{
+            account::{Account, AccountField},
+            chain::ChainOrRpc,
+            ens::NameOrAddress,
+            entity::Entity,
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::primitives::{address, U256};
+    use std::{collections::HashMap, sync::Mutex};
+
+    #[derive(Default)]
+    struct CountingMetrics {
+        requests: Mutex<HashMap<String, usize>>,
+        responses: Mutex<usize>,
+    }
+
+    impl Metrics for CountingMetrics {
+        fn on_request(&self, method: &str) {
+            *self
+                .requests
+                .lock()
+                .unwrap()
+                .entry(method.to_string())
+                .or_insert(0) += 1;
+        }
+
+        fn on_response(&self, _method: &str, _duration: Duration) {
+            *self.responses.lock().unwrap() += 1;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metrics_record_every_request() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBalance" => result(&U256::from(100)),
+            "eth_getTransactionCount" => result(&"0x2"),
+            // Multicall3 isn't deployed, so the balances are read individually
+            "eth_getCode" => result(&"0x"),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let expression = Expression::Get(GetExpression {
+            entity: Entity::Account(Account::new(
+                Some(vec![
+                    NameOrAddress::Address(address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045")),
+                    NameOrAddress::Address(address!("dAC17F958D2ee523a2206206994597C13D831ec7")),
+                ]),
+                None,
+                vec![AccountField::Balance, AccountField::Nonce],
+            )),
+            chains: vec![ChainOrRpc::Rpc(rpc.url())],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        });
+        let metrics = Arc::new(CountingMetrics::default());
+
+        ExecutionEngine::with_metrics(metrics.clone())
+            .run(vec![expression])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *metrics.requests.lock().unwrap(),
+            HashMap::from([
+                ("eth_chainId".to_string(), 2),
+                ("eth_getCode".to_string(), 1),
+                ("eth_getBalance".to_string(), 2),
+                ("eth_getTransactionCount".to_string(), 2),
+            ])
+        );
+        assert_eq!(*metrics.responses.lock().unwrap(), 7);
+    }
+}
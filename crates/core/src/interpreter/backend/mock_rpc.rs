@@ -0,0 +1,439 @@
+//! A minimal JSON-RPC server over HTTP, WebSocket or IPC used to test the resolvers without
+//! network access. Every request is answered by a handler closure, and the number of calls per method is
+//! recorded. WebSocket servers can also push subscription notifications to their clients.
+//!
+//! `MockTransport` answers the requests with the same kind of handler, but in memory, for tests
+//! that call a function taking a provider directly and don't need a server.
+use alloy::{
+    providers::{ProviderBuilder, RootProvider},
+    rpc::{
+        client::RpcClient,
+        json_rpc::{RequestPacket, ResponsePacket},
+    },
+    transports::{http::reqwest::Url, BoxTransport, TransportError, TransportFut},
+};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    task::JoinHandle,
+};
+use tokio_tungstenite::tungstenite::Message;
+use tower::Service;
+
+type Handler = dyn Fn(&str, &Value) -> Result<Value, String> + Send + Sync;
+
+/// Events sent by the test to the open WebSocket connections
+#[derive(Clone)]
+enum Event {
+    Notification(String),
+    Disconnect,
+}
+
+pub struct MockRpc {
+    url: Url,
+    calls: Arc<Mutex<HashMap<String, usize>>>,
+    in_flight: Arc<InFlight>,
+    events: broadcast::Sender<Event>,
+    server: JoinHandle<()>,
+}
+
+/// HTTP requests being answered, and the most answered at once
+#[derive(Default)]
+struct InFlight {
+    current: AtomicUsize,
+    max: AtomicUsize,
+}
+
+impl MockRpc {
+    /// Starts the server on a random local port. The handler receives the method name and the
+    /// params of each request, and returns either the result or a JSON-RPC error message.
+    pub async fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        Self::listen(Arc::new(handler), false, Duration::ZERO).await
+    }
+
+    /// Same as `start`, but every response is delayed by `latency`, like a distant RPC.
+    pub async fn start_with_latency<F>(latency: Duration, handler: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        Self::listen(Arc::new(handler), false, latency).await
+    }
+
+    /// Same as `start`, but the requests are received over a WebSocket connection.
+    pub async fn start_ws<F>(handler: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        Self::listen(Arc::new(handler), true, Duration::ZERO).await
+    }
+
+    /// Same as `start`, but the requests are received over a Unix socket created in the temporary
+    /// directory, like the IPC endpoint of a local node.
+    #[cfg(all(unix, feature = "ipc"))]
+    pub async fn start_ipc<F>(handler: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        static SOCKETS: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "eql-mock-rpc-{}-{}.ipc",
+            std::process::id(),
+            SOCKETS.fetch_add(1, Ordering::SeqCst)
+        ));
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let url = format!("ipc://{}", path.display()).parse().unwrap();
+        let handler: Arc<Handler> = Arc::new(handler);
+        let calls = Arc::new(Mutex::new(HashMap::new()));
+        let (events, _) = broadcast::channel(16);
+
+        let server_calls = calls.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_ipc(stream, handler.clone(), server_calls.clone()));
+            }
+        });
+
+        Self {
+            url,
+            calls,
+            in_flight: Arc::new(InFlight::default()),
+            events,
+            server,
+        }
+    }
+
+    async fn listen(handler: Arc<Handler>, ws: bool, latency: Duration) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let scheme = if ws { "ws" } else { "http" };
+        let url = format!("{scheme}://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let calls = Arc::new(Mutex::new(HashMap::new()));
+        let in_flight = Arc::new(InFlight::default());
+        let (events, _) = broadcast::channel(16);
+
+        let server_calls = calls.clone();
+        let server_in_flight = in_flight.clone();
+        let server_events = events.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                if ws {
+                    tokio::spawn(serve_ws(
+                        stream,
+                        handler.clone(),
+                        server_calls.clone(),
+                        server_events.subscribe(),
+                    ));
+                } else {
+                    tokio::spawn(serve(
+                        stream,
+                        handler.clone(),
+                        server_calls.clone(),
+                        server_in_flight.clone(),
+                        latency,
+                    ));
+                }
+            }
+        });
+
+        Self {
+            url,
+            calls,
+            in_flight,
+            events,
+            server,
+        }
+    }
+
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// Provider connected to the server over HTTP, only available for servers created with `start`.
+    pub fn provider(&self) -> Arc<RootProvider<BoxTransport>> {
+        Arc::new(ProviderBuilder::new().on_http(self.url()).boxed())
+    }
+
+    /// Number of requests received for the given method.
+    pub fn calls(&self, method: &str) -> usize {
+        *self.calls.lock().unwrap().get(method).unwrap_or(&0)
+    }
+
+    /// Number of requests received, whatever their method.
+    pub fn total_calls(&self) -> usize {
+        self.calls.lock().unwrap().values().sum()
+    }
+
+    /// Most HTTP requests answered at the same time, a batch counting as a single request. Only
+    /// meaningful with a latency, otherwise requests are answered as soon as they arrive.
+    pub fn max_in_flight(&self) -> usize {
+        self.in_flight.max.load(Ordering::SeqCst)
+    }
+
+    /// Pushes a notification for the given subscription id to the WebSocket clients.
+    pub fn notify<T: Serialize>(&self, subscription: &str, value: &T) {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "eth_subscription",
+            "params": { "subscription": subscription, "result": value }
+        });
+        let _ = self
+            .events
+            .send(Event::Notification(notification.to_string()));
+    }
+
+    /// Closes the WebSocket connections and stops accepting new ones, so clients can't reconnect.
+    pub fn disconnect(&self) {
+        self.server.abort();
+        let _ = self.events.send(Event::Disconnect);
+    }
+}
+
+impl Drop for MockRpc {
+    fn drop(&mut self) {
+        self.server.abort();
+
+        if self.url.scheme() == "ipc" {
+            let _ = std::fs::remove_file(self.url.path());
+        }
+    }
+}
+
+/// Transport answering the requests with the handler, without sending them anywhere.
+#[derive(Clone)]
+pub struct MockTransport {
+    handler: Arc<Handler>,
+    calls: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl MockTransport {
+    /// Same handler as `MockRpc::start`.
+    pub fn new<F>(handler: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        Self {
+            handler: Arc::new(handler),
+            calls: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Provider sending its requests to this transport.
+    pub fn provider(&self) -> Arc<RootProvider<BoxTransport>> {
+        let client = RpcClient::new(self.clone(), true);
+        Arc::new(ProviderBuilder::new().on_client(client).boxed())
+    }
+
+    /// Number of requests received for the given method.
+    pub fn calls(&self, method: &str) -> usize {
+        *self.calls.lock().unwrap().get(method).unwrap_or(&0)
+    }
+}
+
+impl Service<RequestPacket> for MockTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let response = serde_json::to_vec(&request)
+            .map_err(TransportError::ser_err)
+            .map(|body| handle(&body, &self.handler, &self.calls));
+
+        Box::pin(async move {
+            let response = response?;
+            serde_json::from_value(response.clone())
+                .map_err(|e| TransportError::deser_err(e, response.to_string()))
+        })
+    }
+}
+
+/// Serializes a value into the result of a successful response.
+pub fn result<T: Serialize>(value: &T) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|e| e.to_string())
+}
+
+/// Separates the message of the errors made by `revert` from their revert data
+const REVERT_DATA: &str = ", data: ";
+
+/// Error answered by nodes like geth for a call reverting with `data`.
+pub fn revert(data: &[u8]) -> Result<Value, String> {
+    Err(format!(
+        "execution reverted{REVERT_DATA}{}",
+        alloy::hex::encode_prefixed(data)
+    ))
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    handler: Arc<Handler>,
+    calls: Arc<Mutex<HashMap<String, usize>>>,
+    in_flight: Arc<InFlight>,
+    latency: Duration,
+) {
+    let mut buffer = Vec::new();
+
+    // Connections are kept alive, so several requests can arrive on the same stream
+    while let Some(body) = read_request(&mut stream, &mut buffer).await {
+        let current = in_flight.current.fetch_add(1, Ordering::SeqCst) + 1;
+        in_flight.max.fetch_max(current, Ordering::SeqCst);
+        let body = handle(&body, &handler, &calls).to_string();
+        tokio::time::sleep(latency).await;
+        in_flight.current.fetch_sub(1, Ordering::SeqCst);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        if stream.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn serve_ws(
+    stream: TcpStream,
+    handler: Arc<Handler>,
+    calls: Arc<Mutex<HashMap<String, usize>>>,
+    mut events: broadcast::Receiver<Event>,
+) {
+    let Ok(mut stream) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+
+    loop {
+        let response = tokio::select! {
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(body))) => handle(body.as_bytes(), &handler, &calls).to_string(),
+                Some(Ok(_)) => continue,
+                _ => return,
+            },
+            event = events.recv() => match event {
+                Ok(Event::Notification(notification)) => notification,
+                _ => {
+                    let _ = stream.close(None).await;
+                    return;
+                }
+            },
+        };
+
+        if stream.send(Message::Text(response)).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "ipc"))]
+async fn serve_ipc(
+    mut stream: tokio::net::UnixStream,
+    handler: Arc<Handler>,
+    calls: Arc<Mutex<HashMap<String, usize>>>,
+) {
+    let mut buffer = Vec::new();
+
+    loop {
+        // Requests aren't delimited, each one ends with its JSON value, and the last one may not
+        // be complete yet
+        let mut requests = serde_json::Deserializer::from_slice(&buffer).into_iter::<Value>();
+        let mut responses = String::new();
+        while let Some(Ok(request)) = requests.next() {
+            responses
+                .push_str(&handle(request.to_string().as_bytes(), &handler, &calls).to_string());
+        }
+        let read = requests.byte_offset();
+        buffer.drain(..read);
+
+        if stream.write_all(responses.as_bytes()).await.is_err() {
+            return;
+        }
+
+        let mut chunk = [0; 4096];
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+async fn read_request(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    loop {
+        if let Some(header_end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&buffer[..header_end]).to_lowercase();
+            let content_length = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|length| length.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            let request_end = header_end + 4 + content_length;
+
+            if buffer.len() >= request_end {
+                let body = buffer[header_end + 4..request_end].to_vec();
+                buffer.drain(..request_end);
+                return Some(body);
+            }
+        }
+
+        let mut chunk = [0; 4096];
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+fn handle(
+    body: &[u8],
+    handler: &Arc<Handler>,
+    calls: &Arc<Mutex<HashMap<String, usize>>>,
+) -> Value {
+    match serde_json::from_slice(body).unwrap() {
+        Value::Array(batch) => Value::Array(
+            batch
+                .iter()
+                .map(|request| respond(request, handler, calls))
+                .collect(),
+        ),
+        request => respond(&request, handler, calls),
+    }
+}
+
+fn respond(
+    request: &Value,
+    handler: &Arc<Handler>,
+    calls: &Arc<Mutex<HashMap<String, usize>>>,
+) -> Value {
+    let method = request["method"].as_str().unwrap_or_default();
+    *calls.lock().unwrap().entry(method.to_string()).or_insert(0) += 1;
+
+    match handler(method, &request["params"]) {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+        Err(message) => {
+            let error = match message.split_once(REVERT_DATA) {
+                Some((message, data)) => json!({ "code": 3, "message": message, "data": data }),
+                None => json!({ "code": -32000, "message": message }),
+            };
+            json!({ "jsonrpc": "2.0", "id": request["id"], "error": error })
+        }
+    }
+}
@@ -1,5 +1,21 @@
+pub(crate) mod block_cache;
+pub mod chain_id;
+pub mod disk_cache;
+pub mod execution_engine;
+pub mod explain;
+pub(crate) mod failover;
+pub mod metrics;
+#[cfg(test)]
+pub(crate) mod mock_rpc;
+mod partial_results;
+pub(crate) mod rate_limit;
+mod raw_items;
 mod resolve_account;
 mod resolve_block;
+mod resolve_chain;
 mod resolve_logs;
+mod resolve_receipt;
+mod resolve_token;
 mod resolve_transaction;
-pub mod execution_engine;
+pub mod retry;
+pub mod timeout;
@@ -0,0 +1,149 @@
+//! Partial results of the query being run. A query can fetch thousands of transactions or
+//! accounts, and a single item failing on a flaky RPC would otherwise fail the whole query.
+//! When partial results are enabled, the items that can't be fetched are left out of the
+//! results, and their errors are returned alongside them instead.
+use anyhow::Result;
+use futures::future::{join_all, try_join_all};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone, Default)]
+pub(crate) struct ItemErrors {
+    errors: Arc<Mutex<Vec<String>>>,
+}
+
+impl ItemErrors {
+    fn push(&self, error: anyhow::Error) {
+        self.errors.lock().unwrap().push(error.to_string());
+    }
+
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.errors.lock().unwrap())
+    }
+}
+
+tokio::task_local! {
+    static ITEM_ERRORS: ItemErrors;
+}
+
+/// Runs the future with partial results enabled, returning its output along with the errors of
+/// the items left out of it.
+pub(crate) async fn scope<F: Future>(future: F) -> (F::Output, Vec<String>) {
+    let errors = ItemErrors::default();
+    let output = ITEM_ERRORS.scope(errors.clone(), future).await;
+
+    (output, errors.take())
+}
+
+/// Errors of the query being run, if it returns partial results.
+pub(crate) fn current() -> Option<ItemErrors> {
+    ITEM_ERRORS.try_with(Clone::clone).ok()
+}
+
+/// Awaits the futures fetching the items of a query. The first error fails the whole query,
+/// unless partial results are enabled, in which case the failed items are left out.
+pub(crate) async fn join_items<I, T>(futures: I) -> Result<Vec<T>>
+where
+    I: IntoIterator,
+    I::Item: Future<Output = Result<T>>,
+{
+    let Some(errors) = current() else {
+        return try_join_all(futures).await;
+    };
+
+    Ok(join_all(futures)
+        .await
+        .into_iter()
+        .filter_map(|item| item.map_err(|e| errors.push(e)).ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        common::{
+            chain::ChainOrRpc,
+            entity::Entity,
+            query_result::{ExpressionResult, TransactionQueryRes},
+            transaction::{Transaction, TransactionField},
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::{
+        primitives::{b256, B256},
+        rpc::types::Transaction as RpcTransaction,
+    };
+
+    const FAILING_HASH: B256 =
+        b256!("0000000000000000000000000000000000000000000000000000000000000002");
+
+    #[tokio::test]
+    async fn test_failed_items_are_left_out_of_partial_results() {
+        let rpc = MockRpc::start(|method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionByHash" => {
+                let hash: B256 = params[0].as_str().unwrap().parse().unwrap();
+                if hash == FAILING_HASH {
+                    return Err(String::from("transaction unavailable"));
+                }
+                result(&RpcTransaction {
+                    hash,
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let hashes = vec![
+            b256!("0000000000000000000000000000000000000000000000000000000000000001"),
+            FAILING_HASH,
+            b256!("0000000000000000000000000000000000000000000000000000000000000003"),
+        ];
+        let expression = || {
+            Expression::Get(GetExpression {
+                entity: Entity::Transaction(Transaction::new(
+                    Some(hashes.clone()),
+                    None,
+                    vec![TransactionField::Hash],
+                )),
+                chains: vec![ChainOrRpc::Rpc(rpc.url())],
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            })
+        };
+
+        let results = ExecutionEngine::new()
+            .with_partial_results()
+            .run(vec![expression()])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results[0].result,
+            ExpressionResult::Transaction(
+                [hashes[0], hashes[2]]
+                    .map(|hash| TransactionQueryRes {
+                        hash: Some(hash),
+                        ..Default::default()
+                    })
+                    .to_vec()
+            )
+        );
+        assert_eq!(results[0].errors.len(), 1);
+        assert!(results[0].errors[0].contains(&FAILING_HASH.to_string()));
+
+        // Without partial results, the failed item fails the whole query
+        assert!(ExecutionEngine::new()
+            .run(vec![expression()])
+            .await
+            .is_err());
+    }
+}
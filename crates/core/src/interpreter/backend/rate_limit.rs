@@ -0,0 +1,236 @@
+//! Rate limiting of the RPC requests made while running a query. Besides capping the number of
+//! concurrent requests, many RPC providers enforce a quota of requests per second, which a burst
+//! of requests, like fetching the transactions of a block, would otherwise exceed.
+//!
+//! The requests of all the providers created while the query runs share a single token bucket,
+//! which holds up to one second of requests. Requests beyond it wait for their turn, in the order
+//! they're issued. Each request of a batch counts as a request of its own.
+use alloy::{
+    rpc::json_rpc::{RequestPacket, ResponsePacket},
+    transports::{Transport, TransportError, TransportFut},
+};
+use std::{
+    future::Future,
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tower::{Layer, Service};
+
+/// Token bucket shared by the requests of a query
+#[derive(Clone)]
+pub(crate) struct RateLimiter {
+    requests_per_second: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    /// Requests that can be sent right away. Negative when requests are waiting for their turn.
+    tokens: f64,
+    updated: Instant,
+}
+
+tokio::task_local! {
+    static RATE_LIMITER: RateLimiter;
+}
+
+/// Runs the future with the requests of the providers it creates limited to
+/// `requests_per_second`.
+pub(crate) async fn scope<F: Future>(requests_per_second: NonZeroU32, future: F) -> F::Output {
+    RATE_LIMITER
+        .scope(RateLimiter::new(requests_per_second), future)
+        .await
+}
+
+/// Rate limiter of the query being run, if it limits its requests.
+pub(crate) fn current() -> Option<RateLimiter> {
+    RATE_LIMITER.try_with(Clone::clone).ok()
+}
+
+impl RateLimiter {
+    pub(crate) fn new(requests_per_second: NonZeroU32) -> Self {
+        let requests_per_second = f64::from(requests_per_second.get());
+
+        Self {
+            requests_per_second,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: requests_per_second,
+                updated: Instant::now(),
+            })),
+        }
+    }
+
+    /// Waits until `requests` can be sent. The tokens are taken right away, so later requests
+    /// wait for the earlier ones.
+    pub(crate) async fn acquire(&self, requests: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refilled =
+                now.duration_since(bucket.updated).as_secs_f64() * self.requests_per_second;
+
+            bucket.tokens = (bucket.tokens + refilled).min(self.requests_per_second);
+            bucket.updated = now;
+            bucket.tokens -= requests as f64;
+
+            match bucket.tokens < 0.0 {
+                true => Duration::from_secs_f64(-bucket.tokens / self.requests_per_second),
+                false => Duration::ZERO,
+            }
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Transport layer holding the requests that go through it until the rate limiter lets them out.
+pub(crate) struct RateLimitLayer {
+    limiter: RateLimiter,
+}
+
+impl RateLimitLayer {
+    pub(crate) fn new(limiter: RateLimiter) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct RateLimitService<S> {
+    inner: S,
+    limiter: RateLimiter,
+}
+
+impl<S: Transport + Clone> Service<RequestPacket> for RateLimitService<S> {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let requests = match &request {
+            RequestPacket::Single(_) => 1,
+            RequestPacket::Batch(requests) => requests.len(),
+        };
+        let limiter = self.limiter.clone();
+        // Only sent once the limiter lets it out
+        let mut inner = self.inner.clone();
+
+        Box::pin(async move {
+            limiter.acquire(requests).await;
+            inner.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{
+            chain::ChainOrRpc,
+            entity::Entity,
+            transaction::{Transaction, TransactionField},
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::{primitives::B256, rpc::types::Transaction as RpcTransaction};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_beyond_the_budget_wait_for_their_turn() {
+        let limiter = RateLimiter::new(NonZeroU32::new(5).unwrap());
+        let sent = Arc::new(AtomicUsize::new(0));
+        for _ in 0..10 {
+            let (limiter, sent) = (limiter.clone(), sent.clone());
+            tokio::spawn(async move {
+                limiter.acquire(1).await;
+                sent.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        // Lets the spawned requests run until they wait for the clock
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+
+        // The first 5 requests are sent right away, and the next ones every 200ms
+        settle().await;
+        assert_eq!(sent.load(Ordering::Relaxed), 5);
+
+        time::advance(Duration::from_millis(200)).await;
+        settle().await;
+        assert_eq!(sent.load(Ordering::Relaxed), 6);
+
+        time::advance(Duration::from_millis(790)).await;
+        settle().await;
+        assert_eq!(sent.load(Ordering::Relaxed), 9);
+
+        time::advance(Duration::from_millis(10)).await;
+        settle().await;
+        assert_eq!(sent.load(Ordering::Relaxed), 10);
+    }
+
+    #[tokio::test]
+    async fn test_query_requests_share_the_budget() {
+        let rpc = MockRpc::start(|method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionByHash" => result(&RpcTransaction {
+                hash: params[0].as_str().unwrap().parse().unwrap(),
+                ..Default::default()
+            }),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let hashes: Vec<B256> = (1..=9).map(B256::with_last_byte).collect();
+        let expression = Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                Some(hashes),
+                None,
+                vec![TransactionField::Hash],
+            )),
+            chains: vec![ChainOrRpc::Rpc(rpc.url())],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        });
+        let start = Instant::now();
+
+        let results = ExecutionEngine::new()
+            .with_rate_limit(NonZeroU32::new(5).unwrap())
+            .run(vec![expression])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rpc.calls("eth_chainId") + rpc.calls("eth_getTransactionByHash"),
+            10
+        );
+        assert!(start.elapsed() >= Duration::from_millis(950));
+        assert_eq!(results.len(), 1);
+    }
+}
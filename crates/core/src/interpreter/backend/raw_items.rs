@@ -0,0 +1,190 @@
+//! Alloy types of the results of the query being run. The `...QueryRes` results only hold the
+//! selected fields, converted for serialization, which loses what the query language has no field
+//! for. Library callers can get the blocks and transactions as returned by the RPCs instead,
+//! recorded by the resolvers as they're projected into the results.
+use crate::common::query_result::RawItems;
+use alloy::rpc::types::{Block as RpcBlock, Transaction as RpcTransaction};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone, Default)]
+pub(crate) struct RawItemsRecorder {
+    items: Arc<Mutex<RawItems>>,
+}
+
+tokio::task_local! {
+    static RAW_ITEMS: RawItemsRecorder;
+}
+
+/// Runs the future recording the alloy types of its results, returning its output along with
+/// them.
+pub(crate) async fn scope<F: Future>(future: F) -> (F::Output, RawItems) {
+    let recorder = RawItemsRecorder::default();
+    let output = RAW_ITEMS.scope(recorder.clone(), future).await;
+    let items = std::mem::take(&mut *recorder.items.lock().unwrap());
+
+    (output, items)
+}
+
+/// Whether the query being run returns the alloy types.
+pub(crate) fn is_recording() -> bool {
+    RAW_ITEMS.try_with(|_| ()).is_ok()
+}
+
+/// Records a block of the results, if the query being run returns the alloy types.
+pub(crate) fn record_block(block: &RpcBlock) {
+    let _ = RAW_ITEMS.try_with(|recorder| {
+        recorder.items.lock().unwrap().blocks.push(block.clone());
+    });
+}
+
+/// Records a transaction of the results, if the query being run returns the alloy types.
+pub(crate) fn record_transaction(transaction: &RpcTransaction) {
+    let _ = RAW_ITEMS.try_with(|recorder| {
+        recorder
+            .items
+            .lock()
+            .unwrap()
+            .transactions
+            .push(transaction.clone());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        common::{
+            block::{Block, BlockField, BlockId},
+            chain::ChainOrRpc,
+            entity::Entity,
+            query_result::{ExpressionResult, TransactionQueryRes},
+            transaction::{Transaction, TransactionField},
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::{
+        eips::BlockNumberOrTag,
+        primitives::{address, Bytes, B256, U256},
+        rpc::types::{Block as RpcBlock, Header, Transaction as RpcTransaction},
+    };
+
+    fn rpc_transaction() -> RpcTransaction {
+        RpcTransaction {
+            hash: B256::with_last_byte(1),
+            block_number: Some(1),
+            from: address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045"),
+            value: U256::from(100),
+            input: Bytes::from_static(&[0xde, 0xad, 0xbe, 0xef]),
+            gas: 21_000,
+            gas_price: Some(10),
+            ..Default::default()
+        }
+    }
+
+    fn expression(rpc: &MockRpc) -> Expression {
+        Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                Some(vec![B256::with_last_byte(1)]),
+                None,
+                vec![TransactionField::Value],
+            )),
+            chains: vec![ChainOrRpc::Rpc(rpc.url())],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_full_transaction_is_returned_alongside_the_selected_fields() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionByHash" => result(&rpc_transaction()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        let results = ExecutionEngine::new()
+            .with_raw_items()
+            .run(vec![expression(&rpc)])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results[0].result,
+            ExpressionResult::Transaction(vec![TransactionQueryRes {
+                value: Some(U256::from(100)),
+                ..Default::default()
+            }])
+        );
+        let raw = results[0].raw.as_ref().unwrap();
+        assert_eq!(raw.transactions, vec![rpc_transaction()]);
+        assert!(raw.blocks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_full_block_is_returned_alongside_the_selected_fields() {
+        let rpc_block = RpcBlock {
+            header: Header {
+                number: Some(1),
+                hash: Some(B256::with_last_byte(1)),
+                gas_used: 21_000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let served_block = rpc_block.clone();
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&served_block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let expression = Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![BlockId::Number(BlockNumberOrTag::Number(1))]),
+                None,
+                vec![BlockField::Number],
+            )),
+            chains: vec![ChainOrRpc::Rpc(rpc.url())],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        });
+
+        let results = ExecutionEngine::new()
+            .with_raw_items()
+            .run(vec![expression])
+            .await
+            .unwrap();
+
+        let raw = results[0].raw.as_ref().unwrap();
+        assert_eq!(raw.blocks, vec![rpc_block]);
+        assert!(raw.transactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_alloy_types_are_only_returned_when_enabled() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionByHash" => result(&rpc_transaction()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        let results = ExecutionEngine::new()
+            .run(vec![expression(&rpc)])
+            .await
+            .unwrap();
+
+        assert_eq!(results[0].raw, None);
+    }
+}
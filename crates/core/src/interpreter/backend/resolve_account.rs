@@ -1,97 +1,1603 @@
+use super::{
+    partial_results::join_items,
+    resolve_logs::to_json,
+    resolve_token::decimalsCall,
+    resolve_transaction::DEFAULT_MAX_CONCURRENCY,
+    retry::{self, with_retry, RetryConfig},
+};
 use crate::common::{
     account::{Account, AccountField},
-    chain::{Chain, ChainOrRpc},
-    ens::NameOrAddress,
-    query_result::AccountQueryRes,
+    chain::ChainOrRpc,
+    ens::{lookup_address, resolve_names, EnsConfig, EnsRegistryContract, NameOrAddress},
+    query_result::{format_units, AccountQueryRes},
 };
 use alloy::{
-    primitives::Address,
-    providers::{Provider, ProviderBuilder, RootProvider},
-    transports::http::{Client, Http},
+    dyn_abi::FunctionExt,
+    eips::{BlockId, BlockNumberOrTag},
+    json_abi::Function,
+    primitives::{address, b256, hex, Address, FixedBytes, B256, U256},
+    providers::{Provider, RootProvider},
+    rpc::types::TransactionRequest,
+    sol,
+    sol_types::{SolCall, SolValue},
+    transports::{BoxTransport, TransportError},
 };
 use anyhow::Result;
-use futures::future::try_join_all;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+sol! {
+    function balanceOf(address account) external view returns (uint256);
+    function getEthBalance(address addr) external view returns (uint256 balance);
+
+    struct Call3 {
+        address target;
+        bool allowFailure;
+        bytes callData;
+    }
+
+    struct Call3Result {
+        bool success;
+        bytes returnData;
+    }
+
+    function aggregate3(Call3[] calls) external payable returns (Call3Result[] returnData);
+
+    function implementation() external view returns (address);
+}
+
+/// EIP-1967 slot of the implementation of a proxy, `keccak256("eip1967.proxy.implementation") - 1`
+const EIP1967_IMPLEMENTATION_SLOT: B256 =
+    b256!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc");
+/// EIP-1967 slot of the beacon of a beacon proxy, `keccak256("eip1967.proxy.beacon") - 1`
+const EIP1967_BEACON_SLOT: B256 =
+    b256!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50");
+/// EIP-1822 (UUPS) slot of the implementation of a proxy, `keccak256("PROXIABLE")`
+const EIP1822_PROXIABLE_SLOT: B256 =
+    b256!("c5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7");
+/// Slot of the implementation of the ZeppelinOS proxies predating EIP-1967, like USDC's,
+/// `keccak256("org.zeppelinos.proxy.implementation")`
+const ZEPPELINOS_IMPLEMENTATION_SLOT: B256 =
+    b256!("7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3");
+
+/// Multicall3 is deployed at the same address on most chains
+const MULTICALL3: Address = address!("cA11bde05977b3631167028862bE2a173976CA11");
+
+/// Balances read through Multicall3, keyed by account and token. Native balances have no token.
+type Balances = HashMap<(Address, Option<Address>), U256>;
+
+/// Decimals of the tokens whose balances are queried, keyed by token. Tokens whose decimals
+/// couldn't be read are missing.
+type Decimals = HashMap<Address, u8>;
 
 #[derive(Debug, Serialize, Deserialize, thiserror::Error)]
 pub enum AccountResolverErrors {
     #[error("Mismatch between Entity and EntityId, {0} can't be resolved as a account id")]
     MismatchEntityAndEntityId(String),
+    #[error("Query should provide at least one account address or ENS name")]
+    MissingAccountIds,
+    #[error("Unable to resolve ENS name {0}: {1}")]
+    EnsResolution(String, String),
+    #[error("Unable to lookup the ENS name of {0}: {1}")]
+    EnsLookup(Address, String),
+    #[error("Unable to fetch {0} of account {1}: {2}")]
+    Rpc(AccountField, Address, String),
+    #[error("Unable to decode the balance of token {0}: {1}")]
+    TokenBalanceDecoding(Address, String),
+    #[error("Unable to decode the result of {0} on {1}: {2}")]
+    CallDecoding(String, Address, String),
+    #[error(
+        "Unable to read the state of {0} at block {1}, the RPC may not be an archive node: {2}"
+    )]
+    PastStateUnavailable(Address, u64, String),
+    #[error("Unable to find block {0}")]
+    UnknownBlock(BlockNumberOrTag),
 }
 
 /// Resolve the query to get accounts after receiving an account entity expression
 /// Iterate through entity_ids and map them to a futures list. Execute all futures concurrently and collect the results.
+/// The account state is read at the block given by the block filter, or at the latest block otherwise.
+/// ENS names are resolved against the `ens` deployment, regardless of the chains being queried.
+/// With `token_decimals`, the token balances are also formatted with the decimals of their token,
+/// which are read once per chain.
 pub async fn resolve_account_query(
     account: &Account,
     chains: &[ChainOrRpc],
+    ens: &EnsConfig,
+    token_decimals: bool,
 ) -> Result<Vec<AccountQueryRes>> {
+    let block = account.block().unwrap_or(BlockNumberOrTag::Latest);
+    let block_id = BlockId::from(block);
+    let ids = account
+        .ids()
+        .ok_or(AccountResolverErrors::MissingAccountIds)?;
+    let ens_registry = ens.connect().await?;
+    let addresses = to_addresses(ids, &ens_registry, DEFAULT_MAX_CONCURRENCY).await?;
     let mut all_account_futures = Vec::new();
 
     for chain in chains {
-        let provider = Arc::new(ProviderBuilder::new().on_http(chain.rpc_url()?));
+        let provider = Arc::new(chain.provider().await?);
+        let balances =
+            Arc::new(get_balances(&addresses, &account.fields(), &provider, block_id).await);
+        let decimals = match token_decimals {
+            true => Arc::new(get_decimals(&account.fields(), &provider, block_id).await),
+            false => Arc::new(Decimals::new()),
+        };
 
-        // TODO: Handle filter
-        // TODO: Remove unwrap
-        for account_id in account.ids().unwrap() {
+        for address in &addresses {
             let fields = account.fields().clone();
             let provider = provider.clone();
+            let balances = balances.clone();
+            let decimals = decimals.clone();
+            let ens_registry = &ens_registry;
 
             let account_future = async move {
-                match account_id {
-                    NameOrAddress::Address(address) => {
-                        get_account(address, fields, &provider, chain).await
-                    }
-                    NameOrAddress::Name(name) => {
-                        let address = to_address(name).await?;
-                        get_account(&address, fields, &provider, chain).await
-                    }
-                }
+                let context = AccountContext {
+                    provider: &provider,
+                    ens_registry,
+                    chain,
+                    block,
+                    balances: &balances,
+                    decimals: &decimals,
+                };
+                get_account(address, fields, &context).await
             };
 
             all_account_futures.push(account_future);
         }
     }
 
-    let account_res = try_join_all(all_account_futures).await?;
+    let account_res = join_items(all_account_futures).await?;
     Ok(account_res)
 }
 
+/// What the accounts of a chain are read with, shared by all of them
+struct AccountContext<'a> {
+    provider: &'a RootProvider<BoxTransport>,
+    ens_registry: &'a EnsRegistryContract,
+    chain: &'a ChainOrRpc,
+    block: BlockNumberOrTag,
+    balances: &'a Balances,
+    decimals: &'a Decimals,
+}
+
+/// Fetches the account fields one by one, except for the balances already read through Multicall3.
+/// Token balances are also formatted when the decimals of their token are known.
 async fn get_account(
     address: &Address,
     fields: Vec<AccountField>,
-    provider: &RootProvider<Http<Client>>,
-    chain: &ChainOrRpc,
+    context: &AccountContext<'_>,
 ) -> Result<AccountQueryRes> {
+    let AccountContext {
+        provider,
+        ens_registry,
+        chain,
+        block,
+        balances,
+        decimals,
+    } = *context;
+    let block_id = BlockId::from(block);
     let mut account = AccountQueryRes::default();
     let chain = chain.to_chain().await?;
+    let retry = retry::current();
+    // The code is fetched once for all the fields read from it, whatever their order
+    let code_field = fields.iter().find(|field| {
+        matches!(
+            field,
+            AccountField::Code | AccountField::IsContract | AccountField::Selectors
+        )
+    });
+    let code = match code_field {
+        Some(field) => Some(
+            with_retry(&retry, || async {
+                provider.get_code_at(*address).block_id(block_id).await
+            })
+            .await
+            .map_err(|e| AccountResolverErrors::Rpc(field.clone(), *address, e.to_string()))?,
+        ),
+        None => None,
+    };
 
     for field in &fields {
+        let rpc_error =
+            |e: TransportError| AccountResolverErrors::Rpc(field.clone(), *address, e.to_string());
+
         match field {
+            AccountField::Balance if balances.contains_key(&(*address, None)) => {
+                account.balance = Some(balances[&(*address, None)]);
+            }
             AccountField::Balance => {
-                account.balance = Some(provider.get_balance(*address).await?);
+                account.balance = Some(
+                    with_retry(&retry, || async {
+                        provider.get_balance(*address).block_id(block_id).await
+                    })
+                    .await
+                    .map_err(rpc_error)?,
+                );
             }
             AccountField::Nonce => {
-                account.nonce = Some(provider.get_transaction_count(*address).await?);
+                account.nonce = Some(
+                    with_retry(&retry, || async {
+                        provider
+                            .get_transaction_count(*address)
+                            .block_id(block_id)
+                            .await
+                    })
+                    .await
+                    .map_err(rpc_error)?,
+                );
             }
             AccountField::Address => {
                 account.address = Some(*address);
             }
             AccountField::Code => {
-                account.code = Some(provider.get_code_at(*address).await?);
+                account.code = code.clone();
+            }
+            AccountField::IsContract => {
+                account.is_contract = code.as_ref().map(|code| !code.is_empty());
+            }
+            AccountField::Selectors => {
+                account.selectors = code.as_ref().map(|code| function_selectors(code));
             }
             AccountField::Chain => {
                 account.chain = Some(chain.clone());
             }
+            AccountField::EnsName => {
+                account.ens_name = lookup_address(address, ens_registry)
+                    .await
+                    .map_err(|e| AccountResolverErrors::EnsLookup(*address, e.to_string()))?;
+            }
+            AccountField::TokenBalance(token) => {
+                let balance = match balances.get(&(*address, Some(*token))) {
+                    Some(balance) => *balance,
+                    None => get_token_balance(address, token, provider, block_id, &retry).await?,
+                };
+                account
+                    .token_balances
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(*token, balance);
+
+                if let Some(decimals) = decimals.get(token) {
+                    account
+                        .token_balances_formatted
+                        .get_or_insert_with(BTreeMap::new)
+                        .insert(*token, format_units(balance, *decimals));
+                }
+            }
+            AccountField::Storage(slot) => {
+                let value = with_retry(&retry, || async {
+                    provider
+                        .get_storage_at(*address, *slot)
+                        .block_id(block_id)
+                        .await
+                })
+                .await
+                .map_err(rpc_error)?;
+                account
+                    .storage
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(*slot, B256::from(value));
+            }
+            AccountField::FirstActiveBlock => {
+                account.first_active_block =
+                    first_active_block(address, provider, block, &retry).await?;
+            }
+            AccountField::Implementation => {
+                account.implementation =
+                    get_implementation(address, provider, block_id, &retry).await?;
+            }
+            AccountField::Call(call) => {
+                let request = TransactionRequest::default()
+                    .to(*address)
+                    .input(call.calldata().clone().into());
+                let output = with_retry(&retry, || async {
+                    provider.call(&request).block(block_id).await
+                })
+                .await
+                .map_err(rpc_error)?;
+                let result = decode_call_output(call.function(), &output).map_err(|e| {
+                    AccountResolverErrors::CallDecoding(call.name(), *address, e.to_string())
+                })?;
+                account
+                    .calls
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(call.name(), result);
+            }
         }
     }
 
     Ok(account)
 }
 
-async fn to_address(name: &String) -> Result<Address> {
-    let rpc_url = Chain::Ethereum.rpc_url()?;
-    let provider = ProviderBuilder::new().on_http(rpc_url);
-    let address = NameOrAddress::Name(name.clone()).resolve(&provider).await?;
-    Ok(address)
+/// Decodes the output of a call with the return types of the function. A single value is returned
+/// as is and several as an array, while functions declared without return types return the raw
+/// output.
+fn decode_call_output(function: &Function, output: &[u8]) -> Result<Value> {
+    if function.outputs.is_empty() {
+        return Ok(Value::String(hex::encode_prefixed(output)));
+    }
+
+    let mut values = function.abi_decode_output(output, true)?;
+    Ok(match values.len() {
+        1 => to_json(&values.remove(0)),
+        _ => Value::Array(values.iter().map(to_json).collect()),
+    })
+}
+
+/// Selectors of the functions a contract dispatches to, in the order they appear in its code.
+/// Compilers dispatch calls by comparing the selector of the calldata with each function's, so
+/// the selectors are the 4-byte values pushed right before an `EQ`. Push data is skipped while
+/// scanning, so bytes of other constants aren't mistaken for opcodes.
+fn function_selectors(code: &[u8]) -> Vec<FixedBytes<4>> {
+    const PUSH1: u8 = 0x60;
+    const PUSH4: u8 = 0x63;
+    const PUSH32: u8 = 0x7f;
+    const EQ: u8 = 0x14;
+    let mut selectors = vec![];
+    let mut offset = 0;
+
+    while offset < code.len() {
+        let opcode = code[offset];
+
+        if opcode == PUSH4 && code.get(offset + 5) == Some(&EQ) {
+            let selector = FixedBytes::from_slice(&code[offset + 1..offset + 5]);
+            if !selectors.contains(&selector) {
+                selectors.push(selector);
+            }
+        }
+
+        offset += match opcode {
+            PUSH1..=PUSH32 => 2 + (opcode - PUSH1) as usize,
+            _ => 1,
+        };
+    }
+
+    selectors
+}
+
+/// First block at which the account had a nonzero nonce or balance, up to the queried block, or
+/// `None` if it has neither at the queried block. An account stays active once it is, since its
+/// balance can only be spent by sending transactions, which raise its nonce. So the block is
+/// found with a binary search, probing at most log2(block) + 2 blocks. Reading the state of past
+/// blocks needs an archive node.
+async fn first_active_block(
+    address: &Address,
+    provider: &RootProvider<BoxTransport>,
+    block: BlockNumberOrTag,
+    retry: &RetryConfig,
+) -> Result<Option<u64>> {
+    let last = match block {
+        BlockNumberOrTag::Number(number) => number,
+        tag => {
+            let block = with_retry(retry, || async {
+                provider.get_block_by_number(tag, false).await
+            })
+            .await
+            .map_err(|e| {
+                AccountResolverErrors::Rpc(AccountField::FirstActiveBlock, *address, e.to_string())
+            })?;
+            block
+                .and_then(|block| block.header.number)
+                .ok_or(AccountResolverErrors::UnknownBlock(tag))?
+        }
+    };
+
+    if !is_active(address, provider, last, retry).await? {
+        return Ok(None);
+    }
+
+    let (mut low, mut high) = (0, last);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if is_active(address, provider, middle, retry).await? {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+
+    Ok(Some(low))
+}
+
+/// Implementation of the proxy at `address`, read from the EIP-1967 implementation slot, then the
+/// EIP-1967 beacon slot, then the EIP-1822 slot, then the legacy ZeppelinOS slot. The slots are
+/// read at once, and the beacon of a beacon proxy is called for its implementation. The EIP-1967 admin slot holds the account
+/// allowed to upgrade the proxy rather than an implementation, so it isn't read. `None` when no
+/// slot is set, as for accounts that aren't proxies.
+async fn get_implementation(
+    address: &Address,
+    provider: &RootProvider<BoxTransport>,
+    block_id: BlockId,
+    retry: &RetryConfig,
+) -> Result<Option<Address>> {
+    let rpc_error = |e: TransportError| {
+        AccountResolverErrors::Rpc(AccountField::Implementation, *address, e.to_string())
+    };
+    let read_slot = |slot: B256| async move {
+        with_retry(retry, || async {
+            provider
+                .get_storage_at(*address, slot.into())
+                .block_id(block_id)
+                .await
+        })
+        .await
+        .map(|value| Address::from_word(value.into()))
+        .map_err(rpc_error)
+    };
+
+    let (implementation, beacon, proxiable, zeppelinos) = futures::try_join!(
+        read_slot(EIP1967_IMPLEMENTATION_SLOT),
+        read_slot(EIP1967_BEACON_SLOT),
+        read_slot(EIP1822_PROXIABLE_SLOT),
+        read_slot(ZEPPELINOS_IMPLEMENTATION_SLOT),
+    )?;
+
+    if !implementation.is_zero() {
+        return Ok(Some(implementation));
+    }
+    if !beacon.is_zero() {
+        let call = TransactionRequest::default()
+            .to(beacon)
+            .input(implementationCall {}.abi_encode().into());
+        let output = with_retry(retry, || async {
+            provider.call(&call).block(block_id).await
+        })
+        .await
+        .map_err(rpc_error)?;
+        let implementation =
+            implementationCall::abi_decode_returns(&output, true).map_err(|e| {
+                AccountResolverErrors::CallDecoding(
+                    String::from("implementation()"),
+                    beacon,
+                    e.to_string(),
+                )
+            })?;
+        return Ok(Some(implementation._0));
+    }
+
+    Ok([proxiable, zeppelinos]
+        .into_iter()
+        .find(|implementation| !implementation.is_zero()))
+}
+
+/// Whether the account has a nonzero nonce or balance at the block
+async fn is_active(
+    address: &Address,
+    provider: &RootProvider<BoxTransport>,
+    block: u64,
+    retry: &RetryConfig,
+) -> Result<bool> {
+    let block_id = BlockId::number(block);
+    let past_state_error = |e: TransportError| {
+        AccountResolverErrors::PastStateUnavailable(*address, block, e.to_string())
+    };
+
+    let (nonce, balance) = futures::try_join!(
+        async {
+            with_retry(retry, || async {
+                provider
+                    .get_transaction_count(*address)
+                    .block_id(block_id)
+                    .await
+            })
+            .await
+            .map_err(past_state_error)
+        },
+        async {
+            with_retry(retry, || async {
+                provider.get_balance(*address).block_id(block_id).await
+            })
+            .await
+            .map_err(past_state_error)
+        },
+    )?;
+
+    Ok(nonce > 0 || !balance.is_zero())
+}
+
+async fn get_token_balance(
+    address: &Address,
+    token: &Address,
+    provider: &RootProvider<BoxTransport>,
+    block_id: BlockId,
+    retry: &RetryConfig,
+) -> Result<U256> {
+    let call = TransactionRequest::default()
+        .to(*token)
+        .input(balanceOfCall { account: *address }.abi_encode().into());
+    let output = with_retry(retry, || async {
+        provider.call(&call).block(block_id).await
+    })
+    .await
+    .map_err(|e| {
+        AccountResolverErrors::Rpc(AccountField::TokenBalance(*token), *address, e.to_string())
+    })?;
+    let balance = balanceOfCall::abi_decode_returns(&output, true)
+        .map_err(|e| AccountResolverErrors::TokenBalanceDecoding(*token, e.to_string()))?;
+
+    Ok(balance._0)
+}
+
+/// Reads the decimals of every token whose balance is queried, a single time however many
+/// accounts hold it. Tokens that don't implement `decimals()`, or return more than fits in a
+/// `uint8`, are left out so their balances are only shown raw.
+async fn get_decimals(
+    fields: &[AccountField],
+    provider: &RootProvider<BoxTransport>,
+    block_id: BlockId,
+) -> Decimals {
+    let tokens: HashSet<Address> = fields
+        .iter()
+        .filter_map(|field| match field {
+            AccountField::TokenBalance(token) => Some(*token),
+            _ => None,
+        })
+        .collect();
+    let retry = retry::current();
+
+    let decimals = tokens.into_iter().map(|token| {
+        let retry = &retry;
+        async move {
+            let call = TransactionRequest::default()
+                .to(token)
+                .input(decimalsCall {}.abi_encode().into());
+            let output = with_retry(retry, || async {
+                provider.call(&call).block(block_id).await
+            })
+            .await
+            .ok()?;
+            let decimals = decimalsCall::abi_decode_returns(&output, true).ok()?._0;
+
+            Some((token, decimals))
+        }
+    });
+
+    futures::future::join_all(decimals)
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Reads the native and token balances of all the accounts with a single call to Multicall3.
+/// Nothing is read when there's a single balance in the query or when Multicall3 isn't deployed
+/// on the chain at the queried block, and balances missing from the result (because the call
+/// failed) are read individually instead.
+async fn get_balances(
+    addresses: &[Address],
+    fields: &[AccountField],
+    provider: &RootProvider<BoxTransport>,
+    block_id: BlockId,
+) -> Balances {
+    let reads: Vec<(Address, Option<Address>)> = addresses
+        .iter()
+        .flat_map(|address| {
+            fields.iter().filter_map(move |field| match field {
+                AccountField::Balance => Some((*address, None)),
+                AccountField::TokenBalance(token) => Some((*address, Some(*token))),
+                _ => None,
+            })
+        })
+        .collect();
+
+    if reads.len() < 2 {
+        return Balances::new();
+    }
+
+    let retry = retry::current();
+    let code = with_retry(&retry, || async {
+        provider.get_code_at(MULTICALL3).block_id(block_id).await
+    })
+    .await;
+
+    if !code.is_ok_and(|code| !code.is_empty()) {
+        return Balances::new();
+    }
+
+    let calls = reads
+        .iter()
+        .map(|(address, token)| match token {
+            Some(token) => Call3 {
+                target: *token,
+                allowFailure: true,
+                callData: balanceOfCall { account: *address }.abi_encode().into(),
+            },
+            None => Call3 {
+                target: MULTICALL3,
+                allowFailure: true,
+                callData: getEthBalanceCall { addr: *address }.abi_encode().into(),
+            },
+        })
+        .collect();
+    let call = TransactionRequest::default()
+        .to(MULTICALL3)
+        .input(aggregate3Call { calls }.abi_encode().into());
+    let output = with_retry(&retry, || async {
+        provider.call(&call).block(block_id).await
+    })
+    .await;
+    let Some(results) = output
+        .ok()
+        .and_then(|output| aggregate3Call::abi_decode_returns(&output, true).ok())
+    else {
+        return Balances::new();
+    };
+
+    reads
+        .into_iter()
+        .zip(results.returnData)
+        .filter(|(_, result)| result.success)
+        .filter_map(|(read, result)| {
+            U256::abi_decode(&result.returnData, true)
+                .ok()
+                .map(|balance| (read, balance))
+        })
+        .collect()
+}
+
+/// Maps the account ids to addresses. Each distinct ENS name is resolved a single time,
+/// however many times it's referenced in the query.
+async fn to_addresses(
+    ids: &[NameOrAddress],
+    registry: &EnsRegistryContract,
+    max_concurrency: usize,
+) -> Result<Vec<Address>> {
+    let names = ids.iter().filter_map(|id| match id {
+        NameOrAddress::Name(name) => Some(name),
+        NameOrAddress::Address(_) => None,
+    });
+    let resolved = resolve_names(names, registry, max_concurrency)
+        .await
+        .map_err(|e| AccountResolverErrors::EnsResolution(e.name, e.source.to_string()))?;
+
+    Ok(ids
+        .iter()
+        .map(|id| match id {
+            NameOrAddress::Name(name) => resolved[name],
+            NameOrAddress::Address(address) => *address,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{
+            account::{AccountFilter, ContractCall},
+            chain::Chain,
+            ens::{EnsRegistry, EnsResolver, ENS_ADDRESS},
+            entity::Entity,
+            types::{Expression, GetExpression},
+        },
+        interpreter::{
+            backend::mock_rpc::{result, MockRpc},
+            frontend::parser::Parser,
+        },
+    };
+    use alloy::{
+        primitives::{bytes, fixed_bytes, keccak256, Bytes},
+        rpc::types::{Block as RpcBlock, Header},
+    };
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers the balance reads of both the batched and the per-field paths with the same
+    /// state. Multicall3 is only deployed when `multicall` is set.
+    fn balances_rpc(
+        multicall: bool,
+    ) -> impl Fn(&str, &Value) -> std::result::Result<Value, String> {
+        let native = |address: Address| U256::from(address[19]) * U256::from(1000);
+        let token = |address: Address| U256::from(address[19]);
+
+        move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBalance" => result(&native(params[0].as_str().unwrap().parse().unwrap())),
+            "eth_getCode" if multicall => result(&Bytes::from_static(&[0x60, 0x80])),
+            "eth_getCode" => result(&Bytes::new()),
+            "eth_call" => {
+                let to: Address = params[0]["to"].as_str().unwrap().parse().unwrap();
+                let input: Bytes = params[0]["input"].as_str().unwrap().parse().unwrap();
+
+                if to != MULTICALL3 {
+                    let call = balanceOfCall::abi_decode(&input, true).unwrap();
+                    return result(&Bytes::from(token(call.account).abi_encode()));
+                }
+
+                let results: Vec<Call3Result> = aggregate3Call::abi_decode(&input, true)
+                    .unwrap()
+                    .calls
+                    .into_iter()
+                    .map(|call| {
+                        let balance = if call.target == MULTICALL3 {
+                            native(
+                                getEthBalanceCall::abi_decode(&call.callData, true)
+                                    .unwrap()
+                                    .addr,
+                            )
+                        } else {
+                            token(
+                                balanceOfCall::abi_decode(&call.callData, true)
+                                    .unwrap()
+                                    .account,
+                            )
+                        };
+                        Call3Result {
+                            success: true,
+                            returnData: balance.abi_encode().into(),
+                        }
+                    })
+                    .collect();
+                result(&Bytes::from(aggregate3Call::abi_encode_returns(
+                    &(results,),
+                )))
+            }
+            method => Err(format!("Unexpected method {method}")),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_token_balances() {
+        let usdc = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        let usdt = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+        let holder = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_call" => {
+                let balance = match params[0]["to"].as_str().unwrap().parse::<Address>() {
+                    Ok(token) if token == usdc => U256::from(1_500_000),
+                    _ => U256::ZERO,
+                };
+                result(&Bytes::from(balance.abi_encode()))
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let fields = vec![
+            AccountField::TokenBalance(usdc),
+            AccountField::TokenBalance(usdt),
+        ];
+
+        let account = read_account(&rpc, &holder, fields).await.unwrap();
+
+        assert_eq!(
+            account.token_balances,
+            Some(BTreeMap::from([
+                (usdc, U256::from(1_500_000)),
+                (usdt, U256::ZERO)
+            ]))
+        );
+        assert_eq!(rpc.calls("eth_call"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_account_at_block() {
+        let holder = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let rpc = MockRpc::start(|method, params| match (method, params[1].as_str()) {
+            ("eth_chainId", _) => result(&"0x1"),
+            // 18000000
+            ("eth_getBalance", Some("0x112a880")) => result(&U256::from(100)),
+            ("eth_getBalance", Some("latest")) => result(&U256::from(200)),
+            (method, _) => Err(format!("Unexpected request {method} {params}")),
+        })
+        .await;
+        let chains = [ChainOrRpc::Rpc(rpc.url())];
+        let account_at = |block: Option<BlockNumberOrTag>| {
+            Account::new(
+                Some(vec![NameOrAddress::Address(holder)]),
+                block.map(|block| vec![AccountFilter::Block(block)]),
+                vec![AccountField::Balance],
+            )
+        };
+
+        let historical = resolve_account_query(
+            &account_at(Some(BlockNumberOrTag::Number(18000000))),
+            &chains,
+            &EnsConfig::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        let latest =
+            resolve_account_query(&account_at(None), &chains, &EnsConfig::default(), false)
+                .await
+                .unwrap();
+
+        assert_eq!(historical[0].balance, Some(U256::from(100)));
+        assert_eq!(latest[0].balance, Some(U256::from(200)));
+    }
+
+    #[tokio::test]
+    async fn test_get_is_contract() {
+        let usdc = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        let holder = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBalance" => result(&U256::from(1_000_000)),
+            "eth_getCode" => match params[0].as_str().unwrap().parse::<Address>() {
+                Ok(address) if address == usdc => result(&Bytes::from_static(&[0x60, 0x80])),
+                _ => result(&Bytes::new()),
+            },
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let account = Account::new(
+            Some(vec![
+                NameOrAddress::Address(usdc),
+                NameOrAddress::Address(holder),
+            ]),
+            None,
+            vec![AccountField::Balance, AccountField::IsContract],
+        );
+
+        let accounts = resolve_account_query(
+            &account,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            &EnsConfig::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(accounts[0].is_contract, Some(true));
+        assert_eq!(accounts[1].is_contract, Some(false));
+        assert_eq!(accounts[1].balance, Some(U256::from(1_000_000)));
+    }
+
+    #[tokio::test]
+    async fn test_get_eip1967_implementation_slot() {
+        let proxy = ARBITRUM_INBOX;
+        let implementation = Address::repeat_byte(0x11);
+        // keccak256("eip1967.proxy.implementation") - 1
+        let slot = U256::from_str_radix(
+            "360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc",
+            16,
+        )
+        .unwrap();
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_getStorageAt" => {
+                assert_eq!(params[0].as_str().unwrap().parse::<Address>(), Ok(proxy));
+                assert_eq!(params[1].as_str().unwrap().parse::<U256>(), Ok(slot));
+                result(&implementation.into_word())
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        let account = read_account(&rpc, &proxy, vec![AccountField::Storage(slot)])
+            .await
+            .unwrap();
+        let value = account.storage.unwrap()[&slot];
+
+        assert_eq!(Address::from_word(value), implementation);
+    }
+
+    /// Delayed inbox of Arbitrum One, an OpenZeppelin `TransparentUpgradeableProxy`
+    const ARBITRUM_INBOX: Address = address!("4Dbd4fc535Ac27206064B68FfCf827b0A60BAB3f");
+    const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+    const BEACON: Address = address!("5a2a4F2F3C18f09179B6703e63D9eDD165909073");
+    const BEACON_IMPLEMENTATION: Address = address!("76bE3b62873462d2142405439777e971754E8E77");
+
+    /// Serves the storage slots of a proxy, the other slots being empty
+    async fn start_proxy_rpc(slots: Vec<(B256, Address)>) -> MockRpc {
+        MockRpc::start(move |method, params| match method {
+            "eth_getStorageAt" => {
+                let slot = params[1].as_str().unwrap().parse::<U256>().unwrap();
+                let value = slots
+                    .iter()
+                    .find(|(s, _)| U256::from_be_bytes(s.0) == slot)
+                    .map_or(B256::ZERO, |(_, address)| address.into_word());
+                result(&value)
+            }
+            "eth_call" => {
+                assert_eq!(
+                    params[0]["to"].as_str().unwrap().parse::<Address>(),
+                    Ok(BEACON)
+                );
+                result(&Bytes::from(BEACON_IMPLEMENTATION.abi_encode()))
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await
+    }
+
+    async fn implementation_of(rpc: &MockRpc, proxy: Address) -> Option<Address> {
+        read_account(&rpc, &proxy, vec![AccountField::Implementation])
+            .await
+            .unwrap()
+            .implementation
+    }
+
+    #[tokio::test]
+    async fn test_implementation_of_eip1967_proxy() {
+        let implementation = Address::repeat_byte(0x11);
+        let rpc = start_proxy_rpc(vec![(EIP1967_IMPLEMENTATION_SLOT, implementation)]).await;
+
+        assert_eq!(
+            implementation_of(&rpc, ARBITRUM_INBOX).await,
+            Some(implementation)
+        );
+        assert_eq!(rpc.calls("eth_call"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_implementation_of_zeppelinos_proxy() {
+        // USDC predates EIP-1967, its implementation is in the ZeppelinOS slot
+        let implementation = address!("43506849D7C04F9138D1A2050bbF3A0c054402dd");
+        let rpc = start_proxy_rpc(vec![(ZEPPELINOS_IMPLEMENTATION_SLOT, implementation)]).await;
+
+        assert_eq!(implementation_of(&rpc, USDC).await, Some(implementation));
+    }
+
+    #[tokio::test]
+    async fn test_implementation_of_mainnet_proxies() {
+        let block = BlockNumberOrTag::Number(18000000);
+        let proxies = Account::new(
+            Some(vec![
+                NameOrAddress::Address(ARBITRUM_INBOX),
+                NameOrAddress::Address(USDC),
+            ]),
+            Some(vec![AccountFilter::Block(block)]),
+            vec![
+                AccountField::Implementation,
+                AccountField::Storage(EIP1967_IMPLEMENTATION_SLOT.into()),
+                AccountField::Storage(ZEPPELINOS_IMPLEMENTATION_SLOT.into()),
+            ],
+        );
+
+        let accounts = resolve_account_query(
+            &proxies,
+            &[ChainOrRpc::Chain(Chain::Ethereum)],
+            &EnsConfig::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let slot = |account: &AccountQueryRes, slot: B256| {
+            Address::from_word(account.storage.as_ref().unwrap()[&slot.into()])
+        };
+        let (inbox, usdc) = (&accounts[0], &accounts[1]);
+        assert!(!slot(inbox, EIP1967_IMPLEMENTATION_SLOT).is_zero());
+        assert_eq!(
+            inbox.implementation,
+            Some(slot(inbox, EIP1967_IMPLEMENTATION_SLOT))
+        );
+        assert!(!slot(usdc, ZEPPELINOS_IMPLEMENTATION_SLOT).is_zero());
+        assert_eq!(
+            usdc.implementation,
+            Some(slot(usdc, ZEPPELINOS_IMPLEMENTATION_SLOT))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_implementation_of_beacon_and_eip1822_proxies() {
+        let proxy = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let uups_implementation = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+
+        let beacon_rpc = start_proxy_rpc(vec![(EIP1967_BEACON_SLOT, BEACON)]).await;
+        assert_eq!(
+            implementation_of(&beacon_rpc, proxy).await,
+            Some(BEACON_IMPLEMENTATION)
+        );
+
+        let uups_rpc = start_proxy_rpc(vec![(EIP1822_PROXIABLE_SLOT, uups_implementation)]).await;
+        assert_eq!(
+            implementation_of(&uups_rpc, proxy).await,
+            Some(uups_implementation)
+        );
+
+        let no_proxy_rpc = start_proxy_rpc(vec![]).await;
+        assert_eq!(implementation_of(&no_proxy_rpc, proxy).await, None);
+    }
+
+    #[test]
+    fn test_proxy_slots_follow_their_standards() {
+        let eip1967_slot =
+            |label: &str| B256::from(U256::from_be_bytes(keccak256(label).0) - U256::from(1));
+
+        assert_eq!(
+            EIP1967_IMPLEMENTATION_SLOT,
+            eip1967_slot("eip1967.proxy.implementation")
+        );
+        assert_eq!(EIP1967_BEACON_SLOT, eip1967_slot("eip1967.proxy.beacon"));
+        assert_eq!(EIP1822_PROXIABLE_SLOT, keccak256("PROXIABLE"));
+        assert_eq!(
+            ZEPPELINOS_IMPLEMENTATION_SLOT,
+            keccak256("org.zeppelinos.proxy.implementation")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_when_account_ids_are_missing() {
+        let account = Account::new(None, None, vec![AccountField::Balance]);
+
+        let error = resolve_account_query(
+            &account,
+            &[ChainOrRpc::Chain(Chain::Ethereum)],
+            &EnsConfig::default(),
+            false,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<AccountResolverErrors>(),
+            Some(AccountResolverErrors::MissingAccountIds)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rpc_errors_are_tagged_with_field_and_account() {
+        let holder = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let rpc = MockRpc::start(|_, _| Err("execution reverted".to_string())).await;
+
+        let error = read_account(&rpc, &holder, vec![AccountField::Nonce])
+            .await
+            .unwrap_err();
+
+        match error.downcast_ref::<AccountResolverErrors>() {
+            Some(AccountResolverErrors::Rpc(field, address, _)) => {
+                assert_eq!(*field, AccountField::Nonce);
+                assert_eq!(*address, holder);
+            }
+            _ => panic!("Unexpected error: {error}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_when_token_balance_cant_be_decoded() {
+        let holder = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        // Calls to addresses without code return empty data
+        let rpc = MockRpc::start(|_, _| result(&Bytes::new())).await;
+
+        let error = read_account(
+            &rpc,
+            &holder,
+            vec![AccountField::TokenBalance(Address::ZERO)],
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<AccountResolverErrors>(),
+            Some(AccountResolverErrors::TokenBalanceDecoding(
+                Address::ZERO,
+                _
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_token_balances_are_formatted_with_the_decimals_of_their_token() {
+        let usdc = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        // Bored Ape Yacht Club, an ERC-721 collection, has balances but no `decimals()`
+        let no_decimals = address!("BC4CA0EdA7647A8aB7C2061c2E118A18a936f13D");
+        let holders = [
+            address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045"),
+            address!("28C6c06298d514Db089934071355E5743bf21d60"),
+        ];
+        let decimals_calls = Arc::new(AtomicUsize::new(0));
+        let rpc_decimals_calls = decimals_calls.clone();
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            // Multicall3 isn't deployed, so the balances are read one by one
+            "eth_getCode" => result(&Bytes::new()),
+            "eth_call" => {
+                let to: Address = params[0]["to"].as_str().unwrap().parse().unwrap();
+                let input: Bytes = params[0]["input"].as_str().unwrap().parse().unwrap();
+
+                if input.starts_with(&decimalsCall::SELECTOR) {
+                    rpc_decimals_calls.fetch_add(1, Ordering::SeqCst);
+                    return match to == usdc {
+                        true => result(&Bytes::from(U256::from(6).abi_encode())),
+                        false => Err("execution reverted".to_string()),
+                    };
+                }
+                let balance = match to == usdc {
+                    true => U256::from(1_000_000),
+                    false => U256::from(2),
+                };
+                result(&Bytes::from(balance.abi_encode()))
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let account = Account::new(
+            Some(holders.map(NameOrAddress::Address).to_vec()),
+            None,
+            vec![
+                AccountField::TokenBalance(usdc),
+                AccountField::TokenBalance(no_decimals),
+            ],
+        );
+
+        let accounts = resolve_account_query(
+            &account,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            &EnsConfig::default(),
+            true,
+        )
+        .await
+        .unwrap();
+
+        for account in &accounts {
+            assert_eq!(
+                account.token_balances,
+                Some(BTreeMap::from([
+                    (usdc, U256::from(1_000_000)),
+                    (no_decimals, U256::from(2))
+                ]))
+            );
+            // The token without decimals only has its raw balance
+            assert_eq!(
+                account.token_balances_formatted,
+                Some(BTreeMap::from([(usdc, "1.0".to_string())]))
+            );
+        }
+        // Read once per token, not once per holder
+        assert_eq!(decimals_calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// Reads the fields of the account at the latest block of `rpc`, without Multicall3
+    async fn read_account(
+        rpc: &MockRpc,
+        address: &Address,
+        fields: Vec<AccountField>,
+    ) -> Result<AccountQueryRes> {
+        let provider = rpc.provider();
+        let context = AccountContext {
+            provider: &provider,
+            ens_registry: &EnsRegistry::new(ENS_ADDRESS, provider.as_ref().clone()),
+            chain: &ChainOrRpc::Chain(Chain::Ethereum),
+            block: BlockNumberOrTag::Latest,
+            balances: &Balances::new(),
+            decimals: &Decimals::new(),
+        };
+
+        get_account(address, fields, &context).await
+    }
+
+    async fn call_account(fields: Vec<AccountField>, rpc: &MockRpc) -> Result<AccountQueryRes> {
+        read_account(
+            rpc,
+            &address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc"),
+            fields,
+        )
+        .await
+    }
+
+    fn call(signature: &str, args: &[&str]) -> AccountField {
+        let args = args.iter().map(ToString::to_string).collect();
+        AccountField::Call(ContractCall::new(signature, args).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_call_returning_uint() {
+        let holder = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_call" => {
+                let input: Bytes = params[0]["input"].as_str().unwrap().parse().unwrap();
+                assert_eq!(input, balanceOfCall { account: holder }.abi_encode());
+                result(&Bytes::from(U256::from(1234).abi_encode()))
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let field = call(
+            "balanceOf(address) returns (uint256)",
+            &[&holder.to_string()],
+        );
+
+        let account = call_account(vec![field], &rpc).await.unwrap();
+
+        assert_eq!(
+            account.calls,
+            Some(BTreeMap::from([(
+                format!("balanceOf({holder})"),
+                json!("1234")
+            )]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_call_returning_tuple() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_call" => result(&Bytes::from(
+                (U256::from(1), U256::from(2), 3u32).abi_encode_params(),
+            )),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let reserves = call_account(
+            vec![call("getReserves()(uint112,uint112,uint32)", &[])],
+            &rpc,
+        )
+        .await
+        .unwrap();
+        // Without return types, the output is left raw
+        let raw = call_account(vec![call("getReserves()", &[])], &rpc)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            reserves.calls.unwrap()["getReserves()"],
+            json!(["1", "2", "3"])
+        );
+        assert_eq!(
+            raw.calls.unwrap()["getReserves()"],
+            json!(hex::encode_prefixed(
+                (U256::from(1), U256::from(2), 3u32).abi_encode_params()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_when_call_output_cant_be_decoded() {
+        // Calls to addresses without code return empty data
+        let rpc = MockRpc::start(|_, _| result(&Bytes::new())).await;
+
+        let error = call_account(vec![call("totalSupply()(uint256)", &[])], &rpc)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<AccountResolverErrors>(),
+            Some(AccountResolverErrors::CallDecoding(..))
+        ));
+    }
+
+    /// Start of the code of WETH9 as laid out by solc 0.4: the selector is read from the calldata,
+    /// masked with `PUSH4 0xffffffff`, then compared with the selector of every function
+    const WETH9_DISPATCHER: Bytes = bytes!("6060604052600436106100af576000357c0100000000000000000000000000000000000000000000000000000000900463ffffffff16806306fdde03146100b9578063095ea7b3146100f957806318160ddd1461013957806323b872dd146101795780632e1a7d4d146101b9578063313ce567146101f957806370a082311461023957806395d89b4114610279578063a9059cbb146102b9578063d0e30db0146102f9578063dd62ed3e1461033957");
+
+    #[test]
+    fn test_function_selectors_of_dispatcher() {
+        let selectors = function_selectors(&WETH9_DISPATCHER);
+
+        assert_eq!(selectors.len(), 11);
+        // name(), balanceOf(address), transfer(address,uint256) and deposit()
+        for selector in [
+            fixed_bytes!("06fdde03"),
+            fixed_bytes!("70a08231"),
+            fixed_bytes!("a9059cbb"),
+            fixed_bytes!("d0e30db0"),
+        ] {
+            assert!(selectors.contains(&selector), "{selector}");
+        }
+        // The mask isn't compared with the calldata
+        assert!(!selectors.contains(&fixed_bytes!("ffffffff")));
+    }
+
+    #[test]
+    fn test_function_selectors_skip_push_data() {
+        // PUSH32 whose data looks like `PUSH4 0x12345678 EQ`, then the same selector compared
+        let code = bytes!(
+            "7f631234567814000000000000000000000000000000000000000000000000000000638765432114"
+        );
+
+        assert_eq!(function_selectors(&code), vec![fixed_bytes!("87654321")]);
+        assert!(function_selectors(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_selectors_of_contract() {
+        let weth = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_getCode" => result(&WETH9_DISPATCHER),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        let account = read_account(
+            &rpc,
+            &weth,
+            vec![AccountField::Code, AccountField::Selectors],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            account.selectors.unwrap()[..2],
+            [fixed_bytes!("06fdde03"), fixed_bytes!("095ea7b3")]
+        );
+        // The code is only fetched once for both fields, whatever their order
+        assert_eq!(rpc.calls("eth_getCode"), 1);
+        let account = read_account(
+            &rpc,
+            &weth,
+            vec![
+                AccountField::Selectors,
+                AccountField::IsContract,
+                AccountField::Code,
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(account.is_contract, Some(true));
+        assert_eq!(account.code, Some(WETH9_DISPATCHER));
+        assert_eq!(rpc.calls("eth_getCode"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_account_on_multiple_chains() {
+        let holder = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let chain_rpc = |chain_id: &'static str, balance: u64| {
+            MockRpc::start(move |method, _| match method {
+                "eth_chainId" => result(&chain_id),
+                "eth_getBalance" => result(&U256::from(balance)),
+                method => Err(format!("Unexpected method {method}")),
+            })
+        };
+        let ethereum = chain_rpc("0x1", 100).await;
+        let polygon = chain_rpc("0x89", 200).await;
+        let account = Account::new(
+            Some(vec![NameOrAddress::Address(holder)]),
+            None,
+            vec![AccountField::Balance, AccountField::Chain],
+        );
+
+        let accounts = resolve_account_query(
+            &account,
+            &[
+                ChainOrRpc::Rpc(ethereum.url()),
+                ChainOrRpc::Rpc(polygon.url()),
+            ],
+            &EnsConfig::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            accounts,
+            vec![
+                AccountQueryRes {
+                    chain: Some(Chain::Ethereum),
+                    balance: Some(U256::from(100)),
+                    ..Default::default()
+                },
+                AccountQueryRes {
+                    chain: Some(Chain::Polygon),
+                    balance: Some(U256::from(200)),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_selects_every_account_field() {
+        let vitalik = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let resolver = address!("231b0Ee14048e9dCcD1d247744d114a4EB5E8E63");
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBalance" => result(&U256::from(1_000_000)),
+            "eth_getTransactionCount" => result(&"0x7"),
+            "eth_getCode" => result(&Bytes::from_static(&[0x60, 0x80])),
+            // The reverse record of the address, and the forward record of its name
+            "eth_call" => {
+                let to: Address = params[0]["to"].as_str().unwrap().parse().unwrap();
+                let input: Bytes = params[0]["input"].as_str().unwrap().parse().unwrap();
+                let output = if to == ENS_ADDRESS {
+                    EnsRegistry::resolverCall::abi_encode_returns(&(resolver,))
+                } else if input.starts_with(&EnsResolver::nameCall::SELECTOR) {
+                    EnsResolver::nameCall::abi_encode_returns(&(String::from("vitalik.eth"),))
+                } else {
+                    EnsResolver::addrCall::abi_encode_returns(&(vitalik,))
+                };
+                result(&Bytes::from(output))
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let source = format!("GET * FROM account {vitalik} ON {}", rpc.url());
+        let expressions = Parser::new(&source).parse_expressions().unwrap();
+        let Expression::Get(GetExpression {
+            entity: Entity::Account(account),
+            chains,
+            ..
+        }) = &expressions[0]
+        else {
+            panic!("Expected an account expression");
+        };
+        let ens = EnsConfig::new(ChainOrRpc::Rpc(rpc.url()), ENS_ADDRESS);
+
+        let accounts = resolve_account_query(account, chains, &ens, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            accounts,
+            vec![AccountQueryRes {
+                chain: Some(Chain::Ethereum),
+                nonce: Some(7),
+                balance: Some(U256::from(1_000_000)),
+                address: Some(vitalik),
+                code: Some(Bytes::from_static(&[0x60, 0x80])),
+                is_contract: Some(true),
+                ens_name: Some(String::from("vitalik.eth")),
+                token_balances: None,
+                token_balances_formatted: None,
+                storage: None,
+                first_active_block: None,
+                selectors: None,
+                implementation: None,
+                calls: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ens_names_are_resolved_once() {
+        let vitalik = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        // Both the registry and the resolver answer with the same address
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_call" => result(&Bytes::from(vitalik.abi_encode())),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let ids = vec![
+            NameOrAddress::Name(String::from("vitalik.eth")),
+            NameOrAddress::Address(Address::ZERO),
+            NameOrAddress::Name(String::from("vitalik.eth")),
+        ];
+
+        let addresses = to_addresses(
+            &ids,
+            &EnsRegistry::new(ENS_ADDRESS, rpc.provider().as_ref().clone()),
+            DEFAULT_MAX_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(addresses, vec![vitalik, Address::ZERO, vitalik]);
+        // One call to the registry and one to the resolver
+        assert_eq!(rpc.calls("eth_call"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_batched_balances_match_individual_reads() {
+        let usdc = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        let holders = [
+            address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045"),
+            address!("dAC17F958D2ee523a2206206994597C13D831ec7"),
+            address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"),
+        ];
+        let batched = MockRpc::start(balances_rpc(true)).await;
+        let individual = MockRpc::start(balances_rpc(false)).await;
+        let account = Account::new(
+            Some(holders.map(NameOrAddress::Address).to_vec()),
+            None,
+            vec![
+                AccountField::Address,
+                AccountField::Balance,
+                AccountField::TokenBalance(usdc),
+            ],
+        );
+
+        let batched_accounts = resolve_account_query(
+            &account,
+            &[ChainOrRpc::Rpc(batched.url())],
+            &EnsConfig::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        let individual_accounts = resolve_account_query(
+            &account,
+            &[ChainOrRpc::Rpc(individual.url())],
+            &EnsConfig::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(batched_accounts, individual_accounts);
+        assert_eq!(batched_accounts[0].balance, Some(U256::from(69_000)));
+        assert_eq!(
+            batched_accounts[0].token_balances,
+            Some(BTreeMap::from([(usdc, U256::from(69))]))
+        );
+        assert_eq!(batched.calls("eth_call"), 1);
+        assert_eq!(batched.calls("eth_getBalance"), 0);
+        assert_eq!(individual.calls("eth_call"), 3);
+        assert_eq!(individual.calls("eth_getBalance"), 3);
+    }
+
+    /// Node serving the state of an account first funded at block 1,000,000, with its head at
+    /// block 20,000,000. Archive nodes serve the state of every block, others only of the last
+    /// 128 blocks.
+    fn activation_rpc(
+        archive: bool,
+    ) -> impl Fn(&str, &Value) -> std::result::Result<Value, String> {
+        const HEAD: u64 = 20_000_000;
+        const ACTIVATION: u64 = 1_000_000;
+
+        move |method, params| {
+            let block = params[1]
+                .as_str()
+                .and_then(|block| u64::from_str_radix(block.trim_start_matches("0x"), 16).ok());
+            let funded = block.is_some_and(|block| block >= ACTIVATION);
+
+            match method {
+                "eth_chainId" => result(&"0x1"),
+                "eth_getBlockByNumber" => result::<RpcBlock>(&RpcBlock {
+                    header: Header {
+                        number: Some(HEAD),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }),
+                "eth_getBalance" | "eth_getTransactionCount"
+                    if !archive && block.is_some_and(|block| block < HEAD - 128) =>
+                {
+                    Err("missing trie node".to_string())
+                }
+                "eth_getBalance" if funded => result(&U256::from(10).pow(U256::from(18))),
+                "eth_getBalance" => result(&U256::ZERO),
+                // The account only sends its first transaction later on
+                "eth_getTransactionCount" => result(&"0x0"),
+                method => Err(format!("Unexpected method {method}")),
+            }
+        }
+    }
+
+    fn first_active_block_query() -> Account {
+        Account::new(
+            Some(vec![NameOrAddress::Address(address!(
+                "d8dA6BF26964aF9D7eEd9e03E53415D37aA96045"
+            ))]),
+            None,
+            vec![AccountField::FirstActiveBlock],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_first_active_block_of_account() {
+        let rpc = MockRpc::start(activation_rpc(true)).await;
+
+        let accounts = resolve_account_query(
+            &first_active_block_query(),
+            &[ChainOrRpc::Rpc(rpc.url())],
+            &EnsConfig::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(accounts[0].first_active_block, Some(1_000_000));
+        // The head, then log2(20,000,000) blocks
+        assert!(rpc.calls("eth_getBalance") <= 26);
+        assert_eq!(
+            rpc.calls("eth_getBalance"),
+            rpc.calls("eth_getTransactionCount")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_active_block_needs_an_archive_node() {
+        let rpc = MockRpc::start(activation_rpc(false)).await;
+
+        let error = resolve_account_query(
+            &first_active_block_query(),
+            &[ChainOrRpc::Rpc(rpc.url())],
+            &EnsConfig::default(),
+            false,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<AccountResolverErrors>(),
+            Some(AccountResolverErrors::PastStateUnavailable(
+                _,
+                10_000_000,
+                _
+            ))
+        ));
+    }
 }
@@ -1,16 +1,21 @@
+use super::{
+    raw_items,
+    retry::{self, with_retry},
+};
 use crate::common::{
-    block::{Block, BlockField, BlockId},
+    block::{missing_block_error, Block, BlockBatchConfig, BlockField, BlockId},
     chain::{Chain, ChainOrRpc},
     query_result::BlockQueryRes,
 };
 use alloy::{
-    eips::BlockNumberOrTag,
-    providers::{Provider, ProviderBuilder, RootProvider},
-    rpc::types::Block as RpcBlock,
-    transports::http::{Client, Http},
+    eips::{calc_next_block_base_fee, eip1559::BaseFeeParams, BlockNumberOrTag},
+    primitives::{B256, U64},
+    providers::{Provider, RootProvider},
+    rpc::{client::BatchRequest, types::Block as RpcBlock},
+    transports::BoxTransport,
 };
 use anyhow::Result;
-use futures::future::try_join_all;
+use futures::{future::try_join_all, stream, Stream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -22,49 +27,70 @@ pub enum BlockResolverErrors {
     MismatchEntityAndEntityId(String),
     #[error("Missing block ids")]
     IdsNotSet,
+    #[error("Missed blocks {0} to {1}, the subscription may have been reconnected")]
+    MissedBlocks(u64, u64),
+    #[error("Unable to find block {0}")]
+    UnknownBlockHash(B256),
 }
 
 async fn resolve_block_id(
     id: &BlockId,
-    provider: Arc<RootProvider<Http<Client>>>,
+    provider: Arc<RootProvider<BoxTransport>>,
+    max_block_range: u64,
 ) -> Result<Vec<u64>> {
     let block_numbers = match id {
-        BlockId::Range(block_range) => block_range.resolve_block_numbers(&provider).await?,
+        BlockId::Range(block_range) => {
+            block_range
+                .resolve_block_numbers(&provider, max_block_range)
+                .await?
+        }
         BlockId::Number(block_number) => {
             resolve_block_numbers(&[block_number.clone()], provider.clone()).await?
         }
+        BlockId::Hash(hash) => {
+            let block = get_block_by_hash(*hash, provider, false).await?;
+            block.header.number.into_iter().collect()
+        }
     };
 
     Ok(block_numbers)
 }
 
+/// Ranges spanning more than `max_block_range` blocks are rejected, see `DEFAULT_MAX_BLOCK_RANGE`.
+/// The blocks of a range are fetched in groups, see `BlockBatchConfig`.
 pub async fn resolve_block_query(
     block: &Block,
     chains: &[ChainOrRpc],
+    max_block_range: u64,
+    batch: BlockBatchConfig,
 ) -> Result<Vec<BlockQueryRes>> {
+    let ids = block.block_ids();
+    if ids.is_empty() {
+        return Err(BlockResolverErrors::IdsNotSet.into());
+    }
+    let ids = &ids;
     let mut all_chain_futures = Vec::new();
 
-    let ids = match block.ids() {
-        Some(ids) => ids,
-        None => return Err(BlockResolverErrors::IdsNotSet.into()),
-    };
-
     for chain in chains {
-        let fields = block.fields().clone();
-
         let chain_future = async move {
-            let provider = Arc::new(ProviderBuilder::new().on_http(chain.rpc_url()?));
+            let provider = Arc::new(chain.provider().await?);
             let chain = chain.to_chain().await?;
             let mut all_block_futures = Vec::new();
 
             for id in ids {
                 let provider_clone = provider.clone();
                 let chain_clone = chain.clone();
-                let fields = fields.clone();
 
-                let block_id = resolve_block_id(&id, provider_clone.clone()).await?;
                 let block_future = async move {
-                    get_filtered_blocks(block_id, fields, &provider_clone, &chain_clone).await
+                    get_filtered_blocks(
+                        id,
+                        block,
+                        &provider_clone,
+                        &chain_clone,
+                        max_block_range,
+                        batch,
+                    )
+                    .await
                 };
                 all_block_futures.push(block_future);
             }
@@ -80,24 +106,127 @@ pub async fn resolve_block_query(
     Ok(all_chain_blocks.concat())
 }
 
+/// Follows the chain head, yielding every new block as it's produced. The subscription needs a
+/// pubsub transport, so the RPC must be a WebSocket or IPC URL.
+///
+/// The stream ends when the connection is lost and can't be re-established. When the connection
+/// is re-established, the blocks produced in between are not replayed, so a `MissedBlocks` error
+/// is yielded before the next block. The stream keeps going after it.
+pub async fn stream_block_query(
+    block: &Block,
+    chain: &ChainOrRpc,
+) -> Result<impl Stream<Item = Result<BlockQueryRes>>> {
+    let provider = chain.provider().await?;
+    let chain = chain.to_chain().await?;
+    let fields = block.fields().clone();
+    let subscription = provider.subscribe_blocks().await?;
+    let mut last_block_number = None;
+
+    let blocks = subscription.into_result_stream().flat_map(move |block| {
+        // The subscription is closed once the provider is dropped, so the stream owns it
+        let _ = &provider;
+        let mut results = vec![];
+
+        match block {
+            Ok(block) => {
+                if let (Some(last), Some(number)) = (last_block_number, block.header.number) {
+                    if number > last + 1 {
+                        results.push(Err(
+                            BlockResolverErrors::MissedBlocks(last + 1, number - 1).into()
+                        ));
+                    }
+                }
+                last_block_number = block.header.number.or(last_block_number);
+                results.push(Ok(filter_fields(block, &fields, &chain)));
+            }
+            Err(e) => results.push(Err(e.into())),
+        }
+
+        stream::iter(results)
+    });
+
+    Ok(blocks)
+}
+
 async fn get_filtered_blocks(
-    block_numbers: Vec<u64>,
-    fields: Vec<BlockField>,
-    provider: &Arc<RootProvider<Http<Client>>>,
+    id: &BlockId,
+    block: &Block,
+    provider: &Arc<RootProvider<BoxTransport>>,
     chain: &Chain,
+    max_block_range: u64,
+    batch: BlockBatchConfig,
 ) -> Result<Vec<BlockQueryRes>> {
-    let blocks = batch_get_blocks(block_numbers, &provider, false).await?;
+    // The blocks are fetched anyway when their alloy types are returned
+    if block.counts_transactions_only() && !raw_items::is_recording() {
+        let block_numbers = resolve_block_id(id, provider.clone(), max_block_range).await?;
+        return count_transactions(block_numbers, block.fields(), provider, chain, batch).await;
+    }
+
+    let blocks = match id {
+        // Fetching the block by number could return another block if the chain reorganized
+        BlockId::Hash(hash) => vec![get_block_by_hash(*hash, provider.clone(), false).await?],
+        id => {
+            let block_numbers = resolve_block_id(id, provider.clone(), max_block_range).await?;
+            batch_get_blocks(block_numbers, provider, false, batch).await?
+        }
+    };
     Ok(blocks
         .into_iter()
-        .map(|block| filter_fields(block, &fields, &chain))
+        .filter(|rpc_block| block.filter(&rpc_block.header))
+        .map(|rpc_block| {
+            raw_items::record_block(&rpc_block);
+            filter_fields(rpc_block, block.fields(), chain)
+        })
         .collect())
 }
 
+/// Reads the number of transactions of the blocks with `eth_getBlockTransactionCountByNumber`, so
+/// neither the blocks nor their transaction hashes are downloaded. The counts are requested as
+/// many at a time as the blocks of a batch would be, and stop at the first one that fails.
+async fn count_transactions(
+    block_numbers: Vec<u64>,
+    fields: &[BlockField],
+    provider: &Arc<RootProvider<BoxTransport>>,
+    chain: &Chain,
+    batch: BlockBatchConfig,
+) -> Result<Vec<BlockQueryRes>> {
+    stream::iter(block_numbers)
+        .map(|number| {
+            Ok(async move {
+                let count: Option<U64> = with_retry(&retry::current(), || async {
+                    provider
+                        .raw_request(
+                            "eth_getBlockTransactionCountByNumber".into(),
+                            (BlockNumberOrTag::Number(number),),
+                        )
+                        .await
+                })
+                .await?;
+                let count =
+                    count.ok_or_else(|| missing_block_error(BlockNumberOrTag::Number(number)))?;
+
+                let mut result = BlockQueryRes::default();
+                for field in fields {
+                    match field {
+                        BlockField::Number => result.number = Some(number),
+                        BlockField::TransactionCount => result.transaction_count = Some(count.to()),
+                        BlockField::Chain => result.chain = Some(chain.clone()),
+                        _ => {}
+                    }
+                }
+                Ok(result)
+            })
+        })
+        .try_buffered(batch.size.max(1))
+        .try_collect()
+        .await
+}
+
 // TODO: this method only exists here because it wasn't implemented on the BlockId struct yet.
 // BlockRange has a similar implementation and should be unified.
 async fn resolve_block_numbers(
     block_numbers: &[BlockNumberOrTag],
-    provider: Arc<RootProvider<Http<Client>>>,
+    provider: Arc<RootProvider<BoxTransport>>,
 ) -> Result<Vec<u64>> {
     let mut block_number_futures = Vec::new();
 
@@ -112,36 +241,128 @@ async fn resolve_block_numbers(
     Ok(block_numbers)
 }
 
+/// Fetches the blocks in the order of `block_numbers`, with at most `batch.size` of them requested
+/// at the same time.
 pub async fn batch_get_blocks(
     block_numbers: Vec<u64>,
-    provider: &Arc<RootProvider<Http<Client>>>,
+    provider: &Arc<RootProvider<BoxTransport>>,
     hydrate: bool,
+    batch: BlockBatchConfig,
 ) -> Result<Vec<RpcBlock>> {
-    let mut block_futures = Vec::new();
+    let size = batch.size.max(1);
 
-    for block_number in block_numbers {
-        let provider = Arc::clone(&provider);
-        let block_future = async move {
-            get_block(BlockNumberOrTag::Number(block_number), provider, hydrate).await
-        };
-        block_futures.push(block_future);
+    if batch.json_rpc {
+        let mut blocks = Vec::with_capacity(block_numbers.len());
+        for group in block_numbers.chunks(size) {
+            blocks.extend(get_blocks_in_batch(group, provider, hydrate).await?);
+        }
+        return Ok(blocks);
     }
 
-    let block_results = try_join_all(block_futures).await?;
-    Ok(block_results)
+    // Stops requesting blocks at the first one that fails
+    stream::iter(block_numbers)
+        .map(|block_number| {
+            Ok(get_block(
+                BlockNumberOrTag::Number(block_number),
+                provider.clone(),
+                hydrate,
+            ))
+        })
+        .try_buffered(size)
+        .try_collect()
+        .await
+}
+
+/// Fetches the blocks with a single JSON-RPC batch request, retried as a whole.
+async fn get_blocks_in_batch(
+    block_numbers: &[u64],
+    provider: &RootProvider<BoxTransport>,
+    hydrate: bool,
+) -> Result<Vec<RpcBlock>> {
+    let blocks = with_retry(&retry::current(), || async {
+        let mut batch = BatchRequest::new(provider.client());
+        let blocks = block_numbers
+            .iter()
+            .map(|number| {
+                batch.add_call::<_, Option<RpcBlock>>(
+                    "eth_getBlockByNumber",
+                    &(BlockNumberOrTag::Number(*number), hydrate),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        batch.send().await?;
+        try_join_all(blocks).await
+    })
+    .await?;
+
+    blocks
+        .into_iter()
+        .zip(block_numbers)
+        .map(|(block, number)| {
+            block.ok_or_else(|| missing_block_error(BlockNumberOrTag::Number(*number)).into())
+        })
+        .collect()
 }
 
 pub async fn get_block(
     block_id: BlockNumberOrTag,
-    provider: Arc<RootProvider<Http<Client>>>,
+    provider: Arc<RootProvider<BoxTransport>>,
     hydrate: bool,
 ) -> Result<RpcBlock> {
-    match provider.get_block_by_number(block_id, hydrate).await? {
+    let block = with_retry(&retry::current(), || async {
+        provider.get_block_by_number(block_id, hydrate).await
+    })
+    .await?;
+
+    match block {
         Some(block) => Ok(block),
-        None => return Err(BlockResolverErrors::UnableToFetchBlockNumber(block_id.clone()).into()),
+        None => Err(missing_block_error(block_id).into()),
     }
 }
 
+pub async fn get_block_by_hash(
+    hash: B256,
+    provider: Arc<RootProvider<BoxTransport>>,
+    hydrate: bool,
+) -> Result<RpcBlock> {
+    let block = with_retry(&retry::current(), || async {
+        provider.get_block_by_hash(hash, hydrate.into()).await
+    })
+    .await?;
+
+    match block {
+        Some(block) => Ok(block),
+        None => Err(BlockResolverErrors::UnknownBlockHash(hash).into()),
+    }
+}
+
+/// EIP-1559 parameters the base fee of `chain` is adjusted with. Other chains tune them, and some
+/// change them over time, like the OP stack chains in their block header since Holocene, so only
+/// the chains whose parameters are known and fixed have them.
+fn base_fee_params(chain: &Chain) -> Option<BaseFeeParams> {
+    match chain {
+        Chain::Ethereum | Chain::Sepolia | Chain::Holesky | Chain::Hoodi => {
+            Some(BaseFeeParams::ethereum())
+        }
+        _ => None,
+    }
+}
+
+/// Projects the base fee of the block following `block` from its gas usage, using the EIP-1559
+/// parameters of its chain. Blocks produced before London, and the blocks of chains whose
+/// parameters aren't known, have no base fee to project.
+fn next_base_fee(block: &RpcBlock, chain: &Chain) -> Option<u128> {
+    let base_fee = block.header.base_fee_per_gas?;
+
+    Some(calc_next_block_base_fee(
+        block.header.gas_used,
+        block.header.gas_limit,
+        base_fee,
+        base_fee_params(chain)?,
+    ))
+}
+
 fn filter_fields(block: RpcBlock, fields: &[BlockField], chain: &Chain) -> BlockQueryRes {
     let mut result = BlockQueryRes::default();
 
@@ -149,6 +370,7 @@ fn filter_fields(block: RpcBlock, fields: &[BlockField], chain: &Chain) -> Block
         match field {
             BlockField::Timestamp => {
                 result.timestamp = Some(block.header.timestamp);
+                result.timestamp_utc = result.timestamp;
             }
             BlockField::Number => {
                 result.number = block.header.number;
@@ -186,9 +408,15 @@ fn filter_fields(block: RpcBlock, fields: &[BlockField], chain: &Chain) -> Block
             BlockField::BaseFeePerGas => {
                 result.base_fee_per_gas = block.header.base_fee_per_gas;
             }
+            BlockField::NextBaseFee => {
+                result.next_base_fee = next_base_fee(&block, chain);
+            }
             BlockField::WithdrawalsRoot => {
                 result.withdrawals_root = block.header.withdrawals_root;
             }
+            BlockField::Withdrawals => {
+                result.withdrawals = block.withdrawals.clone();
+            }
             BlockField::BlobGasUsed => {
                 result.blob_gas_used = block.header.blob_gas_used;
             }
@@ -198,6 +426,12 @@ fn filter_fields(block: RpcBlock, fields: &[BlockField], chain: &Chain) -> Block
             BlockField::ParentBeaconBlockRoot => {
                 result.parent_beacon_block_root = block.header.parent_beacon_block_root;
             }
+            BlockField::Uncles => {
+                result.uncles = Some(block.uncles.clone());
+            }
+            BlockField::TransactionCount => {
+                result.transaction_count = Some(block.transactions.len() as u64);
+            }
             BlockField::Chain => {
                 result.chain = Some(chain.clone());
             }
@@ -208,21 +442,23 @@ fn filter_fields(block: RpcBlock, fields: &[BlockField], chain: &Chain) -> Block
 }
 
 async fn get_block_number_from_tag(
-    provider: Arc<RootProvider<Http<Client>>>,
+    provider: Arc<RootProvider<BoxTransport>>,
     number_or_tag: &BlockNumberOrTag,
 ) -> Result<u64> {
     match number_or_tag {
         BlockNumberOrTag::Number(number) => Ok(*number),
-        block_tag => match provider.get_block_by_number(*block_tag, false).await? {
+        block_tag => match with_retry(&retry::current(), || async {
+            provider.get_block_by_number(*block_tag, false).await
+        })
+        .await?
+        {
             Some(block) => match block.header.number {
                 Some(number) => Ok(number),
                 None => {
                     Err(BlockResolverErrors::UnableToFetchBlockNumber(number_or_tag.clone()).into())
                 }
             },
-            None => {
-                Err(BlockResolverErrors::UnableToFetchBlockNumber(number_or_tag.clone()).into())
-            }
+            None => Err(missing_block_error(*number_or_tag).into()),
         },
     }
 }
@@ -230,7 +466,21 @@ async fn get_block_number_from_tag(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::common::{block::BlockRange, chain::Chain};
+    use crate::{
+        common::{
+            block::{BlockFilter, BlockRange, BlockRangeError, DEFAULT_MAX_BLOCK_RANGE},
+            chain::Chain,
+            entity_id::parse_block_range,
+            filters::{ComparisonFilter, FilterType},
+        },
+        interpreter::backend::mock_rpc::{result, MockRpc},
+    };
+    use alloy::{
+        primitives::{address, b256, Address, Bloom},
+        rpc::types::{BlockTransactions, Header, Transaction as RpcTransaction, Withdrawal},
+    };
+    use serde_json::{json, Value};
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_error_when_start_block_is_greater_than_end_block() {
@@ -248,11 +498,871 @@ mod tests {
             fields,
         );
 
-        let result = resolve_block_query(&block, &[chain]).await;
+        let result = resolve_block_query(
+            &block,
+            &[chain],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await;
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().to_string(),
             "Start block must be less than end block"
         );
     }
+
+    #[tokio::test]
+    async fn test_resolve_query_over_websocket() {
+        let hash = b256!("88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6");
+        let rpc_block: RpcBlock = RpcBlock {
+            header: Header {
+                number: Some(1),
+                hash: Some(hash),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rpc = MockRpc::start_ws(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" if params[0] == "0x1" => result(&rpc_block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let block = Block::new(
+            Some(vec![BlockId::Number(BlockNumberOrTag::Number(1))]),
+            None,
+            vec![BlockField::Number, BlockField::Hash],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            blocks,
+            vec![BlockQueryRes {
+                number: Some(1),
+                hash: Some(hash),
+                ..Default::default()
+            }]
+        );
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+    }
+
+    #[cfg(all(unix, feature = "ipc"))]
+    #[tokio::test]
+    async fn test_resolve_query_over_ipc() {
+        let hash = b256!("88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6");
+        let rpc_block: RpcBlock = RpcBlock {
+            header: Header {
+                number: Some(1),
+                hash: Some(hash),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rpc = MockRpc::start_ipc(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" if params[0] == "0x1" => result(&rpc_block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let block = Block::new(
+            Some(vec![BlockId::Number(BlockNumberOrTag::Number(1))]),
+            None,
+            vec![BlockField::Number, BlockField::Hash],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            blocks,
+            vec![BlockQueryRes {
+                number: Some(1),
+                hash: Some(hash),
+                ..Default::default()
+            }]
+        );
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+    }
+
+    const GENESIS_HASH: B256 =
+        b256!("d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3");
+
+    #[tokio::test]
+    async fn test_resolve_genesis_block_through_open_start_range() {
+        // Mainnet's genesis block, which has no transactions, uncles nor base fee
+        let rpc = MockRpc::start(|method, params| match (method, params[0].as_str()) {
+            ("eth_chainId", _) => result(&"0x1"),
+            ("eth_getBlockByNumber", Some("0x0")) => Ok(json!({
+                "number": "0x0",
+                "hash": GENESIS_HASH,
+                "parentHash": B256::ZERO,
+                "sha3Uncles": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+                "miner": Address::ZERO,
+                "stateRoot": "0xd7f8974fb5ac78d9ac099b9ad5018bedc2ce0a72dad1827a1709da30580f0544",
+                "transactionsRoot":
+                    "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+                "receiptsRoot":
+                    "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+                "logsBloom": Bloom::ZERO,
+                "difficulty": "0x400000000",
+                "totalDifficulty": "0x400000000",
+                "gasLimit": "0x1388",
+                "gasUsed": "0x0",
+                "timestamp": "0x0",
+                "extraData": "0x11bbe8db4e347b4e8c937c1c8370e4b5ed33adb3db69cbdb7a38e1e50b1b82fa",
+                "mixHash": B256::ZERO,
+                "nonce": "0x0000000000000042",
+                "size": "0x21c",
+                "transactions": [],
+                "uncles": [],
+            })),
+            (method, _) => Err(format!("Unexpected request {method} {params}")),
+        })
+        .await;
+        let block = Block::new(
+            Some(vec![BlockId::Range(parse_block_range(":0").unwrap())]),
+            None,
+            BlockField::all_variants().to_vec(),
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].number, Some(0));
+        assert_eq!(blocks[0].hash, Some(GENESIS_HASH));
+        assert_eq!(blocks[0].parent_hash, Some(B256::ZERO));
+        assert_eq!(blocks[0].timestamp, Some(0));
+        assert_eq!(blocks[0].base_fee_per_gas, None);
+        assert_eq!(blocks[0].next_base_fee, None);
+        assert_eq!(blocks[0].uncles, Some(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_get_genesis_block_on_mainnet() {
+        let provider = Arc::new(ChainOrRpc::Chain(Chain::Ethereum).provider().await.unwrap());
+
+        let genesis = get_block(BlockNumberOrTag::Number(0), provider, true)
+            .await
+            .unwrap();
+        let result = filter_fields(
+            genesis.clone(),
+            &[BlockField::Hash, BlockField::Timestamp],
+            &Chain::Ethereum,
+        );
+
+        assert!(genesis.transactions.is_empty());
+        assert_eq!(result.hash, Some(GENESIS_HASH));
+        // Mainnet's genesis block has no timestamp set
+        assert_eq!(result.timestamp, Some(0));
+    }
+
+    /// RPC whose safe and finalized blocks trail the latest one, or that has none of them when
+    /// `finality` isn't set.
+    async fn start_finality_rpc(finality: bool) -> MockRpc {
+        MockRpc::start(move |method, params| match (method, params[0].as_str()) {
+            ("eth_getBlockByNumber", Some("latest")) => result(&block_with_number(164)),
+            ("eth_getBlockByNumber", Some("safe")) if finality => result(&block_with_number(132)),
+            ("eth_getBlockByNumber", Some("finalized")) if finality => {
+                result(&block_with_number(100))
+            }
+            ("eth_getBlockByNumber", Some("safe" | "finalized")) => Ok(Value::Null),
+            (method, _) => Err(format!("Unexpected request {method} {params}")),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_finality_tags_resolve_to_block_numbers() {
+        let rpc = start_finality_rpc(true).await;
+        let resolve = |id: BlockId| {
+            let provider = rpc.provider();
+            async move { resolve_block_id(&id, provider, DEFAULT_MAX_BLOCK_RANGE).await }
+        };
+
+        let latest = resolve(BlockId::Number(BlockNumberOrTag::Latest))
+            .await
+            .unwrap();
+        let safe = resolve(BlockId::Number(BlockNumberOrTag::Safe))
+            .await
+            .unwrap();
+        let finalized = resolve(BlockId::Number(BlockNumberOrTag::Finalized))
+            .await
+            .unwrap();
+        let range = resolve(BlockId::Range(BlockRange::new(
+            BlockNumberOrTag::Finalized,
+            Some(BlockNumberOrTag::Safe),
+        )))
+        .await
+        .unwrap();
+
+        assert_eq!(finalized, vec![100]);
+        assert!(finalized[0] <= safe[0] && safe[0] <= latest[0]);
+        assert_eq!(range, (100..=132).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_error_when_chain_has_no_finalized_block() {
+        let rpc = start_finality_rpc(false).await;
+
+        for id in [
+            BlockId::Number(BlockNumberOrTag::Finalized),
+            BlockId::Range(BlockRange::new(
+                BlockNumberOrTag::Safe,
+                Some(BlockNumberOrTag::Latest),
+            )),
+        ] {
+            let error = resolve_block_id(&id, rpc.provider(), DEFAULT_MAX_BLOCK_RANGE)
+                .await
+                .unwrap_err();
+
+            assert!(matches!(
+                error.downcast_ref::<BlockRangeError>(),
+                Some(BlockRangeError::FinalityNotSupported(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_block_ranges() {
+        // The latest block is 164
+        let rpc = start_finality_rpc(true).await;
+        let parse = |range: &str| BlockId::Range(parse_block_range(range).unwrap());
+
+        let open_end = resolve_block_id(&parse("160:"), rpc.provider(), DEFAULT_MAX_BLOCK_RANGE)
+            .await
+            .unwrap();
+        let open_start = resolve_block_id(&parse(":3"), rpc.provider(), DEFAULT_MAX_BLOCK_RANGE)
+            .await
+            .unwrap();
+
+        assert_eq!(open_end, vec![160, 161, 162, 163, 164]);
+        assert_eq!(open_start, vec![0, 1, 2, 3]);
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_relative_blocks() {
+        let rpc = MockRpc::start(|method, params| match (method, params[0].as_str()) {
+            ("eth_getBlockByNumber", Some("latest")) => result(&block_with_number(100)),
+            (method, _) => Err(format!("Unexpected request {method} {params}")),
+        })
+        .await;
+        let resolve = |range: &str| {
+            let id = BlockId::Range(parse_block_range(range).unwrap());
+            let provider = rpc.provider();
+            async move { resolve_block_id(&id, provider, DEFAULT_MAX_BLOCK_RANGE).await }
+        };
+
+        let block = resolve("latest-10").await.unwrap();
+        let range = resolve("latest-3:latest-1").await.unwrap();
+        let error = resolve("latest-200").await.unwrap_err();
+
+        assert_eq!(block, vec![90]);
+        assert_eq!(range, vec![97, 98, 99]);
+        assert_eq!(
+            error.to_string(),
+            "latest-200 is before the genesis block, latest is block 100"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_range_cap() {
+        // Numeric ranges are resolved without sending any request
+        let rpc = MockRpc::start(|method, _| Err(format!("Unexpected method {method}"))).await;
+        let range = |end: u64| {
+            BlockId::Range(BlockRange::new(
+                BlockNumberOrTag::Number(1),
+                Some(BlockNumberOrTag::Number(end)),
+            ))
+        };
+
+        let under_cap = resolve_block_id(&range(10_000), rpc.provider(), DEFAULT_MAX_BLOCK_RANGE)
+            .await
+            .unwrap();
+        let above_cap = resolve_block_id(&range(10_001), rpc.provider(), DEFAULT_MAX_BLOCK_RANGE)
+            .await
+            .unwrap_err();
+
+        assert_eq!(under_cap.len(), 10_000);
+        assert_eq!(
+            above_cap.to_string(),
+            "Block range 1:10001 spans more than 10000 blocks, narrow it down or raise the limit"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_blocks_passing_the_filters_are_returned() {
+        // Block n used n * 5M gas
+        let rpc = MockRpc::start(|method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => {
+                let number = params[0].as_str().unwrap().trim_start_matches("0x");
+                let number = u64::from_str_radix(number, 16).unwrap();
+                let mut block = block_with_number(number);
+                block.header.gas_used = number as u128 * 5_000_000;
+                result(&block)
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let block = Block::new(
+            Some(vec![]),
+            Some(vec![
+                BlockFilter::Range(parse_block_range("1:5").unwrap()),
+                BlockFilter::GasUsed(FilterType::Comparison(ComparisonFilter::Gt(15_000_000))),
+            ]),
+            vec![BlockField::Number],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let numbers: Vec<_> = blocks.iter().map(|block| block.number.unwrap()).collect();
+        assert_eq!(numbers, vec![4, 5]);
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 5);
+    }
+
+    #[tokio::test]
+    async fn test_blocks_are_filtered_by_a_base_fee_range() {
+        // Block n has a base fee of n gwei
+        let rpc = MockRpc::start(|method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => {
+                let number = params[0].as_str().unwrap().trim_start_matches("0x");
+                let number = u64::from_str_radix(number, 16).unwrap();
+                let mut block = block_with_number(number);
+                block.header.base_fee_per_gas = Some(number as u128 * 1_000_000_000);
+                result(&block)
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let block = Block::new(
+            Some(vec![]),
+            Some(vec![
+                BlockFilter::Range(parse_block_range("1:5").unwrap()),
+                BlockFilter::BaseFeePerGas(FilterType::Comparison(ComparisonFilter::Between(
+                    2_000_000_000,
+                    4_000_000_000,
+                ))),
+            ]),
+            vec![BlockField::Number],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let numbers: Vec<_> = blocks.iter().map(|block| block.number.unwrap()).collect();
+        assert_eq!(numbers, vec![2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_withdrawals_are_only_set_after_shanghai() {
+        // Block 2 is produced after Shanghai, block 1 before
+        let rpc = MockRpc::start(|method, params| match (method, params[0].as_str()) {
+            ("eth_chainId", _) => result(&"0x1"),
+            ("eth_getBlockByNumber", Some("0x1")) => result(&block_with_number(1)),
+            ("eth_getBlockByNumber", Some("0x2")) => {
+                let mut block = serde_json::to_value(block_with_number(2)).unwrap();
+                block["withdrawals"] = json!([
+                    {
+                        "index": "0x1",
+                        "validatorIndex": "0x3c5a",
+                        "address": "0x8d66d8c4ae5c6a4a4b0e4d7b2bdae7cb4e5d1bd0",
+                        "amount": "0xc7ca2b"
+                    },
+                    {
+                        "index": "0x2",
+                        "validatorIndex": "0x3c5b",
+                        "address": "0x8d66d8c4ae5c6a4a4b0e4d7b2bdae7cb4e5d1bd0",
+                        "amount": "0xc60b65"
+                    }
+                ]);
+                Ok(block)
+            }
+            (method, _) => Err(format!("Unexpected request {method} {params}")),
+        })
+        .await;
+        let block = Block::new(
+            Some(vec![BlockId::Range(parse_block_range("1:2").unwrap())]),
+            None,
+            vec![BlockField::Number, BlockField::Withdrawals],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(blocks[0].withdrawals, None);
+        let withdrawals = blocks[1].withdrawals.as_ref().unwrap();
+        assert_eq!(withdrawals.len(), 2);
+        assert_eq!(
+            withdrawals[0],
+            Withdrawal {
+                index: 1,
+                validator_index: 15450,
+                address: address!("8d66d8c4ae5c6a4a4b0e4d7b2bdae7cb4e5d1bd0"),
+                amount: 13093419,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_base_fee_of_post_london_block_matches_its_header() {
+        // A post-London block, under its gas target of 15M
+        let rpc = MockRpc::start(|method, params| match (method, params[0].as_str()) {
+            ("eth_chainId", _) => result(&"0x1"),
+            ("eth_getBlockByNumber", Some("0x112a880")) => result::<RpcBlock>(&RpcBlock {
+                header: Header {
+                    number: Some(18000000),
+                    gas_limit: 30_000_000,
+                    gas_used: 12_295_484,
+                    base_fee_per_gas: Some(16_946_797_611),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            (method, _) => Err(format!("Unexpected request {method} {params}")),
+        })
+        .await;
+        let block = Block::new(
+            Some(vec![BlockId::Number(BlockNumberOrTag::Number(18000000))]),
+            None,
+            vec![BlockField::BaseFeePerGas, BlockField::NextBaseFee],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(blocks[0].base_fee_per_gas, Some(16_946_797_611));
+        assert_eq!(blocks[0].next_base_fee, Some(16_564_856_901));
+    }
+
+    #[test]
+    fn test_next_base_fee_follows_gas_usage() {
+        let block_using = |gas_used: u128, base_fee_per_gas: Option<u128>| RpcBlock {
+            header: Header {
+                gas_limit: 30_000_000,
+                gas_used,
+                base_fee_per_gas,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // On target the base fee stays the same, full blocks raise it by an 8th, empty ones
+        // lower it by an 8th
+        assert_eq!(
+            next_base_fee(&block_using(15_000_000, Some(800)), &Chain::Ethereum),
+            Some(800)
+        );
+        assert_eq!(
+            next_base_fee(&block_using(30_000_000, Some(800)), &Chain::Ethereum),
+            Some(900)
+        );
+        assert_eq!(
+            next_base_fee(&block_using(0, Some(800)), &Chain::Ethereum),
+            Some(700)
+        );
+        // Going over the target always raises it by at least 1 wei
+        assert_eq!(
+            next_base_fee(&block_using(15_000_001, Some(7)), &Chain::Ethereum),
+            Some(8)
+        );
+        // Blocks before London have no base fee
+        assert_eq!(
+            next_base_fee(&block_using(30_000_000, None), &Chain::Ethereum),
+            None
+        );
+        // Nor is it projected on chains with other parameters
+        assert_eq!(
+            next_base_fee(&block_using(30_000_000, Some(800)), &Chain::Optimism),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_uncles_of_pre_merge_block() {
+        let uncles = vec![
+            b256!("0e5ad0b5a11a02b8d1de9ce5a1a4d2b7c0e1a4d5e4a7f1f4e6bba9e8b2c9a3f1"),
+            b256!("3c0aec1e0eac6b34d05d8b9cdab3a7f6b6c2d4e2fb8b1a5d7c2b6f3b7a1e6c4d"),
+        ];
+        let block_uncles = uncles.clone();
+        // Block 12000000 is produced before the merge, block 17000000 after
+        let rpc = MockRpc::start(move |method, params| match (method, params[0].as_str()) {
+            ("eth_chainId", _) => result(&"0x1"),
+            ("eth_getBlockByNumber", Some("0xb71b00")) => result(&RpcBlock {
+                uncles: block_uncles.clone(),
+                ..block_with_number(12000000)
+            }),
+            ("eth_getBlockByNumber", Some("0x1036640")) => result(&block_with_number(17000000)),
+            (method, _) => Err(format!("Unexpected request {method} {params}")),
+        })
+        .await;
+        let block = Block::new(
+            Some(vec![
+                BlockId::Number(BlockNumberOrTag::Number(12000000)),
+                BlockId::Number(BlockNumberOrTag::Number(17000000)),
+            ]),
+            None,
+            vec![BlockField::Uncles],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(blocks[0].uncles, Some(uncles));
+        assert_eq!(blocks[1].uncles, Some(vec![]));
+    }
+
+    /// Serves block 16 with three transactions, with their bodies when the block is hydrated
+    async fn start_transaction_count_rpc() -> MockRpc {
+        let transactions: Vec<RpcTransaction> = (1..=3)
+            .map(|n| RpcTransaction {
+                hash: B256::with_last_byte(n),
+                block_number: Some(16),
+                ..Default::default()
+            })
+            .collect();
+
+        MockRpc::start(move |method, params| match (method, params[0].as_str()) {
+            ("eth_chainId", _) => result(&"0x1"),
+            ("eth_getBlockTransactionCountByNumber", Some("0x10")) => result(&"0x3"),
+            ("eth_getBlockByNumber", Some("0x10")) => {
+                let transactions = if params[1] == true {
+                    BlockTransactions::Full(transactions.clone())
+                } else {
+                    BlockTransactions::Hashes(transactions.iter().map(|tx| tx.hash).collect())
+                };
+                result(&RpcBlock {
+                    transactions,
+                    ..block_with_number(16)
+                })
+            }
+            (method, _) => Err(format!("Unexpected request {method} {params}")),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_transaction_count_matches_full_block() {
+        let rpc = start_transaction_count_rpc().await;
+        let block = Block::new(
+            Some(vec![BlockId::Number(BlockNumberOrTag::Number(16))]),
+            None,
+            vec![BlockField::Number, BlockField::TransactionCount],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        // Only the count is read, without the block
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 0);
+        let full_block = get_block(BlockNumberOrTag::Number(16), rpc.provider(), true)
+            .await
+            .unwrap();
+        assert_eq!(blocks[0].number, Some(16));
+        assert_eq!(
+            blocks[0].transaction_count,
+            Some(full_block.transactions.len() as u64)
+        );
+        assert_eq!(blocks[0].transaction_count, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_transaction_count_of_fetched_block_is_read_from_its_hashes() {
+        let rpc = start_transaction_count_rpc().await;
+        let block = Block::new(
+            Some(vec![BlockId::Number(BlockNumberOrTag::Number(16))]),
+            None,
+            vec![BlockField::Hash, BlockField::TransactionCount],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            BlockBatchConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(blocks[0].transaction_count, Some(3));
+        assert_eq!(rpc.calls("eth_getBlockTransactionCountByNumber"), 0);
+    }
+
+    async fn start_slow_block_rpc() -> MockRpc {
+        MockRpc::start_with_latency(Duration::from_millis(50), |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => {
+                let number = params[0].as_str().unwrap().trim_start_matches("0x");
+                result(&block_with_number(u64::from_str_radix(number, 16).unwrap()))
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await
+    }
+
+    async fn resolve_range_in_batches(rpc: &MockRpc, batch: BlockBatchConfig) -> Vec<u64> {
+        let block = Block::new(
+            Some(vec![BlockId::Range(parse_block_range("1:20").unwrap())]),
+            None,
+            vec![BlockField::Number],
+        );
+
+        let blocks = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            batch,
+        )
+        .await
+        .unwrap();
+
+        blocks.iter().map(|block| block.number.unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_blocks_of_range_are_fetched_in_bounded_groups() {
+        let rpc = start_slow_block_rpc().await;
+        let batch = BlockBatchConfig {
+            size: 5,
+            json_rpc: false,
+        };
+
+        let numbers = resolve_range_in_batches(&rpc, batch).await;
+
+        assert_eq!(numbers, (1..=20).collect::<Vec<_>>());
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 20);
+        assert_eq!(rpc.max_in_flight(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_blocks_of_range_are_fetched_in_json_rpc_batches() {
+        let rpc = start_slow_block_rpc().await;
+        let batch = BlockBatchConfig {
+            size: 8,
+            json_rpc: true,
+        };
+
+        let numbers = resolve_range_in_batches(&rpc, batch).await;
+
+        assert_eq!(numbers, (1..=20).collect::<Vec<_>>());
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 20);
+        // The 3 batches are sent one after the other
+        assert_eq!(rpc.max_in_flight(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_blocks_of_range_stop_at_the_first_missing_block() {
+        let rpc =
+            MockRpc::start_with_latency(Duration::from_millis(50), |method, params| match method {
+                "eth_chainId" => result(&"0x1"),
+                "eth_getBlockByNumber" => match params[0].as_str().unwrap() {
+                    "0x3" => result(&None::<RpcBlock>),
+                    number => result(&block_with_number(
+                        u64::from_str_radix(number.trim_start_matches("0x"), 16).unwrap(),
+                    )),
+                },
+                method => Err(format!("Unexpected method {method}")),
+            })
+            .await;
+        let block = Block::new(
+            Some(vec![BlockId::Range(parse_block_range("1:20").unwrap())]),
+            None,
+            vec![BlockField::Number],
+        );
+        let batch = BlockBatchConfig {
+            size: 5,
+            json_rpc: false,
+        };
+
+        let result = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            batch,
+        )
+        .await;
+
+        assert!(result.is_err());
+        // Only the blocks requested along with the missing one, not the whole range
+        assert!(rpc.calls("eth_getBlockByNumber") < 20);
+    }
+
+    #[tokio::test]
+    async fn test_transaction_counts_of_range_stop_at_the_first_missing_block() {
+        let rpc =
+            MockRpc::start_with_latency(Duration::from_millis(50), |method, params| match method {
+                "eth_chainId" => result(&"0x1"),
+                "eth_getBlockTransactionCountByNumber" => match params[0].as_str().unwrap() {
+                    "0x3" => result(&None::<U64>),
+                    _ => result(&"0x1"),
+                },
+                method => Err(format!("Unexpected method {method}")),
+            })
+            .await;
+        let block = Block::new(
+            Some(vec![BlockId::Range(parse_block_range("1:20").unwrap())]),
+            None,
+            vec![BlockField::Number, BlockField::TransactionCount],
+        );
+        let batch = BlockBatchConfig {
+            size: 5,
+            json_rpc: false,
+        };
+
+        let result = resolve_block_query(
+            &block,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_BLOCK_RANGE,
+            batch,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(rpc.calls("eth_getBlockTransactionCountByNumber") < 20);
+    }
+
+    fn block_with_number(number: u64) -> RpcBlock {
+        RpcBlock {
+            header: Header {
+                number: Some(number),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    async fn start_subscription_rpc() -> MockRpc {
+        MockRpc::start_ws(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_subscribe" => result(&"0xabc"),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await
+    }
+
+    fn block_numbers_query() -> Block {
+        Block::new(
+            Some(vec![BlockId::Number(BlockNumberOrTag::Latest)]),
+            None,
+            vec![BlockField::Number],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_stream_blocks_from_subscription() {
+        let rpc = start_subscription_rpc().await;
+        let stream = stream_block_query(&block_numbers_query(), &ChainOrRpc::Rpc(rpc.url()))
+            .await
+            .unwrap();
+
+        for number in 1..=3 {
+            rpc.notify("0xabc", &block_with_number(number));
+        }
+        let blocks: Vec<BlockQueryRes> = stream.take(3).map(Result::unwrap).collect().await;
+
+        assert_eq!(
+            blocks,
+            (1..=3)
+                .map(|number| BlockQueryRes {
+                    number: Some(number),
+                    ..Default::default()
+                })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(rpc.calls("eth_subscribe"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_reports_missed_blocks() {
+        let rpc = start_subscription_rpc().await;
+        let mut stream = stream_block_query(&block_numbers_query(), &ChainOrRpc::Rpc(rpc.url()))
+            .await
+            .unwrap();
+
+        rpc.notify("0xabc", &block_with_number(1));
+        rpc.notify("0xabc", &block_with_number(4));
+
+        assert_eq!(stream.next().await.unwrap().unwrap().number, Some(1));
+        assert_eq!(
+            stream.next().await.unwrap().unwrap_err().to_string(),
+            "Missed blocks 2 to 3, the subscription may have been reconnected"
+        );
+        assert_eq!(stream.next().await.unwrap().unwrap().number, Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_when_connection_is_lost() {
+        let rpc = start_subscription_rpc().await;
+        let mut stream = stream_block_query(&block_numbers_query(), &ChainOrRpc::Rpc(rpc.url()))
+            .await
+            .unwrap();
+
+        rpc.notify("0xabc", &block_with_number(1));
+        assert_eq!(stream.next().await.unwrap().unwrap().number, Some(1));
+
+        rpc.disconnect();
+        let next = tokio::time::timeout(Duration::from_secs(5), stream.next()).await;
+
+        assert!(matches!(next, Ok(None)));
+    }
 }
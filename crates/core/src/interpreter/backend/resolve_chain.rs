@@ -0,0 +1,164 @@
+use super::{
+    resolve_block::get_block,
+    retry::{self, with_retry},
+};
+use crate::common::{
+    chain::{Chain, ChainOrRpc},
+    chain_status::{ChainField, ChainStatus},
+    query_result::ChainQueryRes,
+};
+use alloy::{
+    eips::BlockNumberOrTag,
+    providers::{Provider, RootProvider},
+    transports::BoxTransport,
+};
+use anyhow::Result;
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize, thiserror::Error)]
+pub enum ChainResolverErrors {
+    #[error("Unable to fetch {0} of chain {1}: {2}")]
+    Rpc(ChainField, Chain, String),
+}
+
+/// Reads the status of every chain, with a single result per chain.
+pub async fn resolve_chain_query(
+    status: &ChainStatus,
+    chains: &[ChainOrRpc],
+) -> Result<Vec<ChainQueryRes>> {
+    let mut all_chain_futures = Vec::new();
+
+    for chain in chains {
+        let chain_future = async move {
+            let provider = Arc::new(chain.provider().await?);
+            let chain = chain.to_chain().await?;
+
+            get_chain_status(status.fields(), provider, &chain).await
+        };
+
+        all_chain_futures.push(chain_future);
+    }
+
+    try_join_all(all_chain_futures).await
+}
+
+async fn get_chain_status(
+    fields: &[ChainField],
+    provider: Arc<RootProvider<BoxTransport>>,
+    chain: &Chain,
+) -> Result<ChainQueryRes> {
+    let mut result = ChainQueryRes::default();
+    let rpc_error = |field: ChainField, e: &dyn std::fmt::Display| {
+        ChainResolverErrors::Rpc(field, chain.clone(), e.to_string())
+    };
+
+    // The block number is read from the latest block when its base fee is fetched too, so both
+    // describe the same block
+    if fields.contains(&ChainField::BaseFeePerGas) {
+        let latest = get_block(BlockNumberOrTag::Latest, provider.clone(), false)
+            .await
+            .map_err(|e| rpc_error(ChainField::BaseFeePerGas, &e))?;
+        result.base_fee_per_gas = latest.header.base_fee_per_gas;
+        if fields.contains(&ChainField::BlockNumber) {
+            result.block_number = latest.header.number;
+        }
+    } else if fields.contains(&ChainField::BlockNumber) {
+        let number = with_retry(&retry::current(), || async {
+            provider.get_block_number().await
+        })
+        .await
+        .map_err(|e| rpc_error(ChainField::BlockNumber, &e))?;
+        result.block_number = Some(number);
+    }
+
+    for field in fields {
+        match field {
+            ChainField::ChainId => {
+                let chain_id = with_retry(&retry::current(), || async {
+                    provider.get_chain_id().await
+                })
+                .await
+                .map_err(|e| rpc_error(*field, &e))?;
+                result.chain_id = Some(chain_id);
+            }
+            ChainField::GasPrice => {
+                let gas_price = with_retry(&retry::current(), || async {
+                    provider.get_gas_price().await
+                })
+                .await
+                .map_err(|e| rpc_error(*field, &e))?;
+                result.gas_price = Some(gas_price);
+            }
+            // Both read from the latest block, above
+            ChainField::BlockNumber | ChainField::BaseFeePerGas => {}
+            ChainField::Chain => {
+                result.chain = Some(chain.clone());
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::backend::mock_rpc::{result, MockRpc};
+    use alloy::rpc::types::{Block as RpcBlock, Header};
+
+    #[tokio::test]
+    async fn test_resolve_chain_status() {
+        let rpc = MockRpc::start(|method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_gasPrice" => result(&"0x3b9aca00"),
+            "eth_getBlockByNumber" if params[0] == "latest" => result::<RpcBlock>(&RpcBlock {
+                header: Header {
+                    number: Some(21_000_000),
+                    base_fee_per_gas: Some(900_000_000),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            method => Err(format!("Unexpected method {method} {params}")),
+        })
+        .await;
+        let status = ChainStatus::new(ChainField::all_variants().to_vec());
+
+        let results = resolve_chain_query(&status, &[ChainOrRpc::Rpc(rpc.url())])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results,
+            vec![ChainQueryRes {
+                chain: Some(Chain::Ethereum),
+                chain_id: Some(1),
+                block_number: Some(21_000_000),
+                gas_price: Some(1_000_000_000),
+                base_fee_per_gas: Some(900_000_000),
+            }]
+        );
+        // The block number comes with the latest block, which is fetched for its base fee
+        assert_eq!(rpc.calls("eth_blockNumber"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_block_number_without_base_fee_is_read_alone() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_blockNumber" => result(&"0x1406f40"),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let status = ChainStatus::new(vec![ChainField::BlockNumber]);
+
+        let results = resolve_chain_query(&status, &[ChainOrRpc::Rpc(rpc.url())])
+            .await
+            .unwrap();
+
+        assert_eq!(results[0].block_number, Some(21_000_000));
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 0);
+    }
+}
@@ -1,11 +1,17 @@
 use crate::common::{
     chain::ChainOrRpc,
-    logs::{LogField, Logs},
+    logs::{LogField, LogFilter, Logs},
     query_result::LogQueryRes,
 };
-use alloy::providers::{Provider, ProviderBuilder};
+use alloy::{
+    dyn_abi::{DynSolValue, EventExt},
+    json_abi::Event,
+    primitives::{hex, LogData},
+    providers::Provider,
+};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize, thiserror::Error)]
@@ -19,10 +25,23 @@ pub async fn resolve_log_query(
     chain_or_rpcs: &[ChainOrRpc],
 ) -> Result<Vec<LogQueryRes>> {
     let mut all_results = Vec::new();
+    let event = logs.event();
 
     for chain_or_rpc in chain_or_rpcs {
-        let provider = Arc::new(ProviderBuilder::new().on_http(chain_or_rpc.rpc_url()?));
-        let filtered_logs = provider.get_logs(&logs.build_bloom_filter()).await?;
+        let provider = Arc::new(chain_or_rpc.provider().await?);
+        let mut filter = logs.build_bloom_filter();
+
+        // Relative blocks like `latest-100` can't be sent as is, they're resolved on each chain
+        for log_filter in logs.filter() {
+            if let LogFilter::BlockRange(range) = log_filter {
+                if range.is_relative() {
+                    let (start, end) = range.resolve_bounds(&provider).await?;
+                    filter = filter.from_block(start).to_block(end);
+                }
+            }
+        }
+
+        let filtered_logs = provider.get_logs(&filter).await?;
         let chain = chain_or_rpc.to_chain().await?;
 
         if !filtered_logs.is_empty() {
@@ -48,7 +67,8 @@ pub async fn resolve_log_query(
                             LogField::BlockHash => result.block_hash = log.block_hash,
                             LogField::BlockNumber => result.block_number = log.block_number,
                             LogField::BlockTimestamp => {
-                                result.block_timestamp = log.block_timestamp
+                                result.block_timestamp = log.block_timestamp;
+                                result.block_timestamp_utc = log.block_timestamp;
                             }
                             LogField::TransactionHash => {
                                 result.transaction_hash = log.transaction_hash
@@ -58,6 +78,11 @@ pub async fn resolve_log_query(
                             }
                             LogField::LogIndex => result.log_index = log.log_index,
                             LogField::Removed => result.removed = Some(log.removed),
+                            LogField::Decoded => {
+                                result.decoded = event
+                                    .as_ref()
+                                    .and_then(|event| decode_log(event, &log.inner.data))
+                            }
                             LogField::Chain => result.chain = Some(chain.clone()),
                         }
                     }
@@ -76,3 +101,151 @@ pub async fn resolve_log_query(
 
     Ok(all_results)
 }
+
+/// Decodes the parameters of the log with the event, by name, or by position for unnamed
+/// parameters. When the signature doesn't mark any parameter as indexed, the first ones are taken
+/// as indexed, one for each topic after the event topic. `None` if the log doesn't match the event.
+fn decode_log(event: &Event, log: &LogData) -> Option<Map<String, Value>> {
+    let mut event = event.clone();
+
+    if event.inputs.iter().all(|input| !input.indexed) {
+        let indexed = log.topics().len().saturating_sub(1);
+        event
+            .inputs
+            .iter_mut()
+            .take(indexed)
+            .for_each(|input| input.indexed = true);
+    }
+
+    let decoded = event.decode_log(log, true).ok()?;
+    let (mut indexed, mut body) = (decoded.indexed.into_iter(), decoded.body.into_iter());
+
+    event
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            let value = match input.indexed {
+                true => indexed.next(),
+                false => body.next(),
+            }?;
+            let name = match input.name.is_empty() {
+                true => format!("param{i}"),
+                false => input.name.clone(),
+            };
+
+            Some((name, to_json(&value)))
+        })
+        .collect()
+}
+
+/// Numbers are written in decimal, as they can exceed the precision of JSON numbers. Indexed
+/// parameters of dynamic types, like strings, are only known by their hash.
+pub(crate) fn to_json(value: &DynSolValue) -> Value {
+    match value {
+        DynSolValue::Bool(value) => Value::Bool(*value),
+        DynSolValue::Int(value, _) => Value::String(value.to_string()),
+        DynSolValue::Uint(value, _) => Value::String(value.to_string()),
+        DynSolValue::FixedBytes(value, size) => {
+            Value::String(hex::encode_prefixed(&value[..*size]))
+        }
+        DynSolValue::Address(address) => Value::String(address.to_checksum(None)),
+        DynSolValue::Function(function) => Value::String(function.to_string()),
+        DynSolValue::Bytes(bytes) => Value::String(hex::encode_prefixed(bytes)),
+        DynSolValue::String(value) => Value::String(value.clone()),
+        DynSolValue::Array(values)
+        | DynSolValue::FixedArray(values)
+        | DynSolValue::Tuple(values) => Value::Array(values.iter().map(to_json).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{chain::Chain, query_result::LogQueryRes},
+        interpreter::backend::mock_rpc::{result, MockRpc},
+    };
+    use alloy::{
+        primitives::{address, b256, Address, Log as PrimitiveLog, B256, U256},
+        rpc::types::Log,
+    };
+    use serde_json::json;
+
+    const TRANSFER_TOPIC: B256 =
+        b256!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+
+    /// RPC serving a `Transfer` log for each amount, from `0x01..` to `0x02..`
+    async fn transfers_rpc(amounts: &'static [u64]) -> MockRpc {
+        MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getLogs" => {
+                assert_eq!(params[0]["topics"][0], TRANSFER_TOPIC.to_string());
+
+                let logs: Vec<Log> = amounts
+                    .iter()
+                    .map(|amount| Log {
+                        inner: PrimitiveLog::new_unchecked(
+                            address!("dAC17F958D2ee523a2206206994597C13D831ec7"),
+                            vec![
+                                TRANSFER_TOPIC,
+                                Address::repeat_byte(1).into_word(),
+                                Address::repeat_byte(2).into_word(),
+                            ],
+                            U256::from(*amount).to_be_bytes_vec().into(),
+                        ),
+                        ..Default::default()
+                    })
+                    .collect();
+                result(&logs)
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await
+    }
+
+    fn decoded_logs(signature: &str) -> Logs {
+        Logs::new(
+            vec![LogFilter::EventSignature(signature.to_string())],
+            vec![LogField::Decoded, LogField::Chain],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decode_transfer_events() {
+        let rpc = transfers_rpc(&[1_000, 2_500]).await;
+        let logs =
+            decoded_logs("Transfer(address indexed from, address indexed to, uint256 value)");
+
+        let results = resolve_log_query(&logs, &[ChainOrRpc::Rpc(rpc.url())])
+            .await
+            .unwrap();
+
+        let transfer = |value: &str| LogQueryRes {
+            chain: Some(Chain::Ethereum),
+            decoded: json!({
+                "from": Address::repeat_byte(1).to_checksum(None),
+                "to": Address::repeat_byte(2).to_checksum(None),
+                "value": value,
+            })
+            .as_object()
+            .cloned(),
+            ..Default::default()
+        };
+        assert_eq!(results, vec![transfer("1000"), transfer("2500")]);
+    }
+
+    #[tokio::test]
+    async fn test_unnamed_parameters_are_decoded_by_position() {
+        let rpc = transfers_rpc(&[1_000]).await;
+        let logs = decoded_logs("Transfer(address,address,uint256)");
+
+        let results = resolve_log_query(&logs, &[ChainOrRpc::Rpc(rpc.url())])
+            .await
+            .unwrap();
+
+        let decoded = results[0].decoded.as_ref().unwrap();
+        assert_eq!(decoded["param1"], Address::repeat_byte(2).to_checksum(None));
+        assert_eq!(decoded["param2"], "1000");
+    }
+}
@@ -0,0 +1,206 @@
+use super::retry::{self, with_retry};
+use crate::common::{
+    chain::{Chain, ChainOrRpc},
+    query_result::ReceiptQueryRes,
+    receipt::{Receipt, ReceiptField},
+};
+use alloy::{
+    primitives::B256,
+    providers::{Provider, RootProvider},
+    rpc::types::TransactionReceipt,
+    transports::BoxTransport,
+};
+use anyhow::Result;
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize, thiserror::Error)]
+pub enum ReceiptResolverErrors {
+    #[error("Unable to fetch receipt of transaction {0}: {1}")]
+    Rpc(B256, String),
+}
+
+/// Fetches the receipt of every transaction on every chain. Transactions that don't exist, or
+/// aren't included in a block yet, have no receipt and are left out, like missing transactions.
+pub async fn resolve_receipt_query(
+    receipt: &Receipt,
+    chains: &[ChainOrRpc],
+) -> Result<Vec<ReceiptQueryRes>> {
+    let mut all_receipt_futures = Vec::new();
+
+    for chain in chains {
+        let provider = Arc::new(chain.provider().await?);
+        let chain = chain.to_chain().await?;
+
+        for hash in receipt.ids() {
+            let provider = provider.clone();
+            let chain = chain.clone();
+
+            let receipt_future =
+                async move { get_receipt(hash, receipt.fields(), &provider, &chain).await };
+
+            all_receipt_futures.push(receipt_future);
+        }
+    }
+
+    let receipt_res = try_join_all(all_receipt_futures).await?;
+    Ok(receipt_res.into_iter().flatten().collect())
+}
+
+async fn get_receipt(
+    hash: &B256,
+    fields: &[ReceiptField],
+    provider: &RootProvider<BoxTransport>,
+    chain: &Chain,
+) -> Result<Option<ReceiptQueryRes>> {
+    let receipt = with_retry(&retry::current(), || async {
+        provider.get_transaction_receipt(*hash).await
+    })
+    .await
+    .map_err(|e| ReceiptResolverErrors::Rpc(*hash, e.to_string()))?;
+
+    Ok(receipt.map(|receipt| filter_fields(&receipt, fields, chain)))
+}
+
+fn filter_fields(
+    receipt: &TransactionReceipt,
+    fields: &[ReceiptField],
+    chain: &Chain,
+) -> ReceiptQueryRes {
+    let mut result = ReceiptQueryRes::default();
+
+    for field in fields {
+        match field {
+            ReceiptField::TransactionHash => {
+                result.transaction_hash = Some(receipt.transaction_hash);
+            }
+            ReceiptField::Type => {
+                result.receipt_type = Some(receipt.inner.tx_type().into());
+            }
+            ReceiptField::Status => {
+                result.status = Some(receipt.status());
+            }
+            ReceiptField::GasUsed => {
+                result.gas_used = Some(receipt.gas_used);
+            }
+            ReceiptField::EffectiveGasPrice => {
+                result.effective_gas_price = Some(receipt.effective_gas_price);
+            }
+            ReceiptField::CumulativeGasUsed => {
+                result.cumulative_gas_used = Some(receipt.inner.cumulative_gas_used());
+            }
+            ReceiptField::LogsBloom => {
+                result.logs_bloom = Some(*receipt.inner.logs_bloom());
+            }
+            ReceiptField::ContractAddress => {
+                // Only contract creations have a contract address
+                result.contract_address = receipt.contract_address;
+            }
+            ReceiptField::Logs => {
+                result.logs = Some(receipt.inner.logs().to_vec());
+            }
+            ReceiptField::Chain => {
+                result.chain = Some(chain.clone());
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::backend::mock_rpc::{result, MockRpc};
+    use alloy::{
+        consensus::{Receipt as ConsensusReceipt, ReceiptEnvelope, ReceiptWithBloom},
+        primitives::{address, b256, Address, Bloom, Log as PrimitiveLog, LogData},
+        rpc::types::Log,
+    };
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn test_resolve_receipt_fields() {
+        let hash = b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890");
+        let usdt = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+        let log = Log {
+            inner: PrimitiveLog {
+                address: usdt,
+                data: LogData::new_unchecked(vec![], Default::default()),
+            },
+            transaction_hash: Some(hash),
+            ..Default::default()
+        };
+        let receipt = TransactionReceipt {
+            inner: ReceiptEnvelope::Eip1559(ReceiptWithBloom {
+                receipt: ConsensusReceipt {
+                    status: true.into(),
+                    cumulative_gas_used: 7_093_471,
+                    logs: vec![log.clone()],
+                },
+                logs_bloom: Bloom::ZERO,
+            }),
+            transaction_hash: hash,
+            transaction_index: Some(86),
+            block_hash: None,
+            block_number: Some(20_871_209),
+            gas_used: 46_109,
+            effective_gas_price: 12_519_000_000,
+            blob_gas_used: None,
+            blob_gas_price: None,
+            from: Address::ZERO,
+            to: Some(usdt),
+            contract_address: None,
+            state_root: None,
+            authorization_list: None,
+        };
+        let rpc = MockRpc::start(move |method, params: &Value| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionReceipt" => {
+                assert_eq!(params[0], hash.to_string());
+                result(&receipt)
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let receipt = Receipt::new(vec![hash], ReceiptField::all_variants().to_vec());
+
+        let receipts = resolve_receipt_query(&receipt, &[ChainOrRpc::Rpc(rpc.url())])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            receipts,
+            vec![ReceiptQueryRes {
+                chain: Some(Chain::Ethereum),
+                transaction_hash: Some(hash),
+                receipt_type: Some(2),
+                status: Some(true),
+                gas_used: Some(46_109),
+                effective_gas_price: Some(12_519_000_000),
+                cumulative_gas_used: Some(7_093_471),
+                logs_bloom: Some(Bloom::ZERO),
+                contract_address: None,
+                logs: Some(vec![log]),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_receipts_are_left_out() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionReceipt" => result(&Value::Null),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let receipt = Receipt::new(vec![B256::ZERO], vec![ReceiptField::Status]);
+
+        let receipts = resolve_receipt_query(&receipt, &[ChainOrRpc::Rpc(rpc.url())])
+            .await
+            .unwrap();
+
+        assert_eq!(receipts, vec![]);
+    }
+}
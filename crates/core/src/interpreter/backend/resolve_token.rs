@@ -0,0 +1,224 @@
+use super::retry::{self, with_retry};
+use crate::common::{
+    chain::{Chain, ChainOrRpc},
+    query_result::TokenQueryRes,
+    token::{Token, TokenField},
+};
+use alloy::{
+    primitives::{Address, Bytes, B256},
+    providers::{Provider, RootProvider},
+    rpc::types::TransactionRequest,
+    sol,
+    sol_types::{SolCall, SolValue},
+    transports::BoxTransport,
+};
+use anyhow::Result;
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+sol! {
+    function name() external view returns (string);
+    function symbol() external view returns (string);
+    function decimals() external view returns (uint8);
+    function totalSupply() external view returns (uint256);
+}
+
+#[derive(Debug, Serialize, Deserialize, thiserror::Error)]
+pub enum TokenResolverErrors {
+    #[error("Unable to fetch {0} of token {1}: {2}")]
+    Rpc(TokenField, Address, String),
+    #[error("Unable to decode {0} of token {1}: {2}")]
+    Decoding(TokenField, Address, String),
+}
+
+/// Reads the metadata of every token on every chain, by calling the ERC-20 view functions.
+pub async fn resolve_token_query(
+    token: &Token,
+    chains: &[ChainOrRpc],
+) -> Result<Vec<TokenQueryRes>> {
+    let mut all_token_futures = Vec::new();
+
+    for chain in chains {
+        let provider = Arc::new(chain.provider().await?);
+        let chain = chain.to_chain().await?;
+
+        for address in token.ids() {
+            let provider = provider.clone();
+            let chain = chain.clone();
+
+            let token_future =
+                async move { get_token(address, token.fields(), &provider, &chain).await };
+
+            all_token_futures.push(token_future);
+        }
+    }
+
+    let token_res = try_join_all(all_token_futures).await?;
+    Ok(token_res)
+}
+
+async fn get_token(
+    address: &Address,
+    fields: &[TokenField],
+    provider: &RootProvider<BoxTransport>,
+    chain: &Chain,
+) -> Result<TokenQueryRes> {
+    let mut token = TokenQueryRes::default();
+
+    for field in fields {
+        let decoding_error = |e: alloy::sol_types::Error| {
+            TokenResolverErrors::Decoding(*field, *address, e.to_string())
+        };
+
+        match field {
+            TokenField::Address => {
+                token.address = Some(*address);
+            }
+            TokenField::Name => {
+                let output = call(address, *field, nameCall {}.abi_encode(), provider).await?;
+                token.name = Some(decode_string(&output).map_err(decoding_error)?);
+            }
+            TokenField::Symbol => {
+                let output = call(address, *field, symbolCall {}.abi_encode(), provider).await?;
+                token.symbol = Some(decode_string(&output).map_err(decoding_error)?);
+            }
+            TokenField::Decimals => {
+                let output = call(address, *field, decimalsCall {}.abi_encode(), provider).await?;
+                token.decimals = Some(
+                    decimalsCall::abi_decode_returns(&output, true)
+                        .map_err(decoding_error)?
+                        ._0,
+                );
+            }
+            TokenField::TotalSupply => {
+                let output =
+                    call(address, *field, totalSupplyCall {}.abi_encode(), provider).await?;
+                token.total_supply = Some(
+                    totalSupplyCall::abi_decode_returns(&output, true)
+                        .map_err(decoding_error)?
+                        ._0,
+                );
+            }
+            TokenField::Chain => {
+                token.chain = Some(chain.clone());
+            }
+        }
+    }
+
+    Ok(token)
+}
+
+async fn call(
+    address: &Address,
+    field: TokenField,
+    input: Vec<u8>,
+    provider: &RootProvider<BoxTransport>,
+) -> Result<Bytes> {
+    let call = TransactionRequest::default()
+        .to(*address)
+        .input(input.into());
+    let output = with_retry(&retry::current(), || async { provider.call(&call).await })
+        .await
+        .map_err(|e| TokenResolverErrors::Rpc(field, *address, e.to_string()))?;
+
+    Ok(output)
+}
+
+/// Decodes the string returned by `name` or `symbol`. Some tokens deployed before the ERC-20
+/// standard was finalized, like MKR, return a null-padded bytes32 instead.
+fn decode_string(output: &[u8]) -> Result<String, alloy::sol_types::Error> {
+    match String::abi_decode(output, true) {
+        Ok(value) => Ok(value),
+        Err(e) => match B256::abi_decode(output, true) {
+            Ok(value) => {
+                let value = String::from_utf8_lossy(value.as_slice());
+                Ok(value.trim_end_matches('\0').to_string())
+            }
+            Err(_) => Err(e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::backend::mock_rpc::{result, MockRpc};
+    use alloy::primitives::{address, FixedBytes, U256};
+    use serde_json::Value;
+
+    /// Answers the ERC-20 calls made to `token`
+    async fn start_token_rpc(token: Address, outputs: fn(&[u8]) -> Vec<u8>) -> MockRpc {
+        MockRpc::start(move |method, params: &Value| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_call" => {
+                let to: Address = params[0]["to"].as_str().unwrap().parse().unwrap();
+                let input: Bytes = params[0]["input"].as_str().unwrap().parse().unwrap();
+                assert_eq!(to, token);
+                result(&Bytes::from(outputs(&input[..4])))
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_resolve_token_metadata() {
+        let usdc = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        let rpc = start_token_rpc(usdc, |selector| match selector {
+            s if s == nameCall::SELECTOR => "USD Coin".to_string().abi_encode(),
+            s if s == symbolCall::SELECTOR => "USDC".to_string().abi_encode(),
+            s if s == decimalsCall::SELECTOR => U256::from(6).abi_encode(),
+            _ => U256::from(25_000_000_000_000_000u64).abi_encode(),
+        })
+        .await;
+        let token = Token::new(vec![usdc], TokenField::all_variants().to_vec());
+
+        let tokens = resolve_token_query(&token, &[ChainOrRpc::Rpc(rpc.url())])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![TokenQueryRes {
+                chain: Some(Chain::Ethereum),
+                address: Some(usdc),
+                name: Some("USD Coin".to_string()),
+                symbol: Some("USDC".to_string()),
+                decimals: Some(6),
+                total_supply: Some(U256::from(25_000_000_000_000_000u64)),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_bytes32_token_metadata() {
+        let mkr = address!("9f8F72aA9304c8B593d555F12eF6589cC3A579A2");
+        let rpc = start_token_rpc(mkr, |selector| {
+            let padded = |value: &str| {
+                let mut bytes = [0u8; 32];
+                bytes[..value.len()].copy_from_slice(value.as_bytes());
+                FixedBytes::from(bytes).abi_encode()
+            };
+
+            match selector {
+                s if s == nameCall::SELECTOR => padded("Maker"),
+                s if s == symbolCall::SELECTOR => padded("MKR"),
+                _ => U256::from(18).abi_encode(),
+            }
+        })
+        .await;
+        let token = Token::new(
+            vec![mkr],
+            vec![TokenField::Name, TokenField::Symbol, TokenField::Decimals],
+        );
+
+        let tokens = resolve_token_query(&token, &[ChainOrRpc::Rpc(rpc.url())])
+            .await
+            .unwrap();
+
+        assert_eq!(tokens[0].name, Some("Maker".to_string()));
+        assert_eq!(tokens[0].symbol, Some("MKR".to_string()));
+        assert_eq!(tokens[0].decimals, Some(18));
+    }
+}
@@ -1,27 +1,75 @@
-use super::resolve_block::{batch_get_blocks, get_block};
+use super::{
+    partial_results::join_items,
+    raw_items,
+    resolve_block::{get_block, get_block_by_hash},
+    retry::{self, with_retry},
+};
 use crate::common::{
-    block::BlockId,
-    chain::ChainOrRpc,
-    query_result::TransactionQueryRes,
+    block::{BlockId, BlockRange},
+    chain::{Chain, ChainOrRpc},
+    ens::{resolve_names, EnsConfig, NameOrAddress},
+    query_result::{CallFrame, TransactionQueryRes},
     transaction::{Transaction, TransactionField},
 };
 use alloy::{
-    primitives::FixedBytes,
-    providers::{Provider, ProviderBuilder, RootProvider},
-    rpc::types::{BlockTransactions, Transaction as RpcTransaction},
-    transports::http::{Client, Http},
+    consensus::TxEnvelope,
+    eips::{eip2718::Encodable2718, BlockNumberOrTag},
+    primitives::{hex, Address, Bytes, FixedBytes, TxKind, U128, U256, U64},
+    providers::{Provider, RootProvider},
+    rpc::types::{
+        Block as RpcBlock, BlockTransactions, Transaction as RpcTransaction, TransactionReceipt,
+        TransactionRequest,
+    },
+    sol_types::{ContractError, GenericContractError, SolInterface},
+    transports::{BoxTransport, RpcError},
 };
 use anyhow::{Ok, Result};
-use futures::future::try_join_all;
+use futures::{
+    future::try_join_all,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tokio::sync::Semaphore;
+
+/// Maximum number of requests sent concurrently to a RPC while resolving a transaction query
+pub const DEFAULT_MAX_CONCURRENCY: usize = 20;
+
+/// Number of blocks searched, back from the latest one, for the transactions of queries filtered by
+/// `from` or `to` alone
+pub const DEFAULT_SCAN_DEPTH: u64 = 1_000;
 
 #[derive(Debug, Serialize, Deserialize, thiserror::Error)]
 pub enum TransactionResolverErrors {
     #[error("Mismatch between Entity and EntityId, {0} can't be resolved as a transaction id")]
     MismatchEntityAndEntityId(String),
-    #[error("Query should either provide tx hash or block number/range filter")]
+    #[error("Query should either provide tx hash, block number/range filter or sender and nonce filters")]
     MissingTransactionHashOrFilter,
+    #[error("Expected block to contain full transactions")]
+    ExpectedFullTransactions,
+    #[error("The pending block can't be part of a block range")]
+    PendingBlockInRange,
+    #[error("Unable to fetch pending transactions, the RPC may not serve the pending block: {0}")]
+    PendingBlockUnavailable(String),
+    #[error("Unable to fetch transaction {0}: {1}")]
+    Rpc(FixedBytes<32>, String),
+    #[error("Unable to resolve ENS name {0}: {1}")]
+    EnsResolution(String, String),
+    #[error(
+        "Unable to trace transaction {0}, the RPC may not support debug_traceTransaction: {1}"
+    )]
+    TraceUnavailable(FixedBytes<32>, String),
+    #[error("Unable to replay transaction {0} to read its revert reason: {1}")]
+    RevertReasonUnavailable(FixedBytes<32>, String),
+    #[error("Unable to fetch the transaction of {0} with nonce {1}, the RPC may not support eth_getTransactionBySenderAndNonce: {2}")]
+    SenderAndNonceUnavailable(Address, u64, String),
+    #[error("ENS name {0} wasn't resolved to an address")]
+    UnresolvedSender(String),
 }
 
 /// Resolve the query to get transactions after receiving an transaction entity expression
@@ -33,116 +81,941 @@ pub enum TransactionResolverErrors {
 /// 4. If ids are not provided, then block number or block range filter must be provided.
 /// 5. Fetch the transactions by block number or block range.
 /// 6. If both ids and block number or block range filter are provided, then fetch the transactions by ids first, and filter the result by block number or block range.
+/// 7. Without ids or a block filter, a transaction can be fetched by its sender and nonce, given
+///    by the `from = <address>` and `nonce = <n>` filters, from RPCs serving
+///    `eth_getTransactionBySenderAndNonce`.
+///
+/// The chains are resolved concurrently, and the results are returned in the order of the chains.
+/// At most `max_concurrency` requests are in flight at any time on each chain, since block ranges
+/// can easily fan out into thousands of requests and get rate limited by public RPCs. For the same
+/// reason, block ranges spanning more than `max_block_range` blocks are rejected.
+/// ENS names in the `from` and `to` filters are resolved against the `ens` deployment.
 pub async fn resolve_transaction_query(
     transaction: &Transaction,
     chains: &[ChainOrRpc],
+    max_concurrency: usize,
+    max_block_range: u64,
+    ens: &EnsConfig,
 ) -> Result<Vec<TransactionQueryRes>> {
-    if !transaction.ids().is_some() && !transaction.has_block_filter() {
+    if !has_transaction_source(transaction) {
         return Err(TransactionResolverErrors::MissingTransactionHashOrFilter.into());
     }
+    if transaction.scans_latest_blocks() {
+        return resolve_latest_transactions(
+            transaction,
+            chains,
+            max_concurrency,
+            DEFAULT_SCAN_DEPTH,
+            None,
+            ens,
+        )
+        .await;
+    }
+    let transaction = &resolve_filter_names(transaction, ens, max_concurrency).await?;
+
+    let chain_futures = chains.iter().map(|chain| {
+        resolve_chain_transactions(transaction, chain, max_concurrency, max_block_range)
+    });
+    let chain_results = try_join_all(chain_futures).await?;
 
-    let mut all_results = Vec::new();
+    Ok(chain_results.concat())
+}
+
+/// Resolves the query like `resolve_transaction_query`, `chunk_size` blocks at a time, yielding
+/// the transactions of each chunk once it's resolved. Only the transactions of a chunk are held in
+/// memory, so block ranges aren't capped by a maximum size.
+///
+/// The chains are resolved one after the other, and the tags of a range are resolved once per
+/// chain, so the chunks don't shift while new blocks are produced. Queries by hash or by a single
+/// block are resolved as a single chunk.
+///
+/// Queries searching the latest blocks for the transactions of an address stream the latest
+/// `scan_depth` blocks, from the latest transactions to the oldest.
+pub fn stream_transaction_query(
+    transaction: Transaction,
+    chains: Vec<ChainOrRpc>,
+    chunk_size: u64,
+    max_concurrency: usize,
+    scan_depth: u64,
+    ens: EnsConfig,
+) -> BoxStream<'static, Result<Vec<TransactionQueryRes>>> {
+    let chunks = async move {
+        if !has_transaction_source(&transaction) {
+            return Err(TransactionResolverErrors::MissingTransactionHashOrFilter.into());
+        }
+        let transaction = resolve_filter_names(&transaction, &ens, max_concurrency).await?;
+        let latest_first = transaction.scans_latest_blocks();
 
-    for chain in chains {
-        let provider = Arc::new(ProviderBuilder::new().on_http(chain.rpc_url()?));
+        // The chunks of a chain are only split once its previous chains are resolved
+        let chunks = stream::iter(chains)
+            .then(move |chain| {
+                let transaction = transaction.clone();
+                async move {
+                    let query = match latest_first {
+                        true => latest_blocks(&transaction, &chain, scan_depth).await?,
+                        false => Some(transaction),
+                    };
+                    let chunks = match query {
+                        Some(query) => {
+                            split_block_range(&query, &chain, chunk_size, latest_first).await?
+                        }
+                        None => stream::empty().boxed(),
+                    };
+                    Ok(chunks.map(move |chunk| Ok((chunk, chain.clone()))))
+                }
+            })
+            .try_flatten();
 
-        // Fetch transactions for this chain
-        let rpc_transactions = match transaction.ids() {
-            Some(ids) => get_transactions_by_ids(ids, &provider).await?,
-            None => {
-                let block_id = transaction.get_block_id_filter()?;
-                get_transactions_by_block_id(block_id, &provider).await?
+        Ok(chunks.and_then(move |(chunk, chain)| async move {
+            let mut transactions =
+                resolve_chain_transactions(&chunk, &chain, max_concurrency, u64::MAX).await?;
+            if latest_first {
+                transactions.reverse();
             }
+            Ok(transactions)
+        }))
+    };
+
+    stream::once(chunks).try_flatten().boxed()
+}
+
+/// Resolves a query filtered by `from` or `to` without a hash, block or nonce to fetch its
+/// transactions by. RPCs can't look transactions up by address, so the blocks are searched back from
+/// the latest one, `max_concurrency` blocks at a time, until `limit` transactions are found or
+/// `scan_depth` blocks are searched.
+///
+/// The transactions of each chain are returned from the latest to the oldest.
+pub async fn resolve_latest_transactions(
+    transaction: &Transaction,
+    chains: &[ChainOrRpc],
+    max_concurrency: usize,
+    scan_depth: u64,
+    limit: Option<usize>,
+    ens: &EnsConfig,
+) -> Result<Vec<TransactionQueryRes>> {
+    let transaction = &resolve_filter_names(transaction, ens, max_concurrency).await?;
+
+    let chain_futures = chains.iter().map(|chain| {
+        scan_chain_transactions(transaction, chain, max_concurrency, scan_depth, limit)
+    });
+    let chain_results = try_join_all(chain_futures).await?;
+
+    Ok(chain_results.concat())
+}
+
+async fn scan_chain_transactions(
+    transaction: &Transaction,
+    chain: &ChainOrRpc,
+    max_concurrency: usize,
+    scan_depth: u64,
+    limit: Option<usize>,
+) -> Result<Vec<TransactionQueryRes>> {
+    if scan_depth == 0 {
+        return Ok(vec![]);
+    }
+    let provider = chain.provider().await?;
+    let latest = with_retry(&retry::current(), || async {
+        provider.get_block_number().await
+    })
+    .await?;
+    let floor = latest.saturating_sub(scan_depth - 1);
+    let batch_size = max_concurrency.max(1) as u64;
+
+    let mut results = vec![];
+    let mut end = latest;
+    loop {
+        let start = end.saturating_sub(batch_size - 1).max(floor);
+        let mut batch = transaction.clone();
+        batch.set_block_id(BlockId::Range(BlockRange::new(
+            start.into(),
+            Some(end.into()),
+        )));
+
+        // Resolved in block order, so the batch is reversed to keep the latest transactions first
+        let transactions =
+            resolve_chain_transactions(&batch, chain, max_concurrency, u64::MAX).await?;
+        results.extend(transactions.into_iter().rev());
+
+        if let Some(limit) = limit.filter(|limit| results.len() >= *limit) {
+            results.truncate(limit);
+            break;
+        }
+        if start == floor {
+            break;
+        }
+        end = start - 1;
+    }
+
+    Ok(results)
+}
+
+/// Whether the query says where to fetch its transactions from: their hashes, a block filter, the
+/// sender and nonce of a transaction, or an address to search the latest blocks for.
+fn has_transaction_source(transaction: &Transaction) -> bool {
+    transaction.ids().is_some()
+        || transaction.has_block_filter()
+        || transaction.sender_and_nonce().is_some()
+        || transaction.scans_latest_blocks()
+}
+
+/// The query over the latest `scan_depth` blocks of the chain, or `None` when no block is searched.
+async fn latest_blocks(
+    transaction: &Transaction,
+    chain: &ChainOrRpc,
+    scan_depth: u64,
+) -> Result<Option<Transaction>> {
+    if scan_depth == 0 {
+        return Ok(None);
+    }
+    let provider = chain.provider().await?;
+    let latest = with_retry(&retry::current(), || async {
+        provider.get_block_number().await
+    })
+    .await?;
+
+    let mut scan = transaction.clone();
+    scan.set_block_id(BlockId::Range(BlockRange::new(
+        latest.saturating_sub(scan_depth - 1).into(),
+        Some(latest.into()),
+    )));
+    Ok(Some(scan))
+}
+
+/// Splits the query into queries over `chunk_size` blocks of its block range, generated as they're
+/// polled so a long range isn't split all at once. With `latest_first`, the chunks go from the end
+/// of the range to its start.
+async fn split_block_range(
+    transaction: &Transaction,
+    chain: &ChainOrRpc,
+    chunk_size: u64,
+    latest_first: bool,
+) -> Result<BoxStream<'static, Transaction>> {
+    let range = match transaction.ids() {
+        Some(_) => None,
+        // Queries by sender and nonce have no block filter
+        None => match transaction.get_block_id_filter().ok() {
+            Some(BlockId::Range(range)) => Some(range),
+            _ => None,
+        },
+    };
+    // The pending block can't be resolved to a block number, the resolver handles it
+    let Some(range) = range.filter(|range| {
+        range.start() != BlockNumberOrTag::Pending && range.end() != Some(BlockNumberOrTag::Pending)
+    }) else {
+        return Ok(stream::iter([transaction.clone()]).boxed());
+    };
+
+    let provider = Arc::new(chain.provider().await?);
+    let (start, end) = range.resolve_bounds(&provider).await?;
+    let chunk_size = chunk_size.max(1);
+    let chunks = (end - start) / chunk_size + 1;
+    let transaction = transaction.clone();
+
+    let chunk = move |index: u64| {
+        let index = match latest_first {
+            true => chunks - 1 - index,
+            false => index,
         };
+        let chunk_start = start + index * chunk_size;
+        let chunk_end = chunk_start.saturating_add(chunk_size - 1).min(end);
+        let mut chunk = transaction.clone();
+        chunk.set_block_id(BlockId::Range(BlockRange::new(
+            chunk_start.into(),
+            Some(chunk_end.into()),
+        )));
+        chunk
+    };
 
-        let result_futures = rpc_transactions
-            .iter()
-            .map(|t| pick_transaction_fields(t, transaction.fields(), &provider, chain));
-        let tx_res = try_join_all(result_futures).await?;
+    Ok(stream::iter((0..chunks).map(chunk)).boxed())
+}
 
-        // Filter and collect results for this chain
-        let filtered_tx_res: Vec<TransactionQueryRes> = tx_res
-            .into_iter()
-            .filter(|t| transaction.filter(t))
-            .collect();
+/// Resolves the ENS names of the `from` and `to` filters. The filters are shared by all the chains,
+/// so each name is resolved a single time for the whole query.
+async fn resolve_filter_names(
+    transaction: &Transaction,
+    ens: &EnsConfig,
+    max_concurrency: usize,
+) -> Result<Transaction> {
+    let names = transaction.ens_names();
+    if names.is_empty() {
+        return Ok(transaction.clone());
+    }
+
+    let registry = ens.connect().await?;
+    let addresses = resolve_names(names, &registry, max_concurrency)
+        .await
+        .map_err(|e| TransactionResolverErrors::EnsResolution(e.name, e.source.to_string()))?;
+
+    let mut transaction = transaction.clone();
+    transaction.resolve_names(&addresses);
+    Ok(transaction)
+}
+
+async fn resolve_chain_transactions(
+    transaction: &Transaction,
+    chain: &ChainOrRpc,
+    max_concurrency: usize,
+    max_block_range: u64,
+) -> Result<Vec<TransactionQueryRes>> {
+    let semaphore = Semaphore::new(max_concurrency.max(1));
+    let provider = Arc::new(chain.provider().await?);
+    let chain = chain.to_chain().await?;
 
-        all_results.extend(filtered_tx_res);
+    // The method id filter is applied to the selector, so it's picked even if it wasn't queried
+    let mut fields = transaction.fields().clone();
+    let hide_method_id =
+        transaction.has_method_id_filter() && !fields.contains(&TransactionField::MethodId);
+    if hide_method_id {
+        fields.push(TransactionField::MethodId);
+    }
+    // Same for the nonce filter
+    let hide_nonce = transaction.has_nonce_filter() && !fields.contains(&TransactionField::Nonce);
+    if hide_nonce {
+        fields.push(TransactionField::Nonce);
+    }
+    // And the sender and recipient filters
+    let hide_from = transaction.has_from_filter() && !fields.contains(&TransactionField::From);
+    if hide_from {
+        fields.push(TransactionField::From);
+    }
+    let hide_to = transaction.has_to_filter() && !fields.contains(&TransactionField::To);
+    if hide_to {
+        fields.push(TransactionField::To);
     }
+    // And the status filter, which fetches the receipt for it
+    let hide_status =
+        transaction.has_status_filter() && !fields.contains(&TransactionField::Status);
+    if hide_status {
+        fields.push(TransactionField::Status);
+    }
+
+    // Base fees of the blocks fetched for their transactions, reused for the effective tip
+    let mut fetched_base_fees = HashMap::new();
+    let rpc_transactions = match transaction.ids() {
+        Some(ids) => {
+            let transactions = get_transactions_by_ids(ids, &provider, &semaphore).await?;
+            match transaction.get_block_id_filter().ok() {
+                Some(block_id) => filter_by_block_id(transactions, block_id, &provider).await?,
+                None => transactions,
+            }
+        }
+        None if !transaction.has_block_filter() => {
+            let Some((sender, nonce)) = transaction.sender_and_nonce() else {
+                return Err(TransactionResolverErrors::MissingTransactionHashOrFilter.into());
+            };
+            let sender = match sender {
+                NameOrAddress::Address(address) => *address,
+                NameOrAddress::Name(name) => {
+                    return Err(TransactionResolverErrors::UnresolvedSender(name.clone()).into())
+                }
+            };
+            get_transaction_by_sender_and_nonce(sender, nonce, &provider)
+                .await?
+                .into_iter()
+                .collect()
+        }
+        None => {
+            let block_id = transaction.get_block_id_filter()?;
+            let blocks = get_transactions_by_block_id(
+                block_id,
+                &provider,
+                &semaphore,
+                max_block_range,
+                transaction.needs_bodies(),
+            )
+            .await?;
+            fetched_base_fees = blocks.base_fees;
+            blocks.transactions
+        }
+    };
+
+    let picks_tip = fields.contains(&TransactionField::EffectiveTip);
+    let base_fees = if picks_tip {
+        get_base_fees(&rpc_transactions, fetched_base_fees, &provider, &semaphore).await?
+    } else {
+        HashMap::new()
+    };
+    let picks_percentile = fields.contains(&TransactionField::GasPricePercentile);
+    let fee_distributions = match picks_percentile {
+        // Whole blocks were fetched, so every transaction of the blocks is already at hand
+        true if transaction.reads_whole_blocks() => fee_distributions(&rpc_transactions),
+        true => get_fee_distributions(&rpc_transactions, &provider, &semaphore).await?,
+        false => HashMap::new(),
+    };
+    // Read once for all the transactions, so they're counted against the same block
+    let latest = match fields.contains(&TransactionField::Confirmations) {
+        true => Some(
+            with_retry(&retry::current(), || async {
+                provider.get_block_number().await
+            })
+            .await?,
+        ),
+        false => None,
+    };
 
-    Ok(all_results)
+    // Borrowed by the futures, which own the reference to their transaction
+    let (fields, provider, chain) = (&fields, &provider, &chain);
+    let (semaphore, base_fees, fee_distributions) = (&semaphore, &base_fees, &fee_distributions);
+    let result_futures = rpc_transactions.iter().map(|t| async move {
+        let _permit = semaphore.acquire().await?;
+        let mut result = pick_transaction_fields(t, fields, provider, chain).await?;
+        if picks_tip {
+            result.effective_tip = t
+                .block_number
+                .and_then(|n| base_fees.get(&n))
+                .and_then(|base_fee| effective_tip(t, *base_fee));
+        }
+        if picks_percentile {
+            result.gas_price_percentile = t
+                .block_number
+                .and_then(|n| fee_distributions.get(&n))
+                .and_then(|distribution| gas_price_percentile(t, distribution));
+        }
+        if let Some(latest) = latest {
+            result.confirmations = t.block_number.map(|n| latest.saturating_sub(n));
+        }
+        Ok((t, result))
+    });
+    // Collected first, so the futures of `stream_transaction_query` are `Send`
+    let tx_res = join_items(result_futures.collect::<Vec<_>>()).await?;
+
+    Ok(tx_res
+        .into_iter()
+        .filter(|(_, t)| transaction.filter(t))
+        .map(|(rpc_transaction, mut t)| {
+            raw_items::record_transaction(rpc_transaction);
+            if hide_method_id {
+                t.method_id = None;
+            }
+            if hide_nonce {
+                t.nonce = None;
+            }
+            if hide_from {
+                t.from = None;
+            }
+            if hide_to {
+                t.to = None;
+            }
+            if hide_status {
+                t.status = None;
+            }
+            t
+        })
+        .collect())
 }
 
 async fn get_transactions_by_ids(
     ids: &Vec<FixedBytes<32>>,
-    provider: &RootProvider<Http<Client>>,
+    provider: &RootProvider<BoxTransport>,
+    semaphore: &Semaphore,
 ) -> Result<Vec<RpcTransaction>> {
     let mut tx_futures = Vec::new();
     for id in ids {
         let provider = provider.clone();
-        let tx_future = async move { provider.get_transaction_by_hash(*id).await };
+        let tx_future = async move {
+            let _permit = semaphore.acquire().await?;
+            let tx = with_retry(&retry::current(), || async {
+                provider.get_transaction_by_hash(*id).await
+            })
+            .await
+            .map_err(|e| TransactionResolverErrors::Rpc(*id, e.to_string()))?;
+            Ok(tx)
+        };
 
         tx_futures.push(tx_future);
     }
 
-    let tx_res = try_join_all(tx_futures).await?;
+    let tx_res = join_items(tx_futures).await?;
 
     Ok(tx_res.into_iter().filter_map(|t| t).collect())
 }
 
-async fn get_transactions_by_block_id(
+/// Fetches the transaction of `sender` with `nonce`. There's no standard method for it, so it's only
+/// served by the RPCs implementing `eth_getTransactionBySenderAndNonce`, like Reth.
+async fn get_transaction_by_sender_and_nonce(
+    sender: Address,
+    nonce: u64,
+    provider: &RootProvider<BoxTransport>,
+) -> Result<Option<RpcTransaction>> {
+    let tx = with_retry(&retry::current(), || async {
+        provider
+            .raw_request(
+                "eth_getTransactionBySenderAndNonce".into(),
+                (sender, U64::from(nonce)),
+            )
+            .await
+    })
+    .await
+    .map_err(|e| {
+        TransactionResolverErrors::SenderAndNonceUnavailable(sender, nonce, e.to_string())
+    })?;
+
+    Ok(tx)
+}
+
+/// Keeps the transactions included in the blocks of the filter, for queries by hash that are also
+/// filtered by block. The tags of the filter are resolved on the chain, and the `pending` tag keeps
+/// the transactions that aren't included in a block yet.
+async fn filter_by_block_id(
+    mut transactions: Vec<RpcTransaction>,
     block_id: &BlockId,
-    provider: &Arc<RootProvider<Http<Client>>>,
+    provider: &Arc<RootProvider<BoxTransport>>,
 ) -> Result<Vec<RpcTransaction>> {
-    match block_id {
-        BlockId::Number(n) => {
-            let block = get_block(n.clone(), provider.clone(), true).await?;
-            match &block.transactions {
-                BlockTransactions::Full(txs) => Ok(txs.clone()),
-                _ => panic!("Block transactions should be full"),
-            }
+    let range = match block_id {
+        BlockId::Hash(hash) => {
+            transactions.retain(|tx| tx.block_hash == Some(*hash));
+            return Ok(transactions);
         }
-        BlockId::Range(r) => {
-            let block_numbers = r.resolve_block_numbers(provider).await?;
-            let blocks = batch_get_blocks(block_numbers, provider, true).await?;
-            let txs = blocks
-                .iter()
-                .flat_map(|b| match &b.transactions {
-                    BlockTransactions::Full(txs) => txs.clone(),
-                    _ => panic!("Block transactions should be full"),
-                })
-                .collect::<Vec<_>>();
+        BlockId::Number(BlockNumberOrTag::Pending) => {
+            transactions.retain(|tx| tx.block_number.is_none());
+            return Ok(transactions);
+        }
+        BlockId::Number(n) => BlockRange::new(*n, None),
+        BlockId::Range(range) => range.clone(),
+    };
+
+    if transactions.is_empty() {
+        return Ok(transactions);
+    }
+
+    let (start, end) = range.resolve_bounds(provider).await?;
+    transactions.retain(|tx| tx.block_number.is_some_and(|n| (start..=end).contains(&n)));
+
+    Ok(transactions)
+}
+
+/// Fetches the transactions of the blocks. Without `hydrate`, the blocks are fetched with only the
+/// hashes of their transactions, and the transactions only hold their hash and position.
+async fn get_transactions_by_block_id(
+    block_id: &BlockId,
+    provider: &Arc<RootProvider<BoxTransport>>,
+    semaphore: &Semaphore,
+    max_block_range: u64,
+    hydrate: bool,
+) -> Result<FetchedBlocks> {
+    let block_numbers = match block_id {
+        BlockId::Number(BlockNumberOrTag::Pending) => {
+            return get_pending_transactions(provider, semaphore, hydrate).await
+        }
+        BlockId::Number(n) => vec![*n],
+        BlockId::Hash(hash) => {
+            let block = {
+                let _permit = semaphore.acquire().await?;
+                get_block_by_hash(*hash, provider.clone(), hydrate).await?
+            };
+            return FetchedBlocks::of(&block, provider, semaphore, hydrate).await;
+        }
+        BlockId::Range(r) => match r.range() {
+            // The pending block isn't mined yet, so it can't be resolved to a block number
+            (BlockNumberOrTag::Pending, None) => {
+                return get_pending_transactions(provider, semaphore, hydrate).await
+            }
+            (BlockNumberOrTag::Pending, _) | (_, Some(BlockNumberOrTag::Pending)) => {
+                return Err(TransactionResolverErrors::PendingBlockInRange.into())
+            }
+            _ => r
+                .resolve_block_numbers(provider, max_block_range)
+                .await?
+                .into_iter()
+                .map(BlockNumberOrTag::Number)
+                .collect(),
+        },
+    };
+    let block_txs_futures = block_numbers.into_iter().map(|n| async move {
+        let block = {
+            let _permit = semaphore.acquire().await?;
+            get_block(n, provider.clone(), hydrate).await?
+        };
+        FetchedBlocks::of(&block, provider, semaphore, hydrate).await
+    });
+    let blocks = try_join_all(block_txs_futures).await?;
+
+    Ok(FetchedBlocks {
+        transactions: blocks
+            .iter()
+            .flat_map(|block| block.transactions.clone())
+            .collect(),
+        base_fees: blocks
+            .into_iter()
+            .flat_map(|block| block.base_fees)
+            .collect(),
+    })
+}
+
+/// Returns the transactions of the pending block, or an empty list if the RPC doesn't have one.
+async fn get_pending_transactions(
+    provider: &Arc<RootProvider<BoxTransport>>,
+    semaphore: &Semaphore,
+    hydrate: bool,
+) -> Result<FetchedBlocks> {
+    let block = {
+        let _permit = semaphore.acquire().await?;
+        with_retry(&retry::current(), || async {
+            provider
+                .get_block_by_number(BlockNumberOrTag::Pending, hydrate)
+                .await
+        })
+        .await
+        .map_err(|e| TransactionResolverErrors::PendingBlockUnavailable(e.to_string()))?
+    };
+
+    match block {
+        Some(block) => FetchedBlocks::of(&block, provider, semaphore, hydrate).await,
+        None => Ok(FetchedBlocks::default()),
+    }
+}
+
+/// Transactions of the fetched blocks, along with the base fee of each block, so the effective
+/// tip doesn't fetch the blocks again. Blocks produced before London have no base fee.
+#[derive(Debug, Default)]
+struct FetchedBlocks {
+    transactions: Vec<RpcTransaction>,
+    base_fees: HashMap<u64, Option<u128>>,
+}
+
+impl FetchedBlocks {
+    async fn of(
+        block: &RpcBlock,
+        provider: &Arc<RootProvider<BoxTransport>>,
+        semaphore: &Semaphore,
+        hydrate: bool,
+    ) -> Result<FetchedBlocks> {
+        Ok(FetchedBlocks {
+            transactions: get_block_transactions(block, provider, semaphore, hydrate).await?,
+            // The pending block has no number yet
+            base_fees: block
+                .header
+                .number
+                .map(|n| (n, block.header.base_fee_per_gas))
+                .into_iter()
+                .collect(),
+        })
+    }
+}
 
-            Ok(txs)
+/// Returns the full transaction bodies of a block fetched with `hydrate = true`.
+/// Some providers ignore the hydrate flag and only return the transaction hashes,
+/// in which case each transaction is fetched individually by its hash.
+///
+/// Blocks fetched without `hydrate` only list the hashes, which are returned as transactions
+/// holding their hash and their position in the block.
+async fn get_block_transactions(
+    block: &RpcBlock,
+    provider: &RootProvider<BoxTransport>,
+    semaphore: &Semaphore,
+    hydrate: bool,
+) -> Result<Vec<RpcTransaction>> {
+    match &block.transactions {
+        BlockTransactions::Full(txs) => Ok(txs.clone()),
+        BlockTransactions::Hashes(hashes) if !hydrate => Ok(hashes
+            .iter()
+            .enumerate()
+            .map(|(index, hash)| RpcTransaction {
+                hash: *hash,
+                // Like the transactions of the pending block, which has no hash yet
+                block_hash: block.header.hash,
+                block_number: block.header.hash.and(block.header.number),
+                transaction_index: block.header.hash.map(|_| index as u64),
+                ..Default::default()
+            })
+            .collect()),
+        BlockTransactions::Hashes(hashes) => {
+            get_transactions_by_ids(hashes, provider, semaphore).await
         }
+        BlockTransactions::Uncle => Err(TransactionResolverErrors::ExpectedFullTransactions.into()),
     }
 }
 
+/// Receipt as served by OP-stack chains like Optimism and Base, which also charge for posting the
+/// transaction data to L1. The L1 fields are missing from the receipts of other chains.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct L2Receipt {
+    #[serde(flatten)]
+    receipt: TransactionReceipt,
+    l1_fee: Option<U256>,
+    l1_gas_used: Option<U128>,
+    l1_gas_price: Option<U128>,
+    l1_fee_scalar: Option<String>,
+}
+
 async fn pick_transaction_fields(
     tx: &RpcTransaction,
-    fields: &Vec<TransactionField>,
-    provider: &Arc<RootProvider<Http<Client>>>,
-    chain: &ChainOrRpc,
+    fields: &[TransactionField],
+    provider: &Arc<RootProvider<BoxTransport>>,
+    chain: &Chain,
 ) -> Result<TransactionQueryRes> {
+    // The receipt is fetched once and shared by all the receipt fields
+    let needs_receipt = fields.iter().any(|field| field.is_receipt_field());
+    let receipt: Option<L2Receipt> = if needs_receipt {
+        with_retry(&retry::current(), || async {
+            provider
+                .raw_request("eth_getTransactionReceipt".into(), (tx.hash,))
+                .await
+        })
+        .await
+        .map_err(|e| TransactionResolverErrors::Rpc(tx.hash, e.to_string()))?
+    } else {
+        None
+    };
+    let trace = if fields.contains(&TransactionField::Trace) {
+        Some(get_trace(tx.hash, provider).await?)
+    } else {
+        None
+    };
+
+    let revert_reason = match (fields.contains(&TransactionField::RevertReason), &receipt) {
+        (true, Some(receipt)) if !receipt.receipt.status() => {
+            get_revert_reason(tx, provider).await?
+        }
+        _ => None,
+    };
+
+    let mut result = filter_fields(tx, receipt.as_ref().map(|r| &r.receipt), fields, chain);
+    result.trace = trace;
+    result.revert_reason = revert_reason;
+    if let Some(receipt) = &receipt {
+        pick_l1_fields(&mut result, receipt, fields);
+    }
+    Ok(result)
+}
+
+fn pick_l1_fields(
+    result: &mut TransactionQueryRes,
+    receipt: &L2Receipt,
+    fields: &[TransactionField],
+) {
+    for field in fields {
+        match field {
+            TransactionField::L1Fee => {
+                result.l1_fee = receipt.l1_fee;
+            }
+            TransactionField::L1GasUsed => {
+                result.l1_gas_used = receipt.l1_gas_used.map(|gas| gas.to());
+            }
+            TransactionField::L1GasPrice => {
+                result.l1_gas_price = receipt.l1_gas_price.map(|price| price.to());
+            }
+            TransactionField::L1FeeScalar => {
+                result.l1_fee_scalar = receipt.l1_fee_scalar.clone();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Fetches the base fee of every block the transactions were included in, once per block, unless
+/// the block was already fetched. Blocks produced before London have no base fee, and are left
+/// out.
+async fn get_base_fees(
+    transactions: &[RpcTransaction],
+    fetched: HashMap<u64, Option<u128>>,
+    provider: &Arc<RootProvider<BoxTransport>>,
+    semaphore: &Semaphore,
+) -> Result<HashMap<u64, u128>> {
+    let block_numbers: HashSet<u64> = transactions
+        .iter()
+        .filter_map(|tx| tx.block_number)
+        .filter(|n| !fetched.contains_key(n))
+        .collect();
+    let base_fee_futures = block_numbers.into_iter().map(|n| async move {
+        let _permit = semaphore.acquire().await?;
+        let block = get_block(BlockNumberOrTag::Number(n), provider.clone(), false).await?;
+        Ok((n, block.header.base_fee_per_gas))
+    });
+    let base_fees = join_items(base_fee_futures).await?;
+
+    Ok(base_fees
+        .into_iter()
+        .chain(fetched)
+        .filter_map(|(n, base_fee)| Some((n, base_fee?)))
+        .collect())
+}
+
+/// Fetches every transaction of the blocks the transactions were included in, once per block, and
+/// returns the sorted gas prices of each block.
+async fn get_fee_distributions(
+    transactions: &[RpcTransaction],
+    provider: &Arc<RootProvider<BoxTransport>>,
+    semaphore: &Semaphore,
+) -> Result<HashMap<u64, Vec<u128>>> {
+    let block_numbers: HashSet<u64> = transactions
+        .iter()
+        .filter_map(|tx| tx.block_number)
+        .collect();
+    let block_txs_futures = block_numbers.into_iter().map(|n| async move {
+        let block = {
+            let _permit = semaphore.acquire().await?;
+            get_block(BlockNumberOrTag::Number(n), provider.clone(), true).await?
+        };
+        get_block_transactions(&block, provider, semaphore, true).await
+    });
+    let block_txs = try_join_all(block_txs_futures).await?.concat();
+
+    Ok(fee_distributions(&block_txs))
+}
+
+/// Sorted gas prices of the transactions, grouped by block. For mined transactions, the gas price
+/// is the price they effectively paid.
+fn fee_distributions(transactions: &[RpcTransaction]) -> HashMap<u64, Vec<u128>> {
+    let mut distributions: HashMap<u64, Vec<u128>> = HashMap::new();
+    for tx in transactions {
+        if let (Some(n), Some(gas_price)) = (tx.block_number, tx.gas_price) {
+            distributions.entry(n).or_default().push(gas_price);
+        }
+    }
+    for distribution in distributions.values_mut() {
+        distribution.sort_unstable();
+    }
+
+    distributions
+}
+
+/// Percentage of the block's transactions paying a strictly lower gas price than `tx`, rounded
+/// down. The cheapest transaction of a block is at 0.
+fn gas_price_percentile(tx: &RpcTransaction, distribution: &[u128]) -> Option<u8> {
+    let gas_price = tx.gas_price?;
+    if distribution.is_empty() {
+        return None;
+    }
+    let cheaper = distribution.partition_point(|price| *price < gas_price);
+
+    Some((cheaper * 100 / distribution.len()) as u8)
+}
+
+/// Priority fee per gas paid to the block producer once the base fee is burnt. EIP-1559
+/// transactions pay `min(max_priority_fee_per_gas, max_fee_per_gas - base_fee)`, and the ones
+/// paying a gas price pay `gas_price - base_fee`.
+fn effective_tip(tx: &RpcTransaction, base_fee: u128) -> Option<u128> {
+    match (tx.max_fee_per_gas, tx.max_priority_fee_per_gas) {
+        (Some(max_fee), Some(max_priority_fee)) => {
+            Some(max_priority_fee.min(max_fee.saturating_sub(base_fee)))
+        }
+        _ => tx
+            .gas_price
+            .map(|gas_price| gas_price.saturating_sub(base_fee)),
+    }
+}
+
+/// Traces the calls made by the transaction with the `callTracer` of `debug_traceTransaction`.
+async fn get_trace(
+    hash: FixedBytes<32>,
+    provider: &RootProvider<BoxTransport>,
+) -> Result<CallFrame> {
+    let trace = with_retry(&retry::current(), || async {
+        provider
+            .raw_request(
+                "debug_traceTransaction".into(),
+                (hash, json!({ "tracer": "callTracer" })),
+            )
+            .await
+    })
+    .await
+    .map_err(|e| TransactionResolverErrors::TraceUnavailable(hash, e.to_string()))?;
+
+    Ok(trace)
+}
+
+/// Replays a failed transaction with `eth_call` against the state of its block to read why it
+/// reverted. The state includes the transactions after it in the block, which rarely changes the
+/// outcome. `None` for reverts without a reason, and if the replay doesn't revert.
+async fn get_revert_reason(
+    tx: &RpcTransaction,
+    provider: &RootProvider<BoxTransport>,
+) -> Result<Option<String>> {
+    let Some(block_number) = tx.block_number else {
+        return Ok(None);
+    };
+    let request = TransactionRequest {
+        from: Some(tx.from),
+        to: Some(tx.to.map_or(TxKind::Create, TxKind::Call)),
+        value: Some(tx.value),
+        input: tx.input.clone().into(),
+        gas: Some(tx.gas),
+        ..Default::default()
+    };
+
+    let Err(error) = with_retry(&retry::current(), || async {
+        provider.call(&request).block(block_number.into()).await
+    })
+    .await
+    else {
+        return Ok(None);
+    };
+
+    match error {
+        RpcError::ErrorResp(payload) if payload.message.contains("revert") => Ok(payload
+            .try_data_as::<Bytes>()
+            .and_then(|data| data.ok())
+            .and_then(|data| decode_revert_reason(&data))),
+        error => Err(TransactionResolverErrors::RevertReasonUnavailable(
+            tx.hash,
+            error.to_string(),
+        )
+        .into()),
+    }
+}
+
+/// Message of an `Error(string)` revert, or the kind of a `Panic(uint256)`. Custom errors can't
+/// be decoded without the ABI of the contract, so their data is returned in hex.
+fn decode_revert_reason(data: &[u8]) -> Option<String> {
+    if data.is_empty() {
+        return None;
+    }
+
+    match GenericContractError::abi_decode(data, true) {
+        Result::Ok(ContractError::Revert(revert)) => Some(revert.reason),
+        Result::Ok(ContractError::Panic(panic)) => Some(panic.to_string()),
+        _ => Some(hex::encode_prefixed(data)),
+    }
+}
+
+fn filter_fields(
+    tx: &RpcTransaction,
+    receipt: Option<&TransactionReceipt>,
+    fields: &[TransactionField],
+    chain: &Chain,
+) -> TransactionQueryRes {
     let mut result = TransactionQueryRes::default();
-    let chain = chain.to_chain().await?;
 
     for field in fields {
         match field {
             TransactionField::TransactionType => {
                 result.transaction_type = tx.transaction_type;
             }
+            TransactionField::TxTypeLabel => {
+                // Legacy transactions predate the type, and some RPCs still serve them without it
+                result.tx_type_label =
+                    tx_type_label(tx.transaction_type.unwrap_or(0)).map(ToString::to_string);
+            }
             TransactionField::Hash => {
                 result.hash = Some(tx.hash);
             }
+            TransactionField::TransactionIndex => {
+                // Pending transactions aren't in a block yet
+                result.transaction_index = tx.transaction_index;
+            }
+            TransactionField::BlockNumber => {
+                result.block_number = tx.block_number;
+            }
+            TransactionField::BlockHash => {
+                result.block_hash = tx.block_hash;
+            }
             TransactionField::From => {
                 result.from = Some(tx.from);
             }
             TransactionField::To => {
                 result.to = tx.to;
             }
+            TransactionField::Nonce => {
+                result.nonce = Some(tx.nonce);
+            }
             TransactionField::Data => {
                 result.data = Some(tx.input.clone());
             }
+            TransactionField::MethodId => {
+                result.method_id = method_id(&tx.input);
+            }
             TransactionField::Value => {
                 result.value = Some(tx.value);
             }
@@ -152,14 +1025,9 @@ async fn pick_transaction_fields(
             TransactionField::Gas => {
                 result.gas = Some(tx.gas);
             }
-            TransactionField::Status => match provider.get_transaction_receipt(tx.hash).await? {
-                Some(receipt) => {
-                    result.status = Some(receipt.status());
-                }
-                None => {
-                    result.status = None;
-                }
-            },
+            TransactionField::Status => {
+                result.status = receipt.map(|r| r.status());
+            }
             TransactionField::ChainId => {
                 result.chain_id = tx.chain_id;
             }
@@ -175,80 +1043,2298 @@ async fn pick_transaction_fields(
             TransactionField::MaxFeePerBlobGas => {
                 result.max_fee_per_blob_gas = tx.max_fee_per_blob_gas;
             }
+            TransactionField::BlobVersionedHashes => {
+                result.blob_versioned_hashes = tx.blob_versioned_hashes.clone();
+            }
+            TransactionField::BlobCount => {
+                result.blob_count = Some(tx.blob_versioned_hashes.as_ref().map_or(0, Vec::len));
+            }
             TransactionField::MaxFeePerGas => {
                 result.max_fee_per_gas = tx.max_fee_per_gas;
             }
             TransactionField::MaxPriorityFeePerGas => {
                 result.max_priority_fee_per_gas = tx.max_priority_fee_per_gas;
             }
+            // The tip depends on the base fee of the block, which is fetched separately
+            TransactionField::EffectiveTip => {}
+            // Same for the percentile, ranked against the other transactions of the block
+            TransactionField::GasPricePercentile => {}
+            // And the confirmations, counted from the latest block
+            TransactionField::Confirmations => {}
+            TransactionField::AccessList => {
+                // Legacy transactions don't have an access list
+                result.access_list = tx.access_list.clone();
+            }
             TransactionField::YParity => {
                 result.y_parity = tx
                     .signature
                     .map_or(None, |s| s.y_parity)
                     .map_or(None, |y| Some(y.0));
             }
+            TransactionField::AuthorizationList => {
+                // Only set-code transactions have an authorization list
+                result.authorization_list = tx.authorization_list.clone();
+            }
+            TransactionField::Raw => {
+                result.raw = raw_transaction(tx);
+            }
+            TransactionField::SignerValid => {
+                result.signer_valid = signer_valid(tx);
+            }
             TransactionField::Chain => {
                 result.chain = Some(chain.clone());
             }
+            TransactionField::GasUsed => {
+                result.gas_used = receipt.map(|r| r.gas_used);
+            }
+            TransactionField::EffectiveGasPrice => {
+                result.effective_gas_price = receipt.map(|r| r.effective_gas_price);
+            }
+            TransactionField::CumulativeGasUsed => {
+                result.cumulative_gas_used = receipt.map(|r| r.inner.cumulative_gas_used());
+            }
+            TransactionField::LogsBloom => {
+                result.logs_bloom = receipt.map(|r| *r.inner.logs_bloom());
+            }
+            TransactionField::ContractAddress => {
+                result.contract_address = receipt.and_then(|r| r.contract_address);
+            }
+            TransactionField::Logs => {
+                result.logs = receipt.map(|r| r.inner.logs().to_vec());
+            }
+            // Only served by OP-stack chains, on top of the Ethereum receipt
+            TransactionField::L1Fee
+            | TransactionField::L1GasUsed
+            | TransactionField::L1GasPrice
+            | TransactionField::L1FeeScalar => {}
+            // The trace and the revert reason are fetched separately, since they take their own
+            // requests
+            TransactionField::Trace | TransactionField::RevertReason => {}
         }
     }
 
-    Ok(result)
+    result
+}
+
+/// Returns the function selector of a contract call, which is `None` for plain transfers and
+/// any input too short to hold a selector.
+pub(crate) fn method_id(input: &Bytes) -> Option<FixedBytes<4>> {
+    input.get(..4).map(FixedBytes::from_slice)
+}
+
+/// EIP-2718 encoding of the signed transaction, re-encoded from its fields: the RLP of legacy
+/// transactions, and the type byte followed by the RLP of the others. `None` for the transactions
+/// alloy can't encode, like the deposits of OP-stack chains, and the ones served without a
+/// signature.
+fn raw_transaction(tx: &RpcTransaction) -> Option<Bytes> {
+    let envelope = TxEnvelope::try_from(tx.clone()).ok()?;
+
+    Some(envelope.encoded_2718().into())
+}
+
+/// Whether the transaction was signed by its sender: the signer recovered from the signature must
+/// be `from`, and the hash of the signed fields must be the transaction hash, so an RPC can't serve
+/// a transaction with altered fields. Signatures nothing can be recovered from are invalid. `None`
+/// for the transactions alloy can't encode and the ones served without a signature, as for `raw`.
+fn signer_valid(tx: &RpcTransaction) -> Option<bool> {
+    let envelope = TxEnvelope::try_from(tx.clone()).ok()?;
+    let signed_by_sender = envelope
+        .recover_signer()
+        .is_ok_and(|signer| signer == tx.from);
+
+    Some(signed_by_sender && *envelope.tx_hash() == tx.hash)
+}
+
+/// Name of the EIP-2718 transaction type, `None` for the types that aren't known.
+fn tx_type_label(tx_type: u8) -> Option<&'static str> {
+    match tx_type {
+        0 => Some("legacy"),
+        1 => Some("eip2930"),
+        2 => Some("eip1559"),
+        3 => Some("blob"),
+        4 => Some("setcode"),
+        // Deposits of L1 funds and messages on OP-stack chains
+        126 => Some("deposit"),
+        _ => None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::common::{
-        block::BlockRange,
-        chain::Chain,
-        filters::{ComparisonFilter, EqualityFilter, FilterType},
-        transaction::TransactionFilter,
+    use crate::{
+        common::{
+            block::{BlockRange, BlockRangeError, DEFAULT_MAX_BLOCK_RANGE},
+            chain::Chain,
+            ens::ENS_ADDRESS,
+            filters::{ComparisonFilter, EqualityFilter, FilterType},
+            transaction::TransactionFilter,
+        },
+        interpreter::backend::mock_rpc::{result, revert, MockRpc, MockTransport},
     };
     use alloy::{
-        eips::BlockNumberOrTag,
-        primitives::{address, U256},
+        consensus::{
+            Receipt, ReceiptEnvelope, ReceiptWithBloom, SignableTransaction, TxEip1559, TxEip2930,
+            TxEip4844, TxEip4844Variant, TxLegacy,
+        },
+        eips::{
+            eip2718::Decodable2718,
+            eip2930::{AccessList, AccessListItem},
+            eip7702::Authorization,
+        },
+        primitives::{
+            address, b256, bytes, fixed_bytes, Address, Bloom, Signature, TxKind, B256, U256,
+        },
         providers::ProviderBuilder,
+        sol_types::{Panic, Revert, SolError, SolValue},
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
     };
 
     #[tokio::test]
-    async fn test_get_transactions_by_block_range() {
-        let rpc = Chain::Ethereum.rpc_url().unwrap();
-        let provider = Arc::new(ProviderBuilder::new().on_http(rpc));
-        let block_id = BlockId::Range(BlockRange::new(10000000.into(), Some(10000015.into())));
-        let transactions = get_transactions_by_block_id(&block_id, &provider)
+    async fn test_get_block_transactions_from_hashes_only_block() {
+        // No request should be sent for an empty list of hashes
+        let provider = ProviderBuilder::new()
+            .on_http("http://localhost:8545".parse().unwrap())
+            .boxed();
+        let block = RpcBlock {
+            transactions: BlockTransactions::Hashes(vec![]),
+            ..Default::default()
+        };
+
+        let transactions = get_block_transactions(&block, &provider, &Semaphore::new(1), true)
             .await
             .unwrap();
 
-        assert_eq!(transactions.len(), 2394);
+        assert!(transactions.is_empty());
     }
 
     #[tokio::test]
-    async fn test_get_transactions_by_block_number() {
-        let rpc = Chain::Ethereum.rpc_url().unwrap();
-        let provider = Arc::new(ProviderBuilder::new().on_http(rpc));
-        let block_id = BlockId::Number(BlockNumberOrTag::Number(21036202));
-        let transactions = get_transactions_by_block_id(&block_id, &provider)
-            .await
-            .unwrap();
+    async fn test_error_when_block_transactions_are_not_available() {
+        let provider = ProviderBuilder::new()
+            .on_http("http://localhost:8545".parse().unwrap())
+            .boxed();
+        let block = RpcBlock {
+            transactions: BlockTransactions::Uncle,
+            ..Default::default()
+        };
 
-        assert_eq!(transactions.len(), 177);
-    }
+        let result = get_block_transactions(&block, &provider, &Semaphore::new(1), true).await;
 
-    #[tokio::test]
-    async fn test_resolve_query_using_block_range_filter() {
-        let rpc = Chain::Ethereum.rpc_url().unwrap();
-        let chain = ChainOrRpc::Rpc(rpc);
-        let block_id = BlockId::Range(BlockRange::new(10000000.into(), Some(10000001.into())));
-        let transaction = Transaction::new(
-            None,
-            Some(vec![TransactionFilter::BlockId(block_id)]),
-            TransactionField::all_variants().to_vec(),
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Expected block to contain full transactions"
         );
+    }
 
-        let transactions = resolve_transaction_query(&transaction, &[chain])
-            .await
-            .unwrap();
+    #[test]
+    fn test_pick_receipt_fields_of_contract_creation() {
+        let contract_address = address!("5fbdb2315678afecb367f032d93f642f64180aa3");
+        let tx = RpcTransaction {
+            hash: b256!("4a5f3e8d1b3d6e7c2a9e0f1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f"),
+            to: None,
+            ..Default::default()
+        };
+        let receipt = contract_creation_receipt(tx.hash, contract_address);
+        let fields = vec![
+            TransactionField::To,
+            TransactionField::Status,
+            TransactionField::GasUsed,
+            TransactionField::EffectiveGasPrice,
+            TransactionField::CumulativeGasUsed,
+            TransactionField::LogsBloom,
+            TransactionField::ContractAddress,
+            TransactionField::Logs,
+        ];
+
+        let result = filter_fields(&tx, Some(&receipt), &fields, &Chain::Ethereum);
+
+        assert_eq!(
+            result,
+            TransactionQueryRes {
+                status: Some(true),
+                gas_used: Some(1_200_000),
+                effective_gas_price: Some(10_000_000_000),
+                cumulative_gas_used: Some(1_750_000),
+                logs_bloom: Some(Bloom::ZERO),
+                contract_address: Some(contract_address),
+                logs: Some(vec![]),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_receipt_fields_are_none_without_receipt() {
+        let fields = vec![TransactionField::Status, TransactionField::GasUsed];
+
+        let result = filter_fields(&RpcTransaction::default(), None, &fields, &Chain::Ethereum);
+
+        assert_eq!(result, TransactionQueryRes::default());
+    }
+
+    #[test]
+    fn test_pick_method_id_of_erc20_transfer() {
+        // transfer(0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045, 1000000)
+        let tx = RpcTransaction {
+            input: bytes!(
+                "a9059cbb000000000000000000000000d8da6bf26964af9d7eed9e03e53415d37aa96045\
+                 00000000000000000000000000000000000000000000000000000000000f4240"
+            ),
+            ..Default::default()
+        };
+
+        let result = filter_fields(&tx, None, &[TransactionField::MethodId], &Chain::Ethereum);
+
+        assert_eq!(result.method_id, Some(fixed_bytes!("a9059cbb")));
+        assert_eq!(
+            serde_json::to_value(&result).unwrap()["method_id"],
+            "0xa9059cbb"
+        );
+    }
+
+    #[test]
+    fn test_method_id_is_none_for_plain_transfers() {
+        let fields = [TransactionField::MethodId];
+
+        let result = filter_fields(&RpcTransaction::default(), None, &fields, &Chain::Ethereum);
+
+        assert_eq!(result.method_id, None);
+    }
+
+    #[test]
+    fn test_pick_access_list_of_eip2930_transaction() {
+        let access_list = AccessList(vec![AccessListItem {
+            address: address!("dac17f958d2ee523a2206206994597c13d831ec7"),
+            storage_keys: vec![
+                b256!("0000000000000000000000000000000000000000000000000000000000000002"),
+                b256!("f4ab9c09ec4f1ebc2ed70ac5b0ec8cda4d5a5d77a3d36e29ed8ebac09d5ee7c7"),
+            ],
+        }]);
+        let tx = RpcTransaction {
+            transaction_type: Some(1),
+            access_list: Some(access_list.clone()),
+            ..Default::default()
+        };
+
+        let result = filter_fields(&tx, None, &[TransactionField::AccessList], &Chain::Ethereum);
+
+        assert_eq!(result.access_list, Some(access_list));
+    }
+
+    #[test]
+    fn test_access_list_is_none_for_legacy_transactions() {
+        let tx = RpcTransaction {
+            transaction_type: Some(0),
+            ..Default::default()
+        };
+
+        let result = filter_fields(&tx, None, &[TransactionField::AccessList], &Chain::Ethereum);
+
+        assert_eq!(result.access_list, None);
+    }
+
+    #[test]
+    fn test_pick_authorization_list_of_eip7702_transaction() {
+        let authorization = Authorization {
+            chain_id: 1,
+            address: address!("63c0c19a282a1b52b07dd5a65b58948a07dae32b"),
+            nonce: Some(2).into(),
+        }
+        .into_signed(Signature::from_rs_and_parity(U256::from(1), U256::from(2), false).unwrap());
+        let tx = RpcTransaction {
+            transaction_type: Some(4),
+            authorization_list: Some(vec![authorization.clone()]),
+            ..Default::default()
+        };
+
+        let result = filter_fields(
+            &tx,
+            None,
+            &[TransactionField::AuthorizationList],
+            &Chain::Ethereum,
+        );
+
+        assert_eq!(result.authorization_list, Some(vec![authorization]));
+    }
+
+    /// Transaction as an RPC serves it, with the fields and hash of the signed `envelope`
+    fn served_transaction(envelope: &TxEnvelope) -> RpcTransaction {
+        let mut tx = serde_json::to_value(envelope).unwrap();
+        tx["from"] = json!(Address::ZERO);
+        tx["gas"] = tx["gasLimit"].clone();
+        // The signatures of the tests have an even y parity, which the `v` of legacy transactions
+        // of chain 1 encodes as 37 (EIP-155)
+        tx["v"] = match envelope {
+            TxEnvelope::Legacy(_) => json!("0x25"),
+            _ => tx["yParity"].clone(),
+        };
+
+        serde_json::from_value(tx).unwrap()
+    }
+
+    #[test]
+    fn test_raw_transaction_decodes_to_the_transaction_hash() {
+        let signature = Signature::from_rs_and_parity(U256::from(1), U256::from(2), false).unwrap();
+        let to = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+        let input = bytes!("a9059cbb");
+        let access_list = AccessList(vec![AccessListItem {
+            address: to,
+            storage_keys: vec![B256::with_last_byte(1)],
+        }]);
+        let envelopes = vec![
+            TxEnvelope::Legacy(
+                TxLegacy {
+                    chain_id: Some(1),
+                    nonce: 1,
+                    gas_price: 20_000_000_000,
+                    gas_limit: 21_000,
+                    to: TxKind::Call(to),
+                    value: U256::from(1),
+                    input: input.clone(),
+                }
+                .into_signed(signature),
+            ),
+            TxEnvelope::Eip2930(
+                TxEip2930 {
+                    chain_id: 1,
+                    nonce: 2,
+                    gas_price: 20_000_000_000,
+                    gas_limit: 50_000,
+                    to: TxKind::Call(to),
+                    value: U256::ZERO,
+                    input: input.clone(),
+                    access_list: access_list.clone(),
+                }
+                .into_signed(signature),
+            ),
+            TxEnvelope::Eip1559(
+                TxEip1559 {
+                    chain_id: 1,
+                    nonce: 3,
+                    gas_limit: 50_000,
+                    max_fee_per_gas: 30_000_000_000,
+                    max_priority_fee_per_gas: 1_000_000_000,
+                    to: TxKind::Call(to),
+                    value: U256::ZERO,
+                    input: input.clone(),
+                    access_list: access_list.clone(),
+                }
+                .into_signed(signature),
+            ),
+            TxEnvelope::Eip4844(
+                TxEip4844Variant::TxEip4844(TxEip4844 {
+                    chain_id: 1,
+                    nonce: 4,
+                    gas_limit: 50_000,
+                    max_fee_per_gas: 30_000_000_000,
+                    max_priority_fee_per_gas: 1_000_000_000,
+                    to,
+                    value: U256::ZERO,
+                    access_list,
+                    blob_versioned_hashes: vec![B256::with_last_byte(1)],
+                    max_fee_per_blob_gas: 1,
+                    input,
+                })
+                .into_signed(signature),
+            ),
+        ];
+
+        for envelope in envelopes {
+            let tx = served_transaction(&envelope);
+
+            let result = filter_fields(&tx, None, &[TransactionField::Raw], &Chain::Ethereum);
+
+            let raw = result.raw.unwrap();
+            let decoded = TxEnvelope::decode_2718(&mut raw.as_ref()).unwrap();
+            assert_eq!(decoded.tx_type(), envelope.tx_type());
+            assert_eq!(*decoded.tx_hash(), tx.hash);
+        }
+    }
+
+    #[test]
+    fn test_raw_transaction_is_none_without_signature() {
+        let tx = RpcTransaction {
+            transaction_type: Some(2),
+            ..Default::default()
+        };
+
+        let result = filter_fields(&tx, None, &[TransactionField::Raw], &Chain::Ethereum);
+
+        assert_eq!(result.raw, None);
+    }
+
+    /// Mainnet transaction 0x0ec0b6a2df4d87424e5f6ad2a654e27aaeb7dac20ae9e8385cc09087ad532ee0
+    fn signed_mainnet_transaction() -> RpcTransaction {
+        let envelope = TxEnvelope::Eip1559(
+            TxEip1559 {
+                chain_id: 1,
+                nonce: 0x42,
+                gas_limit: 44386,
+                to: TxKind::Call(address!("6069a6c32cf691f5982febae4faf8a6f3ab2f0f6")),
+                value: U256::ZERO,
+                input: bytes!("a22cb4650000000000000000000000005eee75727d804a2b13038928d36f8b188945a57a0000000000000000000000000000000000000000000000000000000000000000"),
+                max_fee_per_gas: 0x4a817c800,
+                max_priority_fee_per_gas: 0x3b9aca00,
+                access_list: AccessList::default(),
+            }
+            .into_signed(
+                Signature::from_scalars_and_parity(
+                    b256!("840cfc572845f5786e702984c2a582528cad4b49b2a10b9db1be7fca90058565"),
+                    b256!("25e7109ceb98168d95b09b18bbf6b685130e0562f233877d492b94eee0c5b6d1"),
+                    false,
+                )
+                .unwrap(),
+            ),
+        );
+
+        RpcTransaction {
+            from: address!("dd6b8b3dc6b7ad97db52f08a275ff4483e024cea"),
+            ..served_transaction(&envelope)
+        }
+    }
+
+    #[test]
+    fn test_recovered_signer_of_known_transaction_is_its_sender() {
+        let tx = signed_mainnet_transaction();
+        assert_eq!(
+            tx.hash,
+            b256!("0ec0b6a2df4d87424e5f6ad2a654e27aaeb7dac20ae9e8385cc09087ad532ee0")
+        );
+
+        let result = filter_fields(
+            &tx,
+            None,
+            &[TransactionField::SignerValid],
+            &Chain::Ethereum,
+        );
+
+        assert_eq!(result.signer_valid, Some(true));
+    }
+
+    #[test]
+    fn test_signer_mismatches_are_flagged() {
+        let other_sender = RpcTransaction {
+            from: Address::ZERO,
+            ..signed_mainnet_transaction()
+        };
+        let altered_value = RpcTransaction {
+            value: U256::from(1),
+            ..signed_mainnet_transaction()
+        };
+        let unsigned = RpcTransaction {
+            signature: None,
+            ..signed_mainnet_transaction()
+        };
+
+        for (tx, signer_valid) in [
+            (other_sender, Some(false)),
+            (altered_value, Some(false)),
+            (unsigned, None),
+        ] {
+            let result = filter_fields(
+                &tx,
+                None,
+                &[TransactionField::SignerValid],
+                &Chain::Ethereum,
+            );
+            assert_eq!(result.signer_valid, signer_valid);
+        }
+    }
+
+    #[test]
+    fn test_label_of_each_transaction_type() {
+        let labels = [
+            (Some(0), Some("legacy")),
+            (Some(1), Some("eip2930")),
+            (Some(2), Some("eip1559")),
+            (Some(3), Some("blob")),
+            (Some(4), Some("setcode")),
+            (Some(126), Some("deposit")),
+            (Some(5), None),
+            (None, Some("legacy")),
+        ];
+
+        for (transaction_type, label) in labels {
+            let tx = RpcTransaction {
+                transaction_type,
+                ..Default::default()
+            };
+
+            let result = filter_fields(
+                &tx,
+                None,
+                &[TransactionField::TxTypeLabel],
+                &Chain::Ethereum,
+            );
+
+            assert_eq!(result.tx_type_label.as_deref(), label);
+        }
+    }
+
+    #[test]
+    fn test_authorization_list_is_none_for_eip1559_transactions() {
+        let tx = RpcTransaction {
+            transaction_type: Some(2),
+            ..Default::default()
+        };
+
+        let result = filter_fields(
+            &tx,
+            None,
+            &[TransactionField::AuthorizationList],
+            &Chain::Ethereum,
+        );
+
+        assert_eq!(result.authorization_list, None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_query_by_hashes_filtered_by_block_range() {
+        let hashes: Vec<B256> = (1..=4).map(B256::with_last_byte).collect();
+        let rpc = MockRpc::start(|method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionByHash" => {
+                let hash: B256 = params[0].as_str().unwrap().parse().unwrap();
+                // Transactions 0x..01 to 0x..03 are in blocks 99 to 101, 0x..04 is pending
+                let block_number = match hash[31] {
+                    4 => None,
+                    n => Some(98 + n as u64),
+                };
+                result(&RpcTransaction {
+                    hash,
+                    block_number,
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            Some(hashes.clone()),
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                BlockRange::new(100.into(), Some(101.into())),
+            ))]),
+            vec![TransactionField::Hash],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            transactions.iter().map(|tx| tx.hash).collect::<Vec<_>>(),
+            vec![Some(hashes[1]), Some(hashes[2])]
+        );
+        // The transactions are fetched by hash, not by block
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 0);
+    }
+
+    /// Chain at block 100, where the even blocks have a transaction of `sender`, with the block
+    /// number as hash, and every block has a transaction of another account
+    async fn sender_chain_rpc(sender: Address) -> MockRpc {
+        MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_blockNumber" => result(&"0x64"),
+            "eth_getBlockByNumber" => {
+                let number = u64::from_str_radix(&params[0].as_str().unwrap()[2..], 16).unwrap();
+                let tx = |hash, from, index| RpcTransaction {
+                    hash,
+                    from,
+                    block_number: Some(number),
+                    transaction_index: Some(index),
+                    ..Default::default()
+                };
+                let mut transactions = vec![tx(B256::repeat_byte(0xff), Address::ZERO, 0)];
+                if number % 2 == 0 {
+                    transactions.push(tx(B256::with_last_byte(number as u8), sender, 1));
+                }
+                result(&RpcBlock {
+                    header: alloy::rpc::types::Header {
+                        hash: Some(B256::with_last_byte(number as u8)),
+                        number: Some(number),
+                        ..Default::default()
+                    },
+                    transactions: BlockTransactions::Full(transactions),
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_resolve_latest_transactions_of_sender() {
+        let sender = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let rpc = sender_chain_rpc(sender).await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::From(EqualityFilter::Eq(
+                NameOrAddress::Address(sender),
+            ))]),
+            vec![TransactionField::Hash],
+        );
+        assert!(transaction.scans_latest_blocks());
+
+        let transactions = resolve_latest_transactions(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            2,
+            DEFAULT_SCAN_DEPTH,
+            Some(3),
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        // The latest first, with the sender only picked for the filter
+        assert_eq!(
+            transactions,
+            [100, 98, 96]
+                .map(|number| TransactionQueryRes {
+                    hash: Some(B256::with_last_byte(number)),
+                    ..Default::default()
+                })
+                .to_vec()
+        );
+        // The search stops at the batch of 2 blocks where the third transaction is found
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 6);
+    }
+
+    #[tokio::test]
+    async fn test_latest_transactions_are_searched_down_to_the_scan_depth() {
+        let sender = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let rpc = sender_chain_rpc(sender).await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::From(EqualityFilter::Eq(
+                NameOrAddress::Address(sender),
+            ))]),
+            vec![TransactionField::Hash],
+        );
+
+        let transactions = resolve_latest_transactions(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            5,
+            Some(10),
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            transactions.iter().map(|tx| tx.hash).collect::<Vec<_>>(),
+            vec![
+                Some(B256::with_last_byte(100)),
+                Some(B256::with_last_byte(98)),
+                Some(B256::with_last_byte(96)),
+            ]
+        );
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 5);
+    }
+
+    #[tokio::test]
+    async fn test_stream_latest_transactions_of_sender() {
+        let sender = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let rpc = sender_chain_rpc(sender).await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::From(EqualityFilter::Eq(
+                NameOrAddress::Address(sender),
+            ))]),
+            vec![TransactionField::Hash],
+        );
+
+        let chunks: Vec<_> = stream_transaction_query(
+            transaction,
+            vec![ChainOrRpc::Rpc(rpc.url())],
+            3,
+            DEFAULT_MAX_CONCURRENCY,
+            6,
+            EnsConfig::default(),
+        )
+        .try_collect()
+        .await
+        .unwrap();
+
+        // Blocks 98-100, then 95-97, the latest first
+        let hashes = |numbers: &[u8]| -> Vec<_> {
+            numbers
+                .iter()
+                .map(|number| TransactionQueryRes {
+                    hash: Some(B256::with_last_byte(*number)),
+                    ..Default::default()
+                })
+                .collect()
+        };
+        assert_eq!(chunks, vec![hashes(&[100, 98]), hashes(&[96])]);
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 6);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_hashes_of_block_without_transaction_bodies() {
+        let block_hash = B256::with_last_byte(0xff);
+        let hashes: Vec<B256> = (1..=3).map(B256::with_last_byte).collect();
+        let block: RpcBlock = RpcBlock {
+            header: alloy::rpc::types::Header {
+                hash: Some(block_hash),
+                number: Some(100),
+                ..Default::default()
+            },
+            transactions: BlockTransactions::Hashes(hashes.clone()),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => {
+                // Only the hashes are needed, not the transaction bodies
+                assert_eq!(params[1], false);
+                result(&block)
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Number(
+                BlockNumberOrTag::Number(100),
+            ))]),
+            vec![
+                TransactionField::Hash,
+                TransactionField::TransactionIndex,
+                TransactionField::BlockNumber,
+                TransactionField::BlockHash,
+            ],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|tx| (tx.hash, tx.transaction_index))
+                .collect::<Vec<_>>(),
+            vec![
+                (Some(hashes[0]), Some(0)),
+                (Some(hashes[1]), Some(1)),
+                (Some(hashes[2]), Some(2)),
+            ]
+        );
+        assert!(transactions
+            .iter()
+            .all(|tx| tx.block_number == Some(100) && tx.block_hash == Some(block_hash)));
+        assert_eq!(rpc.calls("eth_getTransactionByHash"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_query_using_ens_from_filter() {
+        let vitalik = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let hashes = [
+            b256!("0000000000000000000000000000000000000000000000000000000000000001"),
+            b256!("0000000000000000000000000000000000000000000000000000000000000002"),
+        ];
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            // Both the registry and the resolver answer with the same address
+            "eth_call" => result(&Bytes::from(vitalik.abi_encode())),
+            "eth_getTransactionByHash" => {
+                let hash = params[0].as_str().unwrap().parse().unwrap();
+                let from = if hash == hashes[0] {
+                    vitalik
+                } else {
+                    Address::ZERO
+                };
+                result(&RpcTransaction {
+                    hash,
+                    from,
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            Some(hashes.to_vec()),
+            Some(vec![TransactionFilter::From(EqualityFilter::Eq(
+                NameOrAddress::Name(String::from("vitalik.eth")),
+            ))]),
+            vec![TransactionField::Hash, TransactionField::From],
+        );
+        let chains = [ChainOrRpc::Rpc(rpc.url()), ChainOrRpc::Rpc(rpc.url())];
+        let ens = EnsConfig::new(ChainOrRpc::Rpc(rpc.url()), ENS_ADDRESS);
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &chains,
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &ens,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            transactions.iter().map(|tx| tx.hash).collect::<Vec<_>>(),
+            vec![Some(hashes[0]), Some(hashes[0])]
+        );
+        // One call to the registry and one to the resolver, shared by both chains
+        assert_eq!(rpc.calls("eth_call"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pick_trace_of_transaction() {
+        let hash = b256!("5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060");
+        let rpc = MockRpc::start(|method, params| match method {
+            "debug_traceTransaction" => {
+                assert_eq!(params[1]["tracer"], "callTracer");
+                result(&json!({
+                    "type": "CALL",
+                    "from": "0x1111111111111111111111111111111111111111",
+                    "to": "0x2222222222222222222222222222222222222222",
+                    "value": "0x0",
+                    "gas": "0x5208",
+                    "gasUsed": "0x5000",
+                    "input": "0x",
+                    "calls": [{
+                        "type": "CALL",
+                        "from": "0x2222222222222222222222222222222222222222",
+                        "to": "0x3333333333333333333333333333333333333333",
+                        "value": "0xde0b6b3a7640000",
+                        "gas": "0x1000",
+                        "gasUsed": "0x0",
+                        "input": "0x"
+                    }]
+                }))
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let tx = RpcTransaction {
+            hash,
+            ..Default::default()
+        };
+
+        let result = pick_transaction_fields(
+            &tx,
+            &[TransactionField::Trace],
+            &rpc.provider(),
+            &Chain::Ethereum,
+        )
+        .await
+        .unwrap();
+        let trace = result.trace.unwrap();
+
+        assert_eq!(
+            trace.to,
+            Some(address!("2222222222222222222222222222222222222222"))
+        );
+        assert_eq!(
+            trace.calls,
+            vec![CallFrame {
+                call_type: String::from("CALL"),
+                from: address!("2222222222222222222222222222222222222222"),
+                to: Some(address!("3333333333333333333333333333333333333333")),
+                value: Some(U256::from(10).pow(U256::from(18))),
+                gas: U256::from(0x1000),
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_when_rpc_does_not_support_tracing() {
+        let rpc = MockRpc::start(|method, _| {
+            Err(format!(
+                "the method {method} does not exist/is not available"
+            ))
+        })
+        .await;
+
+        let error = pick_transaction_fields(
+            &RpcTransaction::default(),
+            &[TransactionField::Hash, TransactionField::Trace],
+            &rpc.provider(),
+            &Chain::Ethereum,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<TransactionResolverErrors>(),
+            Some(TransactionResolverErrors::TraceUnavailable(..))
+        ));
+    }
+
+    fn sender_and_nonce_query(sender: Address, nonce: u64) -> Transaction {
+        Transaction::new(
+            None,
+            Some(vec![
+                TransactionFilter::From(EqualityFilter::Eq(NameOrAddress::Address(sender))),
+                TransactionFilter::Nonce(FilterType::Equality(EqualityFilter::Eq(nonce))),
+            ]),
+            vec![TransactionField::Hash, TransactionField::From],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_resolve_query_by_sender_and_nonce() {
+        let sender = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        let hash = B256::with_last_byte(1);
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionBySenderAndNonce" => {
+                assert_eq!(
+                    params[0].as_str().unwrap().parse::<Address>().unwrap(),
+                    sender
+                );
+                assert_eq!(params[1], "0x7");
+                result(&RpcTransaction {
+                    hash,
+                    from: sender,
+                    nonce: 7,
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        let transactions = resolve_transaction_query(
+            &sender_and_nonce_query(sender, 7),
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            transactions,
+            vec![TransactionQueryRes {
+                hash: Some(hash),
+                from: Some(sender),
+                // Only picked for the filter, since it wasn't queried
+                nonce: None,
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_when_rpc_does_not_serve_transactions_by_sender_and_nonce() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            method => Err(format!(
+                "the method {method} does not exist/is not available"
+            )),
+        })
+        .await;
+
+        let error = resolve_transaction_query(
+            &sender_and_nonce_query(Address::ZERO, 0),
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<TransactionResolverErrors>(),
+            Some(TransactionResolverErrors::SenderAndNonceUnavailable(..))
+        ));
+    }
+
+    // Few RPCs serve `eth_getTransactionBySenderAndNonce`, so this test only runs when the
+    // `sender-nonce-tests` feature is enabled, against the RPC set in `SENDER_NONCE_RPC_URL`
+    #[cfg(feature = "sender-nonce-tests")]
+    #[tokio::test]
+    async fn test_resolve_first_mainnet_transaction_by_sender_and_nonce() {
+        let rpc = std::env::var("SENDER_NONCE_RPC_URL").expect("SENDER_NONCE_RPC_URL must be set");
+        let sender = address!("A1E4380A3B1f749673E270229993eE55F35663b4");
+
+        let transactions = resolve_transaction_query(
+            &sender_and_nonce_query(sender, 0),
+            &[ChainOrRpc::Rpc(rpc.parse().unwrap())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            transactions[0].hash,
+            Some(b256!(
+                "5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_trace_is_only_picked_when_requested() {
+        assert!(!TransactionField::all_variants().contains(&TransactionField::Trace));
+    }
+
+    // Tracing is only served by some RPCs, usually archive nodes, so this test only runs when the
+    // `trace-tests` feature is enabled, against the RPC set in `TRACE_RPC_URL`
+    #[cfg(feature = "trace-tests")]
+    #[tokio::test]
+    async fn test_trace_internal_calls_of_mainnet_transaction() {
+        let rpc = std::env::var("TRACE_RPC_URL").expect("TRACE_RPC_URL must be set");
+        let provider = Arc::new(ProviderBuilder::new().on_http(rpc.parse().unwrap()).boxed());
+        // The DAO exploit, which recursively called The DAO to drain it
+        let hash = b256!("0ec3f2488a93839524add10ea229e773f6bc891b4eb4794c3337d4495263790b");
+        let the_dao = address!("bb9bc244d798123fde783fcc1c72d3bb8c189413");
+        let tx = provider
+            .get_transaction_by_hash(hash)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let result =
+            pick_transaction_fields(&tx, &[TransactionField::Trace], &provider, &Chain::Ethereum)
+                .await
+                .unwrap();
+
+        fn calls_to(frame: &CallFrame, address: Address) -> usize {
+            let calls = frame
+                .calls
+                .iter()
+                .map(|call| calls_to(call, address))
+                .sum::<usize>();
+            calls + usize::from(frame.to == Some(address))
+        }
+        assert!(calls_to(&result.trace.unwrap(), the_dao) > 1);
+    }
+
+    #[test]
+    fn test_pick_blob_fields_of_eip4844_transaction() {
+        let blob_versioned_hashes = vec![
+            b256!("01a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8"),
+            b256!("0100a9a5ce9a3b4bd4bc7a24f8c4f1bf11a48a4d80d0b8ec1f2e27fd43cdb3a1"),
+        ];
+        let tx = RpcTransaction {
+            transaction_type: Some(3),
+            blob_versioned_hashes: Some(blob_versioned_hashes.clone()),
+            ..Default::default()
+        };
+        let fields = [
+            TransactionField::BlobVersionedHashes,
+            TransactionField::BlobCount,
+        ];
+
+        let result = filter_fields(&tx, None, &fields, &Chain::Ethereum);
+
+        assert_eq!(result.blob_versioned_hashes, Some(blob_versioned_hashes));
+        assert_eq!(result.blob_count, Some(2));
+    }
+
+    #[test]
+    fn test_blob_fields_of_non_blob_transaction() {
+        let tx = RpcTransaction {
+            transaction_type: Some(2),
+            ..Default::default()
+        };
+        let fields = [
+            TransactionField::BlobVersionedHashes,
+            TransactionField::BlobCount,
+        ];
+
+        let result = filter_fields(&tx, None, &fields, &Chain::Ethereum);
+
+        assert_eq!(result.blob_versioned_hashes, None);
+        assert_eq!(result.blob_count, Some(0));
+    }
+
+    fn contract_creation_receipt(
+        hash: FixedBytes<32>,
+        contract_address: Address,
+    ) -> TransactionReceipt {
+        TransactionReceipt {
+            inner: ReceiptEnvelope::Eip1559(ReceiptWithBloom {
+                receipt: Receipt {
+                    status: true.into(),
+                    cumulative_gas_used: 1_750_000,
+                    logs: vec![],
+                },
+                logs_bloom: Bloom::ZERO,
+            }),
+            transaction_hash: hash,
+            transaction_index: Some(3),
+            block_hash: None,
+            block_number: None,
+            gas_used: 1_200_000,
+            effective_gas_price: 10_000_000_000,
+            blob_gas_used: None,
+            blob_gas_price: None,
+            from: Address::ZERO,
+            to: None,
+            contract_address: Some(contract_address),
+            state_root: None,
+            authorization_list: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_receipt_is_fetched_once_per_transaction() {
+        let tx = RpcTransaction {
+            hash: b256!("4a5f3e8d1b3d6e7c2a9e0f1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f"),
+            ..Default::default()
+        };
+        let receipt = contract_creation_receipt(tx.hash, Address::ZERO);
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_getTransactionReceipt" => result(&receipt),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let fields = vec![
+            TransactionField::Hash,
+            TransactionField::Status,
+            TransactionField::GasUsed,
+            TransactionField::EffectiveGasPrice,
+            TransactionField::CumulativeGasUsed,
+            TransactionField::LogsBloom,
+            TransactionField::ContractAddress,
+            TransactionField::Logs,
+        ];
+        let chain = Chain::Ethereum;
+
+        let result = pick_transaction_fields(&tx, &fields, &rpc.provider(), &chain)
+            .await
+            .unwrap();
+
+        assert_eq!(rpc.calls("eth_getTransactionReceipt"), 1);
+        assert_eq!(result.status, Some(true));
+        assert_eq!(result.gas_used, Some(1_200_000));
+        assert_eq!(result.cumulative_gas_used, Some(1_750_000));
+    }
+
+    #[tokio::test]
+    async fn test_receipt_is_not_fetched_without_receipt_fields() {
+        let rpc = MockRpc::start(|method, _| Err(format!("Unexpected method {method}"))).await;
+        let fields = vec![TransactionField::Hash, TransactionField::Value];
+        let chain = Chain::Ethereum;
+
+        pick_transaction_fields(&RpcTransaction::default(), &fields, &rpc.provider(), &chain)
+            .await
+            .unwrap();
+
+        assert_eq!(rpc.calls("eth_getTransactionReceipt"), 0);
+    }
+
+    /// Transaction included in block 18000000, answering its replay with `replay`
+    async fn replay_rpc(
+        status: bool,
+        replay: impl Fn() -> Result<serde_json::Value, String> + Send + Sync + 'static,
+    ) -> (RpcTransaction, MockRpc) {
+        let tx = RpcTransaction {
+            hash: B256::with_last_byte(1),
+            from: address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045"),
+            to: Some(address!("dAC17F958D2ee523a2206206994597C13D831ec7")),
+            block_number: Some(18_000_000),
+            gas: 60_000,
+            ..Default::default()
+        };
+        let mut receipt = contract_creation_receipt(tx.hash, Address::ZERO);
+        if let ReceiptEnvelope::Eip1559(inner) = &mut receipt.inner {
+            inner.receipt.status = status.into();
+        }
+        let from = tx.from;
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_getTransactionReceipt" => result(&receipt),
+            "eth_call" => {
+                assert_eq!(params[0]["from"], from.to_string().to_lowercase());
+                assert_eq!(params[1], "0x112a880");
+                replay()
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        (tx, rpc)
+    }
+
+    #[tokio::test]
+    async fn test_revert_reason_of_failed_transaction() {
+        let (tx, rpc) = replay_rpc(false, || {
+            revert(&Revert::from("ERC20: transfer amount exceeds balance").abi_encode())
+        })
+        .await;
+        let fields = [TransactionField::Hash, TransactionField::RevertReason];
+
+        let result = pick_transaction_fields(&tx, &fields, &rpc.provider(), &Chain::Ethereum)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result.revert_reason.as_deref(),
+            Some("ERC20: transfer amount exceeds balance")
+        );
+        assert_eq!(rpc.calls("eth_call"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_revert_reason_is_none_for_opaque_reverts() {
+        let (tx, rpc) = replay_rpc(false, || revert(&[])).await;
+        let fields = [TransactionField::RevertReason];
+
+        let result = pick_transaction_fields(&tx, &fields, &rpc.provider(), &Chain::Ethereum)
+            .await
+            .unwrap();
+
+        assert_eq!(result.revert_reason, None);
+    }
+
+    #[tokio::test]
+    async fn test_successful_transactions_are_not_replayed() {
+        let (tx, rpc) = replay_rpc(true, || result(&Bytes::new())).await;
+        let fields = [TransactionField::RevertReason];
+
+        let result = pick_transaction_fields(&tx, &fields, &rpc.provider(), &Chain::Ethereum)
+            .await
+            .unwrap();
+
+        assert_eq!(result.revert_reason, None);
+        assert_eq!(rpc.calls("eth_call"), 0);
+    }
+
+    #[test]
+    fn test_decode_panics_and_custom_errors() {
+        let panic = Panic::from(U256::from(0x11)).abi_encode();
+        // ERC20InsufficientBalance(address,uint256,uint256) of OpenZeppelin 5
+        let custom_error = bytes!("e450d38c");
+
+        assert_eq!(
+            decode_revert_reason(&panic).as_deref(),
+            Some("panic: arithmetic underflow or overflow (0x11)")
+        );
+        assert_eq!(
+            decode_revert_reason(&custom_error).as_deref(),
+            Some("0xe450d38c")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receipt_is_fetched_after_transient_errors() {
+        let tx = RpcTransaction::default();
+        let receipt = contract_creation_receipt(tx.hash, Address::ZERO);
+        let attempts = AtomicUsize::new(0);
+        let rpc = MockRpc::start(move |_, _| match attempts.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Err("rate limit exceeded".to_string()),
+            _ => result(&receipt),
+        })
+        .await;
+        let chain = Chain::Ethereum;
+
+        let result =
+            pick_transaction_fields(&tx, &[TransactionField::Status], &rpc.provider(), &chain)
+                .await
+                .unwrap();
+
+        assert_eq!(result.status, Some(true));
+        assert_eq!(rpc.calls("eth_getTransactionReceipt"), 3);
+    }
+
+    #[tokio::test]
+    async fn test_pick_transaction_fields_without_server() {
+        let tx = RpcTransaction {
+            hash: b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890"),
+            from: address!("95222290DD7278Aa3Ddd389Cc1E1d165CC4BAfe5"),
+            value: U256::from(1_000_000_000_000_000_000u64),
+            ..Default::default()
+        };
+        let receipt = contract_creation_receipt(tx.hash, Address::ZERO);
+        let transport = MockTransport::new(move |method, params| match method {
+            "eth_getTransactionReceipt" => {
+                assert_eq!(params[0], receipt.transaction_hash.to_string());
+                result(&receipt)
+            }
+            method => Err(format!("Unexpected method {method}")),
+        });
+        let fields = vec![
+            TransactionField::Hash,
+            TransactionField::From,
+            TransactionField::Value,
+            TransactionField::GasUsed,
+            TransactionField::Chain,
+        ];
+
+        let result = pick_transaction_fields(&tx, &fields, &transport.provider(), &Chain::Ethereum)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result,
+            TransactionQueryRes {
+                chain: Some(Chain::Ethereum),
+                hash: Some(tx.hash),
+                from: Some(tx.from),
+                value: Some(tx.value),
+                gas_used: Some(1_200_000),
+                ..Default::default()
+            }
+        );
+        assert_eq!(transport.calls("eth_getTransactionReceipt"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pick_l1_fee_fields_of_op_stack_receipt() {
+        let tx = RpcTransaction::default();
+        // Base receipts carry the L1 data fee on top of the Ethereum receipt fields
+        let mut receipt =
+            serde_json::to_value(contract_creation_receipt(tx.hash, Address::ZERO)).unwrap();
+        receipt["l1Fee"] = json!("0x2d4b2f6fb7");
+        receipt["l1GasUsed"] = json!("0x640");
+        receipt["l1GasPrice"] = json!("0x3b9aca07");
+        receipt["l1FeeScalar"] = json!("0.684");
+        let transport = MockTransport::new(move |method, _| match method {
+            "eth_getTransactionReceipt" => result(&receipt),
+            method => Err(format!("Unexpected method {method}")),
+        });
+        let fields = vec![
+            TransactionField::GasUsed,
+            TransactionField::L1Fee,
+            TransactionField::L1GasUsed,
+            TransactionField::L1GasPrice,
+            TransactionField::L1FeeScalar,
+        ];
+
+        let result = pick_transaction_fields(&tx, &fields, &transport.provider(), &Chain::Base)
+            .await
+            .unwrap();
+
+        assert_eq!(result.gas_used, Some(1_200_000));
+        assert_eq!(result.l1_fee, Some(U256::from(0x2d4b2f6fb7u64)));
+        assert_eq!(result.l1_gas_used, Some(1_600));
+        assert_eq!(result.l1_gas_price, Some(1_000_000_007));
+        assert_eq!(result.l1_fee_scalar, Some(String::from("0.684")));
+        assert_eq!(transport.calls("eth_getTransactionReceipt"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_l1_fee_fields_are_none_on_ethereum() {
+        let tx = RpcTransaction::default();
+        let receipt = contract_creation_receipt(tx.hash, Address::ZERO);
+        let transport = MockTransport::new(move |method, _| match method {
+            "eth_getTransactionReceipt" => result(&receipt),
+            method => Err(format!("Unexpected method {method}")),
+        });
+        let fields = vec![TransactionField::L1Fee, TransactionField::L1GasUsed];
+
+        let result = pick_transaction_fields(&tx, &fields, &transport.provider(), &Chain::Ethereum)
+            .await
+            .unwrap();
+
+        assert_eq!(result, TransactionQueryRes::default());
+    }
+
+    #[tokio::test]
+    async fn test_error_when_receipt_cannot_be_fetched() {
+        let transport = MockTransport::new(|_, _| Err("receipts are pruned".to_string()));
+        let tx = RpcTransaction::default();
+
+        let error = pick_transaction_fields(
+            &tx,
+            &[TransactionField::Status],
+            &transport.provider(),
+            &Chain::Ethereum,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<TransactionResolverErrors>(),
+            Some(TransactionResolverErrors::Rpc(hash, message))
+                if *hash == tx.hash && message.contains("receipts are pruned")
+        ));
+        assert_eq!(transport.calls("eth_getTransactionReceipt"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_query_with_concurrency_of_one() {
+        let hashes = [
+            b256!("0000000000000000000000000000000000000000000000000000000000000001"),
+            b256!("0000000000000000000000000000000000000000000000000000000000000002"),
+            b256!("0000000000000000000000000000000000000000000000000000000000000003"),
+        ];
+        let block: RpcBlock = RpcBlock {
+            transactions: BlockTransactions::Hashes(hashes.to_vec()),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&block),
+            "eth_getTransactionByHash" => result(&RpcTransaction {
+                hash: params[0].as_str().unwrap().parse().unwrap(),
+                ..Default::default()
+            }),
+            "eth_getTransactionReceipt" => result(&contract_creation_receipt(
+                params[0].as_str().unwrap().parse().unwrap(),
+                Address::ZERO,
+            )),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                BlockRange::new(1.into(), Some(1.into())),
+            ))]),
+            vec![TransactionField::Hash, TransactionField::Status],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            1,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            transactions,
+            hashes
+                .iter()
+                .map(|hash| TransactionQueryRes {
+                    hash: Some(*hash),
+                    status: Some(true),
+                    ..Default::default()
+                })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(rpc.calls("eth_getTransactionReceipt"), 3);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_query_using_method_id_filter() {
+        let transfer = bytes!(
+            "a9059cbb000000000000000000000000d8da6bf26964af9d7eed9e03e53415d37aa96045\
+             00000000000000000000000000000000000000000000000000000000000f4240"
+        );
+        let approve = bytes!(
+            "095ea7b3000000000000000000000000d8da6bf26964af9d7eed9e03e53415d37aa96045\
+             00000000000000000000000000000000000000000000000000000000000f4240"
+        );
+        let hashes = [
+            b256!("0000000000000000000000000000000000000000000000000000000000000001"),
+            b256!("0000000000000000000000000000000000000000000000000000000000000002"),
+            b256!("0000000000000000000000000000000000000000000000000000000000000003"),
+            b256!("0000000000000000000000000000000000000000000000000000000000000004"),
+        ];
+        // Two ERC-20 transfers, an approval and a plain ETH transfer
+        let inputs = [transfer.clone(), approve, Bytes::new(), transfer];
+        let block = RpcBlock {
+            transactions: BlockTransactions::Full(
+                hashes
+                    .iter()
+                    .zip(inputs)
+                    .map(|(hash, input)| RpcTransaction {
+                        hash: *hash,
+                        input,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![
+                TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                    1.into(),
+                    Some(1.into()),
+                ))),
+                TransactionFilter::MethodId(EqualityFilter::Eq(fixed_bytes!("a9059cbb"))),
+            ]),
+            vec![TransactionField::Hash],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        // Only the transfers are returned, without the method id since it wasn't queried
+        assert_eq!(
+            transactions,
+            vec![
+                TransactionQueryRes {
+                    hash: Some(hashes[0]),
+                    ..Default::default()
+                },
+                TransactionQueryRes {
+                    hash: Some(hashes[3]),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    /// Serves block 1 with two transactions, the first one succeeding and the second one failing
+    async fn status_filter_rpc() -> MockRpc {
+        let block = RpcBlock {
+            transactions: BlockTransactions::Full(
+                (1..=2)
+                    .map(|n| RpcTransaction {
+                        hash: B256::with_last_byte(n),
+                        block_number: Some(1),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&block),
+            "eth_getTransactionReceipt" => {
+                let hash: B256 = params[0].as_str().unwrap().parse().unwrap();
+                let mut receipt = contract_creation_receipt(hash, Address::ZERO);
+                if let ReceiptEnvelope::Eip1559(inner) = &mut receipt.inner {
+                    inner.receipt.status = (hash == B256::with_last_byte(1)).into();
+                }
+                result(&receipt)
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await
+    }
+
+    fn status_filter_query(fields: Vec<TransactionField>) -> Transaction {
+        Transaction::new(
+            None,
+            Some(vec![
+                TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                    1.into(),
+                    Some(1.into()),
+                ))),
+                TransactionFilter::Status(EqualityFilter::Eq(true)),
+            ]),
+            fields,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_resolve_query_using_status_filter_without_status_field() {
+        let rpc = status_filter_rpc().await;
+
+        let transactions = resolve_transaction_query(
+            &status_filter_query(vec![TransactionField::Hash]),
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        // Only the successful transaction is returned, without the status since it wasn't queried
+        assert_eq!(
+            transactions,
+            vec![TransactionQueryRes {
+                hash: Some(B256::with_last_byte(1)),
+                ..Default::default()
+            }]
+        );
+        assert_eq!(rpc.calls("eth_getTransactionReceipt"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_status_filter_does_not_add_status_to_wildcard() {
+        let rpc = status_filter_rpc().await;
+
+        let transactions = resolve_transaction_query(
+            &status_filter_query(TransactionField::wildcard_fields()),
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].hash, Some(B256::with_last_byte(1)));
+        assert_eq!(transactions[0].status, None);
+    }
+
+    fn pending_transaction_query() -> Transaction {
+        Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                BlockRange::new(BlockNumberOrTag::Pending, None),
+            ))]),
+            vec![TransactionField::Hash],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_resolve_query_using_pending_block() {
+        let hashes = [
+            b256!("0000000000000000000000000000000000000000000000000000000000000001"),
+            b256!("0000000000000000000000000000000000000000000000000000000000000002"),
+        ];
+        let block = RpcBlock {
+            transactions: BlockTransactions::Full(
+                hashes
+                    .iter()
+                    .map(|hash| RpcTransaction {
+                        hash: *hash,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" if params[0] == "pending" => result(&block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        let transactions = resolve_transaction_query(
+            &pending_transaction_query(),
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|t| t.hash.unwrap())
+                .collect::<Vec<_>>(),
+            hashes
+        );
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pending_block_without_transactions_yields_empty_result() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&serde_json::Value::Null),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+
+        let transactions = resolve_transaction_query(
+            &pending_transaction_query(),
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(transactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_error_when_rpc_does_not_serve_pending_block() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            _ => Err("pending block is not available".to_string()),
+        })
+        .await;
+
+        let error = resolve_transaction_query(
+            &pending_transaction_query(),
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<TransactionResolverErrors>(),
+            Some(TransactionResolverErrors::PendingBlockUnavailable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_error_when_pending_block_is_part_of_a_range() {
+        let provider = ProviderBuilder::new()
+            .on_http("http://localhost:8545".parse().unwrap())
+            .boxed();
+        let block_id = BlockId::Range(BlockRange::new(
+            BlockNumberOrTag::Number(1),
+            Some(BlockNumberOrTag::Pending),
+        ));
+
+        let error = get_transactions_by_block_id(
+            &block_id,
+            &Arc::new(provider),
+            &Semaphore::new(DEFAULT_MAX_CONCURRENCY),
+            DEFAULT_MAX_BLOCK_RANGE,
+            true,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "The pending block can't be part of a block range"
+        );
+    }
+
+    // The content of the pending block changes on every request, so this test only runs when
+    // the `pending-block-tests` feature is enabled
+    #[cfg(feature = "pending-block-tests")]
+    #[tokio::test]
+    async fn test_get_transactions_from_pending_block() {
+        let rpc = Chain::Ethereum.rpc_url().unwrap();
+        let provider = Arc::new(ProviderBuilder::new().on_http(rpc).boxed());
+        let block_id = BlockId::Range(BlockRange::new(BlockNumberOrTag::Pending, None));
+
+        let transactions = get_transactions_by_block_id(
+            &block_id,
+            &provider,
+            &Semaphore::new(DEFAULT_MAX_CONCURRENCY),
+            DEFAULT_MAX_BLOCK_RANGE,
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert!(transactions
+            .transactions
+            .iter()
+            .all(|tx| tx.block_number.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_by_block_range() {
+        let rpc = Chain::Ethereum.rpc_url().unwrap();
+        let provider = Arc::new(ProviderBuilder::new().on_http(rpc).boxed());
+        let block_id = BlockId::Range(BlockRange::new(10000000.into(), Some(10000015.into())));
+        let transactions = get_transactions_by_block_id(
+            &block_id,
+            &provider,
+            &Semaphore::new(DEFAULT_MAX_CONCURRENCY),
+            DEFAULT_MAX_BLOCK_RANGE,
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transactions.transactions.len(), 2394);
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_by_block_number() {
+        let rpc = Chain::Ethereum.rpc_url().unwrap();
+        let provider = Arc::new(ProviderBuilder::new().on_http(rpc).boxed());
+        let block_id = BlockId::Number(BlockNumberOrTag::Number(21036202));
+        let transactions = get_transactions_by_block_id(
+            &block_id,
+            &provider,
+            &Semaphore::new(DEFAULT_MAX_CONCURRENCY),
+            DEFAULT_MAX_BLOCK_RANGE,
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transactions.transactions.len(), 177);
+    }
+
+    #[test]
+    fn test_effective_tip_is_capped_by_max_fee() {
+        let gwei = 1_000_000_000;
+        let tx = RpcTransaction {
+            gas_price: Some(31 * gwei),
+            max_fee_per_gas: Some(40 * gwei),
+            max_priority_fee_per_gas: Some(2 * gwei),
+            ..Default::default()
+        };
+
+        assert_eq!(effective_tip(&tx, 30 * gwei), Some(2 * gwei));
+        assert_eq!(effective_tip(&tx, 39 * gwei), Some(gwei));
+        assert_eq!(effective_tip(&tx, 41 * gwei), Some(0));
+    }
+
+    #[test]
+    fn test_effective_tip_of_legacy_transaction() {
+        let gwei = 1_000_000_000;
+        let tx = RpcTransaction {
+            gas_price: Some(25 * gwei),
+            ..Default::default()
+        };
+
+        assert_eq!(effective_tip(&tx, 20 * gwei), Some(5 * gwei));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_effective_tip_with_base_fee_of_block() {
+        let gwei = 1_000_000_000;
+        let hashes = [B256::with_last_byte(1), B256::with_last_byte(2)];
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionByHash" => result(&RpcTransaction {
+                hash: params[0].as_str().unwrap().parse().unwrap(),
+                block_number: Some(1),
+                transaction_type: Some(2),
+                max_fee_per_gas: Some(40 * gwei),
+                max_priority_fee_per_gas: Some(3 * gwei),
+                ..Default::default()
+            }),
+            "eth_getBlockByNumber" => {
+                assert_eq!(params[1], false);
+                result::<RpcBlock>(&RpcBlock {
+                    header: alloy::rpc::types::Header {
+                        number: Some(1),
+                        base_fee_per_gas: Some(38 * gwei),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            Some(hashes.to_vec()),
+            None,
+            vec![TransactionField::EffectiveTip],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        // 40 gwei - 38 gwei is below the max priority fee, so the tip is capped at 2 gwei
+        assert!(transactions
+            .iter()
+            .all(|tx| tx.effective_tip == Some(2 * gwei)));
+        // Both transactions are in the same block, so its base fee is fetched once
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_effective_tip_reuses_blocks_of_range() {
+        let gwei = 1_000_000_000;
+        let block = RpcBlock {
+            header: alloy::rpc::types::Header {
+                number: Some(1),
+                base_fee_per_gas: Some(38 * gwei),
+                ..Default::default()
+            },
+            transactions: BlockTransactions::Full(
+                (0..3)
+                    .map(|i| RpcTransaction {
+                        hash: B256::with_last_byte(i),
+                        block_number: Some(1),
+                        transaction_type: Some(2),
+                        max_fee_per_gas: Some(40 * gwei),
+                        max_priority_fee_per_gas: Some(3 * gwei),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                BlockRange::new(1.into(), None),
+            ))]),
+            vec![TransactionField::EffectiveTip],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transactions.len(), 3);
+        assert!(transactions
+            .iter()
+            .all(|tx| tx.effective_tip == Some(2 * gwei)));
+        // The base fee is taken from the block fetched for its transactions
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_gas_price_percentile_within_block() {
+        let gwei = 1_000_000_000;
+        // Transaction 0x..0n of block 1 pays n * 10 gwei
+        let block_tx = move |n: u8| RpcTransaction {
+            hash: B256::with_last_byte(n),
+            block_number: Some(1),
+            gas_price: Some(n as u128 * 10 * gwei),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getTransactionByHash" => {
+                let hash: B256 = params[0].as_str().unwrap().parse().unwrap();
+                result(&block_tx(hash[31]))
+            }
+            "eth_getBlockByNumber" => {
+                assert_eq!(params[1], true);
+                result::<RpcBlock>(&RpcBlock {
+                    header: alloy::rpc::types::Header {
+                        number: Some(1),
+                        ..Default::default()
+                    },
+                    transactions: BlockTransactions::Full((1..=5).map(block_tx).collect()),
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            Some(vec![B256::with_last_byte(1), B256::with_last_byte(5)]),
+            None,
+            vec![TransactionField::GasPricePercentile],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let percentiles: Vec<_> = transactions
+            .iter()
+            .map(|tx| tx.gas_price_percentile)
+            .collect();
+        // The cheapest transaction of the block paid more than none, the most expensive than 4 of 5
+        assert_eq!(percentiles, vec![Some(0), Some(80)]);
+        // Both transactions are in the same block, so its fee distribution is fetched once
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+    }
+
+    #[test]
+    fn test_gas_price_percentile_ranks_ties_together() {
+        let distribution = [10, 20, 20, 20, 30];
+        let tx = |gas_price| RpcTransaction {
+            gas_price: Some(gas_price),
+            ..Default::default()
+        };
+
+        assert_eq!(gas_price_percentile(&tx(20), &distribution), Some(20));
+        assert_eq!(gas_price_percentile(&tx(30), &distribution), Some(80));
+        assert_eq!(gas_price_percentile(&tx(30), &[]), None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_confirmations_against_latest_block() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_blockNumber" => result(&"0x3e8"),
+            "eth_getTransactionByHash" => result(&RpcTransaction {
+                hash: B256::with_last_byte(1),
+                block_number: Some(990),
+                ..Default::default()
+            }),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            Some(vec![B256::with_last_byte(1)]),
+            None,
+            vec![
+                TransactionField::BlockNumber,
+                TransactionField::Confirmations,
+            ],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transactions[0].block_number, Some(990));
+        assert_eq!(transactions[0].confirmations, Some(1000 - 990));
+    }
+
+    #[tokio::test]
+    async fn test_transaction_indices_are_contiguous_within_block() {
+        let block = RpcBlock {
+            header: alloy::rpc::types::Header {
+                hash: Some(B256::with_last_byte(0xff)),
+                number: Some(1),
+                ..Default::default()
+            },
+            transactions: BlockTransactions::Full(
+                (0..5)
+                    .map(|i| RpcTransaction {
+                        hash: B256::with_last_byte(i as u8),
+                        block_number: Some(1),
+                        transaction_index: Some(i),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                BlockRange::new(1.into(), None),
+            ))]),
+            vec![TransactionField::TransactionIndex],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let indices: Vec<Option<u64>> =
+            transactions.iter().map(|tx| tx.transaction_index).collect();
+        assert_eq!(indices, (0..5).map(Some).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_transactions_report_their_block() {
+        let block_hash = b256!("88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6");
+        let block = RpcBlock {
+            header: alloy::rpc::types::Header {
+                hash: Some(block_hash),
+                number: Some(1),
+                ..Default::default()
+            },
+            transactions: BlockTransactions::Full(
+                (0..3)
+                    .map(|i| RpcTransaction {
+                        hash: B256::with_last_byte(i),
+                        block_number: Some(1),
+                        block_hash: Some(block_hash),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result(&block),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                BlockRange::new(1.into(), None),
+            ))]),
+            vec![TransactionField::BlockNumber, TransactionField::BlockHash],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transactions.len(), 3);
+        assert!(transactions
+            .iter()
+            .all(|tx| tx.block_number == Some(1) && tx.block_hash == Some(block_hash)));
+    }
+
+    #[test]
+    fn test_block_fields_are_none_for_pending_transactions() {
+        let fields = [
+            TransactionField::TransactionIndex,
+            TransactionField::BlockNumber,
+            TransactionField::BlockHash,
+        ];
+        let result = filter_fields(&RpcTransaction::default(), None, &fields, &Chain::Ethereum);
+
+        assert_eq!(result.transaction_index, None);
+        assert_eq!(result.block_number, None);
+        assert_eq!(result.block_hash, None);
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_by_block_hash() {
+        let hash = b256!("bd3e8ba8cd8a1fc1bd63e6d0a1c5a1f5cf1f2c3d1d5bd4a4d0ce1bb86f6e0aa9");
+        let other_hash = b256!("0ccf6f3e8a7e1a3f7c2a3d8b9a3c6e5f4d2b1a0c9e8d7f6a5b4c3d2e1f0a9b8c");
+        let block_with_hash = |hash: B256, number: u64, transactions: u8| RpcBlock {
+            header: alloy::rpc::types::Header {
+                hash: Some(hash),
+                number: Some(number),
+                ..Default::default()
+            },
+            transactions: BlockTransactions::Full(
+                (0..transactions)
+                    .map(|i| RpcTransaction {
+                        hash: B256::with_last_byte(i),
+                        block_hash: Some(hash),
+                        block_number: Some(number),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let block = block_with_hash(hash, 1, 3);
+        // Served for any other block, so a request for the wrong one doesn't go unnoticed
+        let other_block = block_with_hash(other_hash, 2, 1);
+        let rpc = MockRpc::start(move |method, params| match method {
+            "eth_getBlockByHash" => {
+                assert_eq!(params[1], true);
+                match params[0].as_str().unwrap().parse::<B256>().unwrap() {
+                    requested if requested == hash => result(&block),
+                    _ => result(&other_block),
+                }
+            }
+            "eth_getBlockByNumber" => {
+                assert_eq!(params[1], true);
+                match params[0].as_str().unwrap() {
+                    "0x1" => result(&block),
+                    _ => result(&other_block),
+                }
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let provider = rpc.provider();
+        let get_transactions = |block_id| {
+            let provider = provider.clone();
+            async move {
+                get_transactions_by_block_id(
+                    &block_id,
+                    &provider,
+                    &Semaphore::new(DEFAULT_MAX_CONCURRENCY),
+                    DEFAULT_MAX_BLOCK_RANGE,
+                    true,
+                )
+                .await
+                .unwrap()
+                .transactions
+            }
+        };
+
+        let by_hash = get_transactions(BlockId::Hash(hash)).await;
+        assert_eq!(rpc.calls("eth_getBlockByHash"), 1);
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 0);
+
+        let by_number = get_transactions(BlockId::Number(BlockNumberOrTag::Number(1))).await;
+        assert_eq!(rpc.calls("eth_getBlockByHash"), 1);
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 1);
+
+        let origins = |transactions: &[RpcTransaction]| {
+            transactions
+                .iter()
+                .map(|tx| (tx.hash, tx.block_hash, tx.block_number))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(by_hash.len(), 3);
+        assert_eq!(origins(&by_hash), origins(&by_number));
+        assert!(by_hash.iter().all(|tx| tx.block_hash == Some(hash)));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_query_using_block_range_filter() {
+        let rpc = Chain::Ethereum.rpc_url().unwrap();
+        let chain = ChainOrRpc::Rpc(rpc);
+        let block_id = BlockId::Range(BlockRange::new(10000000.into(), Some(10000001.into())));
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(block_id)]),
+            TransactionField::all_variants().to_vec(),
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[chain],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(transactions.len(), 211);
     }
@@ -269,8 +3355,8 @@ mod tests {
             Some(vec![
                 TransactionFilter::BlockId(block_id),
                 TransactionFilter::Value(FilterType::Comparison(ComparisonFilter::Lte(value))),
-                TransactionFilter::From(EqualityFilter::Eq(from)),
-                TransactionFilter::To(EqualityFilter::Eq(to)),
+                TransactionFilter::From(EqualityFilter::Eq(NameOrAddress::Address(from))),
+                TransactionFilter::To(EqualityFilter::Eq(NameOrAddress::Address(to))),
                 TransactionFilter::Gas(FilterType::Comparison(ComparisonFilter::Lte(gas))),
                 TransactionFilter::GasPrice(FilterType::Comparison(ComparisonFilter::Lte(
                     gas_price,
@@ -280,9 +3366,15 @@ mod tests {
             TransactionField::all_variants().to_vec(),
         );
 
-        let transactions = resolve_transaction_query(&transaction, &[chain])
-            .await
-            .unwrap();
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[chain],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
 
         let tx = transactions.first().unwrap();
         let expected_tx: TransactionQueryRes = TransactionQueryRes {
@@ -303,4 +3395,264 @@ mod tests {
         assert_eq!(tx.gas_price, expected_tx.gas_price);
         assert_eq!(tx.status, expected_tx.status);
     }
+
+    #[tokio::test]
+    async fn test_resolve_query_using_list_filter() {
+        let recipients = [
+            address!("dAC17F958D2ee523a2206206994597C13D831ec7"),
+            address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+            address!("6B175474E89094C44Da98b954EedeAC495271d0F"),
+        ];
+        let others = [Address::ZERO, Address::with_last_byte(1)];
+        let rpc = MockRpc::start(move |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => {
+                let transactions = recipients
+                    .iter()
+                    .chain(&others)
+                    .enumerate()
+                    .map(|(i, to)| RpcTransaction {
+                        hash: B256::with_last_byte(i as u8),
+                        to: Some(*to),
+                        ..Default::default()
+                    })
+                    .collect();
+                result(&RpcBlock {
+                    transactions: BlockTransactions::Full(transactions),
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![
+                TransactionFilter::BlockId(BlockId::Range(BlockRange::new(1.into(), None))),
+                TransactionFilter::To(EqualityFilter::In(
+                    recipients.map(NameOrAddress::Address).to_vec(),
+                )),
+            ]),
+            vec![TransactionField::To],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let to: Vec<_> = transactions.iter().map(|tx| tx.to.unwrap()).collect();
+        assert_eq!(to, recipients.to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_streamed_chunks_match_the_whole_query() {
+        let rpc = MockRpc::start(|method, params| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => {
+                let number = u64::from_str_radix(&params[0].as_str().unwrap()[2..], 16).unwrap();
+                let transactions = (0..2)
+                    .map(|i| RpcTransaction {
+                        hash: B256::with_last_byte((number * 2 + i) as u8),
+                        block_number: Some(number),
+                        value: U256::from(number * 10 + i),
+                        ..Default::default()
+                    })
+                    .collect();
+                result(&RpcBlock {
+                    transactions: BlockTransactions::Full(transactions),
+                    ..Default::default()
+                })
+            }
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![
+                TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                    1.into(),
+                    Some(5.into()),
+                ))),
+                TransactionFilter::Value(FilterType::Comparison(ComparisonFilter::Gt(U256::from(
+                    20,
+                )))),
+            ]),
+            vec![
+                TransactionField::Hash,
+                TransactionField::BlockNumber,
+                TransactionField::Value,
+            ],
+        );
+        let chains = vec![ChainOrRpc::Rpc(rpc.url())];
+
+        let chunks: Vec<_> = stream_transaction_query(
+            transaction.clone(),
+            chains.clone(),
+            2,
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_SCAN_DEPTH,
+            EnsConfig::default(),
+        )
+        .try_collect()
+        .await
+        .unwrap();
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &chains,
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        // Blocks 1-2, 3-4 and 5, where block 1 and one of the transactions of 2 are filtered out
+        assert_eq!(
+            chunks.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![1, 4, 2]
+        );
+        assert_eq!(chunks.concat(), transactions);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_are_split_as_they_are_streamed() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result::<RpcBlock>(&RpcBlock::default()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        // A billion chunks of a single block, which can't all be held in memory at once
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                BlockRange::new(1.into(), Some(1_000_000_000.into())),
+            ))]),
+            vec![TransactionField::Hash],
+        );
+
+        let mut chunks = stream_transaction_query(
+            transaction,
+            vec![ChainOrRpc::Rpc(rpc.url())],
+            1,
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_SCAN_DEPTH,
+            EnsConfig::default(),
+        );
+        chunks.try_next().await.unwrap();
+        chunks.try_next().await.unwrap();
+
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_chains_are_resolved_concurrently() {
+        let hash = b256!("72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890");
+        // Requests in the order they reach the RPCs of both chains
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+        let chain_rpc = |chain_id: &'static str| {
+            let arrivals = arrivals.clone();
+            MockRpc::start_with_latency(Duration::from_millis(300), move |method, _| {
+                arrivals.lock().unwrap().push(method.to_string());
+                match method {
+                    "eth_chainId" => result(&chain_id),
+                    "eth_getTransactionByHash" => result(&RpcTransaction {
+                        hash,
+                        ..Default::default()
+                    }),
+                    method => Err(format!("Unexpected method {method}")),
+                }
+            })
+        };
+        // Each chain answers two requests in sequence: the chain id and the transaction
+        let ethereum = chain_rpc("0x1").await;
+        let polygon = chain_rpc("0x89").await;
+        let transaction = Transaction::new(
+            Some(vec![hash]),
+            None,
+            vec![TransactionField::Chain, TransactionField::Hash],
+        );
+
+        let transactions = resolve_transaction_query(
+            &transaction,
+            &[
+                ChainOrRpc::Rpc(ethereum.url()),
+                ChainOrRpc::Rpc(polygon.url()),
+            ],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        // Sequentially, the first chain would get its transaction before the second chain is
+        // asked for its chain id
+        assert_eq!(
+            *arrivals.lock().unwrap(),
+            vec![
+                "eth_chainId",
+                "eth_chainId",
+                "eth_getTransactionByHash",
+                "eth_getTransactionByHash"
+            ]
+        );
+        assert_eq!(
+            transactions,
+            vec![
+                TransactionQueryRes {
+                    chain: Some(Chain::Ethereum),
+                    hash: Some(hash),
+                    ..Default::default()
+                },
+                TransactionQueryRes {
+                    chain: Some(Chain::Polygon),
+                    hash: Some(hash),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_when_block_range_is_above_the_cap() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let transaction = Transaction::new(
+            None,
+            Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                BlockRange::new(1.into(), Some(20_000_000.into())),
+            ))]),
+            vec![TransactionField::Hash],
+        );
+
+        let error = resolve_transaction_query(
+            &transaction,
+            &[ChainOrRpc::Rpc(rpc.url())],
+            DEFAULT_MAX_CONCURRENCY,
+            DEFAULT_MAX_BLOCK_RANGE,
+            &EnsConfig::default(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<BlockRangeError>(),
+            Some(BlockRangeError::RangeTooLarge(
+                1,
+                20_000_000,
+                DEFAULT_MAX_BLOCK_RANGE
+            ))
+        ));
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 0);
+    }
 }
@@ -0,0 +1,167 @@
+use alloy::transports::{http::reqwest, RpcError, TransportError, TransportErrorKind};
+use std::{future::Future, time::Duration};
+
+/// How the requests failing with a transient error are retried, see `with_retry`.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Number of retries after the first attempt fails
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every subsequent retry
+    pub base_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(250),
+        }
+    }
+}
+
+tokio::task_local! {
+    static RETRY_CONFIG: RetryConfig;
+}
+
+/// Runs the future with `config` applied to the requests it retries.
+pub(crate) async fn scope<F: Future>(config: RetryConfig, future: F) -> F::Output {
+    RETRY_CONFIG.scope(config, future).await
+}
+
+/// Retry configuration of the query being run, `RetryConfig::default()` outside of a query.
+pub(crate) fn current() -> RetryConfig {
+    RETRY_CONFIG
+        .try_with(|config| config.clone())
+        .unwrap_or_default()
+}
+
+/// Runs the request built by `request` and retries it with exponential backoff and jitter
+/// while it fails with a transient error (rate limits, server errors and network failures).
+/// The last error is returned once all the retries are exhausted.
+pub async fn with_retry<T, F, Fut>(config: &RetryConfig, request: F) -> Result<T, TransportError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, TransportError>>,
+{
+    let mut retries = 0;
+
+    loop {
+        match request().await {
+            Err(error) if retries < config.max_retries && is_transient(&error) => {
+                tokio::time::sleep(backoff(config.base_delay, retries)).await;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(error: &TransportError) -> bool {
+    match error {
+        RpcError::ErrorResp(payload) => payload.is_retry_err(),
+        RpcError::Transport(kind) => {
+            if kind.is_retry_err() {
+                return true;
+            }
+
+            match kind {
+                TransportErrorKind::HttpError(http_error) => http_error.status >= 500,
+                TransportErrorKind::Custom(error) => {
+                    error.downcast_ref::<reqwest::Error>().is_some_and(|error| {
+                        error.is_timeout()
+                            || error.is_connect()
+                            || error
+                                .status()
+                                .is_some_and(|status| status.is_server_error())
+                    })
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Exponential backoff with up to 50% of random jitter, so concurrent requests that failed
+/// together don't all retry at the same time.
+fn backoff(base_delay: Duration, retry: u32) -> Duration {
+    let delay = base_delay.saturating_mul(2u32.saturating_pow(retry));
+    let mut random = [0u8; 4];
+    let jitter = match getrandom::getrandom(&mut random) {
+        Ok(_) => u32::from_le_bytes(random) as f64 / u32::MAX as f64 / 2.0,
+        Err(_) => 0.0,
+    };
+
+    delay.mul_f64(1.0 + jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::backend::mock_rpc::{result, MockRpc};
+    use alloy::{
+        primitives::{address, U256},
+        providers::Provider,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn config() -> RetryConfig {
+        RetryConfig {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_succeeds_after_transient_errors() {
+        let attempts = AtomicUsize::new(0);
+        let rpc = MockRpc::start(move |_, _| match attempts.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Err("rate limit exceeded".to_string()),
+            _ => result(&U256::from(100)),
+        })
+        .await;
+        let provider = rpc.provider();
+        let address = address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+
+        let balance = with_retry(&config(), || async { provider.get_balance(address).await })
+            .await
+            .unwrap();
+
+        assert_eq!(balance, U256::from(100));
+        assert_eq!(rpc.calls("eth_getBalance"), 3);
+    }
+
+    #[tokio::test]
+    async fn test_request_fails_when_retries_are_exhausted() {
+        let rpc = MockRpc::start(|_, _| Err("rate limit exceeded".to_string())).await;
+        let provider = rpc.provider();
+
+        let result = with_retry(&config(), || async { provider.get_block_number().await }).await;
+
+        assert!(result.is_err());
+        assert_eq!(rpc.calls("eth_blockNumber"), 4);
+    }
+
+    #[tokio::test]
+    async fn test_non_transient_errors_are_not_retried() {
+        let rpc = MockRpc::start(|_, _| Err("execution reverted".to_string())).await;
+        let provider = rpc.provider();
+
+        let result = with_retry(&config(), || async { provider.get_block_number().await }).await;
+
+        assert!(result.is_err());
+        assert_eq!(rpc.calls("eth_blockNumber"), 1);
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially() {
+        let base_delay = Duration::from_millis(100);
+
+        for retry in 0..4 {
+            let delay = backoff(base_delay, retry);
+            let expected = base_delay * 2u32.pow(retry);
+
+            assert!(delay >= expected && delay <= expected.mul_f64(1.5));
+        }
+    }
+}
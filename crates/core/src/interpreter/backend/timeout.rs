@@ -0,0 +1,172 @@
+//! Timeout of the RPC requests made while running a query. A RPC that accepts a request but
+//! never answers it would otherwise stall the whole query, since the resolvers wait for all of
+//! their requests to complete.
+//!
+//! A request that times out fails with a transport error, so it's sent to the next RPC when the
+//! chain has fallback RPCs. It isn't retried on the same RPC, which is unlikely to answer a retry
+//! any faster.
+//!
+//! Only HTTP requests time out. Subscriptions need the WebSocket transport to be left unwrapped,
+//! and a subscription legitimately waits for as long as it's followed.
+use alloy::{
+    rpc::{
+        client::BuiltInConnectionString,
+        json_rpc::{RequestPacket, ResponsePacket},
+    },
+    transports::{
+        BoxTransport, BoxTransportConnect, Transport, TransportError, TransportErrorKind,
+        TransportFut,
+    },
+};
+use std::{
+    future::Future,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::{Layer, Service};
+
+/// Time after which a RPC request fails if it hasn't been answered
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error("Request {0} timed out after {1:?}")]
+    Timeout(String, Duration),
+}
+
+tokio::task_local! {
+    static REQUEST_TIMEOUT: Duration;
+}
+
+/// Runs the future with `timeout` applied to the requests of the providers it creates.
+pub(crate) async fn scope<F: Future>(timeout: Duration, future: F) -> F::Output {
+    REQUEST_TIMEOUT.scope(timeout, future).await
+}
+
+/// Request timeout of the query being run, `DEFAULT_REQUEST_TIMEOUT` outside of a query.
+pub(crate) fn current() -> Duration {
+    REQUEST_TIMEOUT
+        .try_with(|timeout| *timeout)
+        .unwrap_or(DEFAULT_REQUEST_TIMEOUT)
+}
+
+/// Connects to the RPC, failing its HTTP requests once they take longer than `timeout`.
+pub(crate) async fn connect(
+    url: &BuiltInConnectionString,
+    timeout: Duration,
+) -> Result<BoxTransport, TransportError> {
+    let transport = url.get_boxed_transport().await?;
+
+    match url {
+        BuiltInConnectionString::Http(_) => Ok(TimeoutLayer::new(timeout).layer(transport).boxed()),
+        _ => Ok(transport),
+    }
+}
+
+/// Transport layer failing the requests that go through it once they time out.
+pub(crate) struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl TimeoutLayer {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl<S> Layer<S> for TimeoutLayer {
+    type Service = TimeoutService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimeoutService {
+            inner,
+            timeout: self.timeout,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct TimeoutService<S> {
+    inner: S,
+    timeout: Duration,
+}
+
+impl<S: Transport> Service<RequestPacket> for TimeoutService<S> {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let method = match &request {
+            RequestPacket::Single(request) => request.method().to_string(),
+            RequestPacket::Batch(_) => String::from("batch"),
+        };
+        let timeout = self.timeout;
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, response).await {
+                Ok(response) => response,
+                Err(_) => Err(TransportErrorKind::custom(RequestError::Timeout(
+                    method, timeout,
+                ))),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        common::{
+            block::{Block, BlockField, BlockId},
+            chain::ChainOrRpc,
+            entity::Entity,
+            types::{Expression, GetExpression},
+        },
+        interpreter::backend::{
+            execution_engine::ExecutionEngine,
+            mock_rpc::{result, MockRpc},
+        },
+    };
+    use alloy::{eips::BlockNumberOrTag, rpc::types::Block as RpcBlock};
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_request_fails_once_it_times_out() {
+        let rpc = MockRpc::start_with_latency(Duration::from_secs(10), |method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            "eth_getBlockByNumber" => result::<RpcBlock>(&Default::default()),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let expression = Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![BlockId::Number(BlockNumberOrTag::Number(1))]),
+                None,
+                vec![BlockField::Number],
+            )),
+            chains: vec![ChainOrRpc::Rpc(rpc.url())],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        });
+        let start = Instant::now();
+
+        let result = ExecutionEngine::new()
+            .with_request_timeout(Duration::from_millis(100))
+            .run(vec![expression])
+            .await;
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("timed out after 100ms"));
+    }
+}
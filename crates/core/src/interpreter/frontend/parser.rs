@@ -33,6 +33,9 @@ impl<'a> Parser<'a> {
                 Rule::get => {
                     expressions.push(Expression::Get(pair.into_inner().try_into()?));
                 }
+                Rule::combined_get => {
+                    expressions.push(Expression::Combined(pair.into_inner().try_into()?));
+                }
                 _ => {
                     return Err(ParserError::UnexpectedToken(pair.as_str().to_string()).into());
                 }
@@ -47,20 +50,25 @@ impl<'a> Parser<'a> {
 mod tests {
     use super::*;
     use crate::common::{
-        account::{Account, AccountField},
-        block::{Block, BlockField, BlockId, BlockRange},
+        account::{Account, AccountField, AccountFilter},
+        aggregate::{Aggregate, AggregateFunction},
+        block::{Block, BlockField, BlockFilter, BlockId, BlockRange},
         chain::{Chain, ChainOrRpc},
+        chain_status::{ChainField, ChainStatus},
         dump::{Dump, DumpFormat},
         ens::NameOrAddress,
         entity::Entity,
         filters::{ComparisonFilter, EqualityFilter, FilterType},
         logs::{LogField, LogFilter, Logs},
+        order::{OrderBy, OrderDirection},
+        receipt::{Receipt, ReceiptField},
+        token::{Token, TokenField},
         transaction::{Transaction, TransactionField, TransactionFilter},
         types::*,
     };
     use alloy::{
         eips::BlockNumberOrTag,
-        primitives::{address, b256, Address, U128, U256},
+        primitives::{address, b256, fixed_bytes, Address, B256, U128, U256},
     };
     use pretty_assertions::assert_eq;
     use std::str::FromStr;
@@ -68,7 +76,7 @@ mod tests {
     #[test]
     fn test_build_ast_with_account_fields() {
         let source =
-            "GET nonce, balance, code FROM account 0x1234567890123456789012345678901234567890 ON eth";
+            "GET nonce, balance, code, is_contract FROM account 0x1234567890123456789012345678901234567890 ON eth";
         let address = Address::from_str("0x1234567890123456789012345678901234567890").unwrap();
         let expected = vec![Expression::Get(GetExpression {
             entity: Entity::Account(Account::new(
@@ -78,10 +86,14 @@ mod tests {
                     AccountField::Nonce,
                     AccountField::Balance,
                     AccountField::Code,
+                    AccountField::IsContract,
                 ],
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
         let parser = Parser::new(source);
 
@@ -91,6 +103,119 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_build_ast_with_token_balance_fields() {
+        let source = "GET balance, balance_of 0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48, ens_name FROM account 0x1234567890123456789012345678901234567890 ON eth";
+        let address = Address::from_str("0x1234567890123456789012345678901234567890").unwrap();
+        let token = Address::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap();
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Account(Account::new(
+                Some(vec![NameOrAddress::Address(address)]),
+                None,
+                vec![
+                    AccountField::Balance,
+                    AccountField::TokenBalance(token),
+                    AccountField::EnsName,
+                ],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+        let result = Parser::new(source).parse_expressions().unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_build_ast_with_storage_fields() {
+        let source = "GET storage[0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc], storage[0] FROM account 0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48 ON eth";
+        let address = Address::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap();
+        let slot =
+            U256::from_str("0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc")
+                .unwrap();
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Account(Account::new(
+                Some(vec![NameOrAddress::Address(address)]),
+                None,
+                vec![
+                    AccountField::Storage(slot),
+                    AccountField::Storage(U256::ZERO),
+                ],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+        let result = Parser::new(source).parse_expressions().unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_build_ast_with_call_fields() {
+        let source = "GET call(\"balanceOf(address) returns (uint256)\", 0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045), \
+            call(\"getReserves()(uint112,uint112,uint32)\") FROM account 0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc ON eth";
+        let result = Parser::new(source).parse_expressions().unwrap();
+
+        let Expression::Get(GetExpression {
+            entity: Entity::Account(account),
+            ..
+        }) = &result[0]
+        else {
+            panic!("Expected an account query");
+        };
+        let fields = account.fields();
+        let [AccountField::Call(balance_of), AccountField::Call(get_reserves)] = fields.as_slice()
+        else {
+            panic!("Expected two calls, got {fields:?}");
+        };
+        assert_eq!(
+            balance_of.name(),
+            "balanceOf(0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045)"
+        );
+        assert_eq!(&balance_of.calldata()[..4], &[0x70, 0xa0, 0x82, 0x31]);
+        assert_eq!(get_reserves.name(), "getReserves()");
+        assert_eq!(get_reserves.function().outputs.len(), 3);
+    }
+
+    #[test]
+    fn test_call_arguments_must_match_the_signature() {
+        for source in [
+            "GET call(\"balanceOf(address)\") FROM account vitalik.eth ON eth",
+            "GET call(\"balanceOf(address)\", 12) FROM account vitalik.eth ON eth",
+            "GET call(\"balanceOf(address\") FROM account vitalik.eth ON eth",
+        ] {
+            assert!(Parser::new(source).parse_expressions().is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_account_block_filter() {
+        let source = "GET balance FROM account vitalik.eth WHERE block 18000000 ON eth";
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Account(Account::new(
+                Some(vec![NameOrAddress::Name(String::from("vitalik.eth"))]),
+                Some(vec![AccountFilter::Block(BlockNumberOrTag::Number(
+                    18000000,
+                ))]),
+                vec![AccountField::Balance],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+        let result = Parser::new(source).parse_expressions().unwrap();
+
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_build_get_ast_using_ens() {
         let source = "GET nonce, balance FROM account vitalik.eth ON eth";
@@ -103,6 +228,9 @@ mod tests {
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
         let result = Parser::new(source).parse_expressions().unwrap();
 
@@ -113,8 +241,8 @@ mod tests {
     fn test_build_get_ast_with_block_fields() {
         let source = "GET parent_hash, state_root, transactions_root, receipts_root, \
             logs_bloom, extra_data, mix_hash, total_difficulty, base_fee_per_gas, \
-            withdrawals_root, blob_gas_used, excess_blob_gas, parent_beacon_block_root, \
-            size FROM block 1 ON eth";
+            withdrawals_root, withdrawals, blob_gas_used, excess_blob_gas, \
+            parent_beacon_block_root, uncles, transaction_count, size FROM block 1 ON eth";
 
         let expected = vec![Expression::Get(GetExpression {
             entity: Entity::Block(Block::new(
@@ -131,14 +259,20 @@ mod tests {
                     BlockField::TotalDifficulty,
                     BlockField::BaseFeePerGas,
                     BlockField::WithdrawalsRoot,
+                    BlockField::Withdrawals,
                     BlockField::BlobGasUsed,
                     BlockField::ExcessBlobGas,
                     BlockField::ParentBeaconBlockRoot,
+                    BlockField::Uncles,
+                    BlockField::TransactionCount,
                     BlockField::Size,
                 ],
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
 
         let parser = Parser::new(source);
@@ -149,6 +283,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_build_get_ast_with_base_fee_fields() {
+        let source = "GET base_fee, next_base_fee FROM block 18000000 ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![BlockId::Number(BlockNumberOrTag::Number(18000000))]),
+                None,
+                vec![BlockField::BaseFeePerGas, BlockField::NextBaseFee],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+        let result = Parser::new(source).parse_expressions().unwrap();
+
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_build_get_ast_using_block_ranges() {
         let source = "GET timestamp FROM block 1:2 ON eth";
@@ -163,6 +318,9 @@ mod tests {
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
         let result = Parser::new(source).parse_expressions();
 
@@ -172,6 +330,282 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_build_get_ast_using_finality_tags() {
+        let source = "GET timestamp FROM block finalized:safe, safe ON eth";
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![
+                    BlockId::Range(BlockRange::new(
+                        BlockNumberOrTag::Finalized,
+                        Some(BlockNumberOrTag::Safe),
+                    )),
+                    BlockId::Number(BlockNumberOrTag::Safe),
+                ]),
+                None,
+                vec![BlockField::Timestamp],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_get_ast_using_open_block_ranges() {
+        let source = "GET timestamp FROM block 100:, :10 ON eth\n\
+            GET hash FROM tx WHERE block 18000000: ON eth";
+        let expected = vec![
+            Expression::Get(GetExpression {
+                entity: Entity::Block(Block::new(
+                    Some(vec![
+                        BlockId::Range(BlockRange::new(
+                            BlockNumberOrTag::Number(100),
+                            Some(BlockNumberOrTag::Latest),
+                        )),
+                        BlockId::Range(BlockRange::new(
+                            BlockNumberOrTag::Number(0),
+                            Some(BlockNumberOrTag::Number(10)),
+                        )),
+                    ]),
+                    None,
+                    vec![BlockField::Timestamp],
+                )),
+                chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            }),
+            Expression::Get(GetExpression {
+                entity: Entity::Transaction(Transaction::new(
+                    None,
+                    Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                        BlockRange::new(
+                            BlockNumberOrTag::Number(18000000),
+                            Some(BlockNumberOrTag::Latest),
+                        ),
+                    ))]),
+                    vec![TransactionField::Hash],
+                )),
+                chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            }),
+        ];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_get_ast_using_relative_blocks() {
+        let source = "GET timestamp FROM block latest-100:latest, finalized-5 ON eth";
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![
+                    BlockId::Range(
+                        BlockRange::new(BlockNumberOrTag::Latest, Some(BlockNumberOrTag::Latest))
+                            .with_offsets(100, 0),
+                    ),
+                    BlockId::Range(
+                        BlockRange::new(BlockNumberOrTag::Finalized, None).with_offsets(5, 0),
+                    ),
+                ]),
+                None,
+                vec![BlockField::Timestamp],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_get_ast_using_block_filters() {
+        let source =
+            "GET number FROM block WHERE block 18000000:18000100 AND gas_used > 15000000, \
+            miner = 0x95222290DD7278Aa3Ddd389Cc1E1d165CC4BAfe5 ON eth";
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![]),
+                Some(vec![
+                    BlockFilter::Range(BlockRange::new(
+                        BlockNumberOrTag::Number(18000000),
+                        Some(BlockNumberOrTag::Number(18000100)),
+                    )),
+                    BlockFilter::GasUsed(FilterType::Comparison(ComparisonFilter::Gt(15000000))),
+                    BlockFilter::Miner(EqualityFilter::Eq(address!(
+                        "95222290DD7278Aa3Ddd389Cc1E1d165CC4BAfe5"
+                    ))),
+                ]),
+                vec![BlockField::Number],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_get_ast_using_block_between_filter() {
+        let source = "GET number FROM block WHERE block 18000000:18000100 \
+            AND base_fee_per_gas BETWEEN 1 AND 5, timestamp >= 17e8 ON eth";
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![]),
+                Some(vec![
+                    BlockFilter::Range(BlockRange::new(
+                        BlockNumberOrTag::Number(18000000),
+                        Some(BlockNumberOrTag::Number(18000100)),
+                    )),
+                    BlockFilter::BaseFeePerGas(FilterType::Comparison(ComparisonFilter::Between(
+                        1, 5,
+                    ))),
+                    BlockFilter::Timestamp(FilterType::Comparison(ComparisonFilter::Gte(
+                        1700000000,
+                    ))),
+                ]),
+                vec![BlockField::Number],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_get_ast_with_token_fields() {
+        let source =
+            "GET symbol, decimals FROM token 0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48 ON eth";
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Token(Token::new(
+                vec![address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")],
+                vec![TokenField::Symbol, TokenField::Decimals],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_get_ast_with_receipt_fields() {
+        let source = "GET gas_used, status, logs_bloom, logs FROM receipt \
+            0x72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890 ON eth";
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Receipt(Receipt::new(
+                vec![b256!(
+                    "72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890"
+                )],
+                vec![
+                    ReceiptField::GasUsed,
+                    ReceiptField::Status,
+                    ReceiptField::LogsBloom,
+                    ReceiptField::Logs,
+                ],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_get_ast_with_chain_fields() {
+        let expected = |chains| {
+            vec![Expression::Get(GetExpression {
+                entity: Entity::Chain(ChainStatus::new(vec![
+                    ChainField::BlockNumber,
+                    ChainField::GasPrice,
+                    ChainField::ChainId,
+                ])),
+                chains,
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            })]
+        };
+
+        // The chains follow the entity, with or without `ON`
+        for (source, chains) in [
+            (
+                "GET block_number, gas_price, chain_id FROM chain eth",
+                vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            ),
+            (
+                "GET block_number, gas_price, chain_id FROM chain ON eth, base",
+                vec![
+                    ChainOrRpc::Chain(Chain::Ethereum),
+                    ChainOrRpc::Chain(Chain::Base),
+                ],
+            ),
+        ] {
+            match Parser::new(source).parse_expressions() {
+                Ok(result) => assert_eq!(result, expected(chains)),
+                Err(e) => panic!("Error: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_chains_named_without_on_are_set_apart_from_the_entity() {
+        for source in [
+            "GET gas_price FROM chaineth",
+            "GET gas_price FROM chainON eth",
+            "GET gas_price FROM chain_eth",
+        ] {
+            assert!(Parser::new(source).parse_expressions().is_err(), "{source}");
+        }
+    }
+
     #[test]
     fn test_build_get_ast_using_block_number_list() {
         let source = "GET timestamp FROM block 1,2,3 ON eth";
@@ -188,6 +622,9 @@ mod tests {
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
 
         match Parser::new(source).parse_expressions() {
@@ -198,10 +635,10 @@ mod tests {
 
     #[test]
     fn test_build_ast_with_transaction_fields() {
-        let source = "GET transaction_type, hash, from, to, data, value, gas_price, gas, \
-            status, v, r, s, max_fee_per_blob_gas, max_fee_per_gas, \
-            max_priority_fee_per_gas, y_parity \
-            FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 \
+        let source = "GET transaction_type, hash, transaction_index, block_number, block_hash, \
+            from, to, data, value, gas_price, gas, status, v, r, s, max_fee_per_blob_gas, \
+            max_fee_per_gas, max_priority_fee_per_gas, effective_tip, gas_price_percentile, \
+            confirmations, y_parity, signer_valid FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 \
             ON eth";
 
         let expected = vec![Expression::Get(GetExpression {
@@ -213,6 +650,9 @@ mod tests {
                 vec![
                     TransactionField::TransactionType,
                     TransactionField::Hash,
+                    TransactionField::TransactionIndex,
+                    TransactionField::BlockNumber,
+                    TransactionField::BlockHash,
                     TransactionField::From,
                     TransactionField::To,
                     TransactionField::Data,
@@ -226,11 +666,18 @@ mod tests {
                     TransactionField::MaxFeePerBlobGas,
                     TransactionField::MaxFeePerGas,
                     TransactionField::MaxPriorityFeePerGas,
+                    TransactionField::EffectiveTip,
+                    TransactionField::GasPricePercentile,
+                    TransactionField::Confirmations,
                     TransactionField::YParity,
+                    TransactionField::SignerValid,
                 ],
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
 
         match Parser::new(source).parse_expressions() {
@@ -240,20 +687,33 @@ mod tests {
     }
 
     #[test]
-    fn test_build_ast_from_transaction_list() {
-        let source = "GET hash FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81, 0x12afe6797be838900c5632de516ab415addd026335461e9471dfdec17f3d4510 ON eth";
+    fn test_build_ast_with_transaction_receipt_fields() {
+        let source = "GET status, gas_used, effective_gas_price, cumulative_gas_used, \
+            logs_bloom, contract_address, logs \
+            FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 \
+            ON eth";
 
         let expected = vec![Expression::Get(GetExpression {
             entity: Entity::Transaction(Transaction::new(
-                Some(vec![
-                    b256!("8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81"),
-                    b256!("12afe6797be838900c5632de516ab415addd026335461e9471dfdec17f3d4510"),
-                ]),
+                Some(vec![b256!(
+                    "8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81"
+                )]),
                 None,
-                vec![TransactionField::Hash],
+                vec![
+                    TransactionField::Status,
+                    TransactionField::GasUsed,
+                    TransactionField::EffectiveGasPrice,
+                    TransactionField::CumulativeGasUsed,
+                    TransactionField::LogsBloom,
+                    TransactionField::ContractAddress,
+                    TransactionField::Logs,
+                ],
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
 
         match Parser::new(source).parse_expressions() {
@@ -263,17 +723,22 @@ mod tests {
     }
 
     #[test]
-    fn test_build_ast_with_dump() {
-        let source = "GET balance FROM account vitalik.eth ON eth >> vitalik-balance.csv";
+    fn test_build_ast_with_transaction_trace() {
+        let source = "GET hash, trace FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 ON eth";
 
         let expected = vec![Expression::Get(GetExpression {
-            entity: Entity::Account(Account::new(
-                Some(vec![NameOrAddress::Name("vitalik.eth".to_string())]),
+            entity: Entity::Transaction(Transaction::new(
+                Some(vec![b256!(
+                    "8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81"
+                )]),
                 None,
-                vec![AccountField::Balance],
+                vec![TransactionField::Hash, TransactionField::Trace],
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
-            dump: Some(Dump::new("vitalik-balance".to_string(), DumpFormat::Csv)),
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
 
         match Parser::new(source).parse_expressions() {
@@ -283,17 +748,360 @@ mod tests {
     }
 
     #[test]
-    fn test_build_ast_with_log_fields() {
-        let source =
-            "GET address, topic0, topic1, topic2, topic3, data, block_hash, block_number, \
-            block_timestamp, transaction_hash, transaction_index, log_index, removed \
-            FROM log \
-            WHERE block = 4638757, \
-                  address = 0xdAC17F958D2ee523a2206206994597C13D831ec7, \
-                  topic0 = 0xcb8241adb0c3fdb35b70c24ce35c5eb0c17af7431c99f827d44a445ca624176a \
-            ON eth,\n\
-            GET address \
-            FROM log \
+    fn test_build_ast_with_transaction_wildcard() {
+        let source = "GET * FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 ON eth";
+
+        let expressions = Parser::new(source).parse_expressions().unwrap();
+        let Expression::Get(GetExpression {
+            entity: Entity::Transaction(transaction),
+            ..
+        }) = &expressions[0]
+        else {
+            panic!("Expected a transaction expression");
+        };
+
+        assert_eq!(transaction.fields(), &TransactionField::wildcard_fields());
+        assert!(!transaction.fields().iter().any(|f| f.is_receipt_field()));
+        assert!(!transaction.fields().contains(&TransactionField::Trace));
+        assert!(!transaction
+            .fields()
+            .contains(&TransactionField::EffectiveTip));
+    }
+
+    #[test]
+    fn test_build_ast_with_transaction_wildcard_and_receipt_fields() {
+        let source = "GET *, gas_used, trace, hash FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                Some(vec![b256!(
+                    "8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81"
+                )]),
+                None,
+                [
+                    TransactionField::wildcard_fields(),
+                    vec![TransactionField::GasUsed, TransactionField::Trace],
+                ]
+                .concat(),
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_from_transaction_list() {
+        let source = "GET hash FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81, 0x12afe6797be838900c5632de516ab415addd026335461e9471dfdec17f3d4510 ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                Some(vec![
+                    b256!("8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81"),
+                    b256!("12afe6797be838900c5632de516ab415addd026335461e9471dfdec17f3d4510"),
+                ]),
+                None,
+                vec![TransactionField::Hash],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_from_transaction_list_with_block_filter() {
+        let source =
+            "GET hash FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 \
+            WHERE block 18000000:18000100 ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                Some(vec![b256!(
+                    "8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81"
+                )]),
+                Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                    BlockRange::new(18000000.into(), Some(18000100.into())),
+                ))]),
+                vec![TransactionField::Hash],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_sender_and_nonce_filters() {
+        let source = "GET hash, nonce FROM tx \
+            WHERE from = 0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045, nonce = 1000 ON eth";
+
+        #[rustfmt::skip]
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![
+                    TransactionFilter::From(EqualityFilter::Eq(NameOrAddress::Address(
+                        address!("d8dA6BF26964aF9D7eEd9e03E53415D37aA96045"),
+                    ))),
+                    TransactionFilter::Nonce(FilterType::Equality(EqualityFilter::Eq(1000))),
+                ]),
+                vec![TransactionField::Hash, TransactionField::Nonce],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_invalid_nonce_filters() {
+        let query = |nonce: &str| {
+            Parser::new(&format!(
+                "GET hash FROM tx WHERE from = 0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045, \
+                nonce = {nonce} ON eth"
+            ))
+            .parse_expressions()
+        };
+
+        assert_eq!(
+            query("1.5").unwrap_err().to_string(),
+            "Invalid number in filter: 1.5"
+        );
+        assert_eq!(
+            query("18446744073709551616").unwrap_err().to_string(),
+            "Invalid number in filter: 18446744073709551616"
+        );
+        // Integers in scientific notation are expanded
+        let Expression::Get(GetExpression {
+            entity: Entity::Transaction(transaction),
+            ..
+        }) = &query("1e3").unwrap()[0]
+        else {
+            panic!("Expected a transaction expression");
+        };
+        assert_eq!(
+            transaction.filters().unwrap()[1],
+            TransactionFilter::Nonce(FilterType::Equality(EqualityFilter::Eq(1000)))
+        );
+    }
+
+    #[test]
+    fn test_build_ast_with_dump() {
+        let source = "GET balance FROM account vitalik.eth ON eth >> vitalik-balance.csv";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Account(Account::new(
+                Some(vec![NameOrAddress::Name("vitalik.eth".to_string())]),
+                None,
+                vec![AccountField::Balance],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: Some(Dump::new("vitalik-balance".to_string(), DumpFormat::Csv)),
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_compressed_dump() {
+        let source = "GET hash FROM tx WHERE block 18000000 ON eth >> exports/txs.csv.gz";
+
+        let dump = match Parser::new(source).parse_expressions() {
+            Ok(mut result) => match result.remove(0) {
+                Expression::Get(get_expr) => get_expr.dump,
+                expr => panic!("Unexpected expression {:?}", expr),
+            },
+            Err(e) => panic!("Error: {}", e),
+        };
+
+        assert_eq!(
+            dump,
+            Some(Dump::new("exports/txs".to_string(), DumpFormat::Csv).with_compression(true))
+        );
+        assert_eq!(dump.unwrap().path(), "exports/txs.csv.gz");
+    }
+
+    #[test]
+    fn test_build_ast_with_order_by_and_limit() {
+        let source = "GET hash, value FROM tx WHERE block 18000000 ON http://localhost:8545 \
+            ORDER BY value DESC LIMIT 10 >> top-transfers.csv";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                    BlockRange::new(18000000.into(), None),
+                ))]),
+                vec![TransactionField::Hash, TransactionField::Value],
+            )),
+            chains: vec![ChainOrRpc::Rpc("http://localhost:8545".parse().unwrap())],
+            dump: Some(Dump::new("top-transfers".to_string(), DumpFormat::Csv)),
+            order_by: Some(OrderBy::new("value", OrderDirection::Desc)),
+            limit: Some(10),
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_combined_entities() {
+        // The `AND` following the filters of the transactions starts the next entity
+        let source = "GET hash, value FROM tx WHERE block 18000000 AND GET number, hash FROM block 18000000 ON eth";
+
+        let expected = vec![Expression::Combined(CombinedExpression {
+            expressions: vec![
+                GetExpression {
+                    entity: Entity::Transaction(Transaction::new(
+                        None,
+                        Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                            BlockRange::new(18000000.into(), None),
+                        ))]),
+                        vec![TransactionField::Hash, TransactionField::Value],
+                    )),
+                    chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+                    dump: None,
+                    order_by: None,
+                    limit: None,
+                    aggregates: vec![],
+                },
+                GetExpression {
+                    entity: Entity::Block(Block::new(
+                        Some(vec![BlockId::Number(BlockNumberOrTag::Number(18000000))]),
+                        None,
+                        vec![BlockField::Number, BlockField::Hash],
+                    )),
+                    chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+                    dump: None,
+                    order_by: None,
+                    limit: None,
+                    aggregates: vec![],
+                },
+            ],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_order_by_unselected_field() {
+        let source = "GET hash FROM tx WHERE block 18000000 ON eth ORDER BY value";
+
+        let result = Parser::new(source).parse_expressions();
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Results can only be ordered by a selected field, value isn't selected"
+        );
+    }
+
+    #[test]
+    fn test_build_ast_with_aggregates() {
+        let source = "GET count(*), sum(value), avg(gas_price) FROM tx WHERE block 18000000 ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![TransactionFilter::BlockId(BlockId::Range(
+                    BlockRange::new(18000000.into(), None),
+                ))]),
+                vec![TransactionField::Value, TransactionField::GasPrice],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![
+                Aggregate::new(AggregateFunction::Count, None),
+                Aggregate::new(AggregateFunction::Sum, Some("value")),
+                Aggregate::new(AggregateFunction::Avg, Some("gas_price")),
+            ],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_aggregates_and_fields() {
+        let source = "GET from, sum(value) FROM tx WHERE block 18000000 ON eth";
+
+        let result = Parser::new(source).parse_expressions();
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Aggregates can't be selected along with fields"
+        );
+    }
+
+    #[test]
+    fn test_build_receipt_ast_with_aggregates_and_fields() {
+        let source = "GET sum(gas_used), status FROM receipt \
+            0x72546b3ca8ef0dfb85fe66d19645e44cb519858c72fbcad0e1c1699256fed890 ON eth";
+
+        let result = Parser::new(source).parse_expressions();
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Aggregates can't be selected along with fields"
+        );
+    }
+
+    #[test]
+    fn test_build_ast_with_log_fields() {
+        let source =
+            "GET address, topic0, topic1, topic2, topic3, data, block_hash, block_number, \
+            block_timestamp, transaction_hash, transaction_index, log_index, removed \
+            FROM log \
+            WHERE block = 4638757, \
+                  address = 0xdAC17F958D2ee523a2206206994597C13D831ec7, \
+                  topic0 = 0xcb8241adb0c3fdb35b70c24ce35c5eb0c17af7431c99f827d44a445ca624176a \
+            ON eth,\n\
+            GET address \
+            FROM log \
             WHERE block_hash = 0xedb7f4a64744594838f7d9888883ae964fcb4714f6fe5cafb574d3ed6141ad5b, \
                   event_signature = Transfer(address,address,uint256), \
                   topic1 = 0x00000000000000000000000036928500Bc1dCd7af6a2B4008875CC336b927D57, \
@@ -334,6 +1142,9 @@ mod tests {
                 )),
                 chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
                 dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
             }),
             Expression::Get(GetExpression {
                 entity: Entity::Logs(Logs::new(
@@ -355,6 +1166,9 @@ mod tests {
                 )),
                 chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
                 dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
             }),
         ];
 
@@ -364,6 +1178,100 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_build_ast_with_named_event_signature() {
+        let source = "GET decoded FROM log \
+            WHERE block = 4638757, \
+                  event_signature = Transfer(address indexed from, address indexed to, uint256 value) \
+            ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Logs(Logs::new(
+                vec![
+                    LogFilter::BlockRange(BlockRange::new(BlockNumberOrTag::Number(4638757), None)),
+                    LogFilter::EventSignature(String::from(
+                        "Transfer(address indexed from, address indexed to, uint256 value)",
+                    )),
+                ],
+                vec![LogField::Decoded],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_method_id_filter() {
+        let source = "GET hash FROM tx WHERE block 18000000 AND method_id 0xa9059cbb ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![
+                    TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                        BlockNumberOrTag::Number(18000000),
+                        None,
+                    ))),
+                    TransactionFilter::MethodId(EqualityFilter::Eq(fixed_bytes!("a9059cbb"))),
+                ]),
+                vec![TransactionField::Hash],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_log_filters_without_operators() {
+        let source = "GET address, data FROM log \
+            WHERE block 18000000:18000100 \
+            AND address 0xdAC17F958D2ee523a2206206994597C13D831ec7 \
+            AND topic0 0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef \
+            ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Logs(Logs::new(
+                vec![
+                    LogFilter::BlockRange(BlockRange::new(
+                        BlockNumberOrTag::Number(18000000),
+                        Some(BlockNumberOrTag::Number(18000100)),
+                    )),
+                    LogFilter::EmitterAddress(address!("dac17f958d2ee523a2206206994597c13d831ec7")),
+                    LogFilter::Topic0(b256!(
+                        "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+                    )),
+                ],
+                vec![LogField::Address, LogField::Data],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
     #[test]
     fn test_build_ast_with_rpc_url() {
         let source = "GET nonce, balance FROM account 0x1234567890123456789012345678901234567890 ON http://localhost:8545";
@@ -376,6 +1284,57 @@ mod tests {
             )),
             chains: vec![ChainOrRpc::Rpc("http://localhost:8545".parse().unwrap())],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_websocket_rpc_url() {
+        let source = "GET number FROM block 1 ON wss://ethereum-rpc.publicnode.com";
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![BlockId::Number(BlockNumberOrTag::Number(1))]),
+                None,
+                vec![BlockField::Number],
+            )),
+            chains: vec![ChainOrRpc::Rpc(
+                "wss://ethereum-rpc.publicnode.com".parse().unwrap(),
+            )],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_ipc_rpc_url() {
+        let source = "GET number FROM block 1 ON ipc:///home/eth/.ethereum/geth.ipc";
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Block(Block::new(
+                Some(vec![BlockId::Number(BlockNumberOrTag::Number(1))]),
+                None,
+                vec![BlockField::Number],
+            )),
+            chains: vec![ChainOrRpc::Rpc(
+                "ipc:///home/eth/.ethereum/geth.ipc".parse().unwrap(),
+            )],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
 
         match Parser::new(source).parse_expressions() {
@@ -428,17 +1387,209 @@ mod tests {
                     )))),
                     TransactionFilter::Status(EqualityFilter::Eq(true)),
                     TransactionFilter::YParity(EqualityFilter::Eq(false)),
-                    TransactionFilter::From(EqualityFilter::Eq(
+                    TransactionFilter::From(EqualityFilter::Eq(NameOrAddress::Address(
                         Address::from_str("0x1234567890123456789012345678901234567890").unwrap(),
-                    )),
-                    TransactionFilter::To(EqualityFilter::Eq(
+                    ))),
+                    TransactionFilter::To(EqualityFilter::Eq(NameOrAddress::Address(
                         Address::from_str("0x1234567890123456789012345678901234567890").unwrap(),
+                    ))),
+                ]),
+                // The status filter reads the status without adding it to `*`
+                TransactionField::wildcard_fields(),
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_transaction_ens_filters() {
+        let source =
+            "GET hash FROM tx WHERE block = 4638757, from = vitalik.eth, to != nick.eth ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![
+                    TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                        BlockNumberOrTag::Number(4638757),
+                        None,
+                    ))),
+                    TransactionFilter::From(EqualityFilter::Eq(NameOrAddress::Name(String::from(
+                        "vitalik.eth",
+                    )))),
+                    TransactionFilter::To(EqualityFilter::Neq(NameOrAddress::Name(String::from(
+                        "nick.eth",
+                    )))),
+                ]),
+                vec![TransactionField::Hash],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_transaction_between_filters() {
+        let source = "GET hash FROM tx WHERE block = 4638757 \
+            AND value BETWEEN 100000000000000000 AND 1000000000000000000 \
+            AND gas_price between 1000000000 and 2000000000 AND gas > 21000 ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![
+                    TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                        BlockNumberOrTag::Number(4638757),
+                        None,
+                    ))),
+                    TransactionFilter::Value(FilterType::Comparison(ComparisonFilter::Between(
+                        U256::from(100000000000000000u64),
+                        U256::from(1000000000000000000u64),
+                    ))),
+                    TransactionFilter::GasPrice(FilterType::Comparison(ComparisonFilter::Between(
+                        1000000000, 2000000000,
+                    ))),
+                    TransactionFilter::Gas(FilterType::Comparison(ComparisonFilter::Gt(21000))),
+                ]),
+                vec![TransactionField::Hash],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_transaction_exponent_filters() {
+        let source = "GET hash FROM tx WHERE block = 4638757 \
+            AND value BETWEEN 1e17 AND 1E18 AND gas between 21000 and 1e5 \
+            AND max_fee_per_gas > 3e10 ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![
+                    TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                        BlockNumberOrTag::Number(4638757),
+                        None,
+                    ))),
+                    TransactionFilter::Value(FilterType::Comparison(ComparisonFilter::Between(
+                        U256::from(100000000000000000u64),
+                        U256::from(1000000000000000000u64),
+                    ))),
+                    TransactionFilter::Gas(FilterType::Comparison(ComparisonFilter::Between(
+                        21000, 100000,
+                    ))),
+                    TransactionFilter::MaxFeePerGas(FilterType::Comparison(ComparisonFilter::Gt(
+                        30000000000,
+                    ))),
+                ]),
+                vec![TransactionField::Hash],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_transaction_list_filters() {
+        let source = "GET hash FROM tx WHERE block = 4638757, \
+            to IN (0xdAC17F958D2ee523a2206206994597C13D831ec7, vitalik.eth), \
+            from NOT IN (0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48) ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![
+                    TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                        BlockNumberOrTag::Number(4638757),
+                        None,
+                    ))),
+                    TransactionFilter::To(EqualityFilter::In(vec![
+                        NameOrAddress::Address(address!(
+                            "dAC17F958D2ee523a2206206994597C13D831ec7"
+                        )),
+                        NameOrAddress::Name(String::from("vitalik.eth")),
+                    ])),
+                    TransactionFilter::From(EqualityFilter::NotIn(vec![NameOrAddress::Address(
+                        address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+                    )])),
+                ]),
+                vec![TransactionField::Hash],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_build_ast_with_transaction_address_range_filters() {
+        let source = "GET hash FROM tx WHERE block = 4638757, \
+            to < 0x0000000000000000000000000000000000001000, \
+            from BETWEEN 0x0000000000000000000000000000000000000001 \
+            AND 0x00000000000000000000000000000000000000ff ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![
+                    TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                        BlockNumberOrTag::Number(4638757),
+                        None,
+                    ))),
+                    TransactionFilter::ToRange(ComparisonFilter::Lt(U256::from(0x1000))),
+                    TransactionFilter::FromRange(ComparisonFilter::Between(
+                        U256::from(1),
+                        U256::from(0xff),
                     )),
                 ]),
-                TransactionField::all_variants().to_vec(),
+                vec![TransactionField::Hash],
             )),
             chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
 
         match Parser::new(source).parse_expressions() {
@@ -447,6 +1598,98 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_build_ast_with_transaction_or_filters() {
+        let source = "GET hash FROM tx WHERE block = 4638757, \
+            (to 0xdAC17F958D2ee523a2206206994597C13D831ec7 OR to vitalik.eth AND value > 0) \
+            AND status = true ON eth";
+
+        let expected = vec![Expression::Get(GetExpression {
+            entity: Entity::Transaction(Transaction::new(
+                None,
+                Some(vec![
+                    TransactionFilter::BlockId(BlockId::Range(BlockRange::new(
+                        BlockNumberOrTag::Number(4638757),
+                        None,
+                    ))),
+                    TransactionFilter::Or(vec![
+                        TransactionFilter::To(EqualityFilter::Eq(NameOrAddress::Address(
+                            address!("dAC17F958D2ee523a2206206994597C13D831ec7"),
+                        ))),
+                        TransactionFilter::And(vec![
+                            TransactionFilter::To(EqualityFilter::Eq(NameOrAddress::Name(
+                                String::from("vitalik.eth"),
+                            ))),
+                            TransactionFilter::Value(FilterType::Comparison(ComparisonFilter::Gt(
+                                U256::from(0),
+                            ))),
+                        ]),
+                    ]),
+                    TransactionFilter::Status(EqualityFilter::Eq(true)),
+                ]),
+                vec![TransactionField::Hash],
+            )),
+            chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+            dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
+        })];
+
+        match Parser::new(source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_block_filter_cannot_be_grouped_with_or() {
+        let source = "GET hash FROM tx WHERE (block = 1 OR block = 2) ON eth";
+
+        assert!(Parser::new(source).parse_expressions().is_err());
+    }
+
+    #[test]
+    fn test_build_ast_with_block_hash() {
+        let hash = "0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6";
+        let source = format!(
+            "GET number FROM block {hash} ON eth; GET hash FROM tx WHERE block = {hash} ON eth"
+        );
+        let block_id = BlockId::Hash(B256::from_str(hash).unwrap());
+
+        let expected = vec![
+            Expression::Get(GetExpression {
+                entity: Entity::Block(Block::new(
+                    Some(vec![block_id.clone()]),
+                    None,
+                    vec![BlockField::Number],
+                )),
+                chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            }),
+            Expression::Get(GetExpression {
+                entity: Entity::Transaction(Transaction::new(
+                    None,
+                    Some(vec![TransactionFilter::BlockId(block_id)]),
+                    vec![TransactionField::Hash],
+                )),
+                chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
+                dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
+            }),
+        ];
+
+        match Parser::new(&source).parse_expressions() {
+            Ok(result) => assert_eq!(result, expected),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
     #[test]
     fn test_build_ast_with_chain_fields() {
         let test_cases = vec![
@@ -492,6 +1735,9 @@ mod tests {
                 entity: expected_entity,
                 chains: vec![ChainOrRpc::Chain(Chain::Ethereum)],
                 dump: None,
+                order_by: None,
+                limit: None,
+                aggregates: vec![],
             })];
 
             let parser = Parser::new(source);
@@ -517,6 +1763,9 @@ mod tests {
                 ChainOrRpc::Chain(Chain::Arbitrum),
             ],
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
 
         match Parser::new(source).parse_expressions() {
@@ -539,6 +1788,9 @@ mod tests {
                 .map(|c| ChainOrRpc::Chain(c.clone()))
                 .collect(),
             dump: None,
+            order_by: None,
+            limit: None,
+            aggregates: vec![],
         })];
 
         match Parser::new(source).parse_expressions() {
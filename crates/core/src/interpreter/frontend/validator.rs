@@ -0,0 +1,542 @@
+//! Validation of queries without running them, for editors and linters of saved queries. Besides
+//! the syntax, it checks that the fields and filters are supported by the entity they're used
+//! with, that addresses are well formed and that block ranges don't end before they start.
+//!
+//! The grammar only accepts the fields and filters of each entity, so a field the entity doesn't
+//! have is a syntax error, which only tells which tokens the parser expected. Programs the
+//! grammar rejects are outlined instead, splitting each statement into its entity, fields and
+//! filters, which are then checked against the rules of the entity. Every error is reported with
+//! the line and column it was found at.
+use super::parser::{Parser, Rule};
+use crate::common::{
+    block::{BlockFilter, BlockId, BlockRange},
+    entity::Entity,
+    logs::{LogField, LogFilter},
+    transaction::TransactionFilter,
+    types::{CombinedExpression, GetExpression},
+};
+use alloy::eips::BlockNumberOrTag;
+use pest::{
+    error::{Error, ErrorVariant, InputLocation},
+    iterators::Pair,
+    Parser as PestParser,
+};
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+
+/// Line and column of the source, both starting at 1
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    fn new(source: &str, offset: usize) -> Self {
+        let (line, column) = pest::Position::new(source, offset)
+            .map(|position| position.line_col())
+            .unwrap_or((1, 1));
+
+        Self { line, column }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, thiserror::Error)]
+pub enum ValidationError {
+    #[error("{at}: {message}")]
+    Syntax { at: Position, message: String },
+    #[error("{at}: unknown entity {entity}")]
+    UnknownEntity { at: Position, entity: String },
+    #[error("{at}: {entity} has no field {field}")]
+    UnknownField {
+        at: Position,
+        entity: String,
+        field: String,
+    },
+    #[error("{at}: {entity} queries can't be filtered by {filter}")]
+    UnsupportedFilter {
+        at: Position,
+        entity: String,
+        filter: String,
+    },
+    #[error("{at}: invalid address {address}, addresses are 0x followed by 40 hex digits")]
+    InvalidAddress { at: Position, address: String },
+    #[error("{at}: block range {range} ends before it starts")]
+    ReversedBlockRange { at: Position, range: String },
+    #[error("{at}: the {field} field requires an {filter} filter")]
+    MissingFilter {
+        at: Position,
+        field: String,
+        filter: String,
+    },
+    #[error("{at}: {message}")]
+    Invalid { at: Position, message: String },
+}
+
+/// Validates every query of the program, returning the errors found. The source is only
+/// parsed, no RPC is contacted, so ENS names and block tags aren't resolved.
+///
+/// When the grammar rejects the program, its statements are only outlined, and the errors are
+/// the entities, fields and filters of the outline that the grammar doesn't accept, or the syntax
+/// error itself if there are none. The checks of the parsed queries, like block ranges ending
+/// before they start, are left out until the syntax is fixed.
+pub fn validate(source: &str) -> Vec<ValidationError> {
+    let pairs = match Parser::parse(Rule::program, source) {
+        Ok(pairs) => pairs,
+        Err(error) => {
+            let errors = check_outline(source);
+
+            return match errors.is_empty() {
+                true => vec![diagnose(source, error)],
+                false => errors,
+            };
+        }
+    };
+    let mut errors = vec![];
+
+    for pair in pairs {
+        let at = Position::new(source, pair.as_span().start());
+        let expressions = match pair.as_rule() {
+            Rule::get => GetExpression::try_from(pair.into_inner()).map(|expr| vec![expr]),
+            Rule::combined_get => {
+                CombinedExpression::try_from(pair.into_inner()).map(|combined| combined.expressions)
+            }
+            _ => continue,
+        };
+
+        match expressions {
+            Ok(expressions) => {
+                for expression in expressions {
+                    errors.extend(check_expression(&expression, at));
+                }
+            }
+            Err(e) => errors.push(ValidationError::Invalid {
+                at,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    errors
+}
+
+/// Checks what the grammar can't, at the position of the statement
+fn check_expression(expression: &GetExpression, at: Position) -> Vec<ValidationError> {
+    let mut errors: Vec<ValidationError> = block_ranges(&expression.entity)
+        .into_iter()
+        .filter(|range| is_reversed(range))
+        .map(|range| ValidationError::ReversedBlockRange {
+            at,
+            range: range.to_string(),
+        })
+        .collect();
+
+    if let Entity::Logs(logs) = &expression.entity {
+        let has_signature = logs
+            .filter()
+            .iter()
+            .any(|filter| matches!(filter, LogFilter::EventSignature(_)));
+
+        if logs.fields().contains(&LogField::Decoded) && !has_signature {
+            errors.push(ValidationError::MissingFilter {
+                at,
+                field: LogField::Decoded.to_string(),
+                filter: "event_signature".to_string(),
+            });
+        }
+    }
+
+    errors
+}
+
+/// Block ranges of the ids and filters of the entity
+fn block_ranges(entity: &Entity) -> Vec<&BlockRange> {
+    match entity {
+        Entity::Block(block) => block
+            .ids()
+            .into_iter()
+            .flatten()
+            .filter_map(|id| match id {
+                BlockId::Range(range) => Some(range),
+                _ => None,
+            })
+            .chain(
+                block
+                    .filters()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|filter| match filter {
+                        BlockFilter::Range(range) => Some(range),
+                        _ => None,
+                    }),
+            )
+            .collect(),
+        Entity::Transaction(tx) => tx
+            .filters()
+            .into_iter()
+            .flatten()
+            .filter_map(|filter| match filter {
+                TransactionFilter::BlockId(BlockId::Range(range)) => Some(range),
+                _ => None,
+            })
+            .collect(),
+        Entity::Logs(logs) => logs
+            .filter()
+            .iter()
+            .filter_map(|filter| match filter {
+                LogFilter::BlockRange(range) => Some(range),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Whether the range ends before it starts. Ranges between different tags, like
+/// `finalized:latest`, can only be checked once the tags are resolved.
+fn is_reversed(range: &BlockRange) -> bool {
+    let (start_offset, end_offset) = range.offsets();
+
+    match (range.start(), range.end()) {
+        (BlockNumberOrTag::Number(start), Some(BlockNumberOrTag::Number(end))) => {
+            start.saturating_sub(start_offset) > end.saturating_sub(end_offset)
+        }
+        (start, Some(end)) if start == end => start_offset < end_offset,
+        _ => false,
+    }
+}
+
+/// Checks the statements outlined by the `outline` rule against the rules of their entity.
+fn check_outline(source: &str) -> Vec<ValidationError> {
+    let Ok(outline) = Parser::parse(Rule::outline, source) else {
+        return vec![];
+    };
+
+    outline
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::outline_get)
+        .filter_map(|get| Statement::new(source, get))
+        .flat_map(|statement| statement.check())
+        .collect()
+}
+
+/// Explains a syntax error the outline doesn't, falling back to the tokens the parser expected.
+fn diagnose(source: &str, error: Error<Rule>) -> ValidationError {
+    let offset = error_offset(&error);
+    let positives = match &error.variant {
+        ErrorVariant::ParsingError { positives, .. } => positives.as_slice(),
+        ErrorVariant::CustomError { .. } => &[],
+    };
+    let expects_address = positives
+        .iter()
+        .any(|rule| matches!(rule, Rule::address | Rule::account_id | Rule::token_id));
+    let token =
+        Parser::parse(Rule::outline_token, &source[offset..]).map_or("", |pairs| pairs.as_str());
+
+    if expects_address && token.starts_with("0x") {
+        return ValidationError::InvalidAddress {
+            at: Position::new(source, offset),
+            address: token.to_string(),
+        };
+    }
+
+    ValidationError::Syntax {
+        at: Position::new(source, offset),
+        message: error.variant.message().to_string(),
+    }
+}
+
+fn error_offset(error: &Error<Rule>) -> usize {
+    match error.location {
+        InputLocation::Pos(offset) => offset,
+        InputLocation::Span((start, _)) => start,
+    }
+}
+
+/// `GET ... FROM entity` statement of the outline
+struct Statement<'a> {
+    source: &'a str,
+    entity: Pair<'a, Rule>,
+    fields: Vec<Pair<'a, Rule>>,
+    filters: Vec<Pair<'a, Rule>>,
+}
+
+impl<'a> Statement<'a> {
+    fn new(source: &'a str, get: Pair<'a, Rule>) -> Option<Self> {
+        let (mut entity, mut fields, mut filters) = (None, vec![], vec![]);
+
+        for pair in get.into_inner() {
+            match pair.as_rule() {
+                Rule::outline_entity => entity = Some(pair),
+                Rule::outline_fields => fields.extend(pair.into_inner()),
+                Rule::outline_filters => filters.extend(pair.into_inner()),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            source,
+            entity: entity?,
+            fields,
+            filters,
+        })
+    }
+
+    fn at(&self, pair: &Pair<'a, Rule>) -> Position {
+        Position::new(self.source, pair.as_span().start())
+    }
+
+    fn entity(&self) -> &'a str {
+        self.entity.as_str()
+    }
+
+    fn field_rule(&self) -> Option<Rule> {
+        match self.entity() {
+            "account" => Some(Rule::account_field),
+            "block" => Some(Rule::block_field),
+            "tx" => Some(Rule::tx_field),
+            "log" => Some(Rule::log_field),
+            "token" => Some(Rule::token_field),
+            "receipt" => Some(Rule::receipt_field),
+            "chain" => Some(Rule::chain_field),
+            _ => None,
+        }
+    }
+
+    /// Token, receipt and chain queries take no filters
+    fn filter_rule(&self) -> Option<Rule> {
+        match self.entity() {
+            "account" => Some(Rule::account_filter),
+            "block" => Some(Rule::block_filter),
+            "tx" => Some(Rule::tx_filter),
+            "log" => Some(Rule::log_filter),
+            _ => None,
+        }
+    }
+
+    fn check(&self) -> Vec<ValidationError> {
+        let Some(field_rule) = self.field_rule() else {
+            return vec![ValidationError::UnknownEntity {
+                at: self.at(&self.entity),
+                entity: self.entity().to_string(),
+            }];
+        };
+
+        let fields = self
+            .fields
+            .iter()
+            .filter_map(|field| self.check_field(field_rule, field));
+        let filters = self
+            .filters
+            .iter()
+            .filter_map(|filter| self.check_filter(filter));
+
+        fields.chain(filters).collect()
+    }
+
+    /// Reports the field if the field rule of the entity doesn't accept it.
+    fn check_field(&self, rule: Rule, field: &Pair<'a, Rule>) -> Option<ValidationError> {
+        let source = field.as_str();
+
+        if [rule, Rule::aggregate, Rule::wildcard]
+            .iter()
+            .any(|rule| parses_fully(*rule, source))
+        {
+            return None;
+        }
+
+        // `balance_of` is the only field taking an address
+        if let Some(address) = source.strip_prefix("balance_of") {
+            if rule == Rule::account_field {
+                let address = address.trim_start();
+
+                return Some(ValidationError::InvalidAddress {
+                    at: Position::new(self.source, field.as_span().end() - address.len()),
+                    address: address.to_string(),
+                });
+            }
+        }
+
+        Some(ValidationError::UnknownField {
+            at: self.at(field),
+            entity: self.entity().to_string(),
+            field: source.to_string(),
+        })
+    }
+
+    /// Reports the filter if the entity can't be filtered by it. A filter the entity supports
+    /// parses past its name, even with a malformed value, which is left to the syntax error.
+    fn check_filter(&self, filter: &Pair<'a, Rule>) -> Option<ValidationError> {
+        // Filters grouped with `OR` have no name of their own
+        let name = filter
+            .clone()
+            .into_inner()
+            .find(|pair| pair.as_rule() == Rule::outline_filter_name)?;
+
+        let supported =
+            self.filter_rule()
+                .is_some_and(|rule| match Parser::parse(rule, filter.as_str()) {
+                    Ok(_) => true,
+                    Err(error) => error_offset(&error) >= name.as_str().len(),
+                });
+
+        match supported {
+            true => None,
+            false => Some(ValidationError::UnsupportedFilter {
+                at: self.at(&name),
+                entity: self.entity().to_string(),
+                filter: name.as_str().to_string(),
+            }),
+        }
+    }
+}
+
+fn parses_fully(rule: Rule, source: &str) -> bool {
+    Parser::parse(rule, source).is_ok_and(|pairs| pairs.as_str().len() == source.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    #[test]
+    fn test_valid_queries_have_no_errors() {
+        let source = "GET nonce, balance, balance_of 0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48 \
+            FROM account 0x1234567890123456789012345678901234567890 ON eth\n\
+            GET count(*) FROM block 1:10 ON eth\n\
+            GET hash FROM tx WHERE block = latest-10:latest, status = true ON eth";
+
+        assert_eq!(validate(source), vec![]);
+    }
+
+    #[test]
+    fn test_field_the_entity_does_not_have() {
+        assert_eq!(
+            validate("GET number, status FROM block 1 ON eth"),
+            vec![ValidationError::UnknownField {
+                at: position(1, 13),
+                entity: "block".to_string(),
+                field: "status".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_malformed_addresses() {
+        assert_eq!(
+            validate("GET balance FROM account 0x123 ON eth"),
+            vec![ValidationError::InvalidAddress {
+                at: position(1, 26),
+                address: "0x123".to_string(),
+            }]
+        );
+        assert_eq!(
+            validate("GET * FROM log WHERE block = 10, address = 0x12zz ON eth"),
+            vec![ValidationError::InvalidAddress {
+                at: position(1, 44),
+                address: "0x12zz".to_string(),
+            }]
+        );
+        assert_eq!(
+            validate("GET balance_of 0x1234 FROM account vitalik.eth ON eth"),
+            vec![ValidationError::InvalidAddress {
+                at: position(1, 16),
+                address: "0x1234".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_filter_the_entity_does_not_support() {
+        assert_eq!(
+            validate("GET hash FROM tx WHERE block 10, event_signature = Transfer() ON eth"),
+            vec![ValidationError::UnsupportedFilter {
+                at: position(1, 34),
+                entity: "tx".to_string(),
+                filter: "event_signature".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_errors_of_every_statement_are_reported() {
+        let source = "GET number, status FROM block 1 ON eth\n\
+            GET call(\"transfer(address,uint256)\", 0x1234567890123456789012345678901234567890, 1), \
+            gas_used FROM account vitalik.eth ON eth\n\
+            GET hash FROM tx WHERE value BETWEEN 1 AND 5 AND topic0 = 0x00 ON eth";
+
+        assert_eq!(
+            validate(source),
+            vec![
+                ValidationError::UnknownField {
+                    at: position(1, 13),
+                    entity: "block".to_string(),
+                    field: "status".to_string(),
+                },
+                ValidationError::UnknownField {
+                    at: position(2, 87),
+                    entity: "account".to_string(),
+                    field: "gas_used".to_string(),
+                },
+                ValidationError::UnsupportedFilter {
+                    at: position(3, 50),
+                    entity: "tx".to_string(),
+                    filter: "topic0".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_entity() {
+        assert_eq!(
+            validate("GET * FROM blocks 1 ON eth"),
+            vec![ValidationError::UnknownEntity {
+                at: position(1, 12),
+                entity: "blocks".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_block_ranges_ending_before_they_start() {
+        let source = "GET number FROM block 10:5 ON eth\n\
+            GET hash FROM tx WHERE block latest-10:latest-100 ON eth";
+
+        assert_eq!(
+            validate(source),
+            vec![
+                ValidationError::ReversedBlockRange {
+                    at: position(1, 1),
+                    range: "10:5".to_string(),
+                },
+                ValidationError::ReversedBlockRange {
+                    at: position(2, 1),
+                    range: "latest-10:latest-100".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decoded_logs_require_an_event_signature() {
+        assert_eq!(
+            validate("GET decoded FROM log WHERE block = 10 ON eth"),
+            vec![ValidationError::MissingFilter {
+                at: position(1, 1),
+                field: "decoded".to_string(),
+                filter: "event_signature".to_string(),
+            }]
+        );
+    }
+}
@@ -1,10 +1,19 @@
 pub mod backend;
 pub mod frontend;
 
-use crate::common::{query_result::QueryResult, types::Expression};
+use crate::common::{
+    config::Config,
+    query_result::{BlockQueryRes, QueryResult},
+    types::Expression,
+};
 use anyhow::Result;
-use backend::execution_engine::ExecutionEngine;
-use frontend::parser::Parser;
+use backend::{execution_engine::ExecutionEngine, explain::QueryPlan, metrics::Metrics};
+use frontend::{
+    parser::Parser,
+    validator::{validate, ValidationError},
+};
+use futures::stream::BoxStream;
+use std::{path::PathBuf, sync::Arc};
 
 pub struct Interpreter;
 
@@ -14,30 +23,194 @@ pub enum InterpreterError {
         "eql() should receive a single query. For multiple queries use Interpreter::run_program"
     )]
     SingleQueryError,
+    #[error("{entity} has no field {field}")]
+    InvalidFieldForEntity { entity: String, field: String },
 }
 
 impl Interpreter {
+    /// Runs the queries of the program, caching their finalized data in the `cache_dir` of the
+    /// config, if it sets one.
     pub async fn run_program(source: &str) -> Result<Vec<QueryResult>> {
         let exressions = Interpreter::run_frontend(source)?;
-        Interpreter::run_backend(exressions).await
+        Interpreter::run_backend(exressions, Config::new().get_cache_dir()?).await
+    }
+
+    /// Same as `run_program`, caching the finalized data of the queries in `cache_dir`.
+    pub async fn run_program_with_disk_cache(
+        source: &str,
+        cache_dir: PathBuf,
+    ) -> Result<Vec<QueryResult>> {
+        let exressions = Interpreter::run_frontend(source)?;
+        Interpreter::run_backend(exressions, Some(cache_dir)).await
     }
 
     fn run_frontend(source: &str) -> Result<Vec<Expression>> {
-        let expressions = Parser::new(source).parse_expressions()?;
-        Ok(expressions)
+        match Parser::new(source).parse_expressions() {
+            Ok(expressions) => Ok(expressions),
+            // The grammar only accepts the fields of each entity, so a field paired with another
+            // entity is a syntax error, which the validator finds in the outline of the statement
+            Err(e) => {
+                let invalid_field = validate(source).into_iter().find_map(|error| match error {
+                    ValidationError::UnknownField { entity, field, .. } => Some((entity, field)),
+                    _ => None,
+                });
+
+                match invalid_field {
+                    Some((entity, field)) => {
+                        Err(InterpreterError::InvalidFieldForEntity { entity, field }.into())
+                    }
+                    None => Err(e),
+                }
+            }
+        }
     }
 
-    async fn run_backend(expressions: Vec<Expression>) -> Result<Vec<QueryResult>> {
-        let result = ExecutionEngine::new().run(expressions).await?;
+    async fn run_backend(
+        expressions: Vec<Expression>,
+        cache_dir: Option<PathBuf>,
+    ) -> Result<Vec<QueryResult>> {
+        let engine = match cache_dir {
+            Some(cache_dir) => ExecutionEngine::new().with_disk_cache(cache_dir),
+            None => ExecutionEngine::new(),
+        };
+        let result = engine.run(expressions).await?;
         Ok(result)
     }
 }
 
+/// Follows a single block query, yielding the new blocks as they're produced.
+/// The query must run on WebSocket or IPC RPCs, e.g.
+/// `GET number, hash FROM block latest ON wss://...`.
+pub async fn eql_follow(source: &str) -> Result<BoxStream<'static, Result<BlockQueryRes>>> {
+    let expressions = Interpreter::run_frontend(source)?;
+
+    match expressions.as_slice() {
+        [expression] => ExecutionEngine::new().follow(expression).await,
+        _ => Err(InterpreterError::SingleQueryError.into()),
+    }
+}
+
+/// Plans the RPC requests every query of the program would make, without contacting the network.
+pub fn eql_explain(source: &str) -> Result<Vec<QueryPlan>> {
+    let expressions = Interpreter::run_frontend(source)?;
+    ExecutionEngine::new().explain(&expressions)
+}
+
+/// Validates every query of the program without contacting the network, returning all the errors
+/// found, e.g. fields the entity doesn't have or malformed addresses.
+pub fn eql_validate(source: &str) -> Vec<ValidationError> {
+    validate(source)
+}
+
 pub async fn eql(source: &str) -> Result<QueryResult> {
-    let result = Interpreter::run_program(source).await?;
+    first_result(Interpreter::run_program(source).await?)
+}
+
+/// Same as `eql`, reporting every RPC request made by the query to `metrics`.
+pub async fn eql_with_metrics(source: &str, metrics: Arc<dyn Metrics>) -> Result<QueryResult> {
+    let expressions = Interpreter::run_frontend(source)?;
+    let results = ExecutionEngine::with_metrics(metrics)
+        .run(expressions)
+        .await?;
+    first_result(results)
+}
+
+/// Result of the first query of the program, the one `eql` and its variants return.
+fn first_result(results: Vec<QueryResult>) -> Result<QueryResult> {
+    results
+        .into_iter()
+        .next()
+        .ok_or_else(|| InterpreterError::SingleQueryError.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::mock_rpc::{result, MockRpc};
+
+    fn invalid_field(source: &str) -> Option<(String, String)> {
+        match Interpreter::run_frontend(source) {
+            Err(e) => match e.downcast::<InterpreterError>() {
+                Ok(InterpreterError::InvalidFieldForEntity { entity, field }) => {
+                    Some((entity, field))
+                }
+                _ => None,
+            },
+            Ok(_) => None,
+        }
+    }
+
+    #[test]
+    fn test_fields_paired_with_another_entity_are_reported() {
+        let cases = [
+            ("GET number, status FROM block 1 ON eth", "block", "status"),
+            (
+                "GET balance FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 ON eth",
+                "tx",
+                "balance",
+            ),
+            (
+                "GET topic0 FROM account 0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045 ON eth",
+                "account",
+                "topic0",
+            ),
+            ("GET nonce FROM log WHERE block = 1 ON eth", "log", "nonce"),
+            (
+                "GET symbol FROM receipt 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 ON eth",
+                "receipt",
+                "symbol",
+            ),
+            ("GET balance FROM chain eth", "chain", "balance"),
+            // After a statement with another error
+            (
+                "GET hash FROM tx WHERE topic0 = 0x00 ON eth\nGET status FROM block 1 ON eth",
+                "block",
+                "status",
+            ),
+        ];
+
+        for (source, entity, field) in cases {
+            assert_eq!(
+                invalid_field(source),
+                Some((entity.to_string(), field.to_string())),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fields_of_the_entity_are_not_reported() {
+        let sources = [
+            "GET number, base_fee FROM block 1 ON eth",
+            "GET value, status FROM tx 0x8a6a279a4d28dcc62bcb2f2a3214c93345c107b74f3081754e27471c50783f81 ON eth",
+            "GET balance, nonce FROM account 0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045 ON eth",
+            "GET topic0 FROM log WHERE block = 1 ON eth",
+        ];
+
+        for source in sources {
+            assert!(Interpreter::run_frontend(source).is_ok(), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_other_syntax_errors_are_not_reported_as_invalid_fields() {
+        assert_eq!(invalid_field("GET number FROM block 1 ON"), None);
+        assert_eq!(invalid_field("GET number FROM blocks 1 ON eth"), None);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_field_is_reported_before_any_request() {
+        let rpc = MockRpc::start(|method, _| match method {
+            "eth_chainId" => result(&"0x1"),
+            method => Err(format!("Unexpected method {method}")),
+        })
+        .await;
+        let source = format!("GET status FROM block 1 ON {}", rpc.url());
+
+        let error = Interpreter::run_program(&source).await.unwrap_err();
 
-    match result.first() {
-        Some(result) => Ok(result.clone()),
-        None => Err(InterpreterError::SingleQueryError.into()),
+        assert_eq!(error.to_string(), "block has no field status");
+        assert_eq!(rpc.calls("eth_chainId"), 0);
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 0);
     }
 }
@@ -2,9 +2,11 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Variant};
 
-#[proc_macro_derive(EnumVariants)]
+/// Lists the unit variants of an enum with `all_variants`. Variants marked with
+/// `#[enum_variants(skip)]` are left out.
+#[proc_macro_derive(EnumVariants, attributes(enum_variants))]
 pub fn enum_variants_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = input.ident;
@@ -14,7 +16,12 @@ pub fn enum_variants_derive(input: TokenStream) -> TokenStream {
         _ => panic!("EnumVariants can only be applied to enums"),
     };
 
-    let variant_names = variants.iter().map(|variant| &variant.ident);
+    // Variants holding data can't be listed without their values, so only unit variants are returned
+    let variant_names = variants
+        .iter()
+        .filter(|variant| matches!(variant.fields, Fields::Unit))
+        .filter(|variant| !is_skipped(variant))
+        .map(|variant| &variant.ident);
 
     let expanded = quote! {
         impl #name {
@@ -27,4 +34,11 @@ pub fn enum_variants_derive(input: TokenStream) -> TokenStream {
     };
 
     TokenStream::from(expanded)
-}
\ No newline at end of file
+}
+
+fn is_skipped(variant: &Variant) -> bool {
+    variant.attrs.iter().any(|attr| {
+        attr.path.is_ident("enum_variants")
+            && attr.parse_args::<Ident>().is_ok_and(|arg| arg == "skip")
+    })
+}